@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::lib::collector::{CPUS_SECTION, DISKS_SECTION, DiskSample, MEMORY_SECTION, SystemSource};
+use crate::lib::report::DiskReport;
+
+/// A metric that has risen above its threshold.
+#[derive(Debug,PartialEq)]
+pub struct ThresholdCrossing {
+    pub section: &'static str,
+    /// The usage percentage that crossed the threshold.
+    pub value: f64,
+    pub threshold: f64,
+}
+
+/// Watches usage percentages between scheduled checks. A crossing is reported once, and the section is only re-armed
+/// once its usage has fallen back to or below the threshold, so that a metric hovering above it does not repeat
+/// alerts.
+pub struct ThresholdMonitor {
+    thresholds: HashMap<&'static str, f64>,
+    crossed: HashSet<&'static str>,
+}
+
+impl ThresholdMonitor {
+    pub fn new(thresholds: HashMap<&'static str, f64>) -> ThresholdMonitor {
+        ThresholdMonitor {
+            thresholds,
+            crossed: HashSet::new(),
+        }
+    }
+
+    /// Refreshes the source and returns the sections that have newly crossed their thresholds.
+    pub fn evaluate(&mut self, source: &mut dyn SystemSource) -> Result<Vec<ThresholdCrossing>, Box<dyn Error>> {
+        source.refresh();
+        let mut crossings = Vec::new();
+        for (section, threshold) in self.thresholds.iter() {
+            let value = match usage_pct(section, source)? {
+                Some(value) => value,
+                None => continue
+            };
+            if value <= *threshold {
+                self.crossed.remove(section);
+            } else if self.crossed.insert(section) {
+                crossings.push(ThresholdCrossing { section, value, threshold: *threshold });
+            }
+        }
+        Ok(crossings)
+    }
+}
+
+/// A mount whose free space has dropped below its alert threshold.
+#[derive(Debug,PartialEq)]
+pub struct FreeSpaceShortfall {
+    pub mount_point: String,
+    pub available_bytes: u64,
+    pub threshold_bytes: u64,
+}
+
+/// Watches the free space of individual mounts between scheduled checks, so that a disk filling up is caught before
+/// the next report. As with usage thresholds, a shortfall is reported once and the mount is re-armed once its free
+/// space has recovered to the threshold.
+pub struct FreeSpaceMonitor {
+    thresholds: HashMap<String, u64>,
+    short: HashSet<String>,
+}
+
+impl FreeSpaceMonitor {
+    pub fn new(thresholds: HashMap<String, u64>) -> FreeSpaceMonitor {
+        FreeSpaceMonitor {
+            thresholds,
+            short: HashSet::new(),
+        }
+    }
+
+    /// Returns the mounts among the disks whose free space has newly dropped below their thresholds. Mounts that
+    /// are not among the disks are skipped.
+    pub fn evaluate(&mut self, disks: &[DiskSample]) -> Vec<FreeSpaceShortfall> {
+        let mut shortfalls = Vec::new();
+        for disk in disks.iter() {
+            let threshold_bytes = match self.thresholds.get(&disk.mount_point) {
+                Some(threshold_bytes) => *threshold_bytes,
+                None => continue
+            };
+            if disk.available_space >= threshold_bytes {
+                self.short.remove(&disk.mount_point);
+            } else if self.short.insert(disk.mount_point.clone()) {
+                shortfalls.push(FreeSpaceShortfall {
+                    mount_point: disk.mount_point.clone(),
+                    available_bytes: disk.available_space,
+                    threshold_bytes,
+                });
+            }
+        }
+        shortfalls
+    }
+}
+
+/// Watches the mount state of each disk across reports. A disk is only reported once it has been seen mounted
+/// read-write and is then found read-only, so that disks which are always read-only, such as squashfs images, never
+/// raise an alert.
+pub struct ReadOnlyMonitor {
+    writable: HashSet<String>,
+}
+
+impl ReadOnlyMonitor {
+    pub fn new() -> ReadOnlyMonitor {
+        ReadOnlyMonitor {
+            writable: HashSet::new(),
+        }
+    }
+
+    /// Returns the names of the disks that have gone from read-write to read-only since the previous report.
+    pub fn evaluate(&mut self, disks: &[DiskReport]) -> Vec<String> {
+        let mut remounted = Vec::new();
+        for disk in disks.iter() {
+            match disk.read_only {
+                Some(false) => {
+                    self.writable.insert(disk.name.clone());
+                }
+                Some(true) if self.writable.remove(&disk.name) => remounted.push(disk.name.clone()),
+                _ => {}
+            }
+        }
+        remounted
+    }
+}
+
+/// The usage percentage of a section: the fullest disk, the memory in use, or the average CPU usage.
+fn usage_pct(section: &str, source: &dyn SystemSource) -> Result<Option<f64>, Box<dyn Error>> {
+    match section {
+        DISKS_SECTION => Ok(source.disks()?.iter()
+            .filter(|d| d.total_space > 0)
+            .map(|d| (d.total_space - d.available_space) as f64 / d.total_space as f64 * 100.0)
+            .fold(None, |fullest: Option<f64>, pct| Some(fullest.map_or(pct, |f| f.max(pct))))),
+        MEMORY_SECTION => {
+            let memory = source.memory()?;
+            if memory.total_memory == 0 {
+                return Ok(None);
+            }
+            Ok(Some((memory.total_memory - memory.available_memory) as f64 / memory.total_memory as f64 * 100.0))
+        }
+        CPUS_SECTION => {
+            let cpus = source.cpus()?;
+            if cpus.is_empty() {
+                return Ok(None);
+            }
+            Ok(Some(cpus.iter().map(|c| c.usage as f64).sum::<f64>() / cpus.len() as f64))
+        }
+        _ => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::alert::{FreeSpaceMonitor, FreeSpaceShortfall, ReadOnlyMonitor, ThresholdCrossing, ThresholdMonitor};
+    use crate::lib::collector::{CPUS_SECTION, DISKS_SECTION, DiskSample, MEMORY_SECTION};
+    use crate::lib::collector::fake::FakeSource;
+    use crate::lib::report::DiskReport;
+
+    fn monitor(section: &'static str, threshold: f64) -> ThresholdMonitor {
+        let mut thresholds = HashMap::new();
+        thresholds.insert(section, threshold);
+        ThresholdMonitor::new(thresholds)
+    }
+
+    #[test]
+    fn report_crossing_once() {
+        let mut monitor = monitor(MEMORY_SECTION, 70.0);
+        let mut source = FakeSource::new();
+        assert_eq!(
+            vec![ThresholdCrossing { section: MEMORY_SECTION, value: 75.0, threshold: 70.0 }],
+            monitor.evaluate(&mut source).unwrap()
+        );
+        // Still above the threshold, so the crossing is not reported again
+        assert!(monitor.evaluate(&mut source).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rearm_after_falling_below_threshold() {
+        let mut monitor = monitor(MEMORY_SECTION, 70.0);
+        let mut source = FakeSource::new();
+        assert_eq!(1, monitor.evaluate(&mut source).unwrap().len());
+        source.memory.available_memory = 1024;
+        assert!(monitor.evaluate(&mut source).unwrap().is_empty());
+        source.memory.available_memory = 512;
+        assert_eq!(1, monitor.evaluate(&mut source).unwrap().len());
+    }
+
+    #[test]
+    fn stay_quiet_below_threshold() {
+        let mut monitor = monitor(DISKS_SECTION, 90.0);
+        assert!(monitor.evaluate(&mut FakeSource::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn measure_fullest_disk_and_average_cpu() {
+        let mut source = FakeSource::new();
+        source.disks[0].available_space = 50;
+        let crossings = monitor(DISKS_SECTION, 90.0).evaluate(&mut source).unwrap();
+        assert_eq!(95.0, crossings[0].value);
+        let crossings = monitor(CPUS_SECTION, 10.0).evaluate(&mut source).unwrap();
+        assert_eq!(12.5, crossings[0].value);
+    }
+
+    fn mount(mount_point: &str, available_space: u64) -> DiskSample {
+        DiskSample {
+            name: String::from("sda1"),
+            mount_point: String::from(mount_point),
+            total_space: 1000,
+            available_space,
+        }
+    }
+
+    #[test]
+    fn report_free_space_shortfall_once() {
+        let mut thresholds = HashMap::new();
+        thresholds.insert(String::from("/data"), 200);
+        let mut monitor = FreeSpaceMonitor::new(thresholds);
+        assert!(monitor.evaluate(&[mount("/data", 200), mount("/", 10)]).is_empty());
+        assert_eq!(
+            vec![FreeSpaceShortfall { mount_point: String::from("/data"), available_bytes: 150, threshold_bytes: 200 }],
+            monitor.evaluate(&[mount("/data", 150)])
+        );
+        assert!(monitor.evaluate(&[mount("/data", 100)]).is_empty());
+        // Recovering to the threshold re-arms the alert
+        assert!(monitor.evaluate(&[mount("/data", 300)]).is_empty());
+        assert_eq!(1, monitor.evaluate(&[mount("/data", 100)]).len());
+    }
+
+    fn disk(name: &str, read_only: Option<bool>) -> DiskReport {
+        DiskReport {
+            name: String::from(name),
+            disk_used: 600,
+            disk_capacity: 1000,
+            io_busy_pct: None,
+            inodes_used: None,
+            inodes_total: None,
+            read_only,
+            disk_temperature: None,
+            near_full: None,
+        }
+    }
+
+    #[test]
+    fn report_remount_read_only_once() {
+        let mut monitor = ReadOnlyMonitor::new();
+        assert!(monitor.evaluate(&[disk("sda1", Some(false))]).is_empty());
+        assert_eq!(vec![String::from("sda1")], monitor.evaluate(&[disk("sda1", Some(true))]));
+        assert!(monitor.evaluate(&[disk("sda1", Some(true))]).is_empty());
+        // Once writable again, a further remount is reported
+        assert!(monitor.evaluate(&[disk("sda1", Some(false))]).is_empty());
+        assert_eq!(vec![String::from("sda1")], monitor.evaluate(&[disk("sda1", Some(true))]));
+    }
+
+    #[test]
+    fn ignore_disks_never_seen_writable() {
+        let mut monitor = ReadOnlyMonitor::new();
+        assert!(monitor.evaluate(&[disk("loop0", Some(true)), disk("sda1", None)]).is_empty());
+        assert!(monitor.evaluate(&[disk("loop0", Some(true)), disk("sda1", None)]).is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::error::Error;
+use std::fs;
+
+use crate::lib::common::RuntimeError;
+
+/// Share of the budget, in percent, beyond which reports are throttled.
+const THROTTLE_THRESHOLD_PCT: u64 = 90;
+/// Once throttled, only every Nth report is sent, multiplying the effective interval.
+const THROTTLED_REPORT_DIVISOR: u64 = 4;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Tracks the bytes transmitted in the current calendar month (UTC) against a monthly budget.
+pub struct ByteBudget {
+    monthly_limit: u64,
+    state_path: Option<String>,
+    month: u64,
+    used_bytes: u64,
+    skipped_reports: u64,
+}
+
+impl ByteBudget {
+    /// Creates a budget, resuming the month's usage from the state file if one exists.
+    pub fn new(monthly_limit: u64, state_path: Option<String>) -> ByteBudget {
+        let (month, used_bytes) = state_path.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|state| parse_state(state.as_str()))
+            .unwrap_or((0, 0));
+        ByteBudget {
+            monthly_limit,
+            state_path,
+            month,
+            used_bytes,
+            skipped_reports: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn used_bytes(&self) -> u64 {
+        self.used_bytes
+    }
+
+    /// Decides whether a report due at the timestamp should be sent. Past the throttle threshold only
+    /// every few reports go out, and none do once the budget is exhausted, until the month rolls over.
+    pub fn should_send_report(&mut self, timestamp: u64) -> bool {
+        self.roll_over(timestamp);
+        if self.used_bytes >= self.monthly_limit {
+            return false;
+        }
+        if self.used_bytes * 100 < self.monthly_limit * THROTTLE_THRESHOLD_PCT {
+            self.skipped_reports = 0;
+            return true;
+        }
+        if self.skipped_reports + 1 >= THROTTLED_REPORT_DIVISOR {
+            self.skipped_reports = 0;
+            true
+        } else {
+            self.skipped_reports += 1;
+            false
+        }
+    }
+
+    /// Adds transmitted bytes to the month's usage and persists it.
+    pub fn record(&mut self, bytes: u64, timestamp: u64) -> Result<(), Box<dyn Error>> {
+        self.roll_over(timestamp);
+        self.used_bytes += bytes;
+        match &self.state_path {
+            Some(path) => match fs::write(path, format!("{} {}\n", self.month, self.used_bytes)) {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    let error = Box::new(RuntimeError::new(format!("Unable to write budget state file '{}': {}", path, e).as_str()));
+                    Err(error)
+                }
+            },
+            None => Ok(())
+        }
+    }
+
+    fn roll_over(&mut self, timestamp: u64) {
+        let month = month_index(timestamp);
+        if month != self.month {
+            self.month = month;
+            self.used_bytes = 0;
+            self.skipped_reports = 0;
+        }
+    }
+}
+
+/// Parses the `<month index> <used bytes>` state file contents.
+fn parse_state(state: &str) -> Option<(u64, u64)> {
+    let mut fields = state.split_whitespace();
+    let month = fields.next()?.parse().ok()?;
+    let used_bytes = fields.next()?.parse().ok()?;
+    Some((month, used_bytes))
+}
+
+/// Number of whole months between January 1970 and the UTC month containing the timestamp.
+fn month_index(timestamp: u64) -> u64 {
+    // Civil-from-days conversion over 400-year eras, with years starting in March
+    let days = timestamp / SECONDS_PER_DAY + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year - 1970) * 12 + (month - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::budget::{ByteBudget, month_index};
+
+    // 2021-06-15T00:00:00Z and 2021-07-01T00:00:00Z
+    const MID_JUNE: u64 = 1_623_715_200;
+    const START_OF_JULY: u64 = 1_625_097_600;
+
+    #[test]
+    fn compute_month_index() {
+        assert_eq!(0, month_index(0));
+        assert_eq!(617, month_index(MID_JUNE));
+        assert_eq!(617, month_index(START_OF_JULY - 1));
+        assert_eq!(618, month_index(START_OF_JULY));
+        // 2024-02-29T12:00:00Z
+        assert_eq!(649, month_index(1_709_208_000));
+    }
+
+    #[test]
+    fn send_below_threshold() {
+        let mut budget = ByteBudget::new(1000, None);
+        budget.record(899, MID_JUNE).unwrap();
+        assert!(budget.should_send_report(MID_JUNE));
+        assert!(budget.should_send_report(MID_JUNE));
+    }
+
+    #[test]
+    fn throttle_past_threshold() {
+        let mut budget = ByteBudget::new(1000, None);
+        budget.record(900, MID_JUNE).unwrap();
+        let sent: Vec<bool> = (0..8).map(|_| budget.should_send_report(MID_JUNE)).collect();
+        assert_eq!(vec![false, false, false, true, false, false, false, true], sent);
+    }
+
+    #[test]
+    fn stop_when_exhausted() {
+        let mut budget = ByteBudget::new(1000, None);
+        budget.record(1000, MID_JUNE).unwrap();
+        assert!((0..8).all(|_| !budget.should_send_report(MID_JUNE)));
+    }
+
+    #[test]
+    fn reset_on_month_rollover() {
+        let mut budget = ByteBudget::new(1000, None);
+        budget.record(1000, MID_JUNE).unwrap();
+        assert!(!budget.should_send_report(MID_JUNE));
+        assert!(budget.should_send_report(START_OF_JULY));
+        assert_eq!(0, budget.used_bytes());
+    }
+
+    #[test]
+    fn persist_usage_across_restarts() {
+        let state_path = std::env::temp_dir().join(format!("device_stats_reporter_budget_{}", std::process::id()));
+        let state_path = String::from(state_path.to_str().unwrap());
+        let _ = fs::remove_file(&state_path);
+        let mut budget = ByteBudget::new(1000, Some(state_path.clone()));
+        budget.record(400, MID_JUNE).unwrap();
+        budget.record(200, MID_JUNE).unwrap();
+        let mut resumed_budget = ByteBudget::new(1000, Some(state_path.clone()));
+        let _ = fs::remove_file(&state_path);
+        assert_eq!(600, resumed_budget.used_bytes());
+        // Usage from a previous month is discarded
+        assert!(resumed_budget.should_send_report(START_OF_JULY));
+        assert_eq!(0, resumed_budget.used_bytes());
+    }
+}
@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::lib::collector::CgroupSample;
+use crate::lib::common::RuntimeError;
+
+/// Where the reporter's own cgroup is mounted inside a container with its own cgroup namespace.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+// Only present at the root of a cgroup v2 hierarchy
+const CGROUP_V2_MARKER: &str = "cgroup.controllers";
+const UNLIMITED: &str = "max";
+// cgroup v1 reports an unlimited memory limit as the largest page-aligned signed 64-bit value
+const CGROUP_V1_UNLIMITED_MEMORY: u64 = 0x7FFF_FFFF_FFFF_F000;
+const BYTES_PER_KB: u64 = 1024;
+
+/// Reads the memory and CPU limits of the cgroup mounted at the root, along with its memory and cumulative CPU usage,
+/// from either a cgroup v2 or a cgroup v1 hierarchy. Returns `None` if no cgroup hierarchy is mounted there. Memory
+/// is in KB, as in `MemorySample`; page cache that can be dropped is not counted as used, as `docker stats` does.
+pub fn read_cgroup(cgroup_root: &Path) -> Result<Option<CgroupSample>, Box<dyn Error>> {
+    if !cgroup_root.is_dir() {
+        return Ok(None);
+    }
+    if cgroup_root.join(CGROUP_V2_MARKER).is_file() {
+        read_cgroup_v2(cgroup_root).map(Some)
+    } else {
+        read_cgroup_v1(cgroup_root).map(Some)
+    }
+}
+
+fn read_cgroup_v2(cgroup_root: &Path) -> Result<CgroupSample, Box<dyn Error>> {
+    let memory_limit = match read_cgroup_file(cgroup_root, "memory.max")? {
+        Some(memory_max) => parse_limit(cgroup_root, "memory.max", memory_max.as_str())?,
+        None => None
+    };
+    let memory_used = read_memory_used(cgroup_root, "memory.current", "memory.stat", "inactive_file")?;
+    let cpu_limit = match read_cgroup_file(cgroup_root, "cpu.max")? {
+        Some(cpu_max) => parse_cpu_max(cgroup_root, cpu_max.as_str())?,
+        None => None
+    };
+    let cpu_usage_usec = match read_cgroup_file(cgroup_root, "cpu.stat")? {
+        Some(cpu_stat) => stat_value(cpu_stat.as_str(), "usage_usec"),
+        None => None
+    };
+    Ok(CgroupSample {
+        memory_limit: memory_limit.map(|memory_limit| memory_limit / BYTES_PER_KB),
+        memory_used,
+        cpu_limit,
+        cpu_usage_usec,
+    })
+}
+
+fn read_cgroup_v1(cgroup_root: &Path) -> Result<CgroupSample, Box<dyn Error>> {
+    let memory_limit = match read_cgroup_file(cgroup_root, "memory/memory.limit_in_bytes")? {
+        Some(limit_in_bytes) => parse_limit(cgroup_root, "memory/memory.limit_in_bytes", limit_in_bytes.as_str())?
+            .filter(|memory_limit| *memory_limit < CGROUP_V1_UNLIMITED_MEMORY),
+        None => None
+    };
+    let memory_used = read_memory_used(
+        cgroup_root,
+        "memory/memory.usage_in_bytes",
+        "memory/memory.stat",
+        "total_inactive_file"
+    )?;
+    let cpu_quota = read_cgroup_file(cgroup_root, "cpu/cpu.cfs_quota_us")?;
+    let cpu_period = read_cgroup_file(cgroup_root, "cpu/cpu.cfs_period_us")?;
+    let cpu_limit = match (cpu_quota, cpu_period) {
+        // An unlimited quota is given as -1
+        (Some(quota), Some(period)) => match (quota.trim().parse::<i64>(), period.trim().parse::<u64>()) {
+            (Ok(quota), Ok(period)) if quota > 0 && period > 0 => Some(quota as f64 / period as f64),
+            (Ok(_), Ok(_)) => None,
+            _ => return Err(unexpected_contents(cgroup_root, "cpu/cpu.cfs_quota_us", quota.as_str()))
+        },
+        _ => None
+    };
+    // cpuacct.usage is in nanoseconds
+    let cpu_usage_usec = match read_cgroup_file(cgroup_root, "cpuacct/cpuacct.usage")? {
+        Some(usage) => match usage.trim().parse::<u64>() {
+            Ok(usage) => Some(usage / 1000),
+            Err(_) => return Err(unexpected_contents(cgroup_root, "cpuacct/cpuacct.usage", usage.as_str()))
+        },
+        None => None
+    };
+    Ok(CgroupSample {
+        memory_limit: memory_limit.map(|memory_limit| memory_limit / BYTES_PER_KB),
+        memory_used,
+        cpu_limit,
+        cpu_usage_usec,
+    })
+}
+
+/// Reads a file of the cgroup, or `None` where the controller providing it is not enabled.
+fn read_cgroup_file(cgroup_root: &Path, file_name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let path = cgroup_root.join(file_name);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(format!("Unable to read {}: {}", path.display(), e).as_str()));
+            Err(error)
+        }
+    }
+}
+
+/// The memory in use in KB, less the inactive page cache listed under the given key of the stat file.
+fn read_memory_used(
+    cgroup_root: &Path,
+    usage_file_name: &str,
+    stat_file_name: &str,
+    inactive_file_key: &str
+) -> Result<Option<u64>, Box<dyn Error>> {
+    let usage = match read_cgroup_file(cgroup_root, usage_file_name)? {
+        Some(usage) => match usage.trim().parse::<u64>() {
+            Ok(usage) => usage,
+            Err(_) => return Err(unexpected_contents(cgroup_root, usage_file_name, usage.as_str()))
+        },
+        None => return Ok(None)
+    };
+    let inactive_file = read_cgroup_file(cgroup_root, stat_file_name)?
+        .and_then(|stat| stat_value(stat.as_str(), inactive_file_key))
+        .unwrap_or(0);
+    Ok(Some(usage.saturating_sub(inactive_file) / BYTES_PER_KB))
+}
+
+/// Parses a limit that is either a number or `max`.
+fn parse_limit(cgroup_root: &Path, file_name: &str, limit: &str) -> Result<Option<u64>, Box<dyn Error>> {
+    match limit.trim() {
+        UNLIMITED => Ok(None),
+        value => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(unexpected_contents(cgroup_root, file_name, limit))
+        }
+    }
+}
+
+/// Parses `cpu.max`, a quota and a period in microseconds, into the number of cores the quota amounts to.
+fn parse_cpu_max(cgroup_root: &Path, cpu_max: &str) -> Result<Option<f64>, Box<dyn Error>> {
+    let mut fields = cpu_max.split_whitespace();
+    match (fields.next(), fields.next().map(str::parse::<u64>)) {
+        (Some(UNLIMITED), Some(Ok(_))) => Ok(None),
+        (Some(quota), Some(Ok(period))) if period > 0 => match quota.parse::<u64>() {
+            Ok(quota) => Ok(Some(quota as f64 / period as f64)),
+            Err(_) => Err(unexpected_contents(cgroup_root, "cpu.max", cpu_max))
+        },
+        _ => Err(unexpected_contents(cgroup_root, "cpu.max", cpu_max))
+    }
+}
+
+/// Looks up a value in a file of space-separated keys and values, one pair per line.
+fn stat_value(stat: &str, key: &str) -> Option<u64> {
+    stat.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        if name == key { value.trim().parse().ok() } else { None }
+    })
+}
+
+fn unexpected_contents(cgroup_root: &Path, file_name: &str, contents: &str) -> Box<dyn Error> {
+    Box::new(RuntimeError::new(
+        format!("Unexpected contents of {}: '{}'", cgroup_root.join(file_name).display(), contents.trim()).as_str()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    use crate::lib::cgroup::read_cgroup;
+
+    /// Lays out a cgroup hierarchy of the given files in a fresh temporary directory.
+    fn cgroup_tree(files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cgroup-{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        for (file_name, contents) in files {
+            let path = root.join(file_name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn read_cgroup_v2_limits() {
+        let root = cgroup_tree(&[
+            ("cgroup.controllers", "cpu memory pids\n"),
+            ("memory.max", "536870912\n"),
+            ("memory.current", "209715200\n"),
+            ("memory.stat", "anon 104857600\nfile 104857600\ninactive_file 52428800\n"),
+            ("cpu.max", "150000 100000\n"),
+            ("cpu.stat", "usage_usec 8000000\nuser_usec 6000000\nsystem_usec 2000000\n"),
+        ]);
+        let cgroup = read_cgroup(&root).unwrap().unwrap();
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(Some(524288), cgroup.memory_limit);
+        assert_eq!(Some(153600), cgroup.memory_used);
+        assert_eq!(Some(1.5), cgroup.cpu_limit);
+        assert_eq!(Some(8000000), cgroup.cpu_usage_usec);
+    }
+
+    #[test]
+    fn read_unlimited_cgroup_v2() {
+        let root = cgroup_tree(&[
+            ("cgroup.controllers", "cpu memory\n"),
+            ("memory.max", "max\n"),
+            ("cpu.max", "max 100000\n"),
+        ]);
+        let cgroup = read_cgroup(&root).unwrap().unwrap();
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(None, cgroup.memory_limit);
+        assert_eq!(None, cgroup.memory_used);
+        assert_eq!(None, cgroup.cpu_limit);
+        assert_eq!(None, cgroup.cpu_usage_usec);
+    }
+
+    #[test]
+    fn read_cgroup_v1_limits() {
+        let root = cgroup_tree(&[
+            ("memory/memory.limit_in_bytes", "1073741824\n"),
+            ("memory/memory.usage_in_bytes", "314572800\n"),
+            ("memory/memory.stat", "cache 104857600\ntotal_inactive_file 104857600\n"),
+            ("cpu/cpu.cfs_quota_us", "200000\n"),
+            ("cpu/cpu.cfs_period_us", "100000\n"),
+            ("cpuacct/cpuacct.usage", "5000000000\n"),
+        ]);
+        let cgroup = read_cgroup(&root).unwrap().unwrap();
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(Some(1048576), cgroup.memory_limit);
+        assert_eq!(Some(204800), cgroup.memory_used);
+        assert_eq!(Some(2.0), cgroup.cpu_limit);
+        assert_eq!(Some(5000000), cgroup.cpu_usage_usec);
+    }
+
+    #[test]
+    fn read_unlimited_cgroup_v1() {
+        let root = cgroup_tree(&[
+            ("memory/memory.limit_in_bytes", "9223372036854771712\n"),
+            ("cpu/cpu.cfs_quota_us", "-1\n"),
+            ("cpu/cpu.cfs_period_us", "100000\n"),
+        ]);
+        let cgroup = read_cgroup(&root).unwrap().unwrap();
+        fs::remove_dir_all(root).unwrap();
+        assert_eq!(None, cgroup.memory_limit);
+        assert_eq!(None, cgroup.cpu_limit);
+    }
+
+    #[test]
+    fn read_without_cgroup_hierarchy() {
+        let root = std::env::temp_dir().join(format!("cgroup-{}", Uuid::new_v4()));
+        assert!(read_cgroup(&root).unwrap().is_none());
+    }
+
+    #[test]
+    fn read_malformed_cpu_max() {
+        let root = cgroup_tree(&[
+            ("cgroup.controllers", "cpu\n"),
+            ("cpu.max", "unbounded\n"),
+        ]);
+        let result = read_cgroup(&root).err().unwrap();
+        fs::remove_dir_all(root).unwrap();
+        assert!(result.to_string().contains("Unexpected contents of"));
+    }
+}
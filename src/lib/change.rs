@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use crate::lib::collector::{CPUS_SECTION, DISKS_SECTION, MEMORY_SECTION};
+use crate::lib::report::{CollectionError, SystemReport};
+
+/// Drops report sections whose values have not moved by more than a per-section threshold since
+/// they were last sent. Disk thresholds are in bytes used, CPU thresholds in usage percentage
+/// points, and memory thresholds in bytes used.
+pub struct ChangeFilter {
+    thresholds: HashMap<&'static str, f64>,
+    last_disks: Option<HashMap<String, u64>>,
+    last_cpus: Option<HashMap<String, f32>>,
+    last_memory: Option<u64>,
+}
+
+impl ChangeFilter {
+    pub fn new(thresholds: HashMap<&'static str, f64>) -> ChangeFilter {
+        ChangeFilter {
+            thresholds,
+            last_disks: None,
+            last_cpus: None,
+            last_memory: None,
+        }
+    }
+
+    /// Empties the sections of the report that have not changed enough, returning their names.
+    /// Sections that failed to collect are left alone and do not replace the last sent values.
+    pub fn apply(&mut self, report: &mut SystemReport, collection_errors: &[CollectionError]) -> Vec<&'static str> {
+        let failed = |section: &str| collection_errors.iter().any(|e| e.section == section);
+        let mut unchanged_sections = Vec::new();
+        if let Some(threshold) = self.thresholds.get(DISKS_SECTION).cloned() {
+            if !failed(DISKS_SECTION) {
+                let disks: HashMap<String, u64> = report.disks.iter().map(|d| (d.name.clone(), d.disk_used)).collect();
+                if changed(&self.last_disks, &disks, threshold, |previous, current| (previous.max(current) - previous.min(current)) as f64) {
+                    self.last_disks = Some(disks);
+                } else {
+                    report.disks = Box::new([]);
+                    unchanged_sections.push(DISKS_SECTION);
+                }
+            }
+        }
+        if let Some(threshold) = self.thresholds.get(CPUS_SECTION).cloned() {
+            if !failed(CPUS_SECTION) {
+                let cpus: HashMap<String, f32> = report.cpus.iter().map(|c| (c.name.clone(), c.usage)).collect();
+                if changed(&self.last_cpus, &cpus, threshold, |previous, current| (previous - current).abs() as f64) {
+                    self.last_cpus = Some(cpus);
+                } else {
+                    report.cpus = Box::new([]);
+                    unchanged_sections.push(CPUS_SECTION);
+                }
+            }
+        }
+        if let Some(threshold) = self.thresholds.get(MEMORY_SECTION).cloned() {
+            if let Some(memory) = &report.memory {
+                let memory_used = memory.memory_used;
+                let memory_changed = match self.last_memory {
+                    Some(last_memory) => (last_memory.max(memory_used) - last_memory.min(memory_used)) as f64 > threshold,
+                    None => true
+                };
+                if memory_changed {
+                    self.last_memory = Some(memory_used);
+                } else {
+                    report.memory = None;
+                    unchanged_sections.push(MEMORY_SECTION);
+                }
+            }
+        }
+        unchanged_sections
+    }
+}
+
+/// A section has changed if nothing was sent before, its set of entries differs, or any entry moved past the threshold.
+fn changed<T: Copy, F: Fn(T, T) -> f64>(previous: &Option<HashMap<String, T>>, current: &HashMap<String, T>, threshold: f64, difference: F) -> bool {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return true
+    };
+    if previous.len() != current.len() {
+        return true;
+    }
+    current.iter().any(|(name, value)| {
+        match previous.get(name) {
+            Some(previous_value) => difference(*previous_value, *value) > threshold,
+            None => true
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::change::ChangeFilter;
+    use crate::lib::collector::{CPUS_SECTION, MEMORY_SECTION};
+    use crate::lib::report::{CollectionError, CPUReport, FrequencyUnit, MemoryReport, SystemReport};
+
+    fn report(usage: f32, memory_used: u64) -> SystemReport {
+        SystemReport {
+            disks: Box::new([]),
+            cpus: Box::new([CPUReport {
+                name: String::from("cpu0"),
+                brand: String::from("Test Brand"),
+                vendor_id: String::from("Test Vendor"),
+                frequency: 2400.0,
+                frequency_unit: FrequencyUnit::MHz,
+                frequency_min: None,
+                frequency_max: None,
+                usage,
+                temperature: None,
+            }]),
+            memory: Some(MemoryReport {
+                memory_used,
+                memory_capacity: 2048,
+                buffers: None,
+                cached: None,
+                truly_used: None,
+                swap_in_per_sec: None,
+                swap_out_per_sec: None,
+            }),
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        }
+    }
+
+    fn change_filter() -> ChangeFilter {
+        let mut thresholds = HashMap::new();
+        thresholds.insert(CPUS_SECTION, 5.0);
+        thresholds.insert(MEMORY_SECTION, 100.0);
+        ChangeFilter::new(thresholds)
+    }
+
+    #[test]
+    fn include_first_report() {
+        let mut report = report(12.5, 1536);
+        let unchanged_sections = change_filter().apply(&mut report, &[]);
+        assert!(unchanged_sections.is_empty());
+        assert_eq!(1, report.cpus.len());
+        assert!(report.memory.is_some());
+    }
+
+    #[test]
+    fn exclude_sub_threshold_changes() {
+        let mut change_filter = change_filter();
+        change_filter.apply(&mut report(12.5, 1536), &[]);
+        let mut report = report(15.0, 1600);
+        let unchanged_sections = change_filter.apply(&mut report, &[]);
+        assert_eq!(vec![CPUS_SECTION, MEMORY_SECTION], unchanged_sections);
+        assert!(report.cpus.is_empty());
+        assert!(report.memory.is_none());
+    }
+
+    #[test]
+    fn include_above_threshold_changes() {
+        let mut change_filter = change_filter();
+        change_filter.apply(&mut report(12.5, 1536), &[]);
+        let mut report = report(20.0, 1400);
+        let unchanged_sections = change_filter.apply(&mut report, &[]);
+        assert!(unchanged_sections.is_empty());
+        assert_eq!(20.0, report.cpus[0].usage);
+        assert_eq!(1400, report.memory.unwrap().memory_used);
+    }
+
+    #[test]
+    fn compare_against_last_sent_values() {
+        let mut change_filter = change_filter();
+        change_filter.apply(&mut report(12.5, 1536), &[]);
+        // Drift below the threshold on each check accumulates until it crosses the threshold
+        assert_eq!(vec![CPUS_SECTION, MEMORY_SECTION], change_filter.apply(&mut report(15.5, 1536), &[]));
+        assert_eq!(vec![MEMORY_SECTION], change_filter.apply(&mut report(18.5, 1536), &[]));
+    }
+
+    #[test]
+    fn leave_failed_sections_alone() {
+        let mut change_filter = change_filter();
+        change_filter.apply(&mut report(12.5, 1536), &[]);
+        let mut report = report(12.5, 1536);
+        report.cpus = Box::new([]);
+        let collection_errors = vec![CollectionError::new(CPUS_SECTION, "CPUs unavailable")];
+        assert_eq!(vec![MEMORY_SECTION], change_filter.apply(&mut report, &collection_errors));
+    }
+}
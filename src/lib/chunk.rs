@@ -0,0 +1,80 @@
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_PADDING: char = '=';
+
+/// How many payload bytes each chunk can carry when every chunk message must fit in `max_message_bytes`, given the
+/// size of a chunk message carrying no data. Chunk data is base64-encoded, growing it by a third. `None` when the
+/// envelope leaves no room for data.
+pub fn chunk_data_bytes(max_message_bytes: usize, envelope_bytes: usize) -> Option<usize> {
+    let data_bytes = max_message_bytes.saturating_sub(envelope_bytes) / 4 * 3;
+    if data_bytes == 0 {
+        return None;
+    }
+    Some(data_bytes)
+}
+
+/// Encodes the bytes as standard, padded base64, as defined by RFC 4648.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let b = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0)];
+        let sextets = [b[0] >> 2, (b[0] & 0x03) << 4 | b[1] >> 4, (b[1] & 0x0f) << 2 | b[2] >> 6, b[2] & 0x3f];
+        // A group of n bytes fills n + 1 characters, the rest being padding
+        for (i, sextet) in sextets.iter().enumerate() {
+            if i <= group.len() {
+                encoded.push(BASE64_ALPHABET[*sextet as usize] as char);
+            } else {
+                encoded.push(BASE64_PADDING);
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+pub fn decode_base64(encoded: &str) -> Vec<u8> {
+    let sextets: Vec<u8> = encoded.bytes()
+        .filter(|c| *c != BASE64_PADDING as u8)
+        .map(|c| BASE64_ALPHABET.iter().position(|a| *a == c).unwrap() as u8)
+        .collect();
+    let mut decoded = Vec::with_capacity(sextets.len() * 3 / 4);
+    for group in sextets.chunks(4) {
+        let s = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0), *group.get(3).unwrap_or(&0)];
+        let bytes = [s[0] << 2 | s[1] >> 4, (s[1] & 0x0f) << 4 | s[2] >> 2, (s[2] & 0x03) << 6 | s[3]];
+        decoded.extend_from_slice(&bytes[..group.len() - 1]);
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::chunk::{chunk_data_bytes, decode_base64, encode_base64};
+
+    #[test]
+    fn encode_base64_test_vectors() {
+        // From RFC 4648, section 10
+        assert_eq!("", encode_base64(b""));
+        assert_eq!("Zg==", encode_base64(b"f"));
+        assert_eq!("Zm8=", encode_base64(b"fo"));
+        assert_eq!("Zm9v", encode_base64(b"foo"));
+        assert_eq!("Zm9vYg==", encode_base64(b"foob"));
+        assert_eq!("Zm9vYmE=", encode_base64(b"fooba"));
+        assert_eq!("Zm9vYmFy", encode_base64(b"foobar"));
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..bytes.len() {
+            assert_eq!(&bytes[..len], decode_base64(encode_base64(&bytes[..len]).as_str()).as_slice());
+        }
+    }
+
+    #[test]
+    fn chunk_data_bytes_bounds() {
+        assert_eq!(Some(672), chunk_data_bytes(1024, 128));
+        assert_eq!(None, chunk_data_bytes(128, 128));
+        assert_eq!(None, chunk_data_bytes(100, 128));
+    }
+}
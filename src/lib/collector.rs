@@ -0,0 +1,2510 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sysinfo::{ComponentExt, DiskExt, NetworkExt, NetworksExt, ProcessExt, ProcessorExt, System, SystemExt};
+
+use crate::lib::config::RunnerConfig;
+use crate::lib::common::RuntimeError;
+use crate::lib::custom::run_custom_command;
+use crate::lib::report::{AgentReport, CollectionError, ComponentReport, CPUReport, DiskReport, FileDescriptorReport, FrequencyUnit, MemoryReport, NetworkReport, PowerReport, ProcessCountReport, RawReport, SystemReport, TemperatureUnit};
+
+// Report section names
+pub const DISKS_SECTION: &str = "disks";
+pub const CPUS_SECTION: &str = "cpus";
+pub const MEMORY_SECTION: &str = "memory";
+pub const DISK_IO_SECTION: &str = "diskIo";
+pub const DISK_INODES_SECTION: &str = "diskInodes";
+pub const DISK_READ_ONLY_SECTION: &str = "diskReadOnly";
+pub const DISK_TEMPERATURE_SECTION: &str = "diskTemperature";
+pub const MEMORY_BREAKDOWN_SECTION: &str = "memoryBreakdown";
+pub const SWAP_RATES_SECTION: &str = "swapRates";
+pub const FILE_DESCRIPTORS_SECTION: &str = "fileDescriptors";
+pub const COMPONENTS_SECTION: &str = "components";
+pub const POWER_SECTION: &str = "power";
+pub const NETWORKS_SECTION: &str = "networks";
+pub const AGENT_SECTION: &str = "agent";
+pub const PROCESSES_SECTION: &str = "processes";
+pub const CUSTOM_SECTION: &str = "custom";
+pub const CGROUP_SECTION: &str = "cgroup";
+// The name of the single CPU entry that stands in for every core when per-core detail is dropped
+pub const AGGREGATE_CPU_NAME: &str = "all";
+// A custom command taking longer than this would hold up the whole report
+const CUSTOM_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many readings are taken across the frequency sample window, including the first.
+const FREQUENCY_WINDOW_SAMPLES: u32 = 5;
+/// The sections that can be selected for collection.
+pub const REPORT_SECTIONS: [&str; 3] = [DISKS_SECTION, CPUS_SECTION, MEMORY_SECTION];
+
+/// How a cumulative counter that has gone backwards since the previous reading is handled when computing a rate from
+/// it. Counters reset when an interface goes down and up or a device is re-added, and can wrap.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum CounterReset {
+    /// The rate for the interval is left out.
+    Omit,
+    /// The rate for the interval is taken to be zero.
+    Zero,
+}
+
+/// The increase in a cumulative counter between two readings, or `None` when it has reset and is to be left out.
+pub fn counter_delta(previous: u64, current: u64, counter_reset: CounterReset) -> Option<u64> {
+    if current >= previous {
+        return Some(current - previous);
+    }
+    match counter_reset {
+        CounterReset::Omit => None,
+        CounterReset::Zero => Some(0),
+    }
+}
+
+#[derive(Debug,Clone,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskSample {
+    pub name: String,
+    pub mount_point: String,
+    pub total_space: u64,
+    pub available_space: u64,
+}
+
+#[derive(Debug,Clone,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuSample {
+    pub name: String,
+    pub brand: String,
+    pub vendor_id: String,
+    /// Frequency in MHz, as reported by sysinfo on every supported platform.
+    pub frequency: u64,
+    pub usage: f32,
+}
+
+#[derive(Debug,Clone,Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemorySample {
+    pub total_memory: u64,
+    pub available_memory: u64,
+}
+
+#[derive(Clone)]
+pub struct ProcessSample {
+    pub pid: u32,
+    /// Seconds since the Unix epoch.
+    pub start_time: u64,
+}
+
+#[derive(Clone)]
+pub struct ProcessCountSample {
+    pub processes: u64,
+    /// `None` where the platform does not expose the threads of each process.
+    pub threads: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct InodeSample {
+    pub used: u64,
+    pub total: u64,
+}
+
+/// How the memory that is not free is split between buffers and cache, in the same units as `MemorySample`.
+#[derive(Clone)]
+pub struct MemoryBreakdownSample {
+    pub free_memory: u64,
+    pub buffers: u64,
+    pub cached: u64,
+}
+
+/// Cumulative counts of the pages swapped in from and out to swap space.
+#[derive(Clone)]
+pub struct SwapPageSample {
+    pub pages_in: u64,
+    pub pages_out: u64,
+}
+
+/// What the device runs, where the platform exposes it.
+#[derive(Clone,Default)]
+pub struct HostSample {
+    pub host_name: Option<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+}
+
+/// The limits and usage of the cgroup the reporter runs in. Memory is in the same units as `MemorySample`.
+#[derive(Clone)]
+pub struct CgroupSample {
+    pub memory_limit: Option<u64>,
+    pub memory_used: Option<u64>,
+    /// The CPU quota as a number of cores.
+    pub cpu_limit: Option<f64>,
+    /// Cumulative CPU time used across the cgroup, in microseconds.
+    pub cpu_usage_usec: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct FileDescriptorSample {
+    pub open: u64,
+    pub max: u64,
+}
+
+#[derive(Clone)]
+pub struct NetworkInterfaceSample {
+    pub name: String,
+    pub mac_address: Option<String>,
+    pub ip_addresses: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ComponentSample {
+    pub label: String,
+    /// Temperature in degrees Celsius, as reported by sysinfo on every supported platform.
+    pub temperature: f32,
+}
+
+/// Supplies the raw readings that a report is generated from. Readings may be taken from several threads at once when
+/// collection is parallel.
+pub trait SystemSource: Sync {
+    fn refresh(&mut self);
+    /// Refreshes only the CPU readings, so that usage can be measured over a short window.
+    fn refresh_cpus(&mut self) {}
+    fn disks(&self) -> Result<Vec<DiskSample>, Box<dyn Error>>;
+    fn cpus(&self) -> Result<Vec<CpuSample>, Box<dyn Error>>;
+    fn memory(&self) -> Result<MemorySample, Box<dyn Error>>;
+    /// Inode usage of the filesystem mounted at the mount point, or `None` where the platform or filesystem does not
+    /// expose it.
+    fn disk_inodes(&self, _mount_point: &str) -> Result<Option<InodeSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// Whether the filesystem mounted at the mount point is mounted read-only, or `None` where the platform does not
+    /// expose mount flags.
+    fn disk_read_only(&self, _mount_point: &str) -> Result<Option<bool>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// The drive temperature in Celsius, or `None` where it is not available for the disk.
+    fn disk_temperature(&self, _disk_name: &str) -> Result<Option<f32>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// The one-minute load average, or `None` where the platform does not expose it.
+    fn load_average(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// Cumulative bytes received and transmitted over every network interface, or `None` where the platform does not
+    /// expose them.
+    fn network_bytes(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// Cumulative milliseconds each block device has spent doing I/O, keyed by device name.
+    fn disk_io_ticks(&self) -> Result<HashMap<String, u64>, Box<dyn Error>> {
+        Ok(HashMap::new())
+    }
+    /// Buffer and cache usage, or `None` where the platform does not expose it.
+    fn memory_breakdown(&self) -> Result<Option<MemoryBreakdownSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// Cumulative pages swapped in and out, or `None` where the platform does not expose them.
+    fn swap_pages(&self) -> Result<Option<SwapPageSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// System-wide file descriptor usage, or `None` where the platform does not expose it.
+    fn file_descriptors(&self) -> Result<Option<FileDescriptorSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// Cumulative energy consumed in microjoules, or `None` where the platform does not expose it.
+    fn energy_uj(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// The reporter's own process, or `None` where the platform does not expose it.
+    fn own_process(&self) -> Result<Option<ProcessSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// The host's name and operating system.
+    fn host(&self) -> HostSample {
+        HostSample::default()
+    }
+    /// The limits and usage of the reporter's cgroup, or `None` where the platform has no cgroups.
+    fn cgroup(&self) -> Result<Option<CgroupSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// The number of running processes and the threads across them, or `None` where the platform exposes no process list.
+    fn process_counts(&self) -> Result<Option<ProcessCountSample>, Box<dyn Error>> {
+        Ok(None)
+    }
+    /// The addresses of each network interface other than loopback, empty where the platform exposes none.
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterfaceSample>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+    /// Temperature sensor readings, empty where the platform exposes none.
+    fn components(&self) -> Result<Vec<ComponentSample>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+}
+
+impl SystemSource for System {
+    fn refresh(&mut self) {
+        self.refresh_all();
+    }
+
+    fn refresh_cpus(&mut self) {
+        self.refresh_cpu();
+    }
+
+    fn disks(&self) -> Result<Vec<DiskSample>, Box<dyn Error>> {
+        Ok(self.get_disks().iter().filter_map(|d| {
+            let disk_name = match d.get_name().to_str() {
+                Some(name) => name.trim(),
+                None => return None
+            };
+            Some(DiskSample {
+                name: String::from(disk_name),
+                mount_point: d.get_mount_point().to_string_lossy().to_string(),
+                total_space: d.get_total_space(),
+                available_space: d.get_available_space(),
+            })
+        }).collect())
+    }
+
+    fn cpus(&self) -> Result<Vec<CpuSample>, Box<dyn Error>> {
+        Ok(self.get_processors().iter().map(|x| {
+            CpuSample {
+                name: String::from(x.get_name().trim()),
+                brand: String::from(x.get_brand().trim()),
+                vendor_id: String::from(x.get_vendor_id().trim()),
+                frequency: x.get_frequency(),
+                usage: x.get_cpu_usage(),
+            }
+        }).collect())
+    }
+
+    fn memory(&self) -> Result<MemorySample, Box<dyn Error>> {
+        Ok(MemorySample {
+            total_memory: self.get_total_memory(),
+            available_memory: self.get_available_memory(),
+        })
+    }
+
+    fn components(&self) -> Result<Vec<ComponentSample>, Box<dyn Error>> {
+        Ok(self.get_components().iter().map(|c| {
+            ComponentSample {
+                label: String::from(c.get_label().trim()),
+                temperature: c.get_temperature(),
+            }
+        }).collect())
+    }
+
+    fn own_process(&self) -> Result<Option<ProcessSample>, Box<dyn Error>> {
+        let pid = match sysinfo::get_current_pid() {
+            Ok(pid) => pid,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e));
+                return Err(error);
+            }
+        };
+        Ok(self.get_process(pid).map(|p| ProcessSample {
+            pid: p.pid() as u32,
+            start_time: p.start_time(),
+        }))
+    }
+
+    fn host(&self) -> HostSample {
+        HostSample {
+            host_name: self.get_host_name(),
+            os_name: self.get_name(),
+            os_version: self.get_os_version(),
+            kernel_version: self.get_kernel_version(),
+        }
+    }
+
+    fn process_counts(&self) -> Result<Option<ProcessCountSample>, Box<dyn Error>> {
+        let processes = self.get_processes();
+        // Each process's tasks are its threads other than the main one
+        #[cfg(target_os = "linux")]
+        let threads = Some(processes.values().map(|p| 1 + p.tasks.len() as u64).sum());
+        #[cfg(not(target_os = "linux"))]
+        let threads = None;
+        Ok(Some(ProcessCountSample {
+            processes: processes.len() as u64,
+            threads,
+        }))
+    }
+
+    #[cfg(unix)]
+    fn load_average(&self) -> Result<Option<f64>, Box<dyn Error>> {
+        Ok(Some(self.get_load_average().one))
+    }
+
+    fn network_bytes(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(Some(self.get_networks().iter().map(|(_, n)| n.get_total_received() + n.get_total_transmitted()).sum()))
+    }
+
+    #[cfg(unix)]
+    fn disk_inodes(&self, mount_point: &str) -> Result<Option<InodeSample>, Box<dyn Error>> {
+        let stat = statvfs(mount_point, "inode usage")?;
+        Ok(inode_sample(&stat))
+    }
+
+    #[cfg(unix)]
+    fn disk_read_only(&self, mount_point: &str) -> Result<Option<bool>, Box<dyn Error>> {
+        let stat = statvfs(mount_point, "mount flags")?;
+        Ok(Some(stat.f_flag & libc::ST_RDONLY != 0))
+    }
+
+    #[cfg(unix)]
+    fn network_interfaces(&self) -> Result<Vec<NetworkInterfaceSample>, Box<dyn Error>> {
+        let mut addresses: *mut libc::ifaddrs = std::ptr::null_mut();
+        // SAFETY: getifaddrs allocates the list it hands back, which is released with freeifaddrs below
+        if unsafe { libc::getifaddrs(&mut addresses) } != 0 {
+            let error = Box::new(RuntimeError::new(
+                format!("Unable to list network interfaces: {}", std::io::Error::last_os_error()).as_str()
+            ));
+            return Err(error);
+        }
+        let mut interfaces: Vec<NetworkInterfaceSample> = Vec::new();
+        let mut entry = addresses;
+        while !entry.is_null() {
+            // SAFETY: the entries stay valid until the list is freed
+            let address = unsafe { &*entry };
+            entry = address.ifa_next;
+            if address.ifa_name.is_null() || address.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint != 0 {
+                continue;
+            }
+            // An interface is listed once for each of its addresses
+            let name = unsafe { std::ffi::CStr::from_ptr(address.ifa_name) }.to_string_lossy().into_owned();
+            let index = match interfaces.iter().position(|i| i.name == name) {
+                Some(index) => index,
+                None => {
+                    interfaces.push(NetworkInterfaceSample { name, mac_address: None, ip_addresses: Vec::new() });
+                    interfaces.len() - 1
+                }
+            };
+            if !address.ifa_addr.is_null() {
+                // SAFETY: the address family says which sockaddr variant the address points to
+                unsafe { apply_interface_address(&mut interfaces[index], address.ifa_addr) };
+            }
+        }
+        unsafe { libc::freeifaddrs(addresses) };
+        Ok(interfaces)
+    }
+
+    #[cfg(feature = "smart")]
+    fn disk_temperature(&self, disk_name: &str) -> Result<Option<f32>, Box<dyn Error>> {
+        crate::lib::smart::read_disk_temperature(disk_name)
+    }
+
+    #[cfg(all(target_os = "linux", feature = "powercap"))]
+    fn energy_uj(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        crate::lib::powercap::read_energy_uj(Path::new(crate::lib::powercap::POWERCAP_ROOT))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cgroup(&self) -> Result<Option<CgroupSample>, Box<dyn Error>> {
+        crate::lib::cgroup::read_cgroup(Path::new(crate::lib::cgroup::CGROUP_ROOT))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn disk_io_ticks(&self) -> Result<HashMap<String, u64>, Box<dyn Error>> {
+        match std::fs::read_to_string("/proc/diskstats") {
+            Ok(diskstats) => Ok(parse_diskstats(diskstats.as_str())),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn memory_breakdown(&self) -> Result<Option<MemoryBreakdownSample>, Box<dyn Error>> {
+        let meminfo = match std::fs::read_to_string("/proc/meminfo") {
+            Ok(meminfo) => meminfo,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+        };
+        match parse_meminfo(meminfo.as_str()) {
+            Some(memory_breakdown) => Ok(Some(memory_breakdown)),
+            None => Err(Box::new(RuntimeError::new("Unexpected contents of /proc/meminfo")))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn swap_pages(&self) -> Result<Option<SwapPageSample>, Box<dyn Error>> {
+        let vmstat = match std::fs::read_to_string("/proc/vmstat") {
+            Ok(vmstat) => vmstat,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+        };
+        match parse_vmstat(vmstat.as_str()) {
+            Some(swap_pages) => Ok(Some(swap_pages)),
+            None => Err(Box::new(RuntimeError::new("Unexpected contents of /proc/vmstat")))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn file_descriptors(&self) -> Result<Option<FileDescriptorSample>, Box<dyn Error>> {
+        let file_nr = match std::fs::read_to_string("/proc/sys/fs/file-nr") {
+            Ok(file_nr) => file_nr,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+        };
+        match parse_file_nr(file_nr.as_str()) {
+            Some(file_descriptors) => Ok(Some(file_descriptors)),
+            None => Err(Box::new(RuntimeError::new(format!("Unexpected contents of /proc/sys/fs/file-nr: '{}'", file_nr.trim()).as_str())))
+        }
+    }
+}
+
+/// Reads the status of the filesystem mounted at the mount point, naming what was being read should it fail.
+#[cfg(unix)]
+fn statvfs(mount_point: &str, reading: &str) -> Result<libc::statvfs, Box<dyn Error>> {
+    let path = match std::ffi::CString::new(mount_point) {
+        Ok(path) => path,
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            return Err(error);
+        }
+    };
+    // SAFETY: statvfs only writes to the struct it is given, and the path is a valid null-terminated string
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        let error = Box::new(RuntimeError::new(
+            format!("Unable to read {} of {}: {}", reading, mount_point, std::io::Error::last_os_error()).as_str()
+        ));
+        return Err(error);
+    }
+    Ok(stat)
+}
+
+/// Extracts the inode usage from a `statvfs` result. Filesystems that allocate inodes on demand, such as btrfs,
+/// report no inode total, leaving nothing to report.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn inode_sample(stat: &libc::statvfs) -> Option<InodeSample> {
+    // The field widths differ between platforms
+    let total = stat.f_files as u64;
+    if total == 0 {
+        return None;
+    }
+    Some(InodeSample {
+        used: total.saturating_sub(stat.f_ffree as u64),
+        total,
+    })
+}
+
+/// Adds an interface address to the sample: an IPv4 or IPv6 address, or on Linux the hardware address.
+///
+/// # Safety
+///
+/// The address must point to a valid sockaddr of the variant its family names.
+#[cfg(unix)]
+unsafe fn apply_interface_address(interface: &mut NetworkInterfaceSample, address: *const libc::sockaddr) {
+    match (*address).sa_family as libc::c_int {
+        libc::AF_INET => {
+            let address = &*(address as *const libc::sockaddr_in);
+            let ip_address = std::net::Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr));
+            interface.ip_addresses.push(ip_address.to_string());
+        }
+        libc::AF_INET6 => {
+            let address = &*(address as *const libc::sockaddr_in6);
+            let ip_address = std::net::Ipv6Addr::from(address.sin6_addr.s6_addr);
+            interface.ip_addresses.push(ip_address.to_string());
+        }
+        #[cfg(target_os = "linux")]
+        libc::AF_PACKET => {
+            let address = &*(address as *const libc::sockaddr_ll);
+            let length = (address.sll_halen as usize).min(address.sll_addr.len());
+            interface.mac_address = format_mac_address(&address.sll_addr[..length]);
+        }
+        _ => {}
+    }
+}
+
+/// Formats a hardware address as colon-separated hex, or `None` if it is empty or all zeroes as on virtual interfaces.
+fn format_mac_address(bytes: &[u8]) -> Option<String> {
+    if bytes.iter().all(|b| *b == 0) {
+        return None;
+    }
+    Some(bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(":"))
+}
+
+/// Parses the allocated, free and maximum handle counts in `/proc/sys/fs/file-nr`.
+#[cfg(target_os = "linux")]
+fn parse_file_nr(file_nr: &str) -> Option<FileDescriptorSample> {
+    let fields: Vec<u64> = file_nr.split_whitespace().map(|f| f.parse().ok()).collect::<Option<Vec<u64>>>()?;
+    match fields.as_slice() {
+        [allocated, free, max] => Some(FileDescriptorSample {
+            open: allocated.saturating_sub(*free),
+            max: *max,
+        }),
+        _ => None
+    }
+}
+
+/// Extracts the free, buffer and cache sizes in KB from `/proc/meminfo`. Reclaimable slab memory is counted as
+/// cache, as `free` does.
+#[cfg(target_os = "linux")]
+fn parse_meminfo(meminfo: &str) -> Option<MemoryBreakdownSample> {
+    let fields: HashMap<&str, u64> = meminfo.lines().filter_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        let value = value.split_whitespace().next()?.parse().ok()?;
+        Some((name, value))
+    }).collect();
+    Some(MemoryBreakdownSample {
+        free_memory: *fields.get("MemFree")?,
+        buffers: *fields.get("Buffers")?,
+        cached: *fields.get("Cached")? + fields.get("SReclaimable").unwrap_or(&0),
+    })
+}
+
+/// Extracts the pages swapped in and out since boot from `/proc/vmstat`.
+#[cfg(target_os = "linux")]
+fn parse_vmstat(vmstat: &str) -> Option<SwapPageSample> {
+    let fields: HashMap<&str, u64> = vmstat.lines().filter_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        Some((name, value.trim().parse().ok()?))
+    }).collect();
+    Some(SwapPageSample {
+        pages_in: *fields.get("pswpin")?,
+        pages_out: *fields.get("pswpout")?,
+    })
+}
+
+/// Extracts the "time spent doing I/Os" counter from each line of `/proc/diskstats`.
+#[cfg(target_os = "linux")]
+fn parse_diskstats(diskstats: &str) -> HashMap<String, u64> {
+    diskstats.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let io_ticks = fields.get(12)?.parse().ok()?;
+        Some((String::from(*fields.get(2)?), io_ticks))
+    }).collect()
+}
+
+/// Converts a frequency in MHz to the given unit, keeping MHz precision.
+fn normalize_frequency(frequency_mhz: u64, frequency_unit: FrequencyUnit) -> f64 {
+    match frequency_unit {
+        FrequencyUnit::MHz => frequency_mhz as f64,
+        FrequencyUnit::GHz => frequency_mhz as f64 / 1000.0,
+    }
+}
+
+/// Converts a temperature in degrees Celsius to the given unit.
+fn convert_temperature(temperature_celsius: f32, temperature_unit: TemperatureUnit) -> f32 {
+    match temperature_unit {
+        TemperatureUnit::Celsius => temperature_celsius,
+        TemperatureUnit::Fahrenheit => temperature_celsius * 9.0 / 5.0 + 32.0,
+    }
+}
+
+/// The CPU usage of a cgroup between two readings of its cumulative CPU time, as a percentage of the cores it may
+/// use, or `None` if it cannot be determined.
+fn cgroup_cpu_usage(previous_usage_usec: u64, current_usage_usec: u64, elapsed: Duration, cores: f64) -> Option<f32> {
+    if elapsed.as_nanos() == 0 || cores <= 0.0 {
+        return None;
+    }
+    let usage_usec = current_usage_usec.checked_sub(previous_usage_usec)?;
+    let usage_pct = usage_usec as f64 / (elapsed.as_micros() as f64 * cores) * 100.0;
+    Some(usage_pct.min(100.0) as f32)
+}
+
+/// Average power in watts drawn between two energy counter readings, or `None` if it cannot be determined.
+fn average_watts(previous_energy_uj: u64, current_energy_uj: u64, elapsed: Duration, counter_reset: CounterReset) -> Option<f64> {
+    if elapsed.as_nanos() == 0 {
+        return None;
+    }
+    let energy_uj = counter_delta(previous_energy_uj, current_energy_uj, counter_reset)?;
+    Some(energy_uj as f64 / 1_000_000.0 / elapsed.as_secs_f64())
+}
+
+/// The average rate per second at which a cumulative counter rose between two readings, or `None` if it cannot be
+/// determined.
+fn counter_rate(previous: u64, current: u64, elapsed: Duration, counter_reset: CounterReset) -> Option<f64> {
+    if elapsed.as_nanos() == 0 {
+        return None;
+    }
+    Some(counter_delta(previous, current, counter_reset)? as f64 / elapsed.as_secs_f64())
+}
+
+/// Memory in use once free memory, buffers and cache are discounted from the total.
+fn truly_used_memory(total_memory: u64, memory_breakdown: &MemoryBreakdownSample) -> u64 {
+    total_memory
+        .saturating_sub(memory_breakdown.free_memory)
+        .saturating_sub(memory_breakdown.buffers)
+        .saturating_sub(memory_breakdown.cached)
+}
+
+/// How heavily loaded the device is, in percent: the greater of the average CPU usage and the one-minute load average
+/// per core. A reading that cannot be taken counts as no load.
+fn load_pct(source: &dyn SystemSource) -> f64 {
+    let cpus = source.cpus().unwrap_or_default();
+    if cpus.is_empty() {
+        return 0.0;
+    }
+    let cpu_usage_pct = cpus.iter().map(|c| c.usage as f64).sum::<f64>() / cpus.len() as f64;
+    let load_average_pct = source.load_average().ok().flatten()
+        .map(|load_average| load_average / cpus.len() as f64 * 100.0)
+        .unwrap_or(0.0);
+    cpu_usage_pct.max(load_average_pct)
+}
+
+/// The core index of a CPU named with a trailing number, such as `cpu3`.
+fn cpu_core_index(name: &str) -> Option<usize> {
+    let digits = name.len() - name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    name[name.len() - digits..].parse().ok()
+}
+
+/// The core index of a component sensor labelled with its core, such as `coretemp Core 3`.
+fn component_core_index(label: &str) -> Option<usize> {
+    let mut words = label.split_whitespace();
+    words.position(|w| w.eq_ignore_ascii_case("core"))?;
+    words.next()?.parse().ok()
+}
+
+/// A single sample averaging the frequency and usage of every core, or none if there are no cores.
+fn aggregate_cpu_sample(cpus: &[CpuSample]) -> Option<CpuSample> {
+    let first = cpus.first()?;
+    Some(CpuSample {
+        name: String::from(AGGREGATE_CPU_NAME),
+        brand: first.brand.clone(),
+        vendor_id: first.vendor_id.clone(),
+        frequency: cpus.iter().map(|c| c.frequency).sum::<u64>() / cpus.len() as u64,
+        usage: cpus.iter().map(|c| c.usage).sum::<f32>() / cpus.len() as f32,
+    })
+}
+
+/// The lowest, highest and average frequency of a core across the frequency sample window, in MHz.
+#[derive(Debug,PartialEq)]
+struct FrequencyRange {
+    min: u64,
+    max: u64,
+    average: u64,
+}
+
+/// The frequency range of each core named in the samples, keyed by the core's name.
+fn frequency_ranges(samples: &[Vec<CpuSample>]) -> HashMap<String, FrequencyRange> {
+    let mut frequencies: HashMap<String, Vec<u64>> = HashMap::new();
+    for cpu in samples.iter().flatten() {
+        frequencies.entry(cpu.name.clone()).or_default().push(cpu.frequency);
+    }
+    frequencies.into_iter().map(|(name, frequencies)| {
+        let range = FrequencyRange {
+            min: *frequencies.iter().min().unwrap(),
+            max: *frequencies.iter().max().unwrap(),
+            average: frequencies.iter().sum::<u64>() / frequencies.len() as u64,
+        };
+        (name, range)
+    }).collect()
+}
+
+/// Collapses disks sharing a mount point, or a name where there is no mount point, into the entry with the largest
+/// capacity. Some systems list the same physical disk under several device nodes.
+fn dedup_disks(disks: Vec<DiskSample>) -> Vec<DiskSample> {
+    let mut deduped: Vec<DiskSample> = Vec::with_capacity(disks.len());
+    for disk in disks {
+        let key = disk_dedup_key(&disk);
+        match deduped.iter_mut().find(|d| disk_dedup_key(d) == key) {
+            Some(existing) => {
+                if disk.total_space > existing.total_space {
+                    *existing = disk;
+                }
+            }
+            None => deduped.push(disk)
+        }
+    }
+    deduped
+}
+
+fn disk_dedup_key(disk: &DiskSample) -> &str {
+    if disk.mount_point.is_empty() {
+        disk.name.as_str()
+    } else {
+        disk.mount_point.as_str()
+    }
+}
+
+/// Whether a disk's usage has reached the given percentage of its capacity, or `None` for a disk without capacity.
+fn near_full(disk_used: u64, disk_capacity: u64, near_full_pct: f64) -> Option<bool> {
+    if disk_capacity == 0 {
+        return None;
+    }
+    Some(disk_used as f64 / disk_capacity as f64 * 100.0 >= near_full_pct)
+}
+
+/// Percentage of the elapsed time a device spent busy with I/O, or `None` if it cannot be determined.
+fn io_busy_pct(previous_ticks: u64, current_ticks: u64, elapsed_ms: u64, counter_reset: CounterReset) -> Option<f32> {
+    if elapsed_ms == 0 {
+        return None;
+    }
+    let busy_pct = counter_delta(previous_ticks, current_ticks, counter_reset)? as f32 / elapsed_ms as f32 * 100.0;
+    Some(busy_pct.min(100.0))
+}
+
+/// The readings of the sections that do not depend on one another, taken ahead of generating the rest of the report so
+/// that they can be taken concurrently. Failures are kept as messages, which unlike errors can cross threads.
+struct IndependentReadings {
+    disks: Result<Vec<DiskSample>, String>,
+    components: Result<Vec<ComponentSample>, String>,
+    network_interfaces: Result<Vec<NetworkInterfaceSample>, String>,
+    own_process: Result<Option<ProcessSample>, String>,
+}
+
+impl Default for IndependentReadings {
+    fn default() -> Self {
+        IndependentReadings {
+            disks: Ok(Vec::new()),
+            components: Ok(Vec::new()),
+            network_interfaces: Ok(Vec::new()),
+            own_process: Ok(None),
+        }
+    }
+}
+
+enum IndependentReading {
+    Disks(Result<Vec<DiskSample>, String>),
+    Components(Result<Vec<ComponentSample>, String>),
+    NetworkInterfaces(Result<Vec<NetworkInterfaceSample>, String>),
+    OwnProcess(Result<Option<ProcessSample>, String>),
+}
+
+impl IndependentReadings {
+    fn apply(&mut self, reading: IndependentReading) {
+        match reading {
+            IndependentReading::Disks(disks) => self.disks = disks,
+            IndependentReading::Components(components) => self.components = components,
+            IndependentReading::NetworkInterfaces(network_interfaces) => self.network_interfaces = network_interfaces,
+            IndependentReading::OwnProcess(own_process) => self.own_process = own_process,
+        }
+    }
+}
+
+type ReadingTask<'a> = Box<dyn FnOnce() -> IndependentReading + Send + 'a>;
+
+/// Generates reports from a source, retaining whatever readings are needed between reports.
+pub struct ReportGenerator {
+    sections: HashSet<&'static str>,
+    disk_mount_allowlist: Option<Vec<String>>,
+    min_disk_capacity_bytes: u64,
+    dedup_disks: bool,
+    cpu_sample_window: Option<Duration>,
+    frequency_sample_window: Option<Duration>,
+    sleep: Box<dyn FnMut(Duration) + Send>,
+    include_disk_io_busy: bool,
+    include_disk_read_only: bool,
+    include_disk_temperature: bool,
+    disk_near_full_pct: Option<f64>,
+    include_file_descriptors: bool,
+    include_memory_breakdown: bool,
+    include_swap_rates: bool,
+    include_temperatures: bool,
+    include_core_temperatures: bool,
+    collection_threads: usize,
+    include_power: bool,
+    include_network_addresses: bool,
+    include_agent_process: bool,
+    include_process_counts: bool,
+    custom_command: Option<String>,
+    load_adaptive_threshold: Option<f64>,
+    /// Set while reporting over a metered link, where detail is dropped as it is under heavy load.
+    metered_link: bool,
+    debug_raw: bool,
+    frequency_unit: FrequencyUnit,
+    temperature_unit: TemperatureUnit,
+    counter_reset: CounterReset,
+    previous_disk_io: Option<(Instant, HashMap<String, u64>)>,
+    previous_energy: Option<(Instant, u64)>,
+    previous_swap_pages: Option<(Instant, SwapPageSample)>,
+    container_aware: bool,
+    previous_cgroup_cpu_usage: Option<(Instant, u64)>,
+}
+
+impl ReportGenerator {
+    pub fn new(runner_config: &RunnerConfig) -> ReportGenerator {
+        ReportGenerator {
+            sections: runner_config.sections.clone(),
+            disk_mount_allowlist: runner_config.disk_mount_allowlist.clone(),
+            min_disk_capacity_bytes: runner_config.min_disk_capacity_bytes,
+            dedup_disks: runner_config.dedup_disks,
+            cpu_sample_window: runner_config.cpu_sample_window_ms.map(Duration::from_millis),
+            frequency_sample_window: runner_config.frequency_sample_window_ms.map(Duration::from_millis),
+            sleep: Box::new(thread::sleep),
+            include_disk_io_busy: runner_config.include_disk_io_busy,
+            include_disk_read_only: runner_config.include_disk_read_only,
+            include_disk_temperature: runner_config.include_disk_temperature,
+            disk_near_full_pct: runner_config.disk_near_full_pct,
+            include_file_descriptors: runner_config.include_file_descriptors,
+            include_memory_breakdown: runner_config.include_memory_breakdown,
+            include_swap_rates: runner_config.include_swap_rates,
+            include_temperatures: runner_config.include_temperatures,
+            include_core_temperatures: runner_config.include_core_temperatures,
+            collection_threads: runner_config.collection_threads,
+            include_power: runner_config.include_power,
+            include_network_addresses: runner_config.include_network_addresses,
+            include_agent_process: runner_config.include_agent_process,
+            include_process_counts: runner_config.include_process_counts,
+            custom_command: runner_config.custom_command.clone(),
+            load_adaptive_threshold: if runner_config.load_adaptive {
+                Some(runner_config.load_adaptive_threshold)
+            } else {
+                None
+            },
+            metered_link: false,
+            debug_raw: runner_config.debug_raw,
+            frequency_unit: runner_config.frequency_unit,
+            temperature_unit: runner_config.temperature_unit,
+            counter_reset: runner_config.counter_reset,
+            previous_disk_io: None,
+            previous_energy: None,
+            previous_swap_pages: None,
+            container_aware: runner_config.container_aware,
+            previous_cgroup_cpu_usage: None,
+        }
+    }
+
+    /// Generates a report from the source. A section that fails to collect, or was not selected,
+    /// is left empty; failures are recorded in the returned collection errors rather than failing the whole report.
+    pub fn generate_report(&mut self, source: &mut dyn SystemSource) -> (SystemReport, Vec<CollectionError>) {
+        source.refresh();
+        // CPU usage is measured between refreshes, so a second refresh after the window bounds the measurement
+        if let Some(cpu_sample_window) = self.cpu_sample_window {
+            if self.sections.contains(CPUS_SECTION) {
+                (self.sleep)(cpu_sample_window);
+                source.refresh_cpus();
+            }
+        }
+        // Under heavy load the per-core and per-process detail is dropped so that reporting adds less to it
+        let reduced_detail = self.metered_link || match self.load_adaptive_threshold {
+            Some(load_adaptive_threshold) => load_pct(source) > load_adaptive_threshold,
+            None => false
+        };
+        let readings = self.read_independent_sections(source, reduced_detail);
+        let mut collection_errors = Vec::new();
+        let mut raw_report = RawReport {
+            disks: Vec::new(),
+            cpus: Vec::new(),
+            memory: None,
+        };
+        // Inside a container, memory and CPU are reported against the cgroup's limits rather than the host's totals
+        let mut cgroup = None;
+        if self.container_aware {
+            match source.cgroup() {
+                Ok(cgroup_sample) => cgroup = cgroup_sample,
+                Err(e) => collection_errors.push(CollectionError::new(CGROUP_SECTION, e.to_string().as_str()))
+            }
+        }
+        let cgroup_cpu_usage = self.measure_cgroup_cpu_usage(cgroup.as_ref(), source);
+        // Collect disk data
+        let mut disk_reports: Vec<DiskReport> = Vec::new();
+        if self.sections.contains(DISKS_SECTION) {
+            match readings.disks {
+                Ok(disks) => {
+                    let mut disks: Vec<DiskSample> = disks.into_iter().filter(|d| self.include_disk(d)).collect();
+                    if self.dedup_disks {
+                        disks = dedup_disks(disks);
+                    }
+                    for disk in disks.iter() {
+                        let inodes = match source.disk_inodes(disk.mount_point.as_str()) {
+                            Ok(inodes) => inodes,
+                            Err(e) => {
+                                collection_errors.push(CollectionError::new(DISK_INODES_SECTION, e.to_string().as_str()));
+                                None
+                            }
+                        };
+                        let mut read_only = None;
+                        if self.include_disk_read_only {
+                            match source.disk_read_only(disk.mount_point.as_str()) {
+                                Ok(disk_read_only) => read_only = disk_read_only,
+                                Err(e) => collection_errors.push(CollectionError::new(DISK_READ_ONLY_SECTION, e.to_string().as_str()))
+                            }
+                        }
+                        let mut disk_temperature = None;
+                        if self.include_disk_temperature {
+                            match source.disk_temperature(disk.name.as_str()) {
+                                Ok(temperature) => disk_temperature = temperature
+                                    .map(|t| convert_temperature(t, self.temperature_unit)),
+                                Err(e) => collection_errors.push(CollectionError::new(DISK_TEMPERATURE_SECTION, e.to_string().as_str()))
+                            }
+                        }
+                        let disk_used = disk.total_space - disk.available_space;
+                        disk_reports.push(DiskReport {
+                            name: disk.name.clone(),
+                            disk_used,
+                            disk_capacity: disk.total_space,
+                            io_busy_pct: None,
+                            inodes_used: inodes.as_ref().map(|i| i.used),
+                            inodes_total: inodes.as_ref().map(|i| i.total),
+                            read_only,
+                            disk_temperature,
+                            near_full: self.disk_near_full_pct
+                                .and_then(|near_full_pct| near_full(disk_used, disk.total_space, near_full_pct)),
+                        });
+                    }
+                    raw_report.disks = disks;
+                }
+                Err(e) => collection_errors.push(CollectionError::new(DISKS_SECTION, e.as_str()))
+            }
+            if self.include_disk_io_busy {
+                match source.disk_io_ticks() {
+                    Ok(disk_io_ticks) => self.apply_disk_io_busy(&mut disk_reports, Instant::now(), disk_io_ticks),
+                    Err(e) => collection_errors.push(CollectionError::new(DISK_IO_SECTION, e.to_string().as_str()))
+                }
+            }
+        }
+        // Collect memory data
+        let mut memory_report = None;
+        if self.sections.contains(MEMORY_SECTION) {
+            match source.memory() {
+                Ok(memory) => {
+                    let mut report = MemoryReport {
+                        memory_used: memory.total_memory - memory.available_memory,
+                        memory_capacity: memory.total_memory,
+                        buffers: None,
+                        cached: None,
+                        truly_used: None,
+                        swap_in_per_sec: None,
+                        swap_out_per_sec: None,
+                    };
+                    if let Some(memory_limit) = cgroup.as_ref().and_then(|c| c.memory_limit) {
+                        report.memory_capacity = memory_limit.min(memory.total_memory);
+                        let memory_used = cgroup.as_ref().and_then(|c| c.memory_used).unwrap_or(report.memory_used);
+                        report.memory_used = memory_used.min(report.memory_capacity);
+                    }
+                    if self.include_memory_breakdown {
+                        match source.memory_breakdown() {
+                            Ok(Some(memory_breakdown)) => {
+                                report.truly_used = Some(truly_used_memory(memory.total_memory, &memory_breakdown));
+                                report.buffers = Some(memory_breakdown.buffers);
+                                report.cached = Some(memory_breakdown.cached);
+                            }
+                            Ok(None) => {}
+                            Err(e) => collection_errors.push(CollectionError::new(MEMORY_BREAKDOWN_SECTION, e.to_string().as_str()))
+                        }
+                    }
+                    if self.include_swap_rates {
+                        match source.swap_pages() {
+                            Ok(Some(swap_pages)) => self.apply_swap_rates(&mut report, Instant::now(), swap_pages),
+                            Ok(None) => {}
+                            Err(e) => collection_errors.push(CollectionError::new(SWAP_RATES_SECTION, e.to_string().as_str()))
+                        }
+                    }
+                    memory_report = Some(report);
+                    raw_report.memory = Some(memory);
+                }
+                Err(e) => collection_errors.push(CollectionError::new(MEMORY_SECTION, e.to_string().as_str()))
+            }
+        }
+        // Collect CPU data
+        let mut cpu_reports: Vec<CPUReport> = Vec::new();
+        if self.sections.contains(CPUS_SECTION) {
+            match source.cpus() {
+                Ok(cpus) => {
+                    let reported_cpus: Vec<CpuSample> = if let Some(cgroup_cpu_usage) = cgroup_cpu_usage {
+                        // Per-core readings describe the host, so the cgroup is reported as a whole
+                        aggregate_cpu_sample(&cpus).into_iter()
+                            .map(|cpu| CpuSample { usage: cgroup_cpu_usage, ..cpu })
+                            .collect()
+                    } else if reduced_detail {
+                        aggregate_cpu_sample(&cpus).into_iter().collect()
+                    } else {
+                        cpus.clone()
+                    };
+                    let frequency_ranges = match self.frequency_sample_window {
+                        Some(frequency_sample_window) => {
+                            let mut samples = self.sample_frequencies(source, frequency_sample_window);
+                            if reduced_detail {
+                                samples = samples.iter()
+                                    .map(|sample| aggregate_cpu_sample(sample).into_iter().collect())
+                                    .collect();
+                            }
+                            samples.insert(0, reported_cpus.clone());
+                            frequency_ranges(&samples)
+                        }
+                        None => HashMap::new()
+                    };
+                    let mut core_temperatures = HashMap::new();
+                    if self.include_core_temperatures && !reduced_detail {
+                        match &readings.components {
+                            Ok(components) => core_temperatures = components.iter()
+                                .filter_map(|c| Some((component_core_index(c.label.as_str())?, c.temperature)))
+                                .collect(),
+                            // A failure is recorded once, with the components section, when that is reported too
+                            Err(e) if !self.include_temperatures => {
+                                collection_errors.push(CollectionError::new(COMPONENTS_SECTION, e.as_str()))
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                    cpu_reports = reported_cpus.iter().map(|x| {
+                        let temperature = cpu_core_index(x.name.as_str())
+                            .and_then(|i| core_temperatures.get(&i))
+                            .map(|t| convert_temperature(*t, self.temperature_unit));
+                        let frequency_range = frequency_ranges.get(&x.name);
+                        CPUReport {
+                            name: x.name.clone(),
+                            brand: x.brand.clone(),
+                            vendor_id: x.vendor_id.clone(),
+                            frequency: normalize_frequency(
+                                frequency_range.map_or(x.frequency, |r| r.average),
+                                self.frequency_unit
+                            ),
+                            frequency_unit: self.frequency_unit,
+                            frequency_min: frequency_range.map(|r| normalize_frequency(r.min, self.frequency_unit)),
+                            frequency_max: frequency_range.map(|r| normalize_frequency(r.max, self.frequency_unit)),
+                            usage: x.usage,
+                            temperature,
+                        }
+                    }).collect();
+                    raw_report.cpus = cpus;
+                }
+                Err(e) => collection_errors.push(CollectionError::new(CPUS_SECTION, e.to_string().as_str()))
+            }
+        }
+        // Collect file descriptor data
+        let mut file_descriptor_report = None;
+        if self.include_file_descriptors {
+            match source.file_descriptors() {
+                Ok(file_descriptors) => file_descriptor_report = file_descriptors.map(|f| FileDescriptorReport {
+                    open_file_descriptors: f.open,
+                    max_file_descriptors: f.max,
+                }),
+                Err(e) => collection_errors.push(CollectionError::new(FILE_DESCRIPTORS_SECTION, e.to_string().as_str()))
+            }
+        }
+        // Collect component temperatures
+        let mut component_reports: Vec<ComponentReport> = Vec::new();
+        if self.include_temperatures {
+            match readings.components {
+                Ok(components) => component_reports = components.into_iter().map(|c| {
+                    ComponentReport {
+                        label: c.label,
+                        temperature: convert_temperature(c.temperature, self.temperature_unit),
+                        temperature_unit: self.temperature_unit,
+                    }
+                }).collect(),
+                Err(e) => collection_errors.push(CollectionError::new(COMPONENTS_SECTION, e.as_str()))
+            }
+        }
+        // Collect power data
+        let mut power_report = None;
+        if self.include_power {
+            match source.energy_uj() {
+                Ok(energy_uj) => power_report = energy_uj.map(|e| self.power_report(Instant::now(), e)),
+                Err(e) => collection_errors.push(CollectionError::new(POWER_SECTION, e.to_string().as_str()))
+            }
+        }
+        // Collect network addresses
+        let mut network_reports: Vec<NetworkReport> = Vec::new();
+        if self.include_network_addresses {
+            match readings.network_interfaces {
+                Ok(interfaces) => network_reports = interfaces.into_iter().map(|i| NetworkReport {
+                    name: i.name,
+                    mac_address: i.mac_address,
+                    ip_addresses: i.ip_addresses,
+                }).collect(),
+                Err(e) => collection_errors.push(CollectionError::new(NETWORKS_SECTION, e.as_str()))
+            }
+        }
+        // Collect the reporter's own process data
+        let mut agent_report = None;
+        if self.include_agent_process && !reduced_detail {
+            match readings.own_process {
+                Ok(own_process) => agent_report = own_process.map(|p| AgentReport {
+                    pid: p.pid,
+                    start_time: p.start_time,
+                }),
+                Err(e) => collection_errors.push(CollectionError::new(AGENT_SECTION, e.as_str()))
+            }
+        }
+        // Collect process counts
+        let mut process_count_report = None;
+        if self.include_process_counts && !reduced_detail {
+            match source.process_counts() {
+                Ok(process_counts) => process_count_report = process_counts.map(|process_counts| ProcessCountReport {
+                    process_count: process_counts.processes,
+                    thread_count: process_counts.threads,
+                }),
+                Err(e) => collection_errors.push(CollectionError::new(PROCESSES_SECTION, e.to_string().as_str()))
+            }
+        }
+        // Run the custom command
+        let mut custom_report = None;
+        if let Some(custom_command) = &self.custom_command {
+            match run_custom_command(custom_command.as_str(), CUSTOM_COMMAND_TIMEOUT) {
+                Ok(custom) => custom_report = Some(custom),
+                Err(e) => collection_errors.push(CollectionError::new(CUSTOM_SECTION, e.to_string().as_str()))
+            }
+        }
+        // Create report
+        let report = SystemReport {
+            disks: disk_reports.into_boxed_slice(),
+            cpus: cpu_reports.into_boxed_slice(),
+            memory: memory_report,
+            file_descriptors: file_descriptor_report,
+            components: component_reports.into_boxed_slice(),
+            power: power_report,
+            networks: network_reports.into_boxed_slice(),
+            agent: agent_report,
+            processes: process_count_report,
+            raw: if self.debug_raw { Some(raw_report) } else { None },
+            custom: custom_report,
+            reduced_detail,
+        };
+        (report, collection_errors)
+    }
+
+    /// Marks whether reports are sent over a metered link, dropping the per-core and per-process detail while they are.
+    pub fn set_metered_link(&mut self, metered_link: bool) {
+        self.metered_link = metered_link;
+    }
+
+    /// The sections that reports carry, in report order: the selected core sections and the optional sections enabled.
+    pub fn enabled_sections(&self) -> Vec<&'static str> {
+        let mut sections = Vec::new();
+        if self.sections.contains(DISKS_SECTION) {
+            sections.push(DISKS_SECTION);
+            let disk_sections = [
+                (self.include_disk_io_busy, DISK_IO_SECTION),
+                (self.include_disk_read_only, DISK_READ_ONLY_SECTION),
+                (self.include_disk_temperature, DISK_TEMPERATURE_SECTION),
+            ];
+            sections.extend(disk_sections.iter().filter(|(enabled, _)| *enabled).map(|(_, section)| *section));
+        }
+        if self.sections.contains(CPUS_SECTION) {
+            sections.push(CPUS_SECTION);
+        }
+        if self.sections.contains(MEMORY_SECTION) {
+            sections.push(MEMORY_SECTION);
+            let memory_sections = [
+                (self.include_memory_breakdown, MEMORY_BREAKDOWN_SECTION),
+                (self.include_swap_rates, SWAP_RATES_SECTION),
+            ];
+            sections.extend(memory_sections.iter().filter(|(enabled, _)| *enabled).map(|(_, section)| *section));
+        }
+        let optional_sections = [
+            (self.include_file_descriptors, FILE_DESCRIPTORS_SECTION),
+            (self.include_temperatures, COMPONENTS_SECTION),
+            (self.include_power, POWER_SECTION),
+            (self.include_network_addresses, NETWORKS_SECTION),
+            (self.include_agent_process, AGENT_SECTION),
+            (self.include_process_counts, PROCESSES_SECTION),
+            (self.custom_command.is_some(), CUSTOM_SECTION),
+        ];
+        sections.extend(optional_sections.iter().filter(|(enabled, _)| *enabled).map(|(_, section)| *section));
+        sections
+    }
+
+    /// The cgroup's CPU usage since the previous report as a percentage of its CPU quota, or of every core where it
+    /// has none. There is nothing to measure against on the first report.
+    fn measure_cgroup_cpu_usage(&mut self, cgroup: Option<&CgroupSample>, source: &dyn SystemSource) -> Option<f32> {
+        let cpu_usage_usec = cgroup?.cpu_usage_usec?;
+        let sampled_at = Instant::now();
+        let previous_cgroup_cpu_usage = self.previous_cgroup_cpu_usage.replace((sampled_at, cpu_usage_usec));
+        let (previous_sampled_at, previous_usage_usec) = previous_cgroup_cpu_usage?;
+        let host_cores = source.cpus().map(|cpus| cpus.len()).unwrap_or(0) as f64;
+        let cores = match cgroup?.cpu_limit {
+            Some(cpu_limit) if host_cores > 0.0 => cpu_limit.min(host_cores),
+            Some(cpu_limit) => cpu_limit,
+            None => host_cores
+        };
+        cgroup_cpu_usage(previous_usage_usec, cpu_usage_usec, sampled_at.duration_since(previous_sampled_at), cores)
+    }
+
+    /// Takes the further readings across the frequency sample window that follow the report's own. Only the CPU
+    /// readings are refreshed; a reading that fails is left out of the window.
+    fn sample_frequencies(&mut self, source: &mut dyn SystemSource, frequency_sample_window: Duration) -> Vec<Vec<CpuSample>> {
+        let interval = frequency_sample_window / (FREQUENCY_WINDOW_SAMPLES - 1);
+        let mut samples = Vec::new();
+        for _ in 1..FREQUENCY_WINDOW_SAMPLES {
+            (self.sleep)(interval);
+            source.refresh_cpus();
+            if let Ok(cpus) = source.cpus() {
+                samples.push(cpus);
+            }
+        }
+        samples
+    }
+
+    /// Takes the readings of the independent sections that are to be reported. With more than one collection thread
+    /// they are taken concurrently, each thread taking the next outstanding reading until none are left.
+    fn read_independent_sections(&self, source: &dyn SystemSource, reduced_detail: bool) -> IndependentReadings {
+        let mut tasks: Vec<ReadingTask> = Vec::new();
+        if self.sections.contains(DISKS_SECTION) {
+            tasks.push(Box::new(move || IndependentReading::Disks(source.disks().map_err(|e| e.to_string()))));
+        }
+        if self.include_temperatures || (self.include_core_temperatures && !reduced_detail) {
+            tasks.push(Box::new(move || IndependentReading::Components(source.components().map_err(|e| e.to_string()))));
+        }
+        if self.include_network_addresses {
+            tasks.push(Box::new(move || {
+                IndependentReading::NetworkInterfaces(source.network_interfaces().map_err(|e| e.to_string()))
+            }));
+        }
+        if self.include_agent_process && !reduced_detail {
+            tasks.push(Box::new(move || IndependentReading::OwnProcess(source.own_process().map_err(|e| e.to_string()))));
+        }
+        let mut readings = IndependentReadings::default();
+        let threads = self.collection_threads.min(tasks.len());
+        if threads <= 1 {
+            for task in tasks {
+                readings.apply(task());
+            }
+            return readings;
+        }
+        let tasks = Mutex::new(tasks);
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| {
+                let mut taken = Vec::new();
+                loop {
+                    let task = tasks.lock().unwrap().pop();
+                    match task {
+                        Some(task) => taken.push(task()),
+                        None => return taken
+                    }
+                }
+            })).collect();
+            for worker in workers {
+                // A panicking source fails the report as it would have without threads
+                match worker.join() {
+                    Ok(taken) => taken.into_iter().for_each(|reading| readings.apply(reading)),
+                    Err(panic) => std::panic::resume_unwind(panic)
+                }
+            }
+        });
+        readings
+    }
+
+    /// Converts an energy counter reading into a report, averaging the power drawn since the previous reading.
+    fn power_report(&mut self, sampled_at: Instant, energy_uj: u64) -> PowerReport {
+        let watts = self.previous_energy.and_then(|(previous_sampled_at, previous_energy_uj)| {
+            average_watts(previous_energy_uj, energy_uj, sampled_at.duration_since(previous_sampled_at), self.counter_reset)
+        });
+        self.previous_energy = Some((sampled_at, energy_uj));
+        PowerReport {
+            watts,
+            energy_joules: energy_uj as f64 / 1_000_000.0,
+        }
+    }
+
+    /// Fills in the swap rates from the change in the pages swapped since the previous report.
+    fn apply_swap_rates(&mut self, memory_report: &mut MemoryReport, sampled_at: Instant, swap_pages: SwapPageSample) {
+        if let Some((previous_sampled_at, previous_swap_pages)) = &self.previous_swap_pages {
+            let elapsed = sampled_at.duration_since(*previous_sampled_at);
+            memory_report.swap_in_per_sec = counter_rate(previous_swap_pages.pages_in, swap_pages.pages_in, elapsed, self.counter_reset);
+            memory_report.swap_out_per_sec = counter_rate(previous_swap_pages.pages_out, swap_pages.pages_out, elapsed, self.counter_reset);
+        }
+        self.previous_swap_pages = Some((sampled_at, swap_pages));
+    }
+
+    /// Whether a disk is to be reported, i.e. it is large enough and mounted at an allowlisted mount point when there
+    /// is an allowlist.
+    fn include_disk(&self, disk: &DiskSample) -> bool {
+        if disk.total_space < self.min_disk_capacity_bytes {
+            return false;
+        }
+        match &self.disk_mount_allowlist {
+            Some(disk_mount_allowlist) => disk_mount_allowlist.contains(&disk.mount_point),
+            None => true
+        }
+    }
+
+    /// Fills in the I/O busy percentage of each disk from the change in its I/O ticks since the previous report.
+    fn apply_disk_io_busy(&mut self, disk_reports: &mut [DiskReport], sampled_at: Instant, disk_io_ticks: HashMap<String, u64>) {
+        if let Some((previous_sampled_at, previous_disk_io_ticks)) = &self.previous_disk_io {
+            let elapsed_ms = sampled_at.duration_since(*previous_sampled_at).as_millis() as u64;
+            for disk_report in disk_reports.iter_mut() {
+                let device_name = match Path::new(&disk_report.name).file_name().and_then(|n| n.to_str()) {
+                    Some(device_name) => device_name,
+                    None => continue
+                };
+                if let (Some(previous_ticks), Some(current_ticks)) = (previous_disk_io_ticks.get(device_name), disk_io_ticks.get(device_name)) {
+                    disk_report.io_busy_pct = io_busy_pct(*previous_ticks, *current_ticks, elapsed_ms, self.counter_reset);
+                }
+            }
+        }
+        self.previous_disk_io = Some((sampled_at, disk_io_ticks));
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::collections::{HashMap, HashSet};
+    use std::error::Error;
+
+    use crate::lib::collector::{CGROUP_SECTION, CgroupSample, COMPONENTS_SECTION, ComponentSample, CPUS_SECTION, CpuSample, DISK_INODES_SECTION, DISK_IO_SECTION, DISK_READ_ONLY_SECTION, DISK_TEMPERATURE_SECTION, DISKS_SECTION, DiskSample, FILE_DESCRIPTORS_SECTION, FileDescriptorSample, HostSample, InodeSample, MEMORY_BREAKDOWN_SECTION, MEMORY_SECTION, MemoryBreakdownSample, MemorySample, NETWORKS_SECTION, NetworkInterfaceSample, POWER_SECTION, PROCESSES_SECTION, ProcessCountSample, ProcessSample, SWAP_RATES_SECTION, SwapPageSample, SystemSource, AGENT_SECTION};
+    use crate::lib::common::RuntimeError;
+
+    /// A source returning fixed readings; any section listed in `failing_sections` fails to collect.
+    pub struct FakeSource {
+        pub disks: Vec<DiskSample>,
+        pub cpus: Vec<CpuSample>,
+        pub memory: MemorySample,
+        pub memory_breakdown: Option<MemoryBreakdownSample>,
+        pub swap_pages: Option<SwapPageSample>,
+        pub disk_io_ticks: HashMap<String, u64>,
+        /// Inode usage keyed by mount point; disks without an entry report none.
+        pub disk_inodes: HashMap<String, InodeSample>,
+        /// Mount points mounted read-only; every other mount is read-write.
+        pub read_only_mounts: HashSet<String>,
+        pub disk_temperatures: HashMap<String, f32>,
+        pub file_descriptors: Option<FileDescriptorSample>,
+        pub components: Vec<ComponentSample>,
+        pub energy_uj: Option<u64>,
+        pub own_process: Option<ProcessSample>,
+        pub cgroup: Option<CgroupSample>,
+        pub host: HostSample,
+        pub network_interfaces: Vec<NetworkInterfaceSample>,
+        pub load_average: Option<f64>,
+        pub network_bytes: Option<u64>,
+        pub process_counts: Option<ProcessCountSample>,
+        pub failing_sections: Vec<&'static str>,
+        pub cpu_refreshes: usize,
+        /// Frequencies given to every core on successive CPU refreshes.
+        pub refreshed_frequencies: Vec<u64>,
+    }
+
+    impl FakeSource {
+        pub fn new() -> FakeSource {
+            FakeSource {
+                disks: vec![DiskSample {
+                    name: String::from("sda1"),
+                    mount_point: String::from("/"),
+                    total_space: 1000,
+                    available_space: 400,
+                }],
+                cpus: vec![CpuSample {
+                    name: String::from("cpu0"),
+                    brand: String::from("Test Brand"),
+                    vendor_id: String::from("Test Vendor"),
+                    frequency: 2400,
+                    usage: 12.5,
+                }],
+                memory: MemorySample {
+                    total_memory: 2048,
+                    available_memory: 512,
+                },
+                memory_breakdown: Some(MemoryBreakdownSample {
+                    free_memory: 256,
+                    buffers: 128,
+                    cached: 384,
+                }),
+                swap_pages: Some(SwapPageSample {
+                    pages_in: 1000,
+                    pages_out: 4000,
+                }),
+                disk_io_ticks: HashMap::new(),
+                disk_inodes: HashMap::new(),
+                read_only_mounts: HashSet::new(),
+                disk_temperatures: HashMap::new(),
+                file_descriptors: Some(FileDescriptorSample {
+                    open: 1024,
+                    max: 65536,
+                }),
+                components: vec![ComponentSample {
+                    label: String::from("coretemp Package id 0"),
+                    temperature: 45.0,
+                }],
+                energy_uj: Some(5_000_000),
+                cgroup: None,
+                host: HostSample {
+                    host_name: Some(String::from("edge-gateway-01")),
+                    os_name: Some(String::from("Debian GNU/Linux")),
+                    os_version: Some(String::from("11")),
+                    kernel_version: Some(String::from("5.10.0-21-amd64")),
+                },
+                own_process: Some(ProcessSample {
+                    pid: 4242,
+                    start_time: 1_600_000_000,
+                }),
+                network_interfaces: vec![NetworkInterfaceSample {
+                    name: String::from("eth0"),
+                    mac_address: Some(String::from("02:42:ac:11:00:02")),
+                    ip_addresses: vec![String::from("172.17.0.2"), String::from("fe80::42:acff:fe11:2")],
+                }],
+                load_average: Some(0.1),
+                network_bytes: Some(0),
+                process_counts: Some(ProcessCountSample {
+                    processes: 120,
+                    threads: Some(480),
+                }),
+                failing_sections: Vec::new(),
+                cpu_refreshes: 0,
+                refreshed_frequencies: Vec::new(),
+            }
+        }
+
+        fn check_section(&self, section: &str) -> Result<(), Box<dyn Error>> {
+            if self.failing_sections.contains(&section) {
+                return Err(Box::new(RuntimeError::new(format!("Collection of {} failed", section).as_str())));
+            }
+            Ok(())
+        }
+    }
+
+    impl SystemSource for FakeSource {
+        fn refresh(&mut self) {}
+
+        fn refresh_cpus(&mut self) {
+            self.cpu_refreshes += 1;
+            if !self.refreshed_frequencies.is_empty() {
+                let frequency = self.refreshed_frequencies.remove(0);
+                self.cpus.iter_mut().for_each(|cpu| cpu.frequency = frequency);
+            }
+        }
+
+        fn disks(&self) -> Result<Vec<DiskSample>, Box<dyn Error>> {
+            self.check_section(DISKS_SECTION)?;
+            Ok(self.disks.clone())
+        }
+
+        fn cpus(&self) -> Result<Vec<CpuSample>, Box<dyn Error>> {
+            self.check_section(CPUS_SECTION)?;
+            Ok(self.cpus.clone())
+        }
+
+        fn memory(&self) -> Result<MemorySample, Box<dyn Error>> {
+            self.check_section(MEMORY_SECTION)?;
+            Ok(self.memory.clone())
+        }
+
+        fn memory_breakdown(&self) -> Result<Option<MemoryBreakdownSample>, Box<dyn Error>> {
+            self.check_section(MEMORY_BREAKDOWN_SECTION)?;
+            Ok(self.memory_breakdown.clone())
+        }
+
+        fn swap_pages(&self) -> Result<Option<SwapPageSample>, Box<dyn Error>> {
+            self.check_section(SWAP_RATES_SECTION)?;
+            Ok(self.swap_pages.clone())
+        }
+
+        fn disk_inodes(&self, mount_point: &str) -> Result<Option<InodeSample>, Box<dyn Error>> {
+            self.check_section(DISK_INODES_SECTION)?;
+            Ok(self.disk_inodes.get(mount_point).cloned())
+        }
+
+        fn disk_read_only(&self, mount_point: &str) -> Result<Option<bool>, Box<dyn Error>> {
+            self.check_section(DISK_READ_ONLY_SECTION)?;
+            Ok(Some(self.read_only_mounts.contains(mount_point)))
+        }
+
+        fn disk_temperature(&self, disk_name: &str) -> Result<Option<f32>, Box<dyn Error>> {
+            self.check_section(DISK_TEMPERATURE_SECTION)?;
+            Ok(self.disk_temperatures.get(disk_name).cloned())
+        }
+
+        fn load_average(&self) -> Result<Option<f64>, Box<dyn Error>> {
+            Ok(self.load_average)
+        }
+
+        fn network_bytes(&self) -> Result<Option<u64>, Box<dyn Error>> {
+            Ok(self.network_bytes)
+        }
+
+        fn disk_io_ticks(&self) -> Result<HashMap<String, u64>, Box<dyn Error>> {
+            self.check_section(DISK_IO_SECTION)?;
+            Ok(self.disk_io_ticks.clone())
+        }
+
+        fn file_descriptors(&self) -> Result<Option<FileDescriptorSample>, Box<dyn Error>> {
+            self.check_section(FILE_DESCRIPTORS_SECTION)?;
+            Ok(self.file_descriptors.clone())
+        }
+
+        fn energy_uj(&self) -> Result<Option<u64>, Box<dyn Error>> {
+            self.check_section(POWER_SECTION)?;
+            Ok(self.energy_uj)
+        }
+
+        fn own_process(&self) -> Result<Option<ProcessSample>, Box<dyn Error>> {
+            self.check_section(AGENT_SECTION)?;
+            Ok(self.own_process.clone())
+        }
+
+        fn host(&self) -> HostSample {
+            self.host.clone()
+        }
+
+        fn cgroup(&self) -> Result<Option<CgroupSample>, Box<dyn Error>> {
+            self.check_section(CGROUP_SECTION)?;
+            Ok(self.cgroup.clone())
+        }
+
+        fn process_counts(&self) -> Result<Option<ProcessCountSample>, Box<dyn Error>> {
+            self.check_section(PROCESSES_SECTION)?;
+            Ok(self.process_counts.clone())
+        }
+
+        fn network_interfaces(&self) -> Result<Vec<NetworkInterfaceSample>, Box<dyn Error>> {
+            self.check_section(NETWORKS_SECTION)?;
+            Ok(self.network_interfaces.clone())
+        }
+
+        fn components(&self) -> Result<Vec<ComponentSample>, Box<dyn Error>> {
+            self.check_section(COMPONENTS_SECTION)?;
+            Ok(self.components.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use pretty_assertions::assert_eq;
+    use sysinfo::{System, SystemExt};
+
+    use crate::lib::collector::{AGGREGATE_CPU_NAME, average_watts, CGROUP_SECTION, cgroup_cpu_usage, CgroupSample, component_core_index, COMPONENTS_SECTION, ComponentSample, convert_temperature, counter_delta, counter_rate, CounterReset, cpu_core_index, CpuSample, CPUS_SECTION, CUSTOM_SECTION, DISK_INODES_SECTION, DISK_READ_ONLY_SECTION, DISK_TEMPERATURE_SECTION, DISKS_SECTION, DiskSample, format_mac_address, frequency_ranges, FrequencyRange, InodeSample, io_busy_pct, MEMORY_BREAKDOWN_SECTION, MEMORY_SECTION, MemoryBreakdownSample, NETWORKS_SECTION, normalize_frequency, PROCESSES_SECTION, ReportGenerator, SWAP_RATES_SECTION, SwapPageSample, SystemSource, truly_used_memory};
+    use crate::lib::collector::fake::FakeSource;
+    use crate::lib::config::load_config;
+    use crate::lib::report::{FrequencyUnit, TemperatureUnit};
+
+    fn report_generator() -> ReportGenerator {
+        ReportGenerator::new(&load_config(None).unwrap())
+    }
+
+    fn disk_sample(name: &str, mount_point: &str) -> DiskSample {
+        DiskSample {
+            name: String::from(name),
+            mount_point: String::from(mount_point),
+            total_space: 1000,
+            available_space: 400,
+        }
+    }
+
+    #[test]
+    fn generate_full_report() {
+        let (report, collection_errors) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        assert_eq!(1, report.disks.len());
+        assert_eq!(600, report.disks[0].disk_used);
+        assert_eq!(1000, report.disks[0].disk_capacity);
+        assert_eq!(None, report.disks[0].io_busy_pct);
+        assert_eq!(1, report.cpus.len());
+        assert_eq!(1536, report.memory.unwrap().memory_used);
+    }
+
+    #[test]
+    fn generate_report_with_failed_section() {
+        let mut source = FakeSource::new();
+        source.failing_sections.push(DISKS_SECTION);
+        let (report, collection_errors) = report_generator().generate_report(&mut source);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!("disks", collection_errors[0].section);
+        assert_eq!("An error was encountered during runtime. Reason: Collection of disks failed", collection_errors[0].message);
+        assert!(report.disks.is_empty());
+        assert_eq!(1, report.cpus.len());
+        assert_eq!("cpu0", report.cpus[0].name);
+        assert_eq!(2048, report.memory.unwrap().memory_capacity);
+    }
+
+    #[test]
+    fn generate_report_with_selected_sections() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.sections = [CPUS_SECTION].iter().cloned().collect();
+        let mut source = FakeSource::new();
+        // Sections that are not selected are not collected, so their failures go unreported
+        source.failing_sections.push(MEMORY_SECTION);
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        assert!(report.disks.is_empty());
+        assert_eq!(1, report.cpus.len());
+        assert!(report.memory.is_none());
+    }
+
+    #[test]
+    fn generate_report_with_cpu_sample_window() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.cpu_sample_window_ms = Some(250);
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let recorded_sleeps = sleeps.clone();
+        report_generator.sleep = Box::new(move |duration| recorded_sleeps.lock().unwrap().push(duration));
+        let mut source = FakeSource::new();
+        report_generator.generate_report(&mut source);
+        assert_eq!(vec![Duration::from_millis(250)], *sleeps.lock().unwrap());
+        assert_eq!(1, source.cpu_refreshes);
+    }
+
+    #[test]
+    fn generate_report_without_cpu_sample_window() {
+        let mut report_generator = report_generator();
+        report_generator.sleep = Box::new(|_| panic!("No CPU sample window is configured"));
+        let mut source = FakeSource::new();
+        report_generator.generate_report(&mut source);
+        assert_eq!(0, source.cpu_refreshes);
+    }
+
+    #[test]
+    fn generate_report_with_frequency_sample_window() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.frequency_sample_window_ms = Some(400);
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let recorded_sleeps = sleeps.clone();
+        report_generator.sleep = Box::new(move |duration| recorded_sleeps.lock().unwrap().push(duration));
+        let mut source = FakeSource::new();
+        source.refreshed_frequencies = vec![1800, 3000, 2200, 2700];
+        let (report, _) = report_generator.generate_report(&mut source);
+        assert_eq!(vec![Duration::from_millis(100); 4], *sleeps.lock().unwrap());
+        assert_eq!(2420.0, report.cpus[0].frequency);
+        assert_eq!(Some(1800.0), report.cpus[0].frequency_min);
+        assert_eq!(Some(3000.0), report.cpus[0].frequency_max);
+        assert!(serde_json::to_string(&report.cpus[0]).unwrap()
+            .contains(r#""frequency":2420.0,"frequencyUnit":"MHz","frequencyMin":1800.0,"frequencyMax":3000.0"#));
+    }
+
+    #[test]
+    fn aggregate_frequency_ranges_per_core() {
+        let core = FakeSource::new().cpus[0].clone();
+        let sample = |frequencies: [u64; 2]| vec![
+            CpuSample { name: String::from("cpu0"), frequency: frequencies[0], ..core.clone() },
+            CpuSample { name: String::from("cpu1"), frequency: frequencies[1], ..core.clone() },
+        ];
+        let ranges = frequency_ranges(&[sample([2000, 3000]), sample([2400, 2900]), sample([2200, 3400])]);
+        assert_eq!(2, ranges.len());
+        assert_eq!(&FrequencyRange { min: 2000, max: 2400, average: 2200 }, ranges.get("cpu0").unwrap());
+        assert_eq!(&FrequencyRange { min: 2900, max: 3400, average: 3100 }, ranges.get("cpu1").unwrap());
+    }
+
+    #[test]
+    fn list_enabled_sections() {
+        assert_eq!(vec![DISKS_SECTION, CPUS_SECTION, MEMORY_SECTION], report_generator().enabled_sections());
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.sections = [CPUS_SECTION, MEMORY_SECTION].iter().cloned().collect();
+        runner_config.include_disk_read_only = true;
+        runner_config.include_memory_breakdown = true;
+        runner_config.include_process_counts = true;
+        assert_eq!(
+            vec![CPUS_SECTION, MEMORY_SECTION, MEMORY_BREAKDOWN_SECTION, PROCESSES_SECTION],
+            ReportGenerator::new(&runner_config).enabled_sections()
+        );
+    }
+
+    #[test]
+    fn generate_report_with_file_descriptors() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_file_descriptors = true;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        let file_descriptors = report.file_descriptors.unwrap();
+        assert_eq!(1024, file_descriptors.open_file_descriptors);
+        assert_eq!(65536, file_descriptors.max_file_descriptors);
+    }
+
+    #[test]
+    fn generate_report_without_file_descriptors() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.file_descriptors.is_none());
+    }
+
+    #[test]
+    fn generate_report_with_memory_breakdown() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_memory_breakdown = true;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        let memory = report.memory.unwrap();
+        assert_eq!(1536, memory.memory_used);
+        assert_eq!(Some(128), memory.buffers);
+        assert_eq!(Some(384), memory.cached);
+        // 2048 total - 256 free - 128 buffers - 384 cached
+        assert_eq!(Some(1280), memory.truly_used);
+    }
+
+    #[test]
+    fn generate_report_with_failed_memory_breakdown() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_memory_breakdown = true;
+        let mut source = FakeSource::new();
+        source.failing_sections.push(MEMORY_BREAKDOWN_SECTION);
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!("memoryBreakdown", collection_errors[0].section);
+        let memory = report.memory.unwrap();
+        assert_eq!(1536, memory.memory_used);
+        assert_eq!(None, memory.truly_used);
+    }
+
+    #[test]
+    fn generate_report_without_memory_breakdown() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        let memory = report.memory.unwrap();
+        assert_eq!(None, memory.buffers);
+        assert_eq!(None, memory.cached);
+        assert_eq!(None, memory.truly_used);
+        assert_eq!(
+            "{\"memoryUsed\":1536,\"memoryCapacity\":2048}",
+            serde_json::to_string(&memory).unwrap()
+        );
+    }
+
+    #[test]
+    fn compute_truly_used_memory() {
+        let memory_breakdown = MemoryBreakdownSample {
+            free_memory: 1000,
+            buffers: 500,
+            cached: 2500,
+        };
+        assert_eq!(4000, truly_used_memory(8000, &memory_breakdown));
+        assert_eq!(0, truly_used_memory(3000, &memory_breakdown));
+    }
+
+    #[test]
+    fn generate_report_with_inodes() {
+        let mut source = FakeSource::new();
+        source.disks = vec![disk_sample("sda1", "/"), disk_sample("sdb1", "/data")];
+        source.disk_inodes.insert(String::from("/"), InodeSample { used: 250_000, total: 1_000_000 });
+        let (report, collection_errors) = report_generator().generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        assert_eq!(Some(250_000), report.disks[0].inodes_used);
+        assert_eq!(Some(1_000_000), report.disks[0].inodes_total);
+        assert!(serde_json::to_string(&report.disks[0]).unwrap().contains(r#""inodesUsed":250000,"inodesTotal":1000000"#));
+        // A filesystem without an inode table reports none
+        assert_eq!(None, report.disks[1].inodes_used);
+        assert!(!serde_json::to_string(&report.disks[1]).unwrap().contains("inodes"));
+    }
+
+    #[test]
+    fn generate_report_with_read_only_disks() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_disk_read_only = true;
+        let mut source = FakeSource::new();
+        source.disks.push(disk_sample("sdb1", "/mnt/backup"));
+        source.read_only_mounts.insert(String::from("/mnt/backup"));
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        assert_eq!(Some(false), report.disks[0].read_only);
+        assert_eq!(Some(true), report.disks[1].read_only);
+        assert!(serde_json::to_string(&report.disks[1]).unwrap().contains(r#""readOnly":true"#));
+        // Left out unless asked for
+        let (report, _) = report_generator().generate_report(&mut source);
+        assert_eq!(None, report.disks[1].read_only);
+        assert!(!serde_json::to_string(&report.disks[1]).unwrap().contains("readOnly"));
+    }
+
+    #[test]
+    fn generate_report_with_near_full_disks() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.disk_near_full_pct = Some(90.0);
+        let mut source = FakeSource::new();
+        source.disks = [("sda1", "/", 1000, 500), ("sdb1", "/mnt/backup", 1000, 100), ("sdc1", "/mnt/media", 1000, 50), ("sdd1", "/mnt/empty", 0, 0)]
+            .iter()
+            .map(|(name, mount_point, total_space, available_space)| DiskSample {
+                name: String::from(*name),
+                mount_point: String::from(*mount_point),
+                total_space: *total_space,
+                available_space: *available_space,
+            })
+            .collect();
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        let near_full: Vec<Option<bool>> = report.disks.iter().map(|d| d.near_full).collect();
+        // Reaching the threshold counts as near full; a disk without capacity is never flagged
+        assert_eq!(vec![Some(false), Some(true), Some(true), None], near_full);
+        assert!(serde_json::to_string(&report.disks[1]).unwrap().contains(r#""nearFull":true"#));
+        // Left out unless a threshold is configured
+        let (report, _) = report_generator().generate_report(&mut source);
+        assert_eq!(None, report.disks[1].near_full);
+        assert!(!serde_json::to_string(&report.disks[1]).unwrap().contains("nearFull"));
+    }
+
+    #[test]
+    fn generate_report_with_disk_temperatures() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_disk_temperature = true;
+        runner_config.temperature_unit = TemperatureUnit::Fahrenheit;
+        let mut source = FakeSource::new();
+        source.disks.push(disk_sample("sdb1", "/mnt/backup"));
+        source.disk_temperatures.insert(String::from("sdb1"), 35.0);
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        // Omitted where the drive reports no temperature
+        assert_eq!(None, report.disks[0].disk_temperature);
+        assert!(!serde_json::to_string(&report.disks[0]).unwrap().contains("diskTemperature"));
+        assert_eq!(Some(95.0), report.disks[1].disk_temperature);
+        assert!(serde_json::to_string(&report.disks[1]).unwrap().contains(r#""diskTemperature":95.0"#));
+    }
+
+    #[test]
+    fn generate_report_with_failed_disk_temperature() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_disk_temperature = true;
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![DISK_TEMPERATURE_SECTION];
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!(DISK_TEMPERATURE_SECTION, collection_errors[0].section);
+        assert_eq!(None, report.disks[0].disk_temperature);
+    }
+
+    #[test]
+    fn generate_report_with_failed_read_only() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_disk_read_only = true;
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![DISK_READ_ONLY_SECTION];
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!(DISK_READ_ONLY_SECTION, collection_errors[0].section);
+        assert_eq!(None, report.disks[0].read_only);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_of_running_root() {
+        assert!(System::new().disk_read_only("/").unwrap().is_some());
+    }
+
+    #[test]
+    fn generate_report_with_failed_inodes() {
+        let mut source = FakeSource::new();
+        source.failing_sections.push(DISK_INODES_SECTION);
+        let (report, collection_errors) = report_generator().generate_report(&mut source);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!("diskInodes", collection_errors[0].section);
+        assert_eq!(600, report.disks[0].disk_used);
+        assert_eq!(None, report.disks[0].inodes_total);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inode_sample_from_statvfs() {
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        stat.f_files = 655_360;
+        stat.f_ffree = 600_000;
+        let result = crate::lib::collector::inode_sample(&stat).unwrap();
+        assert_eq!(55_360, result.used);
+        assert_eq!(655_360, result.total);
+        stat.f_files = 0;
+        stat.f_ffree = 0;
+        assert!(crate::lib::collector::inode_sample(&stat).is_none());
+    }
+
+    #[test]
+    fn generate_report_with_mount_allowlist() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.disk_mount_allowlist = Some(vec![String::from("/"), String::from("/data")]);
+        let mut source = FakeSource::new();
+        source.disks = vec![
+            disk_sample("sda1", "/"),
+            disk_sample("sda2", "/boot"),
+            disk_sample("sdb1", "/data"),
+            disk_sample("sdc1", "/data/archive"),
+        ];
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        let disk_names: Vec<&str> = report.disks.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(vec!["sda1", "sdb1"], disk_names);
+    }
+
+    #[test]
+    fn generate_report_without_mount_allowlist() {
+        let mut source = FakeSource::new();
+        source.disks = vec![disk_sample("sda1", "/"), disk_sample("sda2", "/boot")];
+        let (report, _) = report_generator().generate_report(&mut source);
+        assert_eq!(2, report.disks.len());
+    }
+
+    #[test]
+    fn generate_report_with_deduped_disks() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.dedup_disks = true;
+        let mut source = FakeSource::new();
+        source.disks = vec![
+            DiskSample { total_space: 512, ..disk_sample("/dev/sda1", "/") },
+            disk_sample("/dev/sdb1", "/data"),
+            DiskSample { total_space: 1024 * 1024, ..disk_sample("/dev/disk/by-uuid/1234", "/") },
+            DiskSample { total_space: 800, ..disk_sample("/dev/mapper/root", "/") },
+        ];
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        let disks: Vec<(&str, u64)> = report.disks.iter().map(|d| (d.name.as_str(), d.disk_capacity)).collect();
+        assert_eq!(vec![("/dev/disk/by-uuid/1234", 1024 * 1024), ("/dev/sdb1", 1000)], disks);
+    }
+
+    #[test]
+    fn generate_report_without_deduped_disks() {
+        let mut source = FakeSource::new();
+        source.disks = vec![disk_sample("/dev/sda1", "/"), disk_sample("/dev/disk/by-uuid/1234", "/")];
+        let (report, _) = report_generator().generate_report(&mut source);
+        assert_eq!(2, report.disks.len());
+    }
+
+    #[test]
+    fn generate_report_with_min_disk_capacity() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.min_disk_capacity_bytes = 1024 * 1024;
+        let mut source = FakeSource::new();
+        source.disks = vec![
+            DiskSample { total_space: 4096, ..disk_sample("tmpfs", "/run/lock") },
+            DiskSample { total_space: 1024 * 1024, ..disk_sample("sda2", "/boot") },
+            DiskSample { total_space: 512 * 1024 * 1024, ..disk_sample("sda1", "/") },
+            DiskSample { total_space: 0, ..disk_sample("proc", "/proc") },
+        ];
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        let disk_names: Vec<&str> = report.disks.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(vec!["sda2", "sda1"], disk_names);
+    }
+
+    #[test]
+    fn generate_report_with_raw_values() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.debug_raw = true;
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        let report_json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "disks": [{"name": "sda1", "mountPoint": "/", "totalSpace": 1000, "availableSpace": 400}],
+                "cpus": [{"name": "cpu0", "brand": "Test Brand", "vendorId": "Test Vendor", "frequency": 2400, "usage": 12.5}],
+                "memory": {"totalMemory": 2048, "availableMemory": 512}
+            }),
+            report_json["raw"]
+        );
+    }
+
+    #[test]
+    fn generate_report_with_custom_metrics() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.custom_command = Some(String::from(r#"echo '{"queueDepth": 7}'"#));
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        assert_eq!(serde_json::json!({"queueDepth": 7}), serde_json::to_value(&report).unwrap()["custom"]);
+    }
+
+    #[test]
+    fn generate_report_with_failing_custom_command() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.custom_command = Some(String::from("echo '{\"queueDepth\": 7}'; exit 1"));
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(report.custom.is_none());
+        assert_eq!(1, report.disks.len());
+        assert_eq!(1, collection_errors.len());
+        assert_eq!(CUSTOM_SECTION, collection_errors[0].section);
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Custom command failed with exit status: 1",
+            collection_errors[0].message
+        );
+    }
+
+    #[test]
+    fn generate_report_without_raw_values() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.raw.is_none());
+        assert!(serde_json::to_value(&report).unwrap().get("raw").is_none());
+    }
+
+    #[test]
+    fn generate_report_with_power() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_power = true;
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let (report, collection_errors) = report_generator.generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        let power = report.power.unwrap();
+        assert_eq!(5.0, power.energy_joules);
+        // Power is averaged between readings, so the first report only carries the energy counter
+        assert_eq!(None, power.watts);
+        let mut source = FakeSource::new();
+        source.energy_uj = Some(7_000_000);
+        let (report, _) = report_generator.generate_report(&mut source);
+        assert!(report.power.unwrap().watts.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn generate_report_without_power() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.power.is_none());
+    }
+
+    fn load_adaptive_generator() -> ReportGenerator {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.load_adaptive = true;
+        runner_config.load_adaptive_threshold = 80.0;
+        runner_config.include_agent_process = true;
+        ReportGenerator::new(&runner_config)
+    }
+
+    fn two_core_source(usage: f32) -> FakeSource {
+        let mut source = FakeSource::new();
+        let core = source.cpus[0].clone();
+        source.cpus = vec![
+            CpuSample { name: String::from("cpu0"), usage, frequency: 2000, ..core.clone() },
+            CpuSample { name: String::from("cpu1"), usage: usage + 4.0, frequency: 3000, ..core },
+        ];
+        source
+    }
+
+    #[test]
+    fn generate_report_with_load_adaptive_detail() {
+        let mut report_generator = load_adaptive_generator();
+        let (report, _) = report_generator.generate_report(&mut two_core_source(93.0));
+        assert!(report.reduced_detail);
+        assert_eq!(1, report.cpus.len());
+        assert_eq!(AGGREGATE_CPU_NAME, report.cpus[0].name);
+        assert_eq!(95.0, report.cpus[0].usage);
+        assert_eq!(2500.0, report.cpus[0].frequency);
+        assert!(report.agent.is_none());
+        // Full detail returns once the load subsides
+        let (report, _) = report_generator.generate_report(&mut two_core_source(20.0));
+        assert!(!report.reduced_detail);
+        assert_eq!(2, report.cpus.len());
+        assert!(report.agent.is_some());
+    }
+
+    #[test]
+    fn reduce_detail_on_high_load_average() {
+        let mut source = two_core_source(20.0);
+        source.load_average = Some(1.9);
+        let (report, _) = load_adaptive_generator().generate_report(&mut source);
+        assert!(report.reduced_detail);
+        assert_eq!(1, report.cpus.len());
+    }
+
+    #[test]
+    fn keep_detail_on_high_load_without_load_adaptive() {
+        let (report, _) = report_generator().generate_report(&mut two_core_source(93.0));
+        assert!(!report.reduced_detail);
+        assert_eq!(2, report.cpus.len());
+        assert!(!serde_json::to_string(&report).unwrap().contains("reducedDetail"));
+    }
+
+    #[test]
+    fn generate_report_with_network_addresses() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_network_addresses = true;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        assert_eq!(
+            "[{\"name\":\"eth0\",\"macAddress\":\"02:42:ac:11:00:02\",\"ipAddresses\":[\"172.17.0.2\",\"fe80::42:acff:fe11:2\"]}]",
+            serde_json::to_string(&report.networks).unwrap()
+        );
+    }
+
+    fn parallel_collection_source() -> FakeSource {
+        let mut source = FakeSource::new();
+        let disk = source.disks[0].clone();
+        source.disks = (0..8).map(|i| DiskSample {
+            name: format!("sd{}1", (b'a' + i) as char),
+            mount_point: format!("/mnt/{}", i),
+            ..disk.clone()
+        }).collect();
+        source.components.push(ComponentSample { label: String::from("coretemp Core 0"), temperature: 48.0 });
+        source
+    }
+
+    fn collect_with_threads(source: &mut FakeSource, collection_threads: usize) -> String {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_temperatures = true;
+        runner_config.include_core_temperatures = true;
+        runner_config.include_network_addresses = true;
+        runner_config.include_agent_process = true;
+        runner_config.collection_threads = collection_threads;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(source);
+        serde_json::to_string(&(report, collection_errors)).unwrap()
+    }
+
+    #[test]
+    fn parallel_collection_matches_sequential() {
+        let mut source = parallel_collection_source();
+        let sequential = collect_with_threads(&mut source, 1);
+        assert!(sequential.contains("sdh1") && sequential.contains("eth0") && sequential.contains("4242"));
+        assert_eq!(sequential, collect_with_threads(&mut source, 2));
+        assert_eq!(sequential, collect_with_threads(&mut source, 4));
+    }
+
+    #[test]
+    fn parallel_collection_records_section_errors() {
+        let mut source = parallel_collection_source();
+        source.failing_sections = vec![COMPONENTS_SECTION, NETWORKS_SECTION];
+        let sequential = collect_with_threads(&mut source, 1);
+        assert!(sequential.contains("Collection of networks failed"));
+        // The components failure is recorded once even though both temperature options read the components
+        assert_eq!(1, sequential.matches("Collection of components failed").count());
+        assert_eq!(sequential, collect_with_threads(&mut source, 4));
+    }
+
+    #[test]
+    fn generate_report_without_network_addresses() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.networks.is_empty());
+        assert!(!serde_json::to_string(&report).unwrap().contains("networks"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_network_interfaces_without_loopback() {
+        let interfaces = System::new().network_interfaces().unwrap();
+        assert!(interfaces.iter().all(|i| !i.ip_addresses.contains(&String::from("127.0.0.1"))));
+    }
+
+    #[test]
+    fn format_mac_addresses() {
+        assert_eq!(Some(String::from("02:42:ac:11:00:02")), format_mac_address(&[0x02, 0x42, 0xac, 0x11, 0x00, 0x02]));
+        assert_eq!(None, format_mac_address(&[0; 6]));
+        assert_eq!(None, format_mac_address(&[]));
+    }
+
+    #[test]
+    fn generate_report_with_agent_process() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_agent_process = true;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        let agent = report.agent.unwrap();
+        assert_eq!(4242, agent.pid);
+        assert_eq!(1_600_000_000, agent.start_time);
+    }
+
+    #[test]
+    fn generate_report_with_running_agent_process() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_agent_process = true;
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut System::new());
+        let agent = report.agent.unwrap();
+        assert_eq!(std::process::id(), agent.pid);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(agent.start_time <= now && agent.start_time > now - 24 * 60 * 60, "Implausible start time {}", agent.start_time);
+    }
+
+    #[test]
+    fn generate_report_without_agent_process() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.agent.is_none());
+    }
+
+    #[test]
+    fn generate_report_with_process_counts() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_process_counts = true;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        let processes = report.processes.unwrap();
+        assert_eq!(120, processes.process_count);
+        assert_eq!(Some(480), processes.thread_count);
+    }
+
+    #[test]
+    fn generate_report_with_failing_process_counts() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_process_counts = true;
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![PROCESSES_SECTION];
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert!(report.processes.is_none());
+        assert_eq!(1, collection_errors.len());
+        assert_eq!(PROCESSES_SECTION, collection_errors[0].section);
+    }
+
+    #[test]
+    fn generate_report_with_running_process_counts() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_process_counts = true;
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut System::new());
+        let processes = report.processes.unwrap();
+        assert!(processes.process_count > 0);
+        if let Some(thread_count) = processes.thread_count {
+            assert!(thread_count >= processes.process_count);
+        }
+    }
+
+    #[test]
+    fn generate_report_without_process_counts() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.processes.is_none());
+    }
+
+    #[test]
+    fn generate_report_against_cgroup_limits() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.container_aware = true;
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let mut source = two_core_source(20.0);
+        source.cgroup = Some(CgroupSample {
+            memory_limit: Some(1024),
+            memory_used: Some(256),
+            cpu_limit: Some(1.0),
+            cpu_usage_usec: Some(1_000_000),
+        });
+        let (report, collection_errors) = report_generator.generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        let memory = report.memory.unwrap();
+        assert_eq!(1024, memory.memory_capacity);
+        assert_eq!(256, memory.memory_used);
+        // The cgroup's CPU usage can only be measured from the second report on
+        assert_eq!(2, report.cpus.len());
+        let (report, _) = report_generator.generate_report(&mut source);
+        assert_eq!(1, report.cpus.len());
+        assert_eq!(AGGREGATE_CPU_NAME, report.cpus[0].name);
+        assert_eq!(0.0, report.cpus[0].usage);
+        assert_eq!(2500.0, report.cpus[0].frequency);
+    }
+
+    #[test]
+    fn generate_report_with_host_totals_outside_cgroup() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.container_aware = true;
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![CGROUP_SECTION];
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!(CGROUP_SECTION, collection_errors[0].section);
+        // A cgroup without limits leaves the host's totals in place
+        source.failing_sections = Vec::new();
+        source.cgroup = Some(CgroupSample { memory_limit: None, memory_used: Some(256), cpu_limit: None, cpu_usage_usec: None });
+        let (report_in_cgroup, _) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        for report in [report, report_in_cgroup] {
+            let memory = report.memory.unwrap();
+            assert_eq!(2048, memory.memory_capacity);
+            assert_eq!(1536, memory.memory_used);
+            assert_eq!(12.5, report.cpus[0].usage);
+        }
+    }
+
+    #[test]
+    fn cgroup_cpu_usage_bounds() {
+        assert_eq!(Some(50.0), cgroup_cpu_usage(1_000_000, 2_000_000, Duration::from_secs(1), 2.0));
+        assert_eq!(Some(100.0), cgroup_cpu_usage(1_000_000, 3_000_000, Duration::from_secs(1), 1.5));
+        assert_eq!(None, cgroup_cpu_usage(2_000_000, 1_000_000, Duration::from_secs(1), 2.0));
+        assert_eq!(None, cgroup_cpu_usage(1_000_000, 2_000_000, Duration::from_secs(0), 2.0));
+    }
+
+    #[test]
+    fn average_watts_bounds() {
+        assert_eq!(Some(2.5), average_watts(1_000_000, 6_000_000, Duration::from_secs(2), CounterReset::Omit));
+        assert_eq!(None, average_watts(6_000_000, 1_000_000, Duration::from_secs(2), CounterReset::Omit));
+        assert_eq!(Some(0.0), average_watts(6_000_000, 1_000_000, Duration::from_secs(2), CounterReset::Zero));
+        assert_eq!(None, average_watts(1_000_000, 6_000_000, Duration::from_secs(0), CounterReset::Omit));
+    }
+
+    #[test]
+    fn generate_report_with_celsius_temperatures() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_temperatures = true;
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        assert_eq!(1, report.components.len());
+        assert_eq!("coretemp Package id 0", report.components[0].label);
+        assert_eq!(45.0, report.components[0].temperature);
+        assert_eq!(TemperatureUnit::Celsius, report.components[0].temperature_unit);
+    }
+
+    #[test]
+    fn generate_report_with_fahrenheit_temperatures() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_temperatures = true;
+        runner_config.temperature_unit = TemperatureUnit::Fahrenheit;
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert_eq!(113.0, report.components[0].temperature);
+        assert_eq!(TemperatureUnit::Fahrenheit, report.components[0].temperature_unit);
+        assert_eq!(
+            "{\"label\":\"coretemp Package id 0\",\"temperature\":113.0,\"temperatureUnit\":\"F\"}",
+            serde_json::to_string(&report.components[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn generate_report_with_core_temperatures() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_core_temperatures = true;
+        let mut source = two_core_source(20.0);
+        let core = source.cpus[0].clone();
+        source.cpus.push(CpuSample { name: String::from("cpu2"), ..core });
+        source.components = vec![
+            ComponentSample { label: String::from("coretemp Package id 0"), temperature: 45.0 },
+            ComponentSample { label: String::from("coretemp Core 1"), temperature: 52.0 },
+            ComponentSample { label: String::from("coretemp Core 0"), temperature: 48.0 },
+        ];
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        assert!(collection_errors.is_empty());
+        assert_eq!(Some(48.0), report.cpus[0].temperature);
+        assert_eq!(Some(52.0), report.cpus[1].temperature);
+        // No sensor is labelled with the third core
+        assert_eq!(None, report.cpus[2].temperature);
+        // The sensors are only paired with the cores, not reported as components
+        assert!(report.components.is_empty());
+    }
+
+    #[test]
+    fn generate_report_without_core_temperatures() {
+        let mut source = two_core_source(20.0);
+        source.components = vec![ComponentSample { label: String::from("coretemp Core 0"), temperature: 48.0 }];
+        let (report, _) = report_generator().generate_report(&mut source);
+        assert_eq!(None, report.cpus[0].temperature);
+        assert!(!serde_json::to_string(&report.cpus[0]).unwrap().contains("temperature"));
+    }
+
+    #[test]
+    fn match_core_indexes() {
+        assert_eq!(Some(12), cpu_core_index("cpu12"));
+        assert_eq!(None, cpu_core_index("all"));
+        assert_eq!(Some(3), component_core_index("coretemp Core 3"));
+        assert_eq!(Some(0), component_core_index("CPU core 0"));
+        assert_eq!(None, component_core_index("coretemp Package id 0"));
+    }
+
+    #[test]
+    fn generate_report_without_temperatures() {
+        let (report, _) = report_generator().generate_report(&mut FakeSource::new());
+        assert!(report.components.is_empty());
+    }
+
+    #[test]
+    fn convert_temperature_units() {
+        assert_eq!(21.5, convert_temperature(21.5, TemperatureUnit::Celsius));
+        assert_eq!(32.0, convert_temperature(0.0, TemperatureUnit::Fahrenheit));
+        assert_eq!(212.0, convert_temperature(100.0, TemperatureUnit::Fahrenheit));
+        assert_eq!(-40.0, convert_temperature(-40.0, TemperatureUnit::Fahrenheit));
+    }
+
+    #[test]
+    fn normalize_frequency_units() {
+        assert_eq!(2400.0, normalize_frequency(2400, FrequencyUnit::MHz));
+        assert_eq!(2.4, normalize_frequency(2400, FrequencyUnit::GHz));
+        assert_eq!(3.001, normalize_frequency(3001, FrequencyUnit::GHz));
+    }
+
+    #[test]
+    fn generate_report_in_ghz() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.frequency_unit = FrequencyUnit::GHz;
+        let (report, _) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+        assert_eq!(2.4, report.cpus[0].frequency);
+        assert_eq!(FrequencyUnit::GHz, report.cpus[0].frequency_unit);
+        assert!(serde_json::to_string(&report.cpus[0]).unwrap().contains(r#""frequency":2.4,"frequencyUnit":"GHz""#));
+    }
+
+    #[test]
+    fn compute_io_busy_pct() {
+        assert_eq!(Some(25.0), io_busy_pct(1000, 1250, 1000, CounterReset::Omit));
+        assert_eq!(Some(100.0), io_busy_pct(1000, 3000, 1000, CounterReset::Omit));
+        assert_eq!(None, io_busy_pct(1000, 500, 1000, CounterReset::Omit));
+        assert_eq!(Some(0.0), io_busy_pct(1000, 500, 1000, CounterReset::Zero));
+        assert_eq!(None, io_busy_pct(1000, 1250, 0, CounterReset::Omit));
+    }
+
+    #[test]
+    fn apply_disk_io_busy_across_samples() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_disk_io_busy = true;
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let (mut report, _) = report_generator.generate_report(&mut FakeSource::new());
+        let start = Instant::now();
+        let mut first_ticks = HashMap::new();
+        first_ticks.insert(String::from("sda1"), 4000);
+        report_generator.apply_disk_io_busy(&mut report.disks, start, first_ticks);
+        assert_eq!(None, report.disks[0].io_busy_pct);
+        let mut second_ticks = HashMap::new();
+        second_ticks.insert(String::from("sda1"), 4500);
+        report_generator.apply_disk_io_busy(&mut report.disks, start + Duration::from_secs(2), second_ticks);
+        assert_eq!(Some(25.0), report.disks[0].io_busy_pct);
+    }
+
+    #[test]
+    fn apply_disk_io_busy_across_counter_reset() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_disk_io_busy = true;
+        runner_config.counter_reset = CounterReset::Zero;
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let (mut report, _) = report_generator.generate_report(&mut FakeSource::new());
+        let start = Instant::now();
+        let mut first_ticks = HashMap::new();
+        first_ticks.insert(String::from("sda1"), u64::MAX - 100);
+        report_generator.apply_disk_io_busy(&mut report.disks, start, first_ticks);
+        // The counter wrapped, which would otherwise read as a huge spike
+        let mut second_ticks = HashMap::new();
+        second_ticks.insert(String::from("sda1"), 400);
+        report_generator.apply_disk_io_busy(&mut report.disks, start + Duration::from_secs(2), second_ticks);
+        assert_eq!(Some(0.0), report.disks[0].io_busy_pct);
+    }
+
+    #[test]
+    fn compute_counter_rate() {
+        assert_eq!(Some(50.0), counter_rate(1000, 1100, Duration::from_secs(2), CounterReset::Omit));
+        assert_eq!(None, counter_rate(1000, 500, Duration::from_secs(2), CounterReset::Omit));
+        assert_eq!(Some(0.0), counter_rate(1000, 500, Duration::from_secs(2), CounterReset::Zero));
+        assert_eq!(None, counter_rate(1000, 1100, Duration::from_secs(0), CounterReset::Omit));
+    }
+
+    #[test]
+    fn apply_swap_rates_across_samples() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_swap_rates = true;
+        let mut report_generator = ReportGenerator::new(&runner_config);
+        let (report, collection_errors) = report_generator.generate_report(&mut FakeSource::new());
+        assert!(collection_errors.is_empty());
+        // There is nothing to measure against on the first report
+        let mut memory_report = report.memory.unwrap();
+        assert_eq!(None, memory_report.swap_in_per_sec);
+        assert_eq!(None, memory_report.swap_out_per_sec);
+        let start = Instant::now();
+        report_generator.apply_swap_rates(&mut memory_report, start, SwapPageSample { pages_in: 1200, pages_out: 4000 });
+        report_generator.apply_swap_rates(&mut memory_report, start + Duration::from_secs(4), SwapPageSample { pages_in: 1300, pages_out: 4600 });
+        assert_eq!(Some(25.0), memory_report.swap_in_per_sec);
+        assert_eq!(Some(150.0), memory_report.swap_out_per_sec);
+        assert!(serde_json::to_string(&memory_report).unwrap().contains(r#""swapInPerSec":25.0,"swapOutPerSec":150.0"#));
+    }
+
+    #[test]
+    fn generate_report_with_failing_swap_rates() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.include_swap_rates = true;
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![SWAP_RATES_SECTION];
+        let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut source);
+        // The rest of the memory section is still reported
+        assert_eq!(1536, report.memory.unwrap().memory_used);
+        assert_eq!(1, collection_errors.len());
+        assert_eq!(SWAP_RATES_SECTION, collection_errors[0].section);
+    }
+
+    #[test]
+    fn counter_delta_across_reset() {
+        assert_eq!(Some(250), counter_delta(1000, 1250, CounterReset::Omit));
+        assert_eq!(Some(0), counter_delta(1000, 1000, CounterReset::Omit));
+        assert_eq!(None, counter_delta(1000, 500, CounterReset::Omit));
+        assert_eq!(Some(0), counter_delta(1000, 500, CounterReset::Zero));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_linux_diskstats() {
+        let diskstats = "   8       0 sda 5 0 40 1 2 0 16 3 0 1234 4 0 0 0 0 0 0\n   8       1 sda1 1 0 8 0 0 0 0 0 0 56 0 0 0 0 0 0 0\n";
+        let result = crate::lib::collector::parse_diskstats(diskstats);
+        assert_eq!(Some(&1234), result.get("sda"));
+        assert_eq!(Some(&56), result.get("sda1"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_linux_file_nr() {
+        let result = crate::lib::collector::parse_file_nr("9632\t0\t9223372036854775807\n").unwrap();
+        assert_eq!(9632, result.open);
+        assert_eq!(9223372036854775807, result.max);
+        let result = crate::lib::collector::parse_file_nr("2048 512 8192\n").unwrap();
+        assert_eq!(1536, result.open);
+        assert!(crate::lib::collector::parse_file_nr("2048 512\n").is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_linux_meminfo() {
+        let meminfo = "MemTotal:       16301148 kB\nMemFree:         1203488 kB\nMemAvailable:    9625300 kB\nBuffers:          412540 kB\nCached:          7512024 kB\nSwapCached:            0 kB\nSReclaimable:     601836 kB\n";
+        let result = crate::lib::collector::parse_meminfo(meminfo).unwrap();
+        assert_eq!(1203488, result.free_memory);
+        assert_eq!(412540, result.buffers);
+        assert_eq!(8113860, result.cached);
+        assert!(crate::lib::collector::parse_meminfo("MemTotal:       16301148 kB\n").is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_linux_vmstat() {
+        let vmstat = "nr_free_pages 295861\npgpgin 18263441\npgpgout 42907316\npswpin 1523\npswpout 20817\npgalloc_dma 0\n";
+        let result = crate::lib::collector::parse_vmstat(vmstat).unwrap();
+        assert_eq!(1523, result.pages_in);
+        assert_eq!(20817, result.pages_out);
+        assert!(crate::lib::collector::parse_vmstat("nr_free_pages 295861\n").is_none());
+    }
+}
@@ -2,6 +2,8 @@ use std::fmt;
 use std::fmt::Formatter;
 use std::error::Error;
 
+use serde::Serialize;
+
 #[derive(Debug,PartialEq)]
 pub enum RuntimeMode {
     Continuous,
@@ -10,6 +12,44 @@ pub enum RuntimeMode {
 
 pub const MINUTES_MULTIPLIER: u64 = 60;
 
+// Error type tags in machine-readable error output
+const ILLEGAL_ARGUMENT_ERROR_TYPE: &str = "illegal_argument";
+const RUNTIME_ERROR_TYPE: &str = "runtime";
+const OTHER_ERROR_TYPE: &str = "other";
+
+/// How the error that ends a run is printed.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ErrorFormat {
+    Text,
+    /// A single JSON object, for tooling that parses errors.
+    Json,
+}
+
+#[derive(Serialize)]
+struct ErrorOutput {
+    error_type: &'static str,
+    message: String,
+}
+
+/// Renders the error that ended a run in the requested format.
+pub fn format_error(error: &(dyn Error + 'static), error_format: ErrorFormat) -> String {
+    match error_format {
+        ErrorFormat::Text => format!("Error running Device Stats Reporter: {:?}", error),
+        ErrorFormat::Json => {
+            let error_type = if error.is::<IllegalArgumentError>() {
+                ILLEGAL_ARGUMENT_ERROR_TYPE
+            } else if error.is::<RuntimeError>() {
+                RUNTIME_ERROR_TYPE
+            } else {
+                OTHER_ERROR_TYPE
+            };
+            let error_output = ErrorOutput { error_type, message: error.to_string() };
+            // Serializing two strings cannot fail
+            serde_json::to_string(&error_output).unwrap_or_default()
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct IllegalArgumentError {
     details: String,
@@ -47,3 +87,36 @@ impl fmt::Display for RuntimeError {
 }
 
 impl Error for RuntimeError {}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use crate::lib::common::{ErrorFormat, format_error, RuntimeError};
+    use crate::lib::config::load_config;
+
+    #[test]
+    fn format_config_error_as_json() {
+        let error = load_config(
+            Some(&String::from("resources/test/bad/too_many_publish_retries.yaml"))
+        ).err().unwrap();
+        let error_output: Value = serde_json::from_str(format_error(error.as_ref(), ErrorFormat::Json).as_str()).unwrap();
+        assert_eq!(
+            json!({
+                "error_type": "illegal_argument",
+                "message": "An illegal argument was encountered. Reason: Publish retries must be between 0 and 10"
+            }),
+            error_output
+        );
+    }
+
+    #[test]
+    fn format_runtime_error_as_json() {
+        let error = RuntimeError::new("Broker unreachable");
+        assert_eq!(
+            "{\"error_type\":\"runtime\",\"message\":\"An error was encountered during runtime. Reason: Broker unreachable\"}",
+            format_error(&error, ErrorFormat::Json)
+        );
+    }
+}
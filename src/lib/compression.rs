@@ -0,0 +1,455 @@
+use std::error::Error;
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use lz4_flex::block::compress_prepend_size_with_dict;
+use lz4_flex::compress_prepend_size;
+use lz4_flex::frame::FrameEncoder;
+
+use crate::lib::common::{IllegalArgumentError, RuntimeError};
+
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum Codec {
+    /// Payloads are sent as-is.
+    None,
+    /// LZ4 block format with the uncompressed size prepended as a little-endian u32.
+    Lz4,
+    /// LZ4 frame format.
+    Lz4Frame,
+    /// Gzip, trading CPU for a better ratio as its compression level rises.
+    Gzip,
+}
+
+/// Every codec the reporter can apply.
+pub const CODECS: [Codec; 4] = [Codec::None, Codec::Lz4, Codec::Lz4Frame, Codec::Gzip];
+
+/// A shared dictionary holding the keys and punctuation that every report repeats, so that even the first
+/// occurrence of a key in a message compresses to a back-reference. Consumers must decompress with exactly these
+/// bytes; it is part of the payload format and must not change without consumers being updated alongside.
+pub const REPORT_DICTIONARY: &[u8] = br#"{"deviceId":"","messageId":"","sessionId":"","timestamp":,"agentVersion":"","report":{"disks":[{"name":"","diskUsed":,"diskCapacity":,"ioBusyPct":}],"cpus":[{"name":"","brand":"","vendorId":"","frequency":,"frequencyUnit":"MHz","usage":}],"memory":{"memoryUsed":,"memoryCapacity":},"fileDescriptors":{"openFileDescriptors":,"maxFileDescriptors":},"components":[{"label":"","temperature":,"temperatureUnit":""}]},"collectionErrors":[{"section":"","message":""}],"compressionRatio":,"unchangedSections":[]}"#;
+
+/// Set in the format header when the payload is padded; the header is then followed by the body length as a
+/// little-endian u32, the body, and zero bytes up to the padded size.
+pub const PADDED_FLAG: u8 = 0x80;
+const BODY_LENGTH_BYTES: usize = 4;
+/// The most a received payload may decompress to. LZ4 block payloads announce their size up front, and the buffer for
+/// it is allocated before decompressing, so a payload from another publisher must not be trusted with it.
+const MAX_DECODED_BYTES: usize = 16 * 1024 * 1024;
+// The level gzip compresses at when none is configured, balancing speed and ratio
+const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+// Codec names
+pub const NONE_CODEC: &str = "none";
+pub const LZ4_CODEC: &str = "lz4";
+pub const LZ4_FRAME_CODEC: &str = "lz4_frame";
+pub const GZIP_CODEC: &str = "gzip";
+
+impl Codec {
+    pub fn from_name(name: &str) -> Result<Codec, IllegalArgumentError> {
+        match name {
+            NONE_CODEC => Ok(Codec::None),
+            LZ4_CODEC => Ok(Codec::Lz4),
+            LZ4_FRAME_CODEC => Ok(Codec::Lz4Frame),
+            GZIP_CODEC => Ok(Codec::Gzip),
+            _ => Err(IllegalArgumentError::new(format!("Unexpected compression codec '{}'", name).as_str()))
+        }
+    }
+
+    /// The identifier written in the payload format header.
+    pub fn id(&self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Lz4 => 1,
+            Codec::Lz4Frame => 2,
+            Codec::Gzip => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Codec, IllegalArgumentError> {
+        match id {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Lz4),
+            2 => Ok(Codec::Lz4Frame),
+            3 => Ok(Codec::Gzip),
+            _ => Err(IllegalArgumentError::new(format!("Unexpected compression codec id {}", id).as_str()))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::None => NONE_CODEC,
+            Codec::Lz4 => LZ4_CODEC,
+            Codec::Lz4Frame => LZ4_FRAME_CODEC,
+            Codec::Gzip => GZIP_CODEC,
+        }
+    }
+
+    /// The range of compression levels accepted by the codec, or `None` if it cannot be tuned.
+    pub fn level_range(&self) -> Option<RangeInclusive<u32>> {
+        match self {
+            // lz4_flex only implements the default (fast) compressor, for both the block and frame formats
+            Codec::None | Codec::Lz4 | Codec::Lz4Frame => None,
+            Codec::Gzip => Some(0..=9),
+        }
+    }
+
+    /// Whether the codec can compress against a shared dictionary.
+    pub fn supports_dictionary(&self) -> bool {
+        matches!(self, Codec::Lz4)
+    }
+
+    pub fn validate_dictionary(&self) -> Result<(), IllegalArgumentError> {
+        if self.supports_dictionary() {
+            Ok(())
+        } else {
+            Err(IllegalArgumentError::new(
+                format!("Codec '{}' does not support a compression dictionary", self.name()).as_str()
+            ))
+        }
+    }
+
+    pub fn validate_level(&self, level: u32) -> Result<(), IllegalArgumentError> {
+        match self.level_range() {
+            Some(range) => {
+                if range.contains(&level) {
+                    Ok(())
+                } else {
+                    Err(IllegalArgumentError::new(
+                        format!(
+                            "Compression level for codec '{}' must be between {} and {}",
+                            self.name(),
+                            range.start(),
+                            range.end()
+                        ).as_str()
+                    ))
+                }
+            }
+            None => Err(IllegalArgumentError::new(
+                format!("Codec '{}' does not support a compression level", self.name()).as_str()
+            ))
+        }
+    }
+}
+
+pub fn compress(codec: Codec, level: Option<u32>, dictionary: Option<&[u8]>, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if dictionary.is_some() {
+        codec.validate_dictionary()?;
+    }
+    match codec {
+        Codec::None => Ok(payload.to_vec()),
+        Codec::Lz4 => match dictionary {
+            Some(dictionary) => Ok(compress_prepend_size_with_dict(payload, dictionary)),
+            None => Ok(compress_prepend_size(payload)),
+        },
+        Codec::Lz4Frame => {
+            let mut encoder = FrameEncoder::new(Vec::new());
+            if let Err(e) = encoder.write_all(payload) {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+            match encoder.finish() {
+                Ok(compressed) => Ok(compressed),
+                Err(e) => {
+                    let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                    Err(error)
+                }
+            }
+        }
+        Codec::Gzip => {
+            let level = Compression::new(level.unwrap_or(DEFAULT_GZIP_LEVEL));
+            let mut encoder = GzEncoder::new(Vec::new(), level);
+            if let Err(e) = encoder.write_all(payload) {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+            match encoder.finish() {
+                Ok(compressed) => Ok(compressed),
+                Err(e) => {
+                    let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                    Err(error)
+                }
+            }
+        }
+    }
+}
+
+/// Compresses the payload only if it is larger than `min_bytes`, prefixing a one-byte format header
+/// holding the id of the codec actually applied so that consumers can tell raw and compressed payloads apart.
+pub fn compress_with_header(codec: Codec, level: Option<u32>, dictionary: Option<&[u8]>, min_bytes: usize, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if payload.len() > min_bytes {
+        let mut encoded = vec![codec.id()];
+        encoded.extend(compress(codec, level, dictionary, payload)?);
+        Ok(encoded)
+    } else {
+        let mut encoded = vec![Codec::None.id()];
+        encoded.extend_from_slice(payload);
+        Ok(encoded)
+    }
+}
+
+/// Pads a payload carrying a format header with zero bytes up to the next multiple of `pad_to_bytes`, so that
+/// payload sizes reveal little about their contents. The body length is recorded so that consumers can strip the
+/// padding.
+pub fn pad_payload(encoded: &[u8], pad_to_bytes: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (codec_id, body) = match encoded.split_first() {
+        Some(header_and_body) => header_and_body,
+        None => return Err(Box::new(RuntimeError::new("Payload is missing its format header")))
+    };
+    if body.len() > u32::MAX as usize {
+        return Err(Box::new(RuntimeError::new("Payload is too large to pad")));
+    }
+    let unpadded_len = 1 + BODY_LENGTH_BYTES + body.len();
+    let padded_len = unpadded_len.div_ceil(pad_to_bytes) * pad_to_bytes;
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.push(codec_id | PADDED_FLAG);
+    padded.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    padded.extend_from_slice(body);
+    padded.resize(padded_len, 0);
+    Ok(padded)
+}
+
+/// Reverses the encoding of a payload: the codec is read from the format header when the payload carries one, and
+/// the dictionary only applies to the codec that supports it.
+pub fn decode(codec: Codec, dictionary: Option<&[u8]>, with_header: bool, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (codec, body) = if with_header { split_header(payload)? } else { (codec, payload) };
+    if codec == Codec::Lz4 && body.len() >= 4 {
+        let decoded_len = u32::from_le_bytes([body[0], body[1], body[2], body[3]]) as usize;
+        if decoded_len > MAX_DECODED_BYTES {
+            let error = Box::new(RuntimeError::new(
+                format!("Payload would decompress to {} bytes, more than the limit of {}", decoded_len, MAX_DECODED_BYTES).as_str()
+            ));
+            return Err(error);
+        }
+    }
+    match dictionary {
+        Some(dictionary) if codec.supports_dictionary() => decompress_with_dictionary(body, dictionary),
+        _ => decompress(codec, body)
+    }
+}
+
+#[cfg(test)]
+pub fn decompress_with_header(payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (codec, body) = split_header(payload)?;
+    decompress(codec, body)
+}
+
+/// Strips the format header, and any padding, from a payload, returning the codec it names and the body.
+fn split_header(payload: &[u8]) -> Result<(Codec, &[u8]), Box<dyn Error>> {
+    let (header, body) = match payload.split_first() {
+        Some(header_and_body) => header_and_body,
+        None => return Err(Box::new(RuntimeError::new("Payload is missing its format header")))
+    };
+    if header & PADDED_FLAG == 0 {
+        return Ok((Codec::from_id(*header)?, body));
+    }
+    if body.len() < BODY_LENGTH_BYTES {
+        return Err(Box::new(RuntimeError::new("Padded payload is missing its body length")));
+    }
+    let (body_len, padded_body) = body.split_at(BODY_LENGTH_BYTES);
+    let body_len = u32::from_le_bytes([body_len[0], body_len[1], body_len[2], body_len[3]]) as usize;
+    match padded_body.get(..body_len) {
+        Some(body) => Ok((Codec::from_id(header & !PADDED_FLAG)?, body)),
+        None => Err(Box::new(RuntimeError::new("Padded payload is shorter than its body length")))
+    }
+}
+
+pub fn decompress(codec: Codec, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use lz4_flex::decompress_size_prepended;
+    use lz4_flex::frame::FrameDecoder;
+
+    match codec {
+        Codec::None => Ok(payload.to_vec()),
+        Codec::Lz4 => {
+            match decompress_size_prepended(payload) {
+                Ok(decompressed) => Ok(decompressed),
+                Err(e) => {
+                    let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                    Err(error)
+                }
+            }
+        }
+        Codec::Lz4Frame => {
+            let mut decompressed = Vec::new();
+            match FrameDecoder::new(payload).read_to_end(&mut decompressed) {
+                Ok(_) => Ok(decompressed),
+                Err(e) => {
+                    let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                    Err(error)
+                }
+            }
+        }
+        Codec::Gzip => {
+            // Gzip does not announce its decompressed size, so the limit is enforced while reading
+            let mut decompressed = Vec::new();
+            let mut decoder = GzDecoder::new(payload).take(MAX_DECODED_BYTES as u64 + 1);
+            if let Err(e) = decoder.read_to_end(&mut decompressed) {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+            if decompressed.len() > MAX_DECODED_BYTES {
+                let error = Box::new(RuntimeError::new(
+                    format!("Payload decompresses to more than the limit of {} bytes", MAX_DECODED_BYTES).as_str()
+                ));
+                return Err(error);
+            }
+            Ok(decompressed)
+        }
+    }
+}
+
+pub fn decompress_with_dictionary(payload: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    use lz4_flex::block::decompress_size_prepended_with_dict;
+
+    match decompress_size_prepended_with_dict(payload, dictionary) {
+        Ok(decompressed) => Ok(decompressed),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::compression::{Codec, CODECS, compress, compress_with_header, decode, decompress, decompress_with_dictionary, decompress_with_header, pad_payload, PADDED_FLAG, REPORT_DICTIONARY};
+
+    const SAMPLE_PAYLOAD: &str = r#"{"deviceId":"Test Device Name","messageId":"1","timestamp":1,"report":{"disks":[],"cpus":[],"memory":{"memoryUsed":1,"memoryCapacity":2}}}"#;
+
+    #[test]
+    fn round_trip_each_codec() {
+        for codec in CODECS.iter() {
+            let compressed = compress(*codec, None, None, SAMPLE_PAYLOAD.as_bytes()).unwrap();
+            let decompressed = decompress(*codec, &compressed).unwrap();
+            assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decompressed.as_slice());
+        }
+    }
+
+    #[test]
+    fn round_trip_each_gzip_level() {
+        let payload = SAMPLE_PAYLOAD.repeat(20);
+        let mut compressed_lens = Vec::new();
+        for level in 0..=9 {
+            let compressed = compress(Codec::Gzip, Some(level), None, payload.as_bytes()).unwrap();
+            let decompressed = decompress(Codec::Gzip, &compressed).unwrap();
+            assert_eq!(payload.as_bytes(), decompressed.as_slice());
+            compressed_lens.push(compressed.len());
+        }
+        // Level 0 only stores the payload, while the higher levels compress it
+        assert!(compressed_lens[0] > payload.len());
+        assert!(compressed_lens[9] < compressed_lens[1], "Level 9 gave {} bytes against {} at level 1", compressed_lens[9], compressed_lens[1]);
+    }
+
+    #[test]
+    fn skip_compression_below_threshold() {
+        let encoded = compress_with_header(Codec::Lz4, None, None, 1024, SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        assert_eq!(Codec::None.id(), encoded[0]);
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), &encoded[1..]);
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decompress_with_header(&encoded).unwrap().as_slice());
+    }
+
+    #[test]
+    fn apply_compression_above_threshold() {
+        let payload = SAMPLE_PAYLOAD.repeat(20);
+        let encoded = compress_with_header(Codec::Lz4, None, None, 1024, payload.as_bytes()).unwrap();
+        assert_eq!(Codec::Lz4.id(), encoded[0]);
+        assert!(encoded.len() < payload.len());
+        assert_eq!(payload.as_bytes(), decompress_with_header(&encoded).unwrap().as_slice());
+    }
+
+    #[test]
+    fn round_trip_with_dictionary() {
+        let compressed = compress(Codec::Lz4, None, Some(REPORT_DICTIONARY), SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        let decompressed = decompress_with_dictionary(&compressed, REPORT_DICTIONARY).unwrap();
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decompressed.as_slice());
+        let compressed_without_dictionary = compress(Codec::Lz4, None, None, SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        assert!(
+            compressed.len() < compressed_without_dictionary.len(),
+            "Dictionary compression gave {} bytes against {} without", compressed.len(), compressed_without_dictionary.len()
+        );
+    }
+
+    #[test]
+    fn dictionary_unsupported_by_codec() {
+        let result = compress(Codec::Lz4Frame, None, Some(REPORT_DICTIONARY), SAMPLE_PAYLOAD.as_bytes()).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Codec 'lz4_frame' does not support a compression dictionary", result.to_string());
+    }
+
+    #[test]
+    fn round_trip_padded_payload() {
+        let encoded = compress_with_header(Codec::Lz4, None, None, 0, SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        let padded = pad_payload(&encoded, 256).unwrap();
+        assert_eq!(256, padded.len());
+        assert_eq!(Codec::Lz4.id() | PADDED_FLAG, padded[0]);
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decompress_with_header(&padded).unwrap().as_slice());
+    }
+
+    #[test]
+    fn pad_to_next_multiple() {
+        let encoded = compress_with_header(Codec::None, None, None, 1024, &[7; 300]).unwrap();
+        // One header byte, four length bytes and 300 body bytes round up to two 256-byte blocks
+        let padded = pad_payload(&encoded, 256).unwrap();
+        assert_eq!(512, padded.len());
+        assert_eq!(vec![7; 300], decompress_with_header(&padded).unwrap());
+        // A payload already filling its blocks exactly is not padded further
+        let encoded = compress_with_header(Codec::None, None, None, 1024, &[7; 251]).unwrap();
+        assert_eq!(256, pad_payload(&encoded, 256).unwrap().len());
+    }
+
+    #[test]
+    fn validate_level_in_range() {
+        assert!(Codec::Gzip.validate_level(0).is_ok());
+        assert!(Codec::Gzip.validate_level(9).is_ok());
+    }
+
+    #[test]
+    fn validate_level_out_of_range() {
+        let result = Codec::Gzip.validate_level(10).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Compression level for codec 'gzip' must be between 0 and 9", result.to_string());
+    }
+
+    #[test]
+    fn validate_level_unsupported() {
+        for codec in [Codec::None, Codec::Lz4, Codec::Lz4Frame].iter() {
+            let result = codec.validate_level(1).err().unwrap();
+            assert_eq!(
+                format!("An illegal argument was encountered. Reason: Codec '{}' does not support a compression level", codec.name()),
+                result.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn decode_each_encoding() {
+        let compressed = compress(Codec::Lz4, None, Some(REPORT_DICTIONARY), SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decode(Codec::Lz4, Some(REPORT_DICTIONARY), false, &compressed).unwrap().as_slice());
+        let encoded = compress_with_header(Codec::Lz4Frame, None, None, 0, SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        let padded = pad_payload(&encoded, 512).unwrap();
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decode(Codec::None, None, true, &padded).unwrap().as_slice());
+        // Below the minimum size the payload is left uncompressed, so the dictionary does not apply
+        let encoded = compress_with_header(Codec::Lz4, None, Some(REPORT_DICTIONARY), 4096, SAMPLE_PAYLOAD.as_bytes()).unwrap();
+        assert_eq!(SAMPLE_PAYLOAD.as_bytes(), decode(Codec::Lz4, Some(REPORT_DICTIONARY), true, &encoded).unwrap().as_slice());
+    }
+
+    #[test]
+    fn decode_rejects_oversized_lz4_payload() {
+        let result = decode(Codec::Lz4, None, false, br#"{"messageId":"1"}"#).err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Payload would decompress to 1701651067 bytes, more than the limit of 16777216",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn unrecognized_codec() {
+        let result = Codec::from_name("zstd").err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected compression codec 'zstd'", result.to_string());
+    }
+}
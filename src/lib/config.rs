@@ -1,210 +1,3801 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::error::Error;
+use std::fs;
+use std::path::Path;
 use uuid::Uuid;
 
-use crate::lib::common::{IllegalArgumentError, RuntimeError, RuntimeMode};
-use config::ConfigError;
+use crate::lib::collector::{CounterReset, REPORT_SECTIONS};
+use crate::lib::common::{IllegalArgumentError, MINUTES_MULTIPLIER, RuntimeError, RuntimeMode};
+use crate::lib::compression::Codec;
+use crate::lib::destination::HttpDestination;
+use crate::lib::identity::{DeviceIdSource, resolve_device_id, SystemIdentity};
+use crate::lib::message_id::MessageIdFormat;
+use crate::lib::quiet_hours::{parse_time_of_day, parse_utc_offset, QuietHours};
+use crate::lib::report::{FrequencyUnit, KeyCase, Location, ReportFormat, TemperatureUnit};
+use crate::lib::spool::SpoolFullStrategy;
+use crate::lib::topic::{render_topic, validate_topic};
+use config::{ConfigError, Source, Value};
+use paho_mqtt::SslVersion;
+use serde::Deserialize;
 
 pub struct RunnerConfig {
     pub device_id: String,
     pub server_address: String,
     pub user_name: String,
     pub user_password: String,
+    /// Disables verification of the broker's TLS certificate and host name. This leaves the connection open to
+    /// interception, exposing the credentials and reports; it is only meant for test brokers with self-signed
+    /// certificates and must never be enabled in production.
+    pub insecure_skip_verify: bool,
+    /// The oldest TLS version the client will negotiate with the broker; the client library's default when unset.
+    pub min_tls_version: Option<SslVersion>,
+    /// A PEM file of the certificates trusted for the broker, used in place of the system trust store.
+    pub ca_file: Option<String>,
+    /// A PEM file of the client's certificate chain, presented to brokers that authenticate clients by certificate.
+    pub client_cert_file: Option<String>,
+    /// A PEM file of the client's private key, when it is not included in the certificate file.
+    pub client_key_file: Option<String>,
+    /// The protocols offered through ALPN during the TLS handshake; none are offered when empty.
+    pub alpn_protocols: Vec<String>,
+    /// Pins the MQTT version used with the broker; the client library negotiates one when unset.
+    pub mqtt_version: Option<u32>,
     pub topic: String,
     pub runtime_mode: RuntimeMode,
     pub check_interval: u64,
+    /// Set when continuous mode fell back to the default check interval because none was configured.
+    pub check_interval_defaulted: bool,
+    /// Rejects a continuous configuration without a check interval instead of falling back to the default.
+    pub require_explicit_interval: bool,
+    pub heartbeat_interval_secs: Option<u64>,
+    pub heartbeat_topic: String,
+    pub compression: Codec,
+    /// The level the codec compresses at, for the codecs that can be tuned; higher levels spend more CPU on a better
+    /// ratio.
+    pub compression_level: Option<u32>,
+    /// When set, payloads no larger than this are sent uncompressed and every payload carries a format header.
+    pub compression_min_bytes: Option<usize>,
+    /// Compresses against the shared report dictionary; consumers must decompress with the same dictionary.
+    pub compression_dictionary: bool,
+    /// When set, payloads carry a format header and are zero-padded to a multiple of this size so that their size
+    /// reveals little about their contents.
+    pub pad_to_bytes: Option<usize>,
+    /// When set, reports whose payload is larger than this are published to the report topics as numbered chunks,
+    /// none larger than this, for consumers to reassemble.
+    pub max_message_bytes: Option<usize>,
+    /// When set, reports carry an HMAC-SHA256 signature keyed with this secret.
+    pub hmac_secret: Option<String>,
+    /// Identifies the signing secret in each report so that consumers can pick the verification key while keys are
+    /// being rotated.
+    pub hmac_key_id: Option<String>,
+    pub qos: i32,
+    pub topics: Vec<TopicConfig>,
+    /// Added around the report topics, and around a topic given on the command line.
+    pub topic_prefix: String,
+    pub topic_suffix: String,
+    /// Publishes each report twice in place of the report topics: retained to the snapshot topic, so that new
+    /// subscribers receive the latest state, and unretained to the event topic as a stream.
+    pub dual_publish: bool,
+    pub snapshot_topic: Option<String>,
+    pub event_topic: Option<String>,
+    pub probe_on_start: bool,
+    pub verbose: bool,
+    pub include_disk_io_busy: bool,
+    /// Adds whether each disk is mounted read-only to reports; omitted on platforms other than Unix.
+    pub include_disk_read_only: bool,
+    /// Adds each drive's temperature from its SMART data to reports; requires the `smart` feature and `smartctl`.
+    pub include_disk_temperature: bool,
+    /// Flags each disk whose usage has reached this percentage of its capacity as near full.
+    pub disk_near_full_pct: Option<f64>,
+    /// Publishes an alert to the alert topic when a disk that was mounted read-write is found mounted read-only.
+    pub read_only_alert: bool,
+    /// Adds system-wide file descriptor usage to reports; omitted on platforms other than Linux.
+    pub include_file_descriptors: bool,
+    /// Adds buffer and cache usage, and the memory used once they are discounted, to the memory section; omitted on
+    /// platforms other than Linux.
+    pub include_memory_breakdown: bool,
+    /// Adds the rates at which pages are swapped in and out to the memory section, measured from the second report
+    /// on; omitted on platforms other than Linux.
+    pub include_swap_rates: bool,
+    pub frequency_unit: FrequencyUnit,
+    /// Adds component temperature sensor readings to reports.
+    pub include_temperatures: bool,
+    /// Adds each CPU core's temperature to its usage in the CPUs section, where a component sensor is labelled with
+    /// the core.
+    pub include_core_temperatures: bool,
+    /// Adds processor energy and power draw to reports; requires Linux and the `powercap` feature.
+    pub include_power: bool,
+    /// Adds each network interface's MAC and IP addresses to reports. These identify the device on its network, so
+    /// they are only reported when asked for.
+    pub include_network_addresses: bool,
+    /// Adds the reporter's own PID and start time to reports.
+    pub include_agent_process: bool,
+    /// Adds the number of running processes, and on Linux the threads across them, to reports.
+    pub include_process_counts: bool,
+    /// A shell command run on every report whose standard output, a JSON object, is nested in the report as `custom`.
+    pub custom_command: Option<String>,
+    /// Attaches the unprocessed readings to reports as a `raw` object, for troubleshooting metric discrepancies.
+    pub debug_raw: bool,
+    pub temperature_unit: TemperatureUnit,
+    /// When set, published messages carry an MQTT 5 message expiry interval and the client connects with MQTT 5.
+    pub message_expiry_secs: Option<u32>,
+    /// When set, each report section is published to its own subtopic beneath every report topic.
+    pub split_topics: bool,
+    /// Bounds how long disconnecting waits for in-flight messages to be acknowledged.
+    pub disconnect_timeout_ms: Option<u64>,
+    /// How long the final disconnect of a continuous run waits for in-flight publishes to be acknowledged, so that
+    /// the last reports are not lost on shutdown. Unlike the disconnect timeout, it only applies when shutting down.
+    /// The synchronous MQTT client waits for each publish to be acknowledged before returning, so no publish is left
+    /// in flight and the grace period has no effect with it.
+    pub shutdown_grace_secs: Option<u64>,
+    /// How long QoS 1 and 2 publishes are waited on to be acknowledged before a connection opened for a single report
+    /// is closed, so that closing it cannot race the broker's acknowledgment. The MQTT client applies it to each of its
+    /// blocking calls, connecting and subscribing included.
+    pub publish_ack_timeout_secs: u64,
+    /// In single mode, how long the check may take before it is abandoned with a timeout error, so that a stuck disk
+    /// or network filesystem cannot hang the process.
+    pub check_deadline_secs: Option<u64>,
+    /// The report sections to collect; all of them unless a `sections` list is configured.
+    pub sections: HashSet<&'static str>,
+    /// When set, only disks mounted at one of these mount points are reported.
+    pub disk_mount_allowlist: Option<Vec<String>>,
+    /// Disks smaller than this, such as tiny pseudo-filesystems, are left out of reports.
+    pub min_disk_capacity_bytes: u64,
+    /// Collapses disks reported more than once under the same mount point into the one with the largest capacity.
+    pub dedup_disks: bool,
+    /// Payload bytes that may be transmitted per calendar month before reports are throttled and then skipped.
+    pub monthly_byte_budget: Option<u64>,
+    /// Sends reports less often and without per-core and per-process detail while the device is only connected over
+    /// cellular, as judged from its interface names.
+    pub cellular_throttle: bool,
+    /// Under the cellular throttle, only every Nth report is sent.
+    pub cellular_interval_multiplier: u64,
+    /// The least time between any two publishes, reports and heartbeats alike; those coming sooner are dropped.
+    pub min_publish_interval_ms: Option<u64>,
+    /// A daily window during which reports are collected but not transmitted.
+    pub quiet_hours: Option<QuietHours>,
+    /// Where the month's transmitted byte count is persisted so that it survives restarts.
+    pub budget_state_file: Option<String>,
+    /// Where cumulative compression statistics are persisted; they are printed by the `--stats` command.
+    pub compression_stats_file: Option<String>,
+    /// Where the sequence numbers and lifetime counters are persisted so that they continue across restarts.
+    pub state_file: Option<String>,
+    /// When set, CPU usage is measured over this window within each check rather than since the previous check.
+    pub cpu_sample_window_ms: Option<u64>,
+    /// When set, each core's frequency is sampled across this window and reported as its minimum, maximum and average
+    /// rather than as a single reading, which power management makes fluctuate from one check to the next.
+    pub frequency_sample_window_ms: Option<u64>,
+    /// Reports memory and CPU usage against the limits of the cgroup the reporter runs in rather than the host's
+    /// totals; only Linux has cgroups.
+    pub container_aware: bool,
+    /// How many threads take the disk, component, network and agent readings; more than one takes them concurrently.
+    pub collection_threads: usize,
+    /// Per-section thresholds below which an unchanged section is left out of the report.
+    pub change_thresholds: HashMap<&'static str, f64>,
+    /// Per-section usage percentages above which an alert is published between scheduled checks.
+    pub thresholds: HashMap<&'static str, f64>,
+    /// Free space in bytes, keyed by mount point, below which an alert is published in continuous mode.
+    pub free_space_alerts: HashMap<String, u64>,
+    pub alert_topic: Option<String>,
+    /// When set, failed checks and collection errors are published here as well as written to stderr.
+    pub diagnostic_topic: Option<String>,
+    /// The least time between two diagnostics; those coming sooner are dropped and counted in the next one.
+    pub diagnostic_min_interval_secs: u64,
+    /// How often usage is checked against the thresholds in continuous mode.
+    pub threshold_check_secs: u64,
+    /// Drops per-core and per-process detail from reports while the device is under heavy load, restoring it once
+    /// the load subsides.
+    pub load_adaptive: bool,
+    /// The average CPU usage, or one-minute load average per core, in percent above which load counts as heavy.
+    pub load_adaptive_threshold: f64,
+    /// Skips reports while the device is idle, to save energy on battery-powered devices. Heartbeats carry on, so a
+    /// heartbeat interval is required.
+    pub idle_skip: bool,
+    /// The average CPU usage in percent below which the device may count as idle.
+    pub idle_cpu_threshold: f64,
+    /// The network rate in bytes received and transmitted per second below which the device may count as idle.
+    pub idle_network_threshold_bps: u64,
+    pub key_case: KeyCase,
+    /// How rates are reported over an interval in which a cumulative counter went backwards.
+    pub counter_reset: CounterReset,
+    pub message_id_format: MessageIdFormat,
+    pub format: ReportFormat,
+    /// Tags added to every line of reports rendered as InfluxDB line protocol. They are kept sorted so that identical
+    /// reports render to identical bytes.
+    pub tags: BTreeMap<String, String>,
+    /// Added to every JSON report, for devices placed on a map.
+    pub location: Option<Location>,
+    /// Publishes reports as a single-level object keyed by dotted paths, for ingestors that cannot handle nesting.
+    pub flatten: bool,
+    /// When set, a crash message is published here if the reporter panics.
+    pub crash_topic: Option<String>,
+    /// Publishes a message describing the device and the sections it reports to the startup topic when the reporter
+    /// starts, ahead of the first report.
+    pub send_startup_report: bool,
+    pub startup_topic: Option<String>,
+    /// Keeps the broker connection open between reports instead of connecting for each one.
+    pub keep_connected: bool,
+    /// How often a kept-open connection is checked and re-established if the broker dropped it.
+    pub connection_check_secs: u64,
+    /// How many of the most recent reports are kept in memory so that they can be requested over the command topic.
+    pub history_size: Option<usize>,
+    /// Commands are received here; as they arrive over the kept-open connection, this requires `keep_connected`.
+    pub command_topic: Option<String>,
+    /// Topic subscribed to in continuous mode for configuration updates; see `parse_config_update`.
+    pub config_topic: Option<String>,
+    /// Where the retained reports are published when they are requested.
+    pub history_topic: Option<String>,
+    /// How many more times delivery of a report is attempted after it fails.
+    pub publish_retries: u32,
+    /// Reports that still fail once their retries are exhausted are spooled here and flushed after the next
+    /// successful delivery, only to the destinations that failed to take them.
+    pub spool_dir: Option<String>,
+    /// Spooled reports older than this are discarded instead of flushed, so that resumed reporting stays current.
+    pub spool_max_age_secs: Option<u64>,
+    /// The most the spooled reports may take up on disk; unlimited when not set.
+    pub spool_max_bytes: Option<u64>,
+    /// What is done with a report that would take the spool past `spool_max_bytes`.
+    pub spool_full_strategy: SpoolFullStrategy,
+    /// When false, the broker keeps the session (subscriptions and queued messages) between connections.
+    pub clean_session: bool,
+    /// Checks at startup whether another device appears to be using the same device ID; only done for persistent
+    /// sessions, where such a collision leaves both devices repeatedly disconnecting each other.
+    pub duplicate_id_check: bool,
+    /// Whether unacknowledged QoS 1 and 2 messages are persisted, so that they are resent after a restart.
+    pub persistence: MqttPersistence,
+    /// Where unacknowledged messages are persisted under `file` persistence.
+    pub persistence_dir: Option<String>,
+    /// Where each report is delivered; only the MQTT broker unless a `destinations` list is configured.
+    pub destinations: Vec<DestinationConfig>,
+}
+
+/// An additional topic to publish reports to; falls back to the global QoS when none is given.
+#[derive(Debug,PartialEq)]
+pub struct TopicConfig {
+    pub name: String,
+    pub qos: Option<i32>,
+}
+
+/// The runtime settings changed by a configuration update received on the config topic. Settings left out of the
+/// update are unchanged.
+#[derive(Debug,Default,PartialEq)]
+pub struct ConfigUpdate {
+    pub check_interval: Option<u64>,
+    pub topic: Option<String>,
+    /// Replaces every alert threshold; an empty table removes them all.
+    pub thresholds: Option<HashMap<&'static str, f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigUpdatePayload {
+    check_interval: Option<u64>,
+    topic: Option<String>,
+    thresholds: Option<HashMap<String, f64>>,
+}
+
+/// Values given as command-line flags, which take precedence over the configuration file.
+#[derive(Debug,Default,PartialEq)]
+pub struct ConfigOverrides {
+    pub check_interval: Option<String>,
+    pub topic: Option<String>,
+}
+
+/// A destination that reports are delivered to. Heartbeats, crash messages and per-section topics are
+/// always published to the MQTT broker. `compress` applies the configured compression to the reports sent to the
+/// destination; when cleared, the destination receives the serialized report as-is.
+#[derive(Debug,PartialEq)]
+pub enum DestinationConfig {
+    Mqtt { compress: bool },
+    Http { url: String, headers: BTreeMap<String, String>, compress: bool },
+}
+
+/// How the MQTT client keeps QoS 1 and 2 messages until the broker acknowledges them.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum MqttPersistence {
+    /// Messages are kept in memory only, and are lost should the reporter restart.
+    None,
+    /// Messages are persisted to files under `persistence_dir`, or the working directory where none is set.
+    File,
+    /// The client library's default, which persists messages to files in the working directory.
+    Default,
+}
+
+impl DestinationConfig {
+    pub fn is_mqtt(&self) -> bool {
+        matches!(self, DestinationConfig::Mqtt { .. })
+    }
 }
 
 // Configuration key names
 const DEVICE_ID_KEY: &str = "device_id";
+const DEVICE_ID_SOURCE_KEY: &str = "device_id_source";
 const SERVER_ADDRESS_KEY: &str = "server_address";
 const USER_NAME_KEY: &str = "user_name";
 const USER_PASSWORD_KEY: &str = "user_password";
+const USER_PASSWORD_FILE_KEY: &str = "user_password_file";
+const INSECURE_SKIP_VERIFY_KEY: &str = "insecure_skip_verify";
+const MIN_TLS_VERSION_KEY: &str = "min_tls_version";
+const CA_FILE_KEY: &str = "ca_file";
+const CLIENT_CERT_FILE_KEY: &str = "client_cert_file";
+const CLIENT_KEY_FILE_KEY: &str = "client_key_file";
+const AWS_IOT_KEY: &str = "aws_iot";
+const AWS_IOT_ENDPOINT_KEY: &str = "endpoint";
+const AWS_IOT_PORT_KEY: &str = "port";
 const RUNTIME_MODE_KEY: &str = "runtime_mode";
 const CHECK_INTERVAL_KEY: &str = "check_interval";
+const REQUIRE_EXPLICIT_INTERVAL_KEY: &str = "require_explicit_interval";
 const TOPIC_KEY: &str = "topic";
+const TOPIC_PREFIX_KEY: &str = "topic_prefix";
+const TOPIC_SUFFIX_KEY: &str = "topic_suffix";
+const HEARTBEAT_INTERVAL_KEY: &str = "heartbeat_interval_secs";
+const HEARTBEAT_TOPIC_KEY: &str = "heartbeat_topic";
+const CRASH_TOPIC_KEY: &str = "crash_topic";
+const SEND_STARTUP_REPORT_KEY: &str = "send_startup_report";
+const STARTUP_TOPIC_KEY: &str = "startup_topic";
+const KEEP_CONNECTED_KEY: &str = "keep_connected";
+const HISTORY_SIZE_KEY: &str = "history_size";
+const COMMAND_TOPIC_KEY: &str = "command_topic";
+const CONFIG_TOPIC_KEY: &str = "config_topic";
+const HISTORY_TOPIC_KEY: &str = "history_topic";
+const CONNECTION_CHECK_KEY: &str = "connection_check_secs";
+const CLEAN_SESSION_KEY: &str = "clean_session";
+const PERSISTENCE_KEY: &str = "persistence";
+const PERSISTENCE_DIR_KEY: &str = "persistence_dir";
+const PUBLISH_RETRIES_KEY: &str = "publish_retries";
+const SPOOL_DIR_KEY: &str = "spool_dir";
+const SPOOL_MAX_AGE_KEY: &str = "spool_max_age_secs";
+const SPOOL_MAX_BYTES_KEY: &str = "spool_max_bytes";
+const SPOOL_FULL_STRATEGY_KEY: &str = "spool_full_strategy";
+const DUPLICATE_ID_CHECK_KEY: &str = "duplicate_id_check";
+const COMPRESSION_KEY: &str = "compression";
+const COMPRESSION_LEVEL_KEY: &str = "compression_level";
+const COMPRESSION_MIN_BYTES_KEY: &str = "compression_min_bytes";
+const COMPRESSION_DICTIONARY_KEY: &str = "compression_dictionary";
+const PAD_TO_BYTES_KEY: &str = "pad_to_bytes";
+const MAX_MESSAGE_BYTES_KEY: &str = "max_message_bytes";
+const HMAC_SECRET_KEY: &str = "hmac_secret";
+const HMAC_SECRET_FILE_KEY: &str = "hmac_secret_file";
+const HMAC_KEY_ID_KEY: &str = "hmac_key_id";
+const QOS_KEY: &str = "qos";
+const TOPICS_KEY: &str = "topics";
+const DUAL_PUBLISH_KEY: &str = "dual_publish";
+const SNAPSHOT_TOPIC_KEY: &str = "snapshot_topic";
+const EVENT_TOPIC_KEY: &str = "event_topic";
+const TOPIC_NAME_KEY: &str = "name";
+const TOPIC_QOS_KEY: &str = "qos";
+const PROBE_ON_START_KEY: &str = "probe_on_start";
+const VERBOSE_KEY: &str = "verbose";
+const INCLUDE_DISK_IO_BUSY_KEY: &str = "include_disk_io_busy";
+const INCLUDE_DISK_READ_ONLY_KEY: &str = "include_disk_read_only";
+const INCLUDE_DISK_TEMPERATURE_KEY: &str = "include_disk_temperature";
+const DISK_NEAR_FULL_PCT_KEY: &str = "disk_near_full_pct";
+const READ_ONLY_ALERT_KEY: &str = "read_only_alert";
+const INCLUDE_FILE_DESCRIPTORS_KEY: &str = "include_file_descriptors";
+const INCLUDE_MEMORY_BREAKDOWN_KEY: &str = "include_memory_breakdown";
+const INCLUDE_SWAP_RATES_KEY: &str = "include_swap_rates";
+const INCLUDE_AGENT_PROCESS_KEY: &str = "include_agent_process";
+const INCLUDE_PROCESS_COUNTS_KEY: &str = "include_process_counts";
+const CUSTOM_COMMAND_KEY: &str = "custom_command";
+const FREQUENCY_UNIT_KEY: &str = "frequency_unit";
+const INCLUDE_TEMPERATURES_KEY: &str = "include_temperatures";
+const INCLUDE_CORE_TEMPERATURES_KEY: &str = "include_core_temperatures";
+const INCLUDE_POWER_KEY: &str = "include_power";
+const INCLUDE_NETWORK_ADDRESSES_KEY: &str = "include_network_addresses";
+const DEBUG_RAW_KEY: &str = "debug_raw";
+const TEMPERATURE_UNIT_KEY: &str = "temperature_unit";
+const MESSAGE_EXPIRY_KEY: &str = "message_expiry_secs";
+const SPLIT_TOPICS_KEY: &str = "split_topics";
+const DISCONNECT_TIMEOUT_KEY: &str = "disconnect_timeout_ms";
+const SHUTDOWN_GRACE_KEY: &str = "shutdown_grace_secs";
+const PUBLISH_ACK_TIMEOUT_KEY: &str = "publish_ack_timeout_secs";
+const CHECK_DEADLINE_KEY: &str = "check_deadline_secs";
+const SECTIONS_KEY: &str = "sections";
+const DISK_MOUNT_ALLOWLIST_KEY: &str = "disk_mount_allowlist";
+const MIN_DISK_CAPACITY_KEY: &str = "min_disk_capacity_bytes";
+const DEDUP_DISKS_KEY: &str = "dedup_disks";
+const MONTHLY_BYTE_BUDGET_KEY: &str = "monthly_byte_budget";
+const CELLULAR_THROTTLE_KEY: &str = "cellular_throttle";
+const CELLULAR_INTERVAL_MULTIPLIER_KEY: &str = "cellular_interval_multiplier";
+const MIN_PUBLISH_INTERVAL_KEY: &str = "min_publish_interval_ms";
+const QUIET_HOURS_KEY: &str = "quiet_hours";
+const QUIET_HOURS_START_KEY: &str = "start";
+const QUIET_HOURS_END_KEY: &str = "end";
+const QUIET_HOURS_UTC_OFFSET_KEY: &str = "utc_offset";
+const BUDGET_STATE_FILE_KEY: &str = "budget_state_file";
+const COMPRESSION_STATS_FILE_KEY: &str = "compression_stats_file";
+const STATE_FILE_KEY: &str = "state_file";
+const CPU_SAMPLE_WINDOW_KEY: &str = "cpu_sample_window_ms";
+const FREQUENCY_SAMPLE_WINDOW_KEY: &str = "frequency_sample_window_ms";
+const CONTAINER_AWARE_KEY: &str = "container_aware";
+const COLLECTION_THREADS_KEY: &str = "collection_threads";
+const CHANGE_THRESHOLDS_KEY: &str = "change_thresholds";
+const THRESHOLDS_KEY: &str = "thresholds";
+const FREE_SPACE_ALERTS_KEY: &str = "free_space_alerts";
+const FREE_SPACE_ALERT_MOUNT_POINT_KEY: &str = "mount_point";
+const FREE_SPACE_ALERT_BYTES_KEY: &str = "free_space_alert_bytes";
+const ALERT_TOPIC_KEY: &str = "alert_topic";
+const DIAGNOSTIC_TOPIC_KEY: &str = "diagnostic_topic";
+const DIAGNOSTIC_MIN_INTERVAL_KEY: &str = "diagnostic_min_interval_secs";
+const THRESHOLD_CHECK_KEY: &str = "threshold_check_secs";
+const LOAD_ADAPTIVE_KEY: &str = "load_adaptive";
+const LOAD_ADAPTIVE_THRESHOLD_KEY: &str = "load_adaptive_threshold";
+const IDLE_SKIP_KEY: &str = "idle_skip";
+const IDLE_CPU_THRESHOLD_KEY: &str = "idle_cpu_threshold";
+const IDLE_NETWORK_THRESHOLD_KEY: &str = "idle_network_threshold_bps";
+const KEY_CASE_KEY: &str = "key_case";
+const COUNTER_RESET_KEY: &str = "counter_reset";
+const MESSAGE_ID_FORMAT_KEY: &str = "message_id_format";
+const FORMAT_KEY: &str = "format";
+const TAGS_KEY: &str = "tags";
+const LATITUDE_KEY: &str = "latitude";
+const LONGITUDE_KEY: &str = "longitude";
+const FLATTEN_KEY: &str = "flatten";
+const DESTINATIONS_KEY: &str = "destinations";
+const DESTINATION_TYPE_KEY: &str = "type";
+const DESTINATION_URL_KEY: &str = "url";
+const DESTINATION_HEADERS_KEY: &str = "headers";
+const DESTINATION_COMPRESS_KEY: &str = "compress";
+const WEBHOOK_URL_KEY: &str = "webhook_url";
+const WEBHOOK_HEADERS_KEY: &str = "webhook_headers";
+// Environment variables starting with this prefix, followed by an underscore and a key name in any case, override
+// the key's value in the configuration file
+const ENVIRONMENT_PREFIX: &str = "DEVICE_STATS_REPORTER";
 // Configuration values
 const DEFAULT_SERVER_ADDRESS: &str = "tcp://localhost:1883";
+const AWS_IOT_MQTT_PORT: i64 = 8883;
+/// AWS IoT accepts MQTT on the HTTPS port when the client offers this protocol through ALPN.
+const AWS_IOT_HTTPS_PORT: i64 = 443;
+const AWS_IOT_ALPN_PROTOCOL: &str = "x-amzn-mqtt-ca";
 const DEFAULT_USER_NAME: &str = "DeviceStatsUploader";
 const DEFAULT_USER_PASSWORD: &str = "DeviceStatsUploaderPassword";
 const DEFAULT_TOPIC: &str = "Device_Status";
+const DEFAULT_HEARTBEAT_TOPIC: &str = "Device_Heartbeat";
 const SINGLE_RUNTIME_MODE: &str = "Single";
 const CONTINUOUS_RUNTIME_MODE: &str = "Continuous";
+const MHZ_FREQUENCY_UNIT: &str = "MHz";
+const GHZ_FREQUENCY_UNIT: &str = "GHz";
+const CELSIUS_TEMPERATURE_UNIT: &str = "c";
+const FAHRENHEIT_TEMPERATURE_UNIT: &str = "f";
+const TLS_1_0_VERSION: &str = "1.0";
+const TLS_1_1_VERSION: &str = "1.1";
+const TLS_1_2_VERSION: &str = "1.2";
+const CAMEL_KEY_CASE: &str = "camel";
+const SNAKE_KEY_CASE: &str = "snake";
+// Values of the counter reset key
+const OMIT_COUNTER_RESET: &str = "omit";
+const ZERO_COUNTER_RESET: &str = "zero";
+// Values of the spool full strategy key
+const DROP_OLDEST_SPOOL_FULL_STRATEGY: &str = "drop_oldest";
+const DROP_NEWEST_SPOOL_FULL_STRATEGY: &str = "drop_newest";
+const BLOCK_SPOOL_FULL_STRATEGY: &str = "block";
+// Values of the persistence key
+const NONE_PERSISTENCE: &str = "none";
+const FILE_PERSISTENCE: &str = "file";
+const DEFAULT_PERSISTENCE: &str = "default";
+const UUID_V4_MESSAGE_ID_FORMAT: &str = "uuid_v4";
+const UUID_V7_MESSAGE_ID_FORMAT: &str = "uuid_v7";
+const SEQUENCE_MESSAGE_ID_FORMAT: &str = "sequence";
+const JSON_REPORT_FORMAT: &str = "json";
+const INFLUX_LINE_REPORT_FORMAT: &str = "influx_line";
+// Every line is already tagged with the device ID
+const RESERVED_TAG: &str = "device_id";
+const MQTT_DESTINATION_TYPE: &str = "mqtt";
+const HTTP_DESTINATION_TYPE: &str = "http";
 const DEFAULT_CHECK_INTERVAL: u64 = 1;
 const MINIMUM_CHECK_INTERVAL: u64 = DEFAULT_CHECK_INTERVAL;
 const MAXIMUM_CHECK_INTERVAL: u64 = 240;
+const MINIMUM_HEARTBEAT_INTERVAL: u64 = 1;
+const DEFAULT_QOS: i32 = 0;
+const MINIMUM_QOS: i64 = 0;
+const MAXIMUM_QOS: i64 = 2;
+// Shorter windows give unreliable usage readings; longer ones delay every check
+const MINIMUM_CPU_SAMPLE_WINDOW: u64 = 100;
+const MAXIMUM_CPU_SAMPLE_WINDOW: u64 = 10_000;
+const MINIMUM_FREQUENCY_SAMPLE_WINDOW: u64 = 100;
+const MAXIMUM_FREQUENCY_SAMPLE_WINDOW: u64 = 10_000;
+const DEFAULT_COLLECTION_THREADS: usize = 1;
+const MINIMUM_COLLECTION_THREADS: usize = DEFAULT_COLLECTION_THREADS;
+// There are only four independent sections to collect
+const MAXIMUM_COLLECTION_THREADS: usize = 4;
+// Smaller chunks would carry little besides their envelope; MQTT allows no larger packet
+const MINIMUM_MAX_MESSAGE_BYTES: usize = 1024;
+const MAXIMUM_MAX_MESSAGE_BYTES: usize = 268_435_455;
+const DEFAULT_CONNECTION_CHECK: u64 = 30;
+const MINIMUM_CONNECTION_CHECK: u64 = 1;
+const MAXIMUM_CONNECTION_CHECK: u64 = 3600;
+const DEFAULT_THRESHOLD_CHECK: u64 = 10;
+const MINIMUM_THRESHOLD_CHECK: u64 = 1;
+const MAXIMUM_THRESHOLD_CHECK: u64 = 3600;
+const DEFAULT_DIAGNOSTIC_MIN_INTERVAL: u64 = 60;
+const MINIMUM_DIAGNOSTIC_MIN_INTERVAL: u64 = 1;
+const MAXIMUM_DIAGNOSTIC_MIN_INTERVAL: u64 = 86_400;
+const MAXIMUM_THRESHOLD: f64 = 100.0;
+const DEFAULT_CELLULAR_INTERVAL_MULTIPLIER: u64 = 4;
+const MAXIMUM_CELLULAR_INTERVAL_MULTIPLIER: u64 = 100;
+const MAXIMUM_LATITUDE: f64 = 90.0;
+const MAXIMUM_LONGITUDE: f64 = 180.0;
+const DEFAULT_LOAD_ADAPTIVE_THRESHOLD: f64 = 90.0;
+const DEFAULT_IDLE_CPU_THRESHOLD: f64 = 5.0;
+const DEFAULT_IDLE_NETWORK_THRESHOLD: u64 = 1024;
+const MAXIMUM_PUBLISH_RETRIES: u32 = 10;
+const MINIMUM_SPOOL_MAX_AGE: u64 = 1;
+const MAXIMUM_SPOOL_MAX_AGE: u64 = 2_592_000;
+const MINIMUM_SPOOL_MAX_BYTES: u64 = 1024;
+const MINIMUM_HISTORY_SIZE: usize = 1;
+const MAXIMUM_HISTORY_SIZE: usize = 1000;
+const MINIMUM_PUBLISH_INTERVAL: u64 = 1;
+const MAXIMUM_PUBLISH_INTERVAL: u64 = 3_600_000;
+const MINIMUM_DISCONNECT_TIMEOUT: u64 = 1;
+const MAXIMUM_DISCONNECT_TIMEOUT: u64 = 60_000;
+const MINIMUM_SHUTDOWN_GRACE: u64 = 1;
+const MAXIMUM_SHUTDOWN_GRACE: u64 = 300;
+const DEFAULT_PUBLISH_ACK_TIMEOUT: u64 = 30;
+const MINIMUM_PUBLISH_ACK_TIMEOUT: u64 = 1;
+const MAXIMUM_PUBLISH_ACK_TIMEOUT: u64 = 300;
+const MINIMUM_CHECK_DEADLINE: u64 = 1;
+const MAXIMUM_CHECK_DEADLINE: u64 = 3600;
+const MINIMUM_MESSAGE_EXPIRY: u32 = 1;
+// The expiry interval is carried as a signed four-byte integer by the MQTT client
+const MAXIMUM_MESSAGE_EXPIRY: u32 = i32::MAX as u32;
 
 pub fn load_config(config_path: Option<&String>) -> Result<RunnerConfig, Box<dyn Error>> {
+    load_layered_config(config_path, ENVIRONMENT_PREFIX)
+}
+
+/// Loads the configuration from the file, if any, overlaid with the environment variables carrying the prefix.
+fn load_layered_config(config_path: Option<&String>, environment_prefix: &str) -> Result<RunnerConfig, Box<dyn Error>> {
     let mut runner_config = RunnerConfig {
         device_id: Uuid::new_v4().to_string(),
         server_address: String::from(DEFAULT_SERVER_ADDRESS),
         user_name: String::from(DEFAULT_USER_NAME),
         user_password: String::from(DEFAULT_USER_PASSWORD),
+        insecure_skip_verify: false,
+        min_tls_version: None,
+        ca_file: None,
+        client_cert_file: None,
+        client_key_file: None,
+        alpn_protocols: Vec::new(),
+        mqtt_version: None,
         topic: String::from(DEFAULT_TOPIC),
         runtime_mode: RuntimeMode::Single,
         check_interval: DEFAULT_CHECK_INTERVAL,
+        check_interval_defaulted: false,
+        require_explicit_interval: false,
+        heartbeat_interval_secs: None,
+        heartbeat_topic: String::from(DEFAULT_HEARTBEAT_TOPIC),
+        compression: Codec::Lz4,
+        compression_level: None,
+        compression_min_bytes: None,
+        compression_dictionary: false,
+        pad_to_bytes: None,
+        max_message_bytes: None,
+        hmac_secret: None,
+        hmac_key_id: None,
+        qos: DEFAULT_QOS,
+        topics: Vec::new(),
+        topic_prefix: String::new(),
+        topic_suffix: String::new(),
+        dual_publish: false,
+        snapshot_topic: None,
+        event_topic: None,
+        probe_on_start: false,
+        verbose: false,
+        include_disk_io_busy: false,
+        include_disk_read_only: false,
+        include_disk_temperature: false,
+        disk_near_full_pct: None,
+        read_only_alert: false,
+        include_file_descriptors: false,
+        include_memory_breakdown: false,
+        include_swap_rates: false,
+        frequency_unit: FrequencyUnit::MHz,
+        include_temperatures: false,
+        include_core_temperatures: false,
+        include_power: false,
+        include_network_addresses: false,
+        include_agent_process: false,
+        include_process_counts: false,
+        custom_command: None,
+        debug_raw: false,
+        temperature_unit: TemperatureUnit::Celsius,
+        message_expiry_secs: None,
+        split_topics: false,
+        disconnect_timeout_ms: None,
+        shutdown_grace_secs: None,
+        publish_ack_timeout_secs: DEFAULT_PUBLISH_ACK_TIMEOUT,
+        check_deadline_secs: None,
+        sections: REPORT_SECTIONS.iter().cloned().collect(),
+        disk_mount_allowlist: None,
+        min_disk_capacity_bytes: 0,
+        dedup_disks: false,
+        monthly_byte_budget: None,
+        cellular_throttle: false,
+        cellular_interval_multiplier: DEFAULT_CELLULAR_INTERVAL_MULTIPLIER,
+        min_publish_interval_ms: None,
+        quiet_hours: None,
+        budget_state_file: None,
+        compression_stats_file: None,
+        state_file: None,
+        cpu_sample_window_ms: None,
+        frequency_sample_window_ms: None,
+        container_aware: false,
+        collection_threads: DEFAULT_COLLECTION_THREADS,
+        change_thresholds: HashMap::new(),
+        thresholds: HashMap::new(),
+        free_space_alerts: HashMap::new(),
+        alert_topic: None,
+        diagnostic_topic: None,
+        diagnostic_min_interval_secs: DEFAULT_DIAGNOSTIC_MIN_INTERVAL,
+        threshold_check_secs: DEFAULT_THRESHOLD_CHECK,
+        load_adaptive: false,
+        load_adaptive_threshold: DEFAULT_LOAD_ADAPTIVE_THRESHOLD,
+        idle_skip: false,
+        idle_cpu_threshold: DEFAULT_IDLE_CPU_THRESHOLD,
+        idle_network_threshold_bps: DEFAULT_IDLE_NETWORK_THRESHOLD,
+        key_case: KeyCase::Camel,
+        counter_reset: CounterReset::Omit,
+        message_id_format: MessageIdFormat::UuidV4,
+        format: ReportFormat::Json,
+        tags: BTreeMap::new(),
+        location: None,
+        flatten: false,
+        crash_topic: None,
+        send_startup_report: false,
+        startup_topic: None,
+        keep_connected: false,
+        connection_check_secs: DEFAULT_CONNECTION_CHECK,
+        history_size: None,
+        command_topic: None,
+        config_topic: None,
+        history_topic: None,
+        publish_retries: 0,
+        spool_dir: None,
+        spool_max_age_secs: None,
+        spool_max_bytes: None,
+        spool_full_strategy: SpoolFullStrategy::DropOldest,
+        clean_session: true,
+        duplicate_id_check: false,
+        persistence: MqttPersistence::Default,
+        persistence_dir: None,
+        destinations: vec![DestinationConfig::Mqtt { compress: true }],
     };
-    let config_path = match config_path {
-        Some(cp) => cp,
-        None => return Ok(runner_config)
-    };
-    let mut settings = config::Config::default();
-    match settings.merge(config::File::with_name(config_path)) {
-        Ok(_) => {}
-        Err(e) => {
-            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
-            return Err(error);
+    let settings = load_settings(config_path.map(String::as_str), environment_prefix)?;
+    // A file that is present but empty, or holds only comments, is treated as if no file had been given
+    if settings.collect().map(|values| values.is_empty()).unwrap_or(false) {
+        if let Some(config_path) = config_path {
+            println!("Configuration file '{}' sets no values; using the default configuration", config_path);
         }
-    };
+        return Ok(runner_config);
+    }
     // Device name
-    match settings.get_str(DEVICE_ID_KEY) {
-        Ok(device_id) => runner_config.device_id = device_id,
-        Err(_) => {}
-    };
+    if let Ok(device_id_source) = settings.get_string(DEVICE_ID_SOURCE_KEY) {
+        let device_id_source = DeviceIdSource::from_name(device_id_source.as_str())?;
+        runner_config.device_id = resolve_device_id(device_id_source, settings.get_string(DEVICE_ID_KEY).ok(), &SystemIdentity)?;
+    } else if let Ok(device_id) = settings.get_string(DEVICE_ID_KEY) {
+        runner_config.device_id = device_id;
+    }
     // Server address
-    match settings.get_str(SERVER_ADDRESS_KEY) {
-        Ok(server_address) => runner_config.server_address = server_address,
-        Err(_) => {}
-    };
+    if let Ok(server_address) = settings.get_string(SERVER_ADDRESS_KEY) {
+        runner_config.server_address = server_address;
+    }
     // User name
-    match settings.get_str(USER_NAME_KEY) {
-        Ok(user_name) => runner_config.user_name = user_name,
-        Err(_) => {}
-    };
+    if let Ok(user_name) = settings.get_string(USER_NAME_KEY) {
+        runner_config.user_name = user_name;
+    }
     // User password
-    match settings.get_str(USER_PASSWORD_KEY) {
-        Ok(user_password) => runner_config.user_password = user_password,
-        Err(_) => {}
-    };
+    if let Ok(user_password) = settings.get_string(USER_PASSWORD_KEY) {
+        runner_config.user_password = user_password;
+    }
+    if let Ok(user_password_file) = settings.get_string(USER_PASSWORD_FILE_KEY) {
+        runner_config.user_password = read_secret_file(user_password_file.as_str())?;
+    }
+    // TLS certificate verification
+    if let Some(insecure_skip_verify) = get_optional(&settings, INSECURE_SKIP_VERIFY_KEY)? {
+        runner_config.insecure_skip_verify = insecure_skip_verify;
+    }
+    // Minimum TLS version
+    if let Ok(min_tls_version) = settings.get_string(MIN_TLS_VERSION_KEY) {
+        runner_config.min_tls_version = match min_tls_version.as_str() {
+            TLS_1_0_VERSION => Some(SslVersion::Tls_1_0),
+            TLS_1_1_VERSION => Some(SslVersion::Tls_1_1),
+            TLS_1_2_VERSION => Some(SslVersion::Tls_1_2),
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!("Unexpected minimum TLS version '{}'; supported versions are 1.0, 1.1 and 1.2", min_tls_version).as_str()
+                ));
+                return Err(error);
+            }
+        };
+    }
+    // TLS certificates
+    runner_config.ca_file = get_certificate_file(&settings, CA_FILE_KEY)?;
+    runner_config.client_cert_file = get_certificate_file(&settings, CLIENT_CERT_FILE_KEY)?;
+    runner_config.client_key_file = get_certificate_file(&settings, CLIENT_KEY_FILE_KEY)?;
+    // AWS IoT preset
+    if let Some(aws_iot) = get_optional::<Value>(&settings, AWS_IOT_KEY)? {
+        if settings.get_string(SERVER_ADDRESS_KEY).is_ok() {
+            let error = Box::new(IllegalArgumentError::new(
+                "The AWS IoT preset sets the server address; server_address must not also be set"
+            ));
+            return Err(error);
+        }
+        apply_aws_iot_preset(&mut runner_config, aws_iot)?;
+    }
     // Topic
-    match settings.get_str(TOPIC_KEY) {
-        Ok(topic) => runner_config.topic = topic,
-        Err(_) => {}
-    };
-    // Runtime mode
-    match settings.get_str(RUNTIME_MODE_KEY) {
-        Ok(mode) => {
-            match mode.as_str() {
-                CONTINUOUS_RUNTIME_MODE => {
-                    runner_config.runtime_mode = RuntimeMode::Continuous;
-                    // Check interval
-                    match settings.get(CHECK_INTERVAL_KEY) {
-                        Ok(check_interval) => {
-                            if check_interval >= MINIMUM_CHECK_INTERVAL && check_interval <= MAXIMUM_CHECK_INTERVAL {
-                                runner_config.check_interval = check_interval;
-                            } else {
-                                let error = Box::new(
-                                    IllegalArgumentError::new(
-                                        format!(
-                                            "Check interval must be between {} and {}",
-                                            MINIMUM_CHECK_INTERVAL,
-                                            MAXIMUM_CHECK_INTERVAL
-                                        ).as_str()
-                                    )
-                                );
-                                return Err(error);
-                            }
-                        }
-                        Err(e) => {
-                            match e {
-                                ConfigError::NotFound(_) => {}
-                                _ => {
-                                    let error = Box::new(
-                                        IllegalArgumentError::new(e.to_string().as_str())
-                                    );
-                                    return Err(error);
-                                }
-                            }
-                        }
-                    }
+    if let Ok(topic) = settings.get_string(TOPIC_KEY) {
+        runner_config.topic = topic;
+    }
+    // Heartbeat topic
+    if let Ok(heartbeat_topic) = settings.get_string(HEARTBEAT_TOPIC_KEY) {
+        runner_config.heartbeat_topic = heartbeat_topic;
+    }
+    // Crash topic
+    if let Ok(crash_topic) = settings.get_string(CRASH_TOPIC_KEY) {
+        runner_config.crash_topic = Some(crash_topic);
+    }
+    // Startup report
+    if let Some(send_startup_report) = get_optional(&settings, SEND_STARTUP_REPORT_KEY)? {
+        runner_config.send_startup_report = send_startup_report;
+    }
+    if let Ok(startup_topic) = settings.get_string(STARTUP_TOPIC_KEY) {
+        runner_config.startup_topic = Some(startup_topic);
+    }
+    if runner_config.send_startup_report && runner_config.startup_topic.is_none() {
+        let error = Box::new(IllegalArgumentError::new("A startup report requires a startup topic"));
+        return Err(error);
+    }
+    // QoS
+    if let Some(qos) = get_optional(&settings, QOS_KEY)? {
+        runner_config.qos = validate_qos(qos)?;
+    }
+    // Topics
+    if let Some(topics) = get_optional::<Vec<Value>>(&settings, TOPICS_KEY)? {
+        for topic in topics {
+            runner_config.topics.push(parse_topic_config(topic)?);
+        }
+    }
+    // Snapshot and event topics
+    if let Some(dual_publish) = get_optional(&settings, DUAL_PUBLISH_KEY)? {
+        runner_config.dual_publish = dual_publish;
+    }
+    if let Ok(snapshot_topic) = settings.get_string(SNAPSHOT_TOPIC_KEY) {
+        runner_config.snapshot_topic = Some(snapshot_topic);
+    }
+    if let Ok(event_topic) = settings.get_string(EVENT_TOPIC_KEY) {
+        runner_config.event_topic = Some(event_topic);
+    }
+    if runner_config.dual_publish && (runner_config.snapshot_topic.is_none() || runner_config.event_topic.is_none()) {
+        let error = Box::new(IllegalArgumentError::new("Dual publishing requires both a snapshot topic and an event topic"));
+        return Err(error);
+    }
+    // Destinations
+    if let Some(destinations) = get_optional::<Vec<Value>>(&settings, DESTINATIONS_KEY)? {
+        runner_config.destinations = Vec::new();
+        for destination in destinations {
+            runner_config.destinations.push(parse_destination_config(destination)?);
+        }
+    }
+    // Webhook, a shorthand for adding an HTTP destination alongside the others
+    if let Ok(webhook_url) = settings.get_string(WEBHOOK_URL_KEY) {
+        let headers = match get_optional::<Value>(&settings, WEBHOOK_HEADERS_KEY)? {
+            Some(headers) => parse_headers(headers)?,
+            None => BTreeMap::new()
+        };
+        runner_config.destinations.push(http_destination_config(webhook_url, headers, true)?);
+    }
+    // Startup connectivity probe
+    if let Some(probe_on_start) = get_optional(&settings, PROBE_ON_START_KEY)? {
+        runner_config.probe_on_start = probe_on_start;
+    }
+    // Verbose output
+    if let Some(verbose) = get_optional(&settings, VERBOSE_KEY)? {
+        runner_config.verbose = verbose;
+    }
+    // Report sections
+    if let Some(sections) = get_optional::<Vec<String>>(&settings, SECTIONS_KEY)? {
+        runner_config.sections = HashSet::new();
+        for section in sections {
+            match REPORT_SECTIONS.iter().find(|s| **s == section) {
+                Some(report_section) => {
+                    runner_config.sections.insert(report_section);
                 }
-                SINGLE_RUNTIME_MODE => runner_config.runtime_mode = RuntimeMode::Single,
-                _ => {
+                None => {
+                    let error = Box::new(
+                        IllegalArgumentError::new(format!("Unexpected report section '{}'", section).as_str())
+                    );
+                    return Err(error);
+                }
+            }
+        }
+    }
+    // Disk mount point allowlist
+    if let Some(disk_mount_allowlist) = get_optional(&settings, DISK_MOUNT_ALLOWLIST_KEY)? {
+        runner_config.disk_mount_allowlist = Some(disk_mount_allowlist);
+    }
+    // Minimum disk capacity
+    if let Some(min_disk_capacity_bytes) = get_optional(&settings, MIN_DISK_CAPACITY_KEY)? {
+        runner_config.min_disk_capacity_bytes = min_disk_capacity_bytes;
+    }
+    // Disk deduplication
+    if let Some(dedup_disks) = get_optional(&settings, DEDUP_DISKS_KEY)? {
+        runner_config.dedup_disks = dedup_disks;
+    }
+    // Monthly byte budget
+    if let Some(monthly_byte_budget) = get_optional(&settings, MONTHLY_BYTE_BUDGET_KEY)? {
+        if monthly_byte_budget == 0 {
+            let error = Box::new(IllegalArgumentError::new("Monthly byte budget must be greater than 0"));
+            return Err(error);
+        }
+        runner_config.monthly_byte_budget = Some(monthly_byte_budget);
+    }
+    if let Ok(budget_state_file) = settings.get_string(BUDGET_STATE_FILE_KEY) {
+        runner_config.budget_state_file = Some(budget_state_file);
+    }
+    // Cellular throttle
+    if let Some(cellular_throttle) = get_optional(&settings, CELLULAR_THROTTLE_KEY)? {
+        runner_config.cellular_throttle = cellular_throttle;
+    }
+    if let Some(cellular_interval_multiplier) = get_optional(&settings, CELLULAR_INTERVAL_MULTIPLIER_KEY)? {
+        if !(1..=MAXIMUM_CELLULAR_INTERVAL_MULTIPLIER).contains(&cellular_interval_multiplier) {
+            let error = Box::new(IllegalArgumentError::new(
+                format!("Cellular interval multiplier must be between 1 and {}", MAXIMUM_CELLULAR_INTERVAL_MULTIPLIER).as_str()
+            ));
+            return Err(error);
+        }
+        runner_config.cellular_interval_multiplier = cellular_interval_multiplier;
+    }
+    // Compression statistics
+    if let Ok(compression_stats_file) = settings.get_string(COMPRESSION_STATS_FILE_KEY) {
+        runner_config.compression_stats_file = Some(compression_stats_file);
+    }
+    // Device state
+    if let Ok(state_file) = settings.get_string(STATE_FILE_KEY) {
+        runner_config.state_file = Some(state_file);
+    }
+    // Minimum publish interval
+    if let Some(min_publish_interval_ms) = get_optional(&settings, MIN_PUBLISH_INTERVAL_KEY)? {
+        if (MINIMUM_PUBLISH_INTERVAL..=MAXIMUM_PUBLISH_INTERVAL).contains(&min_publish_interval_ms) {
+            runner_config.min_publish_interval_ms = Some(min_publish_interval_ms);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Minimum publish interval must be between {} and {} milliseconds",
+                        MINIMUM_PUBLISH_INTERVAL,
+                        MAXIMUM_PUBLISH_INTERVAL
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Quiet hours
+    if let Some(quiet_hours) = get_optional::<Value>(&settings, QUIET_HOURS_KEY)? {
+        runner_config.quiet_hours = Some(parse_quiet_hours(quiet_hours)?);
+    }
+    // CPU sample window
+    if let Some(cpu_sample_window_ms) = get_optional(&settings, CPU_SAMPLE_WINDOW_KEY)? {
+        if (MINIMUM_CPU_SAMPLE_WINDOW..=MAXIMUM_CPU_SAMPLE_WINDOW).contains(&cpu_sample_window_ms) {
+            runner_config.cpu_sample_window_ms = Some(cpu_sample_window_ms);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "CPU sample window must be between {} and {} milliseconds",
+                        MINIMUM_CPU_SAMPLE_WINDOW,
+                        MAXIMUM_CPU_SAMPLE_WINDOW
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Container limits
+    if let Some(container_aware) = get_optional(&settings, CONTAINER_AWARE_KEY)? {
+        runner_config.container_aware = container_aware;
+    }
+    // Frequency sample window
+    if let Some(frequency_sample_window_ms) = get_optional(&settings, FREQUENCY_SAMPLE_WINDOW_KEY)? {
+        if (MINIMUM_FREQUENCY_SAMPLE_WINDOW..=MAXIMUM_FREQUENCY_SAMPLE_WINDOW).contains(&frequency_sample_window_ms) {
+            runner_config.frequency_sample_window_ms = Some(frequency_sample_window_ms);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Frequency sample window must be between {} and {} milliseconds",
+                        MINIMUM_FREQUENCY_SAMPLE_WINDOW,
+                        MAXIMUM_FREQUENCY_SAMPLE_WINDOW
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Collection parallelism
+    if let Some(collection_threads) = get_optional(&settings, COLLECTION_THREADS_KEY)? {
+        if (MINIMUM_COLLECTION_THREADS..=MAXIMUM_COLLECTION_THREADS).contains(&collection_threads) {
+            runner_config.collection_threads = collection_threads;
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Collection threads must be between {} and {}",
+                        MINIMUM_COLLECTION_THREADS,
+                        MAXIMUM_COLLECTION_THREADS
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Change thresholds
+    if let Some(change_thresholds) = get_optional::<HashMap<String, f64>>(&settings, CHANGE_THRESHOLDS_KEY)? {
+        for (section, threshold) in change_thresholds {
+            let report_section = match REPORT_SECTIONS.iter().find(|s| **s == section) {
+                Some(report_section) => *report_section,
+                None => {
                     let error = Box::new(
-                        IllegalArgumentError::new(format!("Unexpected runtime mode '{}'", mode).as_str())
+                        IllegalArgumentError::new(format!("Unexpected report section '{}'", section).as_str())
                     );
                     return Err(error);
                 }
             };
+            if threshold < 0.0 {
+                let error = Box::new(
+                    IllegalArgumentError::new(format!("Change threshold for section '{}' must not be negative", section).as_str())
+                );
+                return Err(error);
+            }
+            runner_config.change_thresholds.insert(report_section, threshold);
         }
-        Err(_) => {}
-    };
-
-    Ok(runner_config)
-}
-
-#[cfg(test)]
-mod tests {
-    use pretty_assertions::assert_eq;
-    use pretty_assertions::assert_ne;
-
-    use crate::lib::common::{IllegalArgumentError, RuntimeMode};
-    use crate::lib::config::{DEFAULT_CHECK_INTERVAL, load_config, DEFAULT_SERVER_ADDRESS, DEFAULT_TOPIC, DEFAULT_USER_NAME, DEFAULT_USER_PASSWORD};
-
-    #[test]
-    fn load_default_config() {
-        let result = load_config(None).unwrap();
-        assert_ne!("", result.device_id);
-        assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
-        assert_eq!(DEFAULT_USER_NAME, result.user_name);
-        assert_eq!(DEFAULT_USER_PASSWORD, result.user_password);
-        assert_eq!(DEFAULT_TOPIC, result.topic);
-        assert_eq!(RuntimeMode::Single, result.runtime_mode);
-        assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
     }
-
-    #[test]
-    fn load_bare_single_config() {
-        let result = load_config(
-            Some(&String::from("resources/test/good/bare_single.yaml"))
-        ).unwrap();
-        assert_ne!("", result.device_id);
-        assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
-        assert_eq!(DEFAULT_USER_NAME, result.user_name);
-        assert_eq!(DEFAULT_USER_PASSWORD, result.user_password);
-        assert_eq!(DEFAULT_TOPIC, result.topic);
-        assert_eq!(RuntimeMode::Single, result.runtime_mode);
+    // Alert thresholds
+    if let Some(thresholds) = get_optional::<HashMap<String, f64>>(&settings, THRESHOLDS_KEY)? {
+        runner_config.thresholds = validate_thresholds(thresholds)?;
     }
-
-    #[test]
-    fn load_bare_continuous_config() {
-        let result = load_config(
-            Some(&String::from("resources/test/good/bare_continuous.yaml"))
-        ).unwrap();
-        assert_ne!("", result.device_id);
-        assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
-        assert_eq!(DEFAULT_USER_NAME, result.user_name);
-        assert_eq!(DEFAULT_USER_PASSWORD, result.user_password);
-        assert_eq!(DEFAULT_TOPIC, result.topic);
-        assert_eq!(RuntimeMode::Continuous, result.runtime_mode);
-        assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
+    if let Ok(alert_topic) = settings.get_string(ALERT_TOPIC_KEY) {
+        runner_config.alert_topic = Some(alert_topic);
     }
-
-    #[test]
-    fn load_full_single_config() {
-        let result = load_config(
-            Some(&String::from("resources/test/good/full_single.yaml"))
-        ).unwrap();
-        assert_eq!("Test Device Name", result.device_id);
-        assert_eq!("tcp://test.server.address:1883", result.server_address);
-        assert_eq!("TestUser", result.user_name);
-        assert_eq!("TestPassword", result.user_password);
-        assert_eq!("Test Topic", result.topic);
-        assert_eq!(RuntimeMode::Single, result.runtime_mode);
-        assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
+    if !runner_config.thresholds.is_empty() && runner_config.alert_topic.is_none() {
+        let error = Box::new(IllegalArgumentError::new("Thresholds require an alert topic"));
+        return Err(error);
     }
-
-    #[test]
-    fn load_full_continuous_config() {
-        let result = load_config(
-            Some(&String::from("resources/test/good/full_continuous.yaml"))
-        ).unwrap();
-        assert_eq!("Test Device Name", result.device_id);
-        assert_eq!("tcp://test.server.address:1883", result.server_address);
-        assert_eq!("TestUser", result.user_name);
-        assert_eq!("TestPassword", result.user_password);
-        assert_eq!("Test Topic", result.topic);
-        assert_eq!(RuntimeMode::Continuous, result.runtime_mode);
-        assert_eq!(5, result.check_interval);
+    // Free space alerts
+    if let Some(free_space_alerts) = get_optional::<Vec<Value>>(&settings, FREE_SPACE_ALERTS_KEY)? {
+        for free_space_alert in free_space_alerts {
+            let (mount_point, free_space_alert_bytes) = parse_free_space_alert(free_space_alert)?;
+            runner_config.free_space_alerts.insert(mount_point, free_space_alert_bytes);
+        }
+    }
+    if !runner_config.free_space_alerts.is_empty() && runner_config.alert_topic.is_none() {
+        let error = Box::new(IllegalArgumentError::new("Free space alerts require an alert topic"));
+        return Err(error);
+    }
+    // Diagnostics
+    if let Ok(diagnostic_topic) = settings.get_string(DIAGNOSTIC_TOPIC_KEY) {
+        runner_config.diagnostic_topic = Some(diagnostic_topic);
+    }
+    if let Some(diagnostic_min_interval_secs) = get_optional(&settings, DIAGNOSTIC_MIN_INTERVAL_KEY)? {
+        if (MINIMUM_DIAGNOSTIC_MIN_INTERVAL..=MAXIMUM_DIAGNOSTIC_MIN_INTERVAL).contains(&diagnostic_min_interval_secs) {
+            runner_config.diagnostic_min_interval_secs = diagnostic_min_interval_secs;
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Diagnostic minimum interval must be between {} and {} seconds",
+                        MINIMUM_DIAGNOSTIC_MIN_INTERVAL,
+                        MAXIMUM_DIAGNOSTIC_MIN_INTERVAL
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    if let Some(threshold_check_secs) = get_optional(&settings, THRESHOLD_CHECK_KEY)? {
+        if (MINIMUM_THRESHOLD_CHECK..=MAXIMUM_THRESHOLD_CHECK).contains(&threshold_check_secs) {
+            runner_config.threshold_check_secs = threshold_check_secs;
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Threshold check interval must be between {} and {} seconds",
+                        MINIMUM_THRESHOLD_CHECK,
+                        MAXIMUM_THRESHOLD_CHECK
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Load-adaptive detail
+    if let Some(load_adaptive) = get_optional(&settings, LOAD_ADAPTIVE_KEY)? {
+        runner_config.load_adaptive = load_adaptive;
+    }
+    if let Some(load_adaptive_threshold) = get_optional(&settings, LOAD_ADAPTIVE_THRESHOLD_KEY)? {
+        if !(0.0..=MAXIMUM_THRESHOLD).contains(&load_adaptive_threshold) {
+            let error = Box::new(IllegalArgumentError::new("Load adaptive threshold must be between 0 and 100 percent"));
+            return Err(error);
+        }
+        runner_config.load_adaptive_threshold = load_adaptive_threshold;
+    }
+    // Idle skipping
+    if let Some(idle_skip) = get_optional(&settings, IDLE_SKIP_KEY)? {
+        runner_config.idle_skip = idle_skip;
+    }
+    if let Some(idle_cpu_threshold) = get_optional(&settings, IDLE_CPU_THRESHOLD_KEY)? {
+        if !(0.0..=MAXIMUM_THRESHOLD).contains(&idle_cpu_threshold) {
+            let error = Box::new(IllegalArgumentError::new("Idle CPU threshold must be between 0 and 100 percent"));
+            return Err(error);
+        }
+        runner_config.idle_cpu_threshold = idle_cpu_threshold;
+    }
+    if let Some(idle_network_threshold_bps) = get_optional(&settings, IDLE_NETWORK_THRESHOLD_KEY)? {
+        runner_config.idle_network_threshold_bps = idle_network_threshold_bps;
+    }
+    // Per-section topics
+    if let Some(split_topics) = get_optional(&settings, SPLIT_TOPICS_KEY)? {
+        runner_config.split_topics = split_topics;
+    }
+    // Disk I/O busy time
+    if let Some(include_disk_io_busy) = get_optional(&settings, INCLUDE_DISK_IO_BUSY_KEY)? {
+        runner_config.include_disk_io_busy = include_disk_io_busy;
+    }
+    // Read-only mounts
+    if let Some(include_disk_read_only) = get_optional(&settings, INCLUDE_DISK_READ_ONLY_KEY)? {
+        runner_config.include_disk_read_only = include_disk_read_only;
+    }
+    // Disk temperatures
+    if let Some(include_disk_temperature) = get_optional(&settings, INCLUDE_DISK_TEMPERATURE_KEY)? {
+        runner_config.include_disk_temperature = include_disk_temperature;
+    }
+    // Near-full disks
+    if let Some(disk_near_full_pct) = get_optional(&settings, DISK_NEAR_FULL_PCT_KEY)? {
+        if !(0.0..=MAXIMUM_THRESHOLD).contains(&disk_near_full_pct) {
+            let error = Box::new(IllegalArgumentError::new("Disk near-full percentage must be between 0 and 100 percent"));
+            return Err(error);
+        }
+        runner_config.disk_near_full_pct = Some(disk_near_full_pct);
+    }
+    if let Some(read_only_alert) = get_optional(&settings, READ_ONLY_ALERT_KEY)? {
+        runner_config.read_only_alert = read_only_alert;
+    }
+    if runner_config.read_only_alert {
+        if !runner_config.include_disk_read_only {
+            let error = Box::new(IllegalArgumentError::new("Read-only alerts require include_disk_read_only"));
+            return Err(error);
+        }
+        if runner_config.alert_topic.is_none() {
+            let error = Box::new(IllegalArgumentError::new("Read-only alerts require an alert topic"));
+            return Err(error);
+        }
+    }
+    // File descriptor usage
+    if let Some(include_file_descriptors) = get_optional(&settings, INCLUDE_FILE_DESCRIPTORS_KEY)? {
+        runner_config.include_file_descriptors = include_file_descriptors;
+    }
+    // Reporter process
+    if let Some(include_agent_process) = get_optional(&settings, INCLUDE_AGENT_PROCESS_KEY)? {
+        runner_config.include_agent_process = include_agent_process;
+    }
+    // Process counts
+    if let Some(include_process_counts) = get_optional(&settings, INCLUDE_PROCESS_COUNTS_KEY)? {
+        runner_config.include_process_counts = include_process_counts;
+    }
+    // Custom command
+    if let Ok(custom_command) = settings.get_string(CUSTOM_COMMAND_KEY) {
+        if custom_command.trim().is_empty() {
+            let error = Box::new(IllegalArgumentError::new("Custom command must not be empty"));
+            return Err(error);
+        }
+        runner_config.custom_command = Some(custom_command);
+    }
+    // Memory breakdown
+    if let Some(include_memory_breakdown) = get_optional(&settings, INCLUDE_MEMORY_BREAKDOWN_KEY)? {
+        runner_config.include_memory_breakdown = include_memory_breakdown;
+    }
+    // Swap rates
+    if let Some(include_swap_rates) = get_optional(&settings, INCLUDE_SWAP_RATES_KEY)? {
+        runner_config.include_swap_rates = include_swap_rates;
+    }
+    // CPU frequency unit
+    if let Ok(frequency_unit) = settings.get_string(FREQUENCY_UNIT_KEY) {
+        runner_config.frequency_unit = match frequency_unit.as_str() {
+            MHZ_FREQUENCY_UNIT => FrequencyUnit::MHz,
+            GHZ_FREQUENCY_UNIT => FrequencyUnit::GHz,
+            _ => {
+                let error = Box::new(
+                    IllegalArgumentError::new(format!("Unexpected frequency unit '{}'", frequency_unit).as_str())
+                );
+                return Err(error);
+            }
+        };
+    }
+    // Raw readings
+    if let Some(debug_raw) = get_optional(&settings, DEBUG_RAW_KEY)? {
+        runner_config.debug_raw = debug_raw;
+    }
+    // Power draw
+    if let Some(include_power) = get_optional(&settings, INCLUDE_POWER_KEY)? {
+        runner_config.include_power = include_power;
+    }
+    // Network addresses
+    if let Some(include_network_addresses) = get_optional(&settings, INCLUDE_NETWORK_ADDRESSES_KEY)? {
+        runner_config.include_network_addresses = include_network_addresses;
+    }
+    // Component temperatures
+    if let Some(include_temperatures) = get_optional(&settings, INCLUDE_TEMPERATURES_KEY)? {
+        runner_config.include_temperatures = include_temperatures;
+    }
+    if let Some(include_core_temperatures) = get_optional(&settings, INCLUDE_CORE_TEMPERATURES_KEY)? {
+        runner_config.include_core_temperatures = include_core_temperatures;
+    }
+    if let Ok(temperature_unit) = settings.get_string(TEMPERATURE_UNIT_KEY) {
+        runner_config.temperature_unit = match temperature_unit.as_str() {
+            CELSIUS_TEMPERATURE_UNIT => TemperatureUnit::Celsius,
+            FAHRENHEIT_TEMPERATURE_UNIT => TemperatureUnit::Fahrenheit,
+            _ => {
+                let error = Box::new(
+                    IllegalArgumentError::new(format!("Unexpected temperature unit '{}'", temperature_unit).as_str())
+                );
+                return Err(error);
+            }
+        };
+    }
+    // Persistent connection
+    if let Some(keep_connected) = get_optional(&settings, KEEP_CONNECTED_KEY)? {
+        runner_config.keep_connected = keep_connected;
+    }
+    if let Some(connection_check_secs) = get_optional(&settings, CONNECTION_CHECK_KEY)? {
+        if (MINIMUM_CONNECTION_CHECK..=MAXIMUM_CONNECTION_CHECK).contains(&connection_check_secs) {
+            runner_config.connection_check_secs = connection_check_secs;
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Connection check interval must be between {} and {} seconds",
+                        MINIMUM_CONNECTION_CHECK,
+                        MAXIMUM_CONNECTION_CHECK
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Report history
+    if let Some(history_size) = get_optional(&settings, HISTORY_SIZE_KEY)? {
+        if (MINIMUM_HISTORY_SIZE..=MAXIMUM_HISTORY_SIZE).contains(&history_size) {
+            runner_config.history_size = Some(history_size);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "History size must be between {} and {} reports",
+                        MINIMUM_HISTORY_SIZE,
+                        MAXIMUM_HISTORY_SIZE
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    if let Ok(command_topic) = settings.get_string(COMMAND_TOPIC_KEY) {
+        if !runner_config.keep_connected {
+            let error = Box::new(IllegalArgumentError::new("A command topic requires keep_connected to be enabled"));
+            return Err(error);
+        }
+        runner_config.command_topic = Some(command_topic);
+    }
+    if let Ok(config_topic) = settings.get_string(CONFIG_TOPIC_KEY) {
+        if !runner_config.keep_connected {
+            let error = Box::new(IllegalArgumentError::new("A config topic requires keep_connected to be enabled"));
+            return Err(error);
+        }
+        runner_config.config_topic = Some(config_topic);
+    }
+    if let Ok(history_topic) = settings.get_string(HISTORY_TOPIC_KEY) {
+        runner_config.history_topic = Some(history_topic);
+    }
+    if runner_config.history_size.is_some() && (runner_config.command_topic.is_none() || runner_config.history_topic.is_none()) {
+        let error = Box::new(IllegalArgumentError::new("A report history requires both a command topic and a history topic"));
+        return Err(error);
+    }
+    // Publish retries
+    if let Some(publish_retries) = get_optional(&settings, PUBLISH_RETRIES_KEY)? {
+        if publish_retries <= MAXIMUM_PUBLISH_RETRIES {
+            runner_config.publish_retries = publish_retries;
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!("Publish retries must be between 0 and {}", MAXIMUM_PUBLISH_RETRIES).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Offline spool
+    if let Ok(spool_dir) = settings.get_string(SPOOL_DIR_KEY) {
+        runner_config.spool_dir = Some(spool_dir);
+    }
+    if let Some(spool_max_age_secs) = get_optional(&settings, SPOOL_MAX_AGE_KEY)? {
+        if runner_config.spool_dir.is_none() {
+            let error = Box::new(IllegalArgumentError::new("A spool maximum age requires a spool directory"));
+            return Err(error);
+        }
+        if (MINIMUM_SPOOL_MAX_AGE..=MAXIMUM_SPOOL_MAX_AGE).contains(&spool_max_age_secs) {
+            runner_config.spool_max_age_secs = Some(spool_max_age_secs);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Spool maximum age must be between {} and {} seconds",
+                        MINIMUM_SPOOL_MAX_AGE,
+                        MAXIMUM_SPOOL_MAX_AGE
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    if let Some(spool_max_bytes) = get_optional(&settings, SPOOL_MAX_BYTES_KEY)? {
+        if runner_config.spool_dir.is_none() {
+            let error = Box::new(IllegalArgumentError::new("A spool maximum size requires a spool directory"));
+            return Err(error);
+        }
+        if spool_max_bytes < MINIMUM_SPOOL_MAX_BYTES {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!("Spool maximum size must be at least {} bytes", MINIMUM_SPOOL_MAX_BYTES).as_str()
+                )
+            );
+            return Err(error);
+        }
+        runner_config.spool_max_bytes = Some(spool_max_bytes);
+    }
+    if let Ok(spool_full_strategy) = settings.get_string(SPOOL_FULL_STRATEGY_KEY) {
+        if runner_config.spool_max_bytes.is_none() {
+            let error = Box::new(IllegalArgumentError::new("A spool full strategy requires a spool maximum size"));
+            return Err(error);
+        }
+        runner_config.spool_full_strategy = match spool_full_strategy.as_str() {
+            DROP_OLDEST_SPOOL_FULL_STRATEGY => SpoolFullStrategy::DropOldest,
+            DROP_NEWEST_SPOOL_FULL_STRATEGY => SpoolFullStrategy::DropNewest,
+            BLOCK_SPOOL_FULL_STRATEGY => SpoolFullStrategy::Block,
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!(
+                        "Unexpected spool full strategy '{}'; supported values are drop_oldest, drop_newest and block",
+                        spool_full_strategy
+                    ).as_str()
+                ));
+                return Err(error);
+            }
+        };
+    }
+    // Session persistence
+    if let Some(clean_session) = get_optional(&settings, CLEAN_SESSION_KEY)? {
+        runner_config.clean_session = clean_session;
+    }
+    if let Some(duplicate_id_check) = get_optional(&settings, DUPLICATE_ID_CHECK_KEY)? {
+        runner_config.duplicate_id_check = duplicate_id_check;
+    }
+    // Message persistence
+    if let Ok(persistence) = settings.get_string(PERSISTENCE_KEY) {
+        runner_config.persistence = match persistence.as_str() {
+            NONE_PERSISTENCE => MqttPersistence::None,
+            FILE_PERSISTENCE => MqttPersistence::File,
+            DEFAULT_PERSISTENCE => MqttPersistence::Default,
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!("Unexpected persistence '{}'; supported values are none, file and default", persistence).as_str()
+                ));
+                return Err(error);
+            }
+        };
+    }
+    if let Ok(persistence_dir) = settings.get_string(PERSISTENCE_DIR_KEY) {
+        if runner_config.persistence != MqttPersistence::File {
+            let error = Box::new(IllegalArgumentError::new("A persistence directory requires file persistence"));
+            return Err(error);
+        }
+        runner_config.persistence_dir = Some(persistence_dir);
+    }
+    // Disconnect timeout
+    if let Some(disconnect_timeout_ms) = get_optional(&settings, DISCONNECT_TIMEOUT_KEY)? {
+        if (MINIMUM_DISCONNECT_TIMEOUT..=MAXIMUM_DISCONNECT_TIMEOUT).contains(&disconnect_timeout_ms) {
+            runner_config.disconnect_timeout_ms = Some(disconnect_timeout_ms);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Disconnect timeout must be between {} and {} milliseconds",
+                        MINIMUM_DISCONNECT_TIMEOUT,
+                        MAXIMUM_DISCONNECT_TIMEOUT
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Shutdown grace period
+    if let Some(shutdown_grace_secs) = get_optional(&settings, SHUTDOWN_GRACE_KEY)? {
+        if (MINIMUM_SHUTDOWN_GRACE..=MAXIMUM_SHUTDOWN_GRACE).contains(&shutdown_grace_secs) {
+            runner_config.shutdown_grace_secs = Some(shutdown_grace_secs);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Shutdown grace period must be between {} and {} seconds",
+                        MINIMUM_SHUTDOWN_GRACE,
+                        MAXIMUM_SHUTDOWN_GRACE
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Publish acknowledgment timeout
+    if let Some(publish_ack_timeout_secs) = get_optional(&settings, PUBLISH_ACK_TIMEOUT_KEY)? {
+        if (MINIMUM_PUBLISH_ACK_TIMEOUT..=MAXIMUM_PUBLISH_ACK_TIMEOUT).contains(&publish_ack_timeout_secs) {
+            runner_config.publish_ack_timeout_secs = publish_ack_timeout_secs;
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Publish acknowledgment timeout must be between {} and {} seconds",
+                        MINIMUM_PUBLISH_ACK_TIMEOUT,
+                        MAXIMUM_PUBLISH_ACK_TIMEOUT
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Check deadline
+    if let Some(check_deadline_secs) = get_optional(&settings, CHECK_DEADLINE_KEY)? {
+        if (MINIMUM_CHECK_DEADLINE..=MAXIMUM_CHECK_DEADLINE).contains(&check_deadline_secs) {
+            runner_config.check_deadline_secs = Some(check_deadline_secs);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Check deadline must be between {} and {} seconds",
+                        MINIMUM_CHECK_DEADLINE,
+                        MAXIMUM_CHECK_DEADLINE
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Message expiry
+    if let Some(message_expiry_secs) = get_optional(&settings, MESSAGE_EXPIRY_KEY)? {
+        if (MINIMUM_MESSAGE_EXPIRY..=MAXIMUM_MESSAGE_EXPIRY).contains(&message_expiry_secs) {
+            runner_config.message_expiry_secs = Some(message_expiry_secs);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Message expiry interval must be between {} and {} seconds",
+                        MINIMUM_MESSAGE_EXPIRY,
+                        MAXIMUM_MESSAGE_EXPIRY
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // JSON key casing
+    if let Ok(key_case) = settings.get_string(KEY_CASE_KEY) {
+        runner_config.key_case = match key_case.as_str() {
+            CAMEL_KEY_CASE => KeyCase::Camel,
+            SNAKE_KEY_CASE => KeyCase::Snake,
+            _ => {
+                let error = Box::new(
+                    IllegalArgumentError::new(format!("Unexpected key case '{}'", key_case).as_str())
+                );
+                return Err(error);
+            }
+        };
+    }
+    // Counter resets
+    if let Ok(counter_reset) = settings.get_string(COUNTER_RESET_KEY) {
+        runner_config.counter_reset = match counter_reset.as_str() {
+            OMIT_COUNTER_RESET => CounterReset::Omit,
+            ZERO_COUNTER_RESET => CounterReset::Zero,
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!("Unexpected counter reset handling '{}'; supported values are omit and zero", counter_reset).as_str()
+                ));
+                return Err(error);
+            }
+        };
+    }
+    // Message IDs
+    if let Ok(message_id_format) = settings.get_string(MESSAGE_ID_FORMAT_KEY) {
+        runner_config.message_id_format = match message_id_format.as_str() {
+            UUID_V4_MESSAGE_ID_FORMAT => MessageIdFormat::UuidV4,
+            UUID_V7_MESSAGE_ID_FORMAT => MessageIdFormat::UuidV7,
+            SEQUENCE_MESSAGE_ID_FORMAT => MessageIdFormat::Sequence,
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!(
+                        "Unexpected message id format '{}'; supported formats are uuid_v4, uuid_v7 and sequence",
+                        message_id_format
+                    ).as_str()
+                ));
+                return Err(error);
+            }
+        };
+    }
+    // Flattened reports
+    if let Some(flatten) = get_optional(&settings, FLATTEN_KEY)? {
+        runner_config.flatten = flatten;
+    }
+    // Compression
+    if let Ok(compression) = settings.get_string(COMPRESSION_KEY) {
+        runner_config.compression = Codec::from_name(compression.as_str())?;
+    }
+    if let Some(compression_level) = get_optional(&settings, COMPRESSION_LEVEL_KEY)? {
+        runner_config.compression.validate_level(compression_level)?;
+        runner_config.compression_level = Some(compression_level);
+    }
+    if let Some(compression_min_bytes) = get_optional(&settings, COMPRESSION_MIN_BYTES_KEY)? {
+        runner_config.compression_min_bytes = Some(compression_min_bytes);
+    }
+    if let Some(compression_dictionary) = get_optional(&settings, COMPRESSION_DICTIONARY_KEY)? {
+        if compression_dictionary {
+            runner_config.compression.validate_dictionary()?;
+        }
+        runner_config.compression_dictionary = compression_dictionary;
+    }
+    // Payload padding
+    if let Some(pad_to_bytes) = get_optional(&settings, PAD_TO_BYTES_KEY)? {
+        if pad_to_bytes == 0 {
+            let error = Box::new(IllegalArgumentError::new("Padding size must be greater than 0"));
+            return Err(error);
+        }
+        runner_config.pad_to_bytes = Some(pad_to_bytes);
+    }
+    // Report chunking
+    if let Some(max_message_bytes) = get_optional(&settings, MAX_MESSAGE_BYTES_KEY)? {
+        if (MINIMUM_MAX_MESSAGE_BYTES..=MAXIMUM_MAX_MESSAGE_BYTES).contains(&max_message_bytes) {
+            runner_config.max_message_bytes = Some(max_message_bytes);
+        } else {
+            let error = Box::new(
+                IllegalArgumentError::new(
+                    format!(
+                        "Maximum message size must be between {} and {} bytes",
+                        MINIMUM_MAX_MESSAGE_BYTES,
+                        MAXIMUM_MAX_MESSAGE_BYTES
+                    ).as_str()
+                )
+            );
+            return Err(error);
+        }
+    }
+    // Report signing
+    if let Ok(hmac_secret) = settings.get_string(HMAC_SECRET_KEY) {
+        runner_config.hmac_secret = Some(hmac_secret);
+    }
+    if let Ok(hmac_secret_file) = settings.get_string(HMAC_SECRET_FILE_KEY) {
+        runner_config.hmac_secret = Some(read_secret_file(hmac_secret_file.as_str())?);
+    }
+    if runner_config.hmac_secret.as_deref() == Some("") {
+        let error = Box::new(IllegalArgumentError::new("HMAC secret cannot be empty"));
+        return Err(error);
+    }
+    if let Ok(hmac_key_id) = settings.get_string(HMAC_KEY_ID_KEY) {
+        if runner_config.hmac_secret.is_none() {
+            let error = Box::new(IllegalArgumentError::new("An HMAC key id requires an HMAC secret"));
+            return Err(error);
+        }
+        runner_config.hmac_key_id = Some(hmac_key_id);
+    }
+    // Report format
+    if let Ok(format) = settings.get_string(FORMAT_KEY) {
+        runner_config.format = match format.as_str() {
+            JSON_REPORT_FORMAT => ReportFormat::Json,
+            INFLUX_LINE_REPORT_FORMAT => ReportFormat::InfluxLine,
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!("Unexpected report format '{}'; supported formats are json and influx_line", format).as_str()
+                ));
+                return Err(error);
+            }
+        };
+    }
+    if runner_config.format == ReportFormat::InfluxLine {
+        // Section topics and signatures are built from the JSON report
+        if runner_config.split_topics || runner_config.hmac_secret.is_some() {
+            let error = Box::new(IllegalArgumentError::new(
+                "The influx_line format cannot be combined with split topics or report signing"
+            ));
+            return Err(error);
+        }
+    }
+    if let Some(tags) = get_optional::<BTreeMap<String, String>>(&settings, TAGS_KEY)? {
+        if tags.contains_key(RESERVED_TAG) {
+            let error = Box::new(IllegalArgumentError::new(
+                format!("The '{}' tag is reserved", RESERVED_TAG).as_str()
+            ));
+            return Err(error);
+        }
+        runner_config.tags = tags;
+    }
+    // Location
+    match (get_optional::<f64>(&settings, LATITUDE_KEY)?, get_optional::<f64>(&settings, LONGITUDE_KEY)?) {
+        (Some(latitude), Some(longitude)) => {
+            if !(-MAXIMUM_LATITUDE..=MAXIMUM_LATITUDE).contains(&latitude) {
+                let error = Box::new(IllegalArgumentError::new("Latitude must be between -90 and 90 degrees"));
+                return Err(error);
+            }
+            if !(-MAXIMUM_LONGITUDE..=MAXIMUM_LONGITUDE).contains(&longitude) {
+                let error = Box::new(IllegalArgumentError::new("Longitude must be between -180 and 180 degrees"));
+                return Err(error);
+            }
+            runner_config.location = Some(Location { latitude, longitude });
+        }
+        (None, None) => {}
+        _ => {
+            let error = Box::new(IllegalArgumentError::new("Latitude and longitude must be configured together"));
+            return Err(error);
+        }
+    }
+    // Runtime mode
+    if let Some(require_explicit_interval) = get_optional(&settings, REQUIRE_EXPLICIT_INTERVAL_KEY)? {
+        runner_config.require_explicit_interval = require_explicit_interval;
+    }
+    if let Ok(mode) = settings.get_string(RUNTIME_MODE_KEY) {
+        match mode.as_str() {
+            CONTINUOUS_RUNTIME_MODE => {
+                runner_config.runtime_mode = RuntimeMode::Continuous;
+                // Check interval
+                // Read as signed so that a negative interval is reported as out of range rather than as a type error
+                match settings.get::<i64>(CHECK_INTERVAL_KEY) {
+                    Ok(check_interval) => {
+                        let check_interval = u64::try_from(check_interval).unwrap_or_default();
+                        runner_config.check_interval = validate_check_interval(check_interval)?;
+                    }
+                    Err(e) => {
+                        match e {
+                            ConfigError::NotFound(_) if runner_config.require_explicit_interval => {
+                                let error = Box::new(
+                                    IllegalArgumentError::new("Continuous mode requires an explicit check_interval")
+                                );
+                                return Err(error);
+                            }
+                            ConfigError::NotFound(_) => runner_config.check_interval_defaulted = true,
+                            _ => {
+                                let error = Box::new(
+                                    IllegalArgumentError::new(e.to_string().as_str())
+                                );
+                                return Err(error);
+                            }
+                        }
+                    }
+                }
+                // Heartbeat interval
+                if let Some(heartbeat_interval) = get_optional(&settings, HEARTBEAT_INTERVAL_KEY)? {
+                    runner_config.heartbeat_interval_secs = Some(
+                        validate_heartbeat_interval(heartbeat_interval, runner_config.check_interval)?
+                    );
+                }
+            }
+            SINGLE_RUNTIME_MODE => runner_config.runtime_mode = RuntimeMode::Single,
+            _ => {
+                let error = Box::new(
+                    IllegalArgumentError::new(format!("Unexpected runtime mode '{}'", mode).as_str())
+                );
+                return Err(error);
+            }
+        };
+    }
+    // Skipped reports leave the heartbeats to show that the device is still alive
+    if runner_config.idle_skip && runner_config.heartbeat_interval_secs.is_none() {
+        let error = Box::new(IllegalArgumentError::new("Idle skipping requires a heartbeat interval"));
+        return Err(error);
+    }
+    // Topic prefix and suffix, applied to the report topics ahead of templating
+    runner_config.topic_prefix = settings.get_string(TOPIC_PREFIX_KEY).unwrap_or_default();
+    runner_config.topic_suffix = settings.get_string(TOPIC_SUFFIX_KEY).unwrap_or_default();
+    runner_config.topic = format!("{}{}{}", runner_config.topic_prefix, runner_config.topic, runner_config.topic_suffix);
+    for topic in runner_config.topics.iter_mut() {
+        topic.name = format!("{}{}{}", runner_config.topic_prefix, topic.name, runner_config.topic_suffix);
+    }
+    // Topic templating
+    runner_config.topic = render_topic(runner_config.topic.as_str(), runner_config.device_id.as_str())?;
+    validate_topic(runner_config.topic.as_str())?;
+    runner_config.heartbeat_topic = render_topic(runner_config.heartbeat_topic.as_str(), runner_config.device_id.as_str())?;
+    validate_topic(runner_config.heartbeat_topic.as_str())?;
+    for topic in runner_config.topics.iter_mut() {
+        topic.name = render_topic(topic.name.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(topic.name.as_str())?;
+    }
+    if let Some(crash_topic) = runner_config.crash_topic.as_mut() {
+        *crash_topic = render_topic(crash_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(crash_topic.as_str())?;
+    }
+    if let Some(startup_topic) = runner_config.startup_topic.as_mut() {
+        *startup_topic = render_topic(startup_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(startup_topic.as_str())?;
+    }
+    if let Some(snapshot_topic) = runner_config.snapshot_topic.as_mut() {
+        *snapshot_topic = render_topic(snapshot_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(snapshot_topic.as_str())?;
+    }
+    if let Some(event_topic) = runner_config.event_topic.as_mut() {
+        *event_topic = render_topic(event_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(event_topic.as_str())?;
+    }
+    if let Some(alert_topic) = runner_config.alert_topic.as_mut() {
+        *alert_topic = render_topic(alert_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(alert_topic.as_str())?;
+    }
+    if let Some(diagnostic_topic) = runner_config.diagnostic_topic.as_mut() {
+        *diagnostic_topic = render_topic(diagnostic_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(diagnostic_topic.as_str())?;
+    }
+    if let Some(command_topic) = runner_config.command_topic.as_mut() {
+        *command_topic = render_topic(command_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(command_topic.as_str())?;
+    }
+    if let Some(config_topic) = runner_config.config_topic.as_mut() {
+        *config_topic = render_topic(config_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(config_topic.as_str())?;
+    }
+    if let Some(history_topic) = runner_config.history_topic.as_mut() {
+        *history_topic = render_topic(history_topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(history_topic.as_str())?;
+    }
+
+    Ok(runner_config)
+}
+
+/// Loads the settings file, if any, with the prefixed environment variables layered over it. Values read from the
+/// environment are parsed into numbers and booleans where they look like one, as they would be in the file.
+fn load_settings(config_path: Option<&str>, environment_prefix: &str) -> Result<config::Config, Box<dyn Error>> {
+    let mut builder = config::Config::builder();
+    if let Some(config_path) = config_path {
+        builder = builder.add_source(config::File::with_name(config_path));
+    }
+    let environment = config::Environment::with_prefix(environment_prefix).try_parsing(true);
+    match builder.add_source(environment).build() {
+        Ok(settings) => Ok(settings),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            Err(error)
+        }
+    }
+}
+
+/// Reads a secret (e.g. a mounted Docker or Kubernetes secret), ignoring any trailing line break.
+fn read_secret_file(secret_path: &str) -> Result<String, Box<dyn Error>> {
+    match fs::read_to_string(secret_path) {
+        Ok(secret) => Ok(String::from(secret.trim_end_matches(['\n', '\r']))),
+        Err(e) => {
+            let error = Box::new(
+                IllegalArgumentError::new(format!("Unable to read secret file '{}': {}", secret_path, e).as_str())
+            );
+            Err(error)
+        }
+    }
+}
+
+/// Reads the path of an optional TLS certificate or key file, which must exist.
+fn get_certificate_file(settings: &config::Config, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+    match settings.get_string(key) {
+        Ok(certificate_path) => {
+            if Path::new(certificate_path.as_str()).is_file() {
+                Ok(Some(certificate_path))
+            } else {
+                let error = Box::new(IllegalArgumentError::new(
+                    format!("Certificate file '{}' does not exist", certificate_path).as_str()
+                ));
+                Err(error)
+            }
+        }
+        Err(_) => Ok(None)
+    }
+}
+
+/// Applies the `aws_iot` preset, a table of the account's device data endpoint and an optional port. AWS IoT Core
+/// authenticates devices by certificate over TLS 1.2 and speaks MQTT 3.1.1; on port 443 MQTT is selected through
+/// ALPN.
+fn apply_aws_iot_preset(runner_config: &mut RunnerConfig, value: Value) -> Result<(), Box<dyn Error>> {
+    let mut aws_iot_table = match value.into_table() {
+        Ok(aws_iot_table) => aws_iot_table,
+        Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+    };
+    let endpoint = match aws_iot_table.remove(AWS_IOT_ENDPOINT_KEY).map(|value| value.into_string()) {
+        Some(Ok(endpoint)) if !endpoint.is_empty() => endpoint,
+        Some(Err(e)) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str()))),
+        _ => return Err(Box::new(IllegalArgumentError::new("The AWS IoT preset requires an endpoint")))
+    };
+    let port = match aws_iot_table.remove(AWS_IOT_PORT_KEY).map(|value| value.into_int()) {
+        Some(Ok(port)) => port,
+        Some(Err(e)) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str()))),
+        None => AWS_IOT_MQTT_PORT
+    };
+    if port == AWS_IOT_HTTPS_PORT {
+        runner_config.alpn_protocols = vec![String::from(AWS_IOT_ALPN_PROTOCOL)];
+    } else if port != AWS_IOT_MQTT_PORT {
+        let error = Box::new(IllegalArgumentError::new(
+            format!("Unexpected AWS IoT port {}; supported ports are 8883 and 443", port).as_str()
+        ));
+        return Err(error);
+    }
+    if runner_config.client_cert_file.is_none() || runner_config.client_key_file.is_none() {
+        let error = Box::new(IllegalArgumentError::new(
+            "The AWS IoT preset requires a client_cert_file and a client_key_file"
+        ));
+        return Err(error);
+    }
+    if runner_config.insecure_skip_verify {
+        let error = Box::new(IllegalArgumentError::new(
+            "The AWS IoT preset must not be combined with insecure_skip_verify"
+        ));
+        return Err(error);
+    }
+    runner_config.server_address = format!("ssl://{}:{}", endpoint, port);
+    if runner_config.min_tls_version.is_none() {
+        runner_config.min_tls_version = Some(SslVersion::Tls_1_2);
+    }
+    runner_config.mqtt_version = Some(paho_mqtt::MQTT_VERSION_3_1_1);
+    Ok(())
+}
+
+/// Reads an optional setting, treating a missing key as `None` and any other failure as an illegal argument.
+fn get_optional<'de, T: Deserialize<'de>>(settings: &config::Config, key: &str) -> Result<Option<T>, Box<dyn Error>> {
+    match settings.get(key) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => {
+            match e {
+                ConfigError::NotFound(_) => Ok(None),
+                _ => {
+                    let error = Box::new(
+                        IllegalArgumentError::new(e.to_string().as_str())
+                    );
+                    Err(error)
+                }
+            }
+        }
+    }
+}
+
+/// Parses a `topics` entry, which is either a bare topic name or a `{name, qos}` table.
+fn parse_topic_config(value: Value) -> Result<TopicConfig, Box<dyn Error>> {
+    let mut topic_table = match value.clone().into_table() {
+        Ok(topic_table) => topic_table,
+        Err(_) => {
+            return match value.into_string() {
+                Ok(name) => Ok(TopicConfig { name, qos: None }),
+                Err(e) => Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            };
+        }
+    };
+    let name = match topic_table.remove(TOPIC_NAME_KEY) {
+        Some(name) => {
+            match name.into_string() {
+                Ok(name) => name,
+                Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            }
+        }
+        None => return Err(Box::new(IllegalArgumentError::new("Topic entries must have a name")))
+    };
+    let qos = match topic_table.remove(TOPIC_QOS_KEY) {
+        Some(qos) => {
+            match qos.into_int() {
+                Ok(qos) => Some(validate_qos(qos)?),
+                Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            }
+        }
+        None => None
+    };
+    Ok(TopicConfig { name, qos })
+}
+
+/// Parses the `quiet_hours` table of `HH:MM` start and end times and an optional `+HH:MM` offset from UTC, which
+/// defaults to UTC itself.
+fn parse_quiet_hours(value: Value) -> Result<QuietHours, Box<dyn Error>> {
+    let mut quiet_hours_table = match value.into_table() {
+        Ok(quiet_hours_table) => quiet_hours_table,
+        Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+    };
+    let mut take_str = |key: &str| -> Result<Option<String>, Box<dyn Error>> {
+        match quiet_hours_table.remove(key).map(|value| value.into_string()) {
+            Some(Ok(value)) => Ok(Some(value)),
+            Some(Err(e)) => Err(Box::new(IllegalArgumentError::new(e.to_string().as_str()))),
+            None => Ok(None)
+        }
+    };
+    let (start, end) = match (take_str(QUIET_HOURS_START_KEY)?, take_str(QUIET_HOURS_END_KEY)?) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(Box::new(IllegalArgumentError::new("Quiet hours must have a start and an end")))
+    };
+    let mut times = Vec::with_capacity(2);
+    for time in [&start, &end] {
+        match parse_time_of_day(time.as_str()) {
+            Some(minutes) => times.push(minutes),
+            None => return Err(Box::new(IllegalArgumentError::new(
+                format!("Unexpected quiet hours time '{}'; expected HH:MM", time).as_str()
+            )))
+        }
+    }
+    if times[0] == times[1] {
+        return Err(Box::new(IllegalArgumentError::new("Quiet hours must not start and end at the same time")));
+    }
+    let utc_offset_minutes = match take_str(QUIET_HOURS_UTC_OFFSET_KEY)? {
+        Some(utc_offset) => match parse_utc_offset(utc_offset.as_str()) {
+            Some(utc_offset_minutes) => utc_offset_minutes,
+            None => return Err(Box::new(IllegalArgumentError::new(
+                format!("Unexpected UTC offset '{}'; expected +HH:MM or -HH:MM", utc_offset).as_str()
+            )))
+        },
+        None => 0
+    };
+    Ok(QuietHours::new(times[0], times[1], utc_offset_minutes))
+}
+
+/// Parses a `free_space_alerts` entry, a table of a mount point and the free space below which it is alerted on.
+/// Mount points are given as values rather than keys as keys are case-folded and split on dots.
+fn parse_free_space_alert(value: Value) -> Result<(String, u64), Box<dyn Error>> {
+    let mut free_space_alert_table = match value.into_table() {
+        Ok(free_space_alert_table) => free_space_alert_table,
+        Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+    };
+    let mount_point = match free_space_alert_table.remove(FREE_SPACE_ALERT_MOUNT_POINT_KEY) {
+        Some(mount_point) => {
+            match mount_point.into_string() {
+                Ok(mount_point) => mount_point,
+                Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            }
+        }
+        None => return Err(Box::new(IllegalArgumentError::new("Free space alert entries must have a mount_point")))
+    };
+    let free_space_alert_bytes = match free_space_alert_table.remove(FREE_SPACE_ALERT_BYTES_KEY) {
+        Some(free_space_alert_bytes) => {
+            match free_space_alert_bytes.into_int() {
+                Ok(free_space_alert_bytes) if free_space_alert_bytes > 0 => free_space_alert_bytes as u64,
+                Ok(_) => return Err(Box::new(IllegalArgumentError::new(
+                    format!("Free space alert for mount '{}' must be greater than 0 bytes", mount_point).as_str()
+                ))),
+                Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            }
+        }
+        None => return Err(Box::new(IllegalArgumentError::new(
+            format!("Free space alert for mount '{}' must have a free_space_alert_bytes", mount_point).as_str()
+        )))
+    };
+    Ok((mount_point, free_space_alert_bytes))
+}
+
+/// Parses a `destinations` entry, a table whose `type` selects which other settings it needs.
+fn parse_destination_config(value: Value) -> Result<DestinationConfig, Box<dyn Error>> {
+    let mut destination_table = match value.into_table() {
+        Ok(destination_table) => destination_table,
+        Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+    };
+    let destination_type = match destination_table.remove(DESTINATION_TYPE_KEY) {
+        Some(destination_type) => {
+            match destination_type.into_string() {
+                Ok(destination_type) => destination_type,
+                Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            }
+        }
+        None => return Err(Box::new(IllegalArgumentError::new("Destination entries must have a type")))
+    };
+    let compress = match destination_table.remove(DESTINATION_COMPRESS_KEY) {
+        Some(compress) => {
+            match compress.into_bool() {
+                Ok(compress) => compress,
+                Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+            }
+        }
+        None => true
+    };
+    match destination_type.as_str() {
+        MQTT_DESTINATION_TYPE => Ok(DestinationConfig::Mqtt { compress }),
+        HTTP_DESTINATION_TYPE => {
+            let url = match destination_table.remove(DESTINATION_URL_KEY) {
+                Some(url) => {
+                    match url.into_string() {
+                        Ok(url) => url,
+                        Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+                    }
+                }
+                None => return Err(Box::new(IllegalArgumentError::new("HTTP destinations must have a url")))
+            };
+            let headers = match destination_table.remove(DESTINATION_HEADERS_KEY) {
+                Some(headers) => parse_headers(headers)?,
+                None => BTreeMap::new()
+            };
+            http_destination_config(url, headers, compress)
+        }
+        _ => Err(Box::new(
+            IllegalArgumentError::new(format!("Unexpected destination type '{}'", destination_type).as_str())
+        ))
+    }
+}
+
+/// Builds an HTTP destination, rejecting an unsupported URL or malformed header at startup rather than on the
+/// first report.
+fn http_destination_config(
+    url: String,
+    headers: BTreeMap<String, String>,
+    compress: bool
+) -> Result<DestinationConfig, Box<dyn Error>> {
+    HttpDestination::new(url.as_str(), headers.clone())?;
+    Ok(DestinationConfig::Http { url, headers, compress })
+}
+
+/// Parses a table of HTTP header names to values.
+fn parse_headers(value: Value) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let header_table = match value.into_table() {
+        Ok(header_table) => header_table,
+        Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+    };
+    let mut headers = BTreeMap::new();
+    for (name, value) in header_table {
+        match value.into_string() {
+            Ok(value) => {
+                headers.insert(name, value);
+            }
+            Err(e) => return Err(Box::new(IllegalArgumentError::new(e.to_string().as_str())))
+        }
+    }
+    Ok(headers)
+}
+
+/// Applies the command-line overrides to a loaded configuration, validating them as if they had come from the file.
+pub fn apply_overrides(runner_config: &mut RunnerConfig, overrides: &ConfigOverrides) -> Result<(), Box<dyn Error>> {
+    if let Some(check_interval) = &overrides.check_interval {
+        // A value that is not a whole number is rejected as out of range
+        runner_config.check_interval = validate_check_interval(check_interval.parse().unwrap_or_default())?;
+        runner_config.check_interval_defaulted = false;
+        if let Some(heartbeat_interval) = runner_config.heartbeat_interval_secs {
+            validate_heartbeat_interval(heartbeat_interval, runner_config.check_interval)?;
+        }
+    }
+    if let Some(topic) = &overrides.topic {
+        // The topics list takes the place of the topic, so overriding the topic would have no effect
+        if !runner_config.topics.is_empty() {
+            let error = Box::new(IllegalArgumentError::new("A topic override cannot be used when topics are configured"));
+            return Err(error);
+        }
+        let topic = format!("{}{}{}", runner_config.topic_prefix, topic, runner_config.topic_suffix);
+        runner_config.topic = render_topic(topic.as_str(), runner_config.device_id.as_str())?;
+        validate_topic(runner_config.topic.as_str())?;
+    }
+    Ok(())
+}
+
+/// Parses and validates a configuration update, a JSON object with any of `check_interval`, `topic` and `thresholds`
+/// given as they are in the configuration file. The heartbeat interval and alert topic that the running configuration
+/// has are needed to validate the interval and thresholds against them.
+pub fn parse_config_update(
+    payload: &str,
+    device_id: &str,
+    heartbeat_interval_secs: Option<u64>,
+    has_alert_topic: bool
+) -> Result<ConfigUpdate, Box<dyn Error>> {
+    let config_update_payload: ConfigUpdatePayload = match serde_json::from_str(payload) {
+        Ok(config_update_payload) => config_update_payload,
+        Err(e) => {
+            let error = Box::new(IllegalArgumentError::new(format!("Malformed configuration update: {}", e).as_str()));
+            return Err(error);
+        }
+    };
+    let mut config_update = ConfigUpdate::default();
+    if let Some(check_interval) = config_update_payload.check_interval {
+        let check_interval = validate_check_interval(check_interval)?;
+        if let Some(heartbeat_interval) = heartbeat_interval_secs {
+            validate_heartbeat_interval(heartbeat_interval, check_interval)?;
+        }
+        config_update.check_interval = Some(check_interval);
+    }
+    if let Some(topic) = config_update_payload.topic {
+        let topic = render_topic(topic.as_str(), device_id)?;
+        validate_topic(topic.as_str())?;
+        config_update.topic = Some(topic);
+    }
+    if let Some(thresholds) = config_update_payload.thresholds {
+        let thresholds = validate_thresholds(thresholds)?;
+        if !thresholds.is_empty() && !has_alert_topic {
+            let error = Box::new(IllegalArgumentError::new("Thresholds require an alert topic"));
+            return Err(error);
+        }
+        config_update.thresholds = Some(thresholds);
+    }
+    Ok(config_update)
+}
+
+fn validate_thresholds(thresholds: HashMap<String, f64>) -> Result<HashMap<&'static str, f64>, Box<dyn Error>> {
+    let mut validated_thresholds = HashMap::new();
+    for (section, threshold) in thresholds {
+        let report_section = match REPORT_SECTIONS.iter().find(|s| **s == section) {
+            Some(report_section) => *report_section,
+            None => {
+                let error = Box::new(
+                    IllegalArgumentError::new(format!("Unexpected report section '{}'", section).as_str())
+                );
+                return Err(error);
+            }
+        };
+        if !(0.0..=MAXIMUM_THRESHOLD).contains(&threshold) {
+            let error = Box::new(
+                IllegalArgumentError::new(format!("Threshold for section '{}' must be between 0 and 100 percent", section).as_str())
+            );
+            return Err(error);
+        }
+        validated_thresholds.insert(report_section, threshold);
+    }
+    Ok(validated_thresholds)
+}
+
+fn validate_check_interval(check_interval: u64) -> Result<u64, IllegalArgumentError> {
+    if (MINIMUM_CHECK_INTERVAL..=MAXIMUM_CHECK_INTERVAL).contains(&check_interval) {
+        Ok(check_interval)
+    } else {
+        Err(IllegalArgumentError::new(
+            format!("Check interval must be between {} and {}", MINIMUM_CHECK_INTERVAL, MAXIMUM_CHECK_INTERVAL).as_str()
+        ))
+    }
+}
+
+/// Heartbeats must be sent more frequently than full reports.
+fn validate_heartbeat_interval(heartbeat_interval: u64, check_interval: u64) -> Result<u64, IllegalArgumentError> {
+    let maximum_heartbeat_interval = check_interval * MINUTES_MULTIPLIER - 1;
+    if (MINIMUM_HEARTBEAT_INTERVAL..=maximum_heartbeat_interval).contains(&heartbeat_interval) {
+        Ok(heartbeat_interval)
+    } else {
+        Err(IllegalArgumentError::new(
+            format!(
+                "Heartbeat interval must be between {} and {} seconds",
+                MINIMUM_HEARTBEAT_INTERVAL,
+                maximum_heartbeat_interval
+            ).as_str()
+        ))
+    }
+}
+
+fn validate_qos(qos: i64) -> Result<i32, IllegalArgumentError> {
+    if (MINIMUM_QOS..=MAXIMUM_QOS).contains(&qos) {
+        Ok(qos as i32)
+    } else {
+        Err(IllegalArgumentError::new(
+            format!("QoS must be between {} and {}", MINIMUM_QOS, MAXIMUM_QOS).as_str()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use paho_mqtt::SslVersion;
+    use pretty_assertions::assert_eq;
+    use pretty_assertions::assert_ne;
+
+    use crate::lib::collector::{CounterReset, MEMORY_SECTION};
+    use crate::lib::quiet_hours::QuietHours;
+    use crate::lib::common::{IllegalArgumentError, RuntimeError, RuntimeMode};
+    use crate::lib::compression::Codec;
+    use crate::lib::message_id::MessageIdFormat;
+    use crate::lib::report::{FrequencyUnit, KeyCase, Location, ReportFormat, TemperatureUnit};
+    use crate::lib::spool::SpoolFullStrategy;
+    use crate::lib::config::{apply_overrides, ConfigOverrides, ConfigUpdate, DestinationConfig, MqttPersistence, parse_config_update, TopicConfig, DEFAULT_CHECK_INTERVAL, DEFAULT_CONNECTION_CHECK, load_config, DEFAULT_SERVER_ADDRESS, DEFAULT_TOPIC, DEFAULT_USER_NAME, DEFAULT_USER_PASSWORD, DEFAULT_HEARTBEAT_TOPIC, load_layered_config};
+
+    #[test]
+    fn load_default_config() {
+        let result = load_config(None).unwrap();
+        assert_ne!("", result.device_id);
+        assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
+        assert_eq!(DEFAULT_USER_NAME, result.user_name);
+        assert_eq!(DEFAULT_USER_PASSWORD, result.user_password);
+        assert!(!result.insecure_skip_verify);
+        assert_eq!(None, result.min_tls_version);
+        assert_eq!(None, result.ca_file);
+        assert_eq!(None, result.client_cert_file);
+        assert_eq!(None, result.client_key_file);
+        assert!(result.alpn_protocols.is_empty());
+        assert_eq!(None, result.mqtt_version);
+        assert_eq!(DEFAULT_TOPIC, result.topic);
+        assert_eq!(RuntimeMode::Single, result.runtime_mode);
+        assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
+        assert!(!result.check_interval_defaulted);
+        assert!(!result.require_explicit_interval);
+        assert_eq!(None, result.heartbeat_interval_secs);
+        assert_eq!(DEFAULT_HEARTBEAT_TOPIC, result.heartbeat_topic);
+        assert_eq!(Codec::Lz4, result.compression);
+        assert_eq!(None, result.compression_level);
+        assert_eq!(None, result.compression_min_bytes);
+        assert!(!result.compression_dictionary);
+        assert_eq!(None, result.pad_to_bytes);
+        assert_eq!(None, result.max_message_bytes);
+        assert_eq!(None, result.hmac_secret);
+        assert_eq!(None, result.hmac_key_id);
+        assert_eq!(0, result.qos);
+        assert!(result.topics.is_empty());
+        assert_eq!("", result.topic_prefix);
+        assert_eq!("", result.topic_suffix);
+        assert!(!result.probe_on_start);
+        assert!(!result.verbose);
+        assert!(!result.include_disk_io_busy);
+        assert!(!result.include_disk_read_only);
+        assert!(!result.include_disk_temperature);
+        assert_eq!(None, result.disk_near_full_pct);
+        assert!(!result.read_only_alert);
+        assert!(!result.include_file_descriptors);
+        assert!(!result.include_memory_breakdown);
+        assert!(!result.include_swap_rates);
+        assert!(!result.include_agent_process);
+        assert!(!result.include_process_counts);
+        assert_eq!(None, result.custom_command);
+        assert_eq!(FrequencyUnit::MHz, result.frequency_unit);
+        assert!(!result.include_temperatures);
+        assert!(!result.include_core_temperatures);
+        assert!(!result.include_power);
+        assert!(!result.include_network_addresses);
+        assert!(!result.debug_raw);
+        assert_eq!(TemperatureUnit::Celsius, result.temperature_unit);
+        assert_eq!(None, result.message_expiry_secs);
+        assert!(!result.split_topics);
+        assert_eq!(None, result.disconnect_timeout_ms);
+        assert_eq!(None, result.shutdown_grace_secs);
+        assert_eq!(30, result.publish_ack_timeout_secs);
+        assert_eq!(None, result.check_deadline_secs);
+        assert_eq!(3, result.sections.len());
+        assert_eq!(None, result.disk_mount_allowlist);
+        assert_eq!(0, result.min_disk_capacity_bytes);
+        assert!(!result.dedup_disks);
+        assert_eq!(None, result.monthly_byte_budget);
+        assert!(!result.cellular_throttle);
+        assert_eq!(4, result.cellular_interval_multiplier);
+        assert_eq!(None, result.min_publish_interval_ms);
+        assert_eq!(None, result.quiet_hours);
+        assert_eq!(None, result.budget_state_file);
+        assert_eq!(None, result.compression_stats_file);
+        assert_eq!(None, result.state_file);
+        assert_eq!(None, result.cpu_sample_window_ms);
+        assert_eq!(None, result.frequency_sample_window_ms);
+        assert!(!result.container_aware);
+        assert_eq!(1, result.collection_threads);
+        assert!(result.change_thresholds.is_empty());
+        assert!(result.thresholds.is_empty());
+        assert!(result.free_space_alerts.is_empty());
+        assert_eq!(None, result.alert_topic);
+        assert_eq!(None, result.diagnostic_topic);
+        assert_eq!(60, result.diagnostic_min_interval_secs);
+        assert_eq!(10, result.threshold_check_secs);
+        assert!(!result.load_adaptive);
+        assert_eq!(90.0, result.load_adaptive_threshold);
+        assert!(!result.idle_skip);
+        assert_eq!(5.0, result.idle_cpu_threshold);
+        assert_eq!(1024, result.idle_network_threshold_bps);
+        assert_eq!(KeyCase::Camel, result.key_case);
+        assert_eq!(CounterReset::Omit, result.counter_reset);
+        assert_eq!(MessageIdFormat::UuidV4, result.message_id_format);
+        assert_eq!(ReportFormat::Json, result.format);
+        assert!(result.tags.is_empty());
+        assert_eq!(None, result.location);
+        assert!(!result.flatten);
+        assert!(!result.dual_publish);
+        assert_eq!(None, result.snapshot_topic);
+        assert_eq!(None, result.event_topic);
+        assert_eq!(None, result.crash_topic);
+        assert!(!result.send_startup_report);
+        assert_eq!(None, result.startup_topic);
+        assert!(!result.keep_connected);
+        assert_eq!(None, result.history_size);
+        assert_eq!(None, result.command_topic);
+        assert_eq!(None, result.config_topic);
+        assert_eq!(None, result.history_topic);
+        assert_eq!(DEFAULT_CONNECTION_CHECK, result.connection_check_secs);
+        assert_eq!(0, result.publish_retries);
+        assert_eq!(None, result.spool_dir);
+        assert_eq!(None, result.spool_max_age_secs);
+        assert_eq!(None, result.spool_max_bytes);
+        assert_eq!(SpoolFullStrategy::DropOldest, result.spool_full_strategy);
+        assert!(result.clean_session);
+        assert!(!result.duplicate_id_check);
+        assert_eq!(MqttPersistence::Default, result.persistence);
+        assert_eq!(None, result.persistence_dir);
+        assert_eq!(vec![DestinationConfig::Mqtt { compress: true }], result.destinations);
+    }
+
+    #[test]
+    fn load_bare_single_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/bare_single.yaml"))
+        ).unwrap();
+        assert_ne!("", result.device_id);
+        assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
+        assert_eq!(DEFAULT_USER_NAME, result.user_name);
+        assert_eq!(DEFAULT_USER_PASSWORD, result.user_password);
+        assert_eq!(DEFAULT_TOPIC, result.topic);
+        assert_eq!(RuntimeMode::Single, result.runtime_mode);
+    }
+
+    #[test]
+    fn load_bare_continuous_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/bare_continuous.yaml"))
+        ).unwrap();
+        assert_ne!("", result.device_id);
+        assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
+        assert_eq!(DEFAULT_USER_NAME, result.user_name);
+        assert_eq!(DEFAULT_USER_PASSWORD, result.user_password);
+        assert_eq!(DEFAULT_TOPIC, result.topic);
+        assert_eq!(RuntimeMode::Continuous, result.runtime_mode);
+        assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
+        assert!(result.check_interval_defaulted);
+    }
+
+    #[test]
+    fn load_full_single_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/full_single.yaml"))
+        ).unwrap();
+        assert_eq!("Test Device Name", result.device_id);
+        assert_eq!("tcp://test.server.address:1883", result.server_address);
+        assert_eq!("TestUser", result.user_name);
+        assert_eq!("TestPassword", result.user_password);
+        assert_eq!("Test Topic", result.topic);
+        assert_eq!(RuntimeMode::Single, result.runtime_mode);
+        assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
+    }
+
+    #[test]
+    fn layer_environment_over_config_file() {
+        // The prefix is unique to this test, as tests share the process environment
+        std::env::set_var("DSR_LAYERING_TEST_TOPIC", "Environment Topic");
+        std::env::set_var("DSR_LAYERING_TEST_CHECK_INTERVAL", "10");
+        let result = load_layered_config(
+            Some(&String::from("resources/test/good/full_continuous.yaml")), "DSR_LAYERING_TEST"
+        );
+        let environment_only = load_layered_config(None, "DSR_LAYERING_TEST");
+        std::env::remove_var("DSR_LAYERING_TEST_TOPIC");
+        std::env::remove_var("DSR_LAYERING_TEST_CHECK_INTERVAL");
+        // The environment wins for the keys it sets, and the file still supplies the rest
+        let result = result.unwrap();
+        assert_eq!("Environment Topic", result.topic);
+        assert_eq!(10, result.check_interval);
+        assert_eq!("TestUser", result.user_name);
+        assert_eq!(RuntimeMode::Continuous, result.runtime_mode);
+        // Without a file, the environment is layered over the defaults
+        let environment_only = environment_only.unwrap();
+        assert_eq!("Environment Topic", environment_only.topic);
+        assert_eq!(DEFAULT_USER_NAME, environment_only.user_name);
+    }
+
+    #[test]
+    fn load_full_continuous_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/full_continuous.yaml"))
+        ).unwrap();
+        assert_eq!("Test Device Name", result.device_id);
+        assert_eq!("tcp://test.server.address:1883", result.server_address);
+        assert_eq!("TestUser", result.user_name);
+        assert_eq!("TestPassword", result.user_password);
+        assert_eq!("Test Topic", result.topic);
+        assert_eq!(RuntimeMode::Continuous, result.runtime_mode);
+        assert_eq!(5, result.check_interval);
+        assert!(!result.check_interval_defaulted);
+    }
+
+    #[test]
+    fn load_continuous_config_without_explicit_interval() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/continuous_without_explicit_interval.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Continuous mode requires an explicit check_interval",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_continuous_config_with_required_interval() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/required_interval.yaml"))
+        ).unwrap();
+        assert!(result.require_explicit_interval);
+        assert_eq!(10, result.check_interval);
+        assert!(!result.check_interval_defaulted);
+    }
+
+    #[test]
+    fn clear_defaulted_interval_on_override() {
+        let mut runner_config = load_config(
+            Some(&String::from("resources/test/good/bare_continuous.yaml"))
+        ).unwrap();
+        let overrides = ConfigOverrides { check_interval: Some(String::from("15")), topic: None };
+        apply_overrides(&mut runner_config, &overrides).unwrap();
+        assert_eq!(15, runner_config.check_interval);
+        assert!(!runner_config.check_interval_defaulted);
+    }
+
+    #[test]
+    fn reject_out_of_range_interval_override() {
+        let mut runner_config = load_config(None).unwrap();
+        for check_interval in ["0", "241", "five"] {
+            let overrides = ConfigOverrides { check_interval: Some(String::from(check_interval)), topic: None };
+            let result = apply_overrides(&mut runner_config, &overrides).err().unwrap();
+            assert_eq!("An illegal argument was encountered. Reason: Check interval must be between 1 and 240", result.to_string());
+        }
+        assert_eq!(DEFAULT_CHECK_INTERVAL, runner_config.check_interval);
+    }
+
+    #[test]
+    fn reject_interval_override_below_heartbeat_interval() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.check_interval = 5;
+        runner_config.heartbeat_interval_secs = Some(120);
+        let overrides = ConfigOverrides { check_interval: Some(String::from("1")), topic: None };
+        let result = apply_overrides(&mut runner_config, &overrides).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Heartbeat interval must be between 1 and 59 seconds", result.to_string());
+    }
+
+    #[test]
+    fn render_topic_override() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.device_id = String::from("device-1");
+        let overrides = ConfigOverrides { check_interval: None, topic: Some(String::from("devices/{device_id}/stats")) };
+        apply_overrides(&mut runner_config, &overrides).unwrap();
+        assert_eq!("devices/device-1/stats", runner_config.topic);
+    }
+
+    #[test]
+    fn apply_topic_prefix_to_topic_override() {
+        let mut runner_config = load_config(
+            Some(&String::from("resources/test/good/topic_prefix.yaml"))
+        ).unwrap();
+        let overrides = ConfigOverrides { check_interval: None, topic: Some(String::from("Override_Status")) };
+        apply_overrides(&mut runner_config, &overrides).unwrap();
+        assert_eq!("staging/Override_Status", runner_config.topic);
+    }
+
+    #[test]
+    fn reject_topic_override_with_topics() {
+        let mut runner_config = load_config(
+            Some(&String::from("resources/test/good/topic_prefix_suffix.yaml"))
+        ).unwrap();
+        let overrides = ConfigOverrides { check_interval: None, topic: Some(String::from("devices/{device_id}")) };
+        let result = apply_overrides(&mut runner_config, &overrides).err().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: A topic override cannot be used when topics are configured",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_config_device_id_source() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/config_device_id_source.yaml"))
+        ).unwrap();
+        assert_eq!("Test Device Name", result.device_id);
+    }
+
+    #[test]
+    fn load_uuid_device_id_source() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/uuid_device_id_source.yaml"))
+        ).unwrap();
+        assert_ne!("Test Device Name", result.device_id);
+        assert_eq!(36, result.device_id.len());
+    }
+
+    #[test]
+    fn load_unrecognized_device_id_source() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_device_id_source.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected device ID source 'serial_number'", result.to_string());
+    }
+
+    #[test]
+    fn load_templated_topic_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/templated_topic.yaml"))
+        ).unwrap();
+        assert_eq!("Test Device #1", result.device_id);
+        assert_eq!("devices/Test%20Device%20%231/status", result.topic);
+    }
+
+    #[test]
+    fn load_topic_prefix_and_suffix_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/topic_prefix_suffix.yaml"))
+        ).unwrap();
+        assert_eq!("prod/devices/Test%20Device%20Name/stats", result.topic);
+        assert_eq!(vec![TopicConfig { name: String::from("prod/aggregate/stats"), qos: None }], result.topics);
+        assert_eq!(DEFAULT_HEARTBEAT_TOPIC, result.heartbeat_topic);
+    }
+
+    #[test]
+    fn load_topic_prefix_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/topic_prefix.yaml"))
+        ).unwrap();
+        assert_eq!("staging/Device_Status", result.topic);
+    }
+
+    #[test]
+    fn load_blank_topic() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/blank_topic.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Topic must not be empty", result.to_string());
+        // Leaving the key out still falls back to the default topic
+        let result = load_config(
+            Some(&String::from("resources/test/good/frequency_unit.yaml"))
+        ).unwrap();
+        assert_eq!(DEFAULT_TOPIC, result.topic);
+    }
+
+    #[test]
+    fn load_topic_suffix_with_null_character() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/null_character_topic_suffix.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Topic 'Device_Status/\\u{0}' contains a null character", result.to_string());
+    }
+
+    #[test]
+    fn load_crash_topic_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/crash_topic.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/crash")), result.crash_topic);
+    }
+
+    #[test]
+    fn load_startup_report_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/startup_report.yaml"))
+        ).unwrap();
+        assert!(result.send_startup_report);
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/startup")), result.startup_topic);
+    }
+
+    #[test]
+    fn load_startup_report_without_topic() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/startup_report_without_topic.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: A startup report requires a startup topic", result.to_string());
+    }
+
+    #[test]
+    fn load_destinations_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/destinations.yaml"))
+        ).unwrap();
+        assert_eq!(
+            vec![
+                DestinationConfig::Mqtt { compress: true },
+                DestinationConfig::Http {
+                    url: String::from("http://collector.local:8080/reports"),
+                    headers: BTreeMap::new(),
+                    compress: true,
+                },
+            ],
+            result.destinations
+        );
+    }
+
+    #[test]
+    fn load_uncompressed_destination_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/uncompressed_destination.yaml"))
+        ).unwrap();
+        assert_eq!(
+            vec![
+                DestinationConfig::Mqtt { compress: true },
+                DestinationConfig::Http {
+                    url: String::from("http://collector.local:8080/reports"),
+                    headers: BTreeMap::new(),
+                    compress: false,
+                },
+            ],
+            result.destinations
+        );
+    }
+
+    #[test]
+    fn load_webhook_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/webhook.yaml"))
+        ).unwrap();
+        let mut headers = BTreeMap::new();
+        headers.insert(String::from("Authorization"), String::from("Bearer token-1"));
+        assert_eq!(
+            vec![
+                DestinationConfig::Mqtt { compress: true },
+                DestinationConfig::Http {
+                    url: String::from("http://collector.local:8080/hooks/stats"),
+                    headers,
+                    compress: true,
+                },
+            ],
+            result.destinations
+        );
+    }
+
+    #[test]
+    fn load_duplicate_id_check_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/duplicate_id_check.yaml"))
+        ).unwrap();
+        assert!(!result.clean_session);
+        assert!(result.duplicate_id_check);
+    }
+
+    #[test]
+    fn load_file_persistence_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/file_persistence.yaml"))
+        ).unwrap();
+        assert_eq!(MqttPersistence::File, result.persistence);
+        assert_eq!(Some(String::from("/var/lib/device_stats_reporter/mqtt")), result.persistence_dir);
+    }
+
+    #[test]
+    fn load_no_persistence_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/no_persistence.yaml"))
+        ).unwrap();
+        assert_eq!(MqttPersistence::None, result.persistence);
+        assert_eq!(None, result.persistence_dir);
+    }
+
+    #[test]
+    fn load_temperature_unit_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/temperature_unit.yaml"))
+        ).unwrap();
+        assert!(result.include_temperatures);
+        assert_eq!(TemperatureUnit::Fahrenheit, result.temperature_unit);
+    }
+
+    #[test]
+    fn load_core_temperatures_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/core_temperatures.yaml"))
+        ).unwrap();
+        assert!(result.include_core_temperatures);
+        assert!(!result.include_temperatures);
+    }
+
+    #[test]
+    fn load_disk_mount_allowlist_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/disk_mount_allowlist.yaml"))
+        ).unwrap();
+        assert_eq!(Some(vec![String::from("/"), String::from("/data")]), result.disk_mount_allowlist);
+    }
+
+    #[test]
+    fn load_min_disk_capacity_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/min_disk_capacity.yaml"))
+        ).unwrap();
+        assert_eq!(1048576, result.min_disk_capacity_bytes);
+    }
+
+    #[test]
+    fn load_dedup_disks_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/dedup_disks.yaml"))
+        ).unwrap();
+        assert!(result.dedup_disks);
+    }
+
+    #[test]
+    fn load_publish_retries_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/publish_retries.yaml"))
+        ).unwrap();
+        assert_eq!(3, result.publish_retries);
+    }
+
+    #[test]
+    fn load_spool_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/spool.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("/var/spool/device_stats_reporter")), result.spool_dir);
+        assert_eq!(Some(900), result.spool_max_age_secs);
+        assert_eq!(Some(1_048_576), result.spool_max_bytes);
+        assert_eq!(SpoolFullStrategy::Block, result.spool_full_strategy);
+    }
+
+    #[test]
+    fn load_disk_temperature_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/disk_temperature.yaml"))
+        ).unwrap();
+        assert!(result.include_disk_temperature);
+    }
+
+    #[test]
+    fn load_disk_near_full_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/disk_near_full.yaml"))
+        ).unwrap();
+        assert_eq!(Some(92.5), result.disk_near_full_pct);
+    }
+
+    #[test]
+    fn load_power_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/power.yaml"))
+        ).unwrap();
+        assert!(result.include_power);
+    }
+
+    #[test]
+    fn load_network_addresses_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/network_addresses.yaml"))
+        ).unwrap();
+        assert!(result.include_network_addresses);
+    }
+
+    #[test]
+    fn load_agent_process_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/agent_process.yaml"))
+        ).unwrap();
+        assert!(result.include_agent_process);
+    }
+
+    #[test]
+    fn load_process_counts_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/process_counts.yaml"))
+        ).unwrap();
+        assert!(result.include_process_counts);
+    }
+
+    #[test]
+    fn load_custom_command_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/custom_command.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("/usr/local/bin/queue-metrics --json")), result.custom_command);
+    }
+
+    #[test]
+    fn load_empty_custom_command() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/empty_custom_command.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Custom command must not be empty", result.to_string());
+    }
+
+    #[test]
+    fn load_memory_breakdown_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/memory_breakdown.yaml"))
+        ).unwrap();
+        assert!(result.include_memory_breakdown);
+    }
+
+    #[test]
+    fn load_swap_rates_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/swap_rates.yaml"))
+        ).unwrap();
+        assert!(result.include_swap_rates);
+    }
+
+    #[test]
+    fn load_debug_raw_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/debug_raw.yaml"))
+        ).unwrap();
+        assert!(result.debug_raw);
+    }
+
+    #[test]
+    fn load_min_publish_interval_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/min_publish_interval.yaml"))
+        ).unwrap();
+        assert_eq!(Some(5000), result.min_publish_interval_ms);
+    }
+
+    #[test]
+    fn load_quiet_hours_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/quiet_hours.yaml"))
+        ).unwrap();
+        assert_eq!(Some(QuietHours::new(22 * 60, 6 * 60 + 30, 60)), result.quiet_hours);
+    }
+
+    #[test]
+    fn load_invalid_quiet_hours_time() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/invalid_quiet_hours_time.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected quiet hours time '25:00'; expected HH:MM", result.to_string());
+    }
+
+    #[test]
+    fn load_empty_quiet_hours() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/empty_quiet_hours.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Quiet hours must not start and end at the same time", result.to_string());
+    }
+
+    #[test]
+    fn load_heartbeat_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/heartbeat_continuous.yaml"))
+        ).unwrap();
+        assert_eq!(RuntimeMode::Continuous, result.runtime_mode);
+        assert_eq!(5, result.check_interval);
+        assert_eq!(Some(30), result.heartbeat_interval_secs);
+        assert_eq!("devices/Test%20Device%20Name/heartbeat", result.heartbeat_topic);
+    }
+
+    #[test]
+    fn load_too_high_heartbeat_interval() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_high_heartbeat_interval.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Heartbeat interval must be between 1 and 299 seconds", result.to_string());
+    }
+
+    #[test]
+    fn load_compression_codec_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/compression_codec.yaml"))
+        ).unwrap();
+        assert_eq!(Codec::Lz4Frame, result.compression);
+        assert_eq!(Some(512), result.compression_min_bytes);
+    }
+
+    #[test]
+    fn load_compression_level_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/compression_level.yaml"))
+        ).unwrap();
+        assert_eq!(Codec::Gzip, result.compression);
+        assert_eq!(Some(9), result.compression_level);
+    }
+
+    #[test]
+    fn load_out_of_range_compression_level() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_compression_level.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Compression level for codec 'gzip' must be between 0 and 9", result.to_string());
+    }
+
+    #[test]
+    fn load_unsupported_compression_level() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unsupported_compression_level.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Codec 'lz4' does not support a compression level", result.to_string());
+    }
+
+    #[test]
+    fn load_compression_dictionary_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/compression_dictionary.yaml"))
+        ).unwrap();
+        assert!(result.compression_dictionary);
+    }
+
+    #[test]
+    fn load_unsupported_compression_dictionary() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unsupported_compression_dictionary.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Codec 'lz4_frame' does not support a compression dictionary", result.to_string());
+    }
+
+    #[test]
+    fn load_pad_to_bytes_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/pad_to_bytes.yaml"))
+        ).unwrap();
+        assert_eq!(Some(512), result.pad_to_bytes);
+    }
+
+    #[test]
+    fn load_zero_pad_to_bytes() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/zero_pad_to_bytes.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Padding size must be greater than 0", result.to_string());
+    }
+
+    #[test]
+    fn load_max_message_bytes_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/max_message_bytes.yaml"))
+        ).unwrap();
+        assert_eq!(Some(4096), result.max_message_bytes);
+    }
+
+    #[test]
+    fn load_out_of_range_max_message_bytes() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_max_message_bytes.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Maximum message size must be between 1024 and 268435455 bytes",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_min_tls_version_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/min_tls_version.yaml"))
+        ).unwrap();
+        assert_eq!(Some(SslVersion::Tls_1_2), result.min_tls_version);
+    }
+
+    #[test]
+    fn load_unknown_min_tls_version() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unknown_min_tls_version.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected minimum TLS version '1.3'; supported versions are 1.0, 1.1 and 1.2", result.to_string());
+    }
+
+    #[test]
+    fn load_aws_iot_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/aws_iot.yaml"))
+        ).unwrap();
+        assert_eq!("ssl://a1b2c3d4e5f6g7-ats.iot.eu-west-1.amazonaws.com:8883", result.server_address);
+        assert_eq!(Some(SslVersion::Tls_1_2), result.min_tls_version);
+        assert_eq!(Some(paho_mqtt::MQTT_VERSION_3_1_1), result.mqtt_version);
+        assert!(result.alpn_protocols.is_empty());
+        assert_eq!(Some(String::from("resources/test/certs/device.pem.crt")), result.client_cert_file);
+        assert_eq!(Some(String::from("resources/test/certs/device.pem.key")), result.client_key_file);
+        assert_eq!(None, result.ca_file);
+    }
+
+    #[test]
+    fn load_aws_iot_https_port_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/aws_iot_https_port.yaml"))
+        ).unwrap();
+        assert_eq!("ssl://a1b2c3d4e5f6g7-ats.iot.eu-west-1.amazonaws.com:443", result.server_address);
+        assert_eq!(vec![String::from("x-amzn-mqtt-ca")], result.alpn_protocols);
+    }
+
+    #[test]
+    fn load_aws_iot_without_client_key() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/aws_iot_without_client_key.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: The AWS IoT preset requires a client_cert_file and a client_key_file",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_aws_iot_unknown_port() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/aws_iot_unknown_port.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected AWS IoT port 1883; supported ports are 8883 and 443",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_missing_client_cert_file() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/missing_client_cert_file.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Certificate file 'resources/test/certs/missing.pem.crt' does not exist",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_hmac_signing_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/hmac_signing.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("TestSigningSecret")), result.hmac_secret);
+        assert_eq!(Some(String::from("2024-06")), result.hmac_key_id);
+    }
+
+    #[test]
+    fn load_hmac_key_id_without_secret() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/hmac_key_id_without_secret.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: An HMAC key id requires an HMAC secret", result.to_string());
+    }
+
+    #[test]
+    fn load_empty_hmac_secret() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/empty_hmac_secret.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: HMAC secret cannot be empty", result.to_string());
+    }
+
+    #[test]
+    fn load_multi_topic_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/multi_topic.yaml"))
+        ).unwrap();
+        assert_eq!(1, result.qos);
+        assert_eq!(
+            vec![
+                TopicConfig { name: String::from("devices/Test%20Device%20Name/status"), qos: Some(1) },
+                TopicConfig { name: String::from("aggregate/status"), qos: Some(0) },
+                TopicConfig { name: String::from("archive/status"), qos: None },
+                TopicConfig { name: String::from("legacy/status"), qos: None },
+            ],
+            result.topics
+        );
+    }
+
+    #[test]
+    fn load_out_of_range_topic_qos() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_topic_qos.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: QoS must be between 0 and 2", result.to_string());
+    }
+
+    #[test]
+    fn load_probe_on_start_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/probe_on_start.yaml"))
+        ).unwrap();
+        assert!(result.probe_on_start);
+    }
+
+    #[test]
+    fn load_verbose_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/verbose.yaml"))
+        ).unwrap();
+        assert!(result.verbose);
+    }
+
+    #[test]
+    fn load_sections_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/sections.yaml"))
+        ).unwrap();
+        let mut sections: Vec<&str> = result.sections.into_iter().collect();
+        sections.sort_unstable();
+        assert_eq!(vec!["cpus", "memory"], sections);
+    }
+
+    #[test]
+    fn load_unrecognized_section() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_section.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected report section 'network'", result.to_string());
+    }
+
+    #[test]
+    fn load_byte_budget_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/byte_budget.yaml"))
+        ).unwrap();
+        assert_eq!(Some(50_000_000), result.monthly_byte_budget);
+        assert_eq!(Some(String::from("/var/lib/device_stats_reporter/budget")), result.budget_state_file);
+    }
+
+    #[test]
+    fn load_cellular_throttle_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/cellular_throttle.yaml"))
+        ).unwrap();
+        assert!(result.cellular_throttle);
+        assert_eq!(6, result.cellular_interval_multiplier);
+    }
+
+    #[test]
+    fn load_out_of_range_cellular_interval_multiplier() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_cellular_interval_multiplier.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Cellular interval multiplier must be between 1 and 100",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_compression_stats_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/compression_stats.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("/var/lib/device_stats_reporter/compression_stats")), result.compression_stats_file);
+    }
+
+    #[test]
+    fn load_state_file_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/state_file.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("/var/lib/device_stats_reporter/state")), result.state_file);
+    }
+
+    #[test]
+    fn load_zero_byte_budget() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/zero_byte_budget.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Monthly byte budget must be greater than 0", result.to_string());
+    }
+
+    #[test]
+    fn load_cpu_sample_window_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/cpu_sample_window.yaml"))
+        ).unwrap();
+        assert_eq!(Some(500), result.cpu_sample_window_ms);
+    }
+
+    #[test]
+    fn load_container_aware_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/container_aware.yaml"))
+        ).unwrap();
+        assert!(result.container_aware);
+    }
+
+    #[test]
+    fn load_frequency_sample_window_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/frequency_sample_window.yaml"))
+        ).unwrap();
+        assert_eq!(Some(400), result.frequency_sample_window_ms);
+    }
+
+    #[test]
+    fn load_too_long_frequency_sample_window() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_long_frequency_sample_window.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Frequency sample window must be between 100 and 10000 milliseconds",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_collection_threads_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/collection_threads.yaml"))
+        ).unwrap();
+        assert_eq!(4, result.collection_threads);
+    }
+
+    #[test]
+    fn load_out_of_range_collection_threads() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_collection_threads.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Collection threads must be between 1 and 4", result.to_string());
+    }
+
+    #[test]
+    fn load_too_short_cpu_sample_window() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_short_cpu_sample_window.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: CPU sample window must be between 100 and 10000 milliseconds", result.to_string());
+    }
+
+    #[test]
+    fn load_change_thresholds_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/change_thresholds.yaml"))
+        ).unwrap();
+        assert_eq!(2, result.change_thresholds.len());
+        assert_eq!(Some(&5.0), result.change_thresholds.get("cpus"));
+        assert_eq!(Some(&1048576.0), result.change_thresholds.get("memory"));
+    }
+
+    #[test]
+    fn load_negative_change_threshold() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/negative_change_threshold.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Change threshold for section 'cpus' must not be negative", result.to_string());
+    }
+
+    #[test]
+    fn load_thresholds_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/thresholds.yaml"))
+        ).unwrap();
+        assert_eq!(2, result.thresholds.len());
+        assert_eq!(Some(&90.0), result.thresholds.get("disks"));
+        assert_eq!(Some(&95.0), result.thresholds.get("memory"));
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/alerts")), result.alert_topic);
+        assert_eq!(5, result.threshold_check_secs);
+    }
+
+    #[test]
+    fn load_diagnostic_topic_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/diagnostic_topic.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/diagnostics")), result.diagnostic_topic);
+        assert_eq!(300, result.diagnostic_min_interval_secs);
+    }
+
+    #[test]
+    fn load_out_of_range_diagnostic_min_interval() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_diagnostic_min_interval.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Diagnostic minimum interval must be between 1 and 86400 seconds",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_load_adaptive_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/load_adaptive.yaml"))
+        ).unwrap();
+        assert!(result.load_adaptive);
+        assert_eq!(75.0, result.load_adaptive_threshold);
+    }
+
+    #[test]
+    fn load_idle_skip_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/idle_skip.yaml"))
+        ).unwrap();
+        assert!(result.idle_skip);
+        assert_eq!(2.5, result.idle_cpu_threshold);
+        assert_eq!(512, result.idle_network_threshold_bps);
+    }
+
+    #[test]
+    fn load_idle_skip_without_heartbeat() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/idle_skip_without_heartbeat.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Idle skipping requires a heartbeat interval", result.to_string());
+    }
+
+    #[test]
+    fn load_out_of_range_idle_cpu_threshold() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_idle_cpu_threshold.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Idle CPU threshold must be between 0 and 100 percent", result.to_string());
+    }
+
+    #[test]
+    fn load_out_of_range_disk_near_full_pct() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_disk_near_full_pct.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Disk near-full percentage must be between 0 and 100 percent",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_out_of_range_load_adaptive_threshold() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_load_adaptive_threshold.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Load adaptive threshold must be between 0 and 100 percent",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_thresholds_without_alert_topic() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/thresholds_without_alert_topic.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Thresholds require an alert topic", result.to_string());
+    }
+
+    #[test]
+    fn load_free_space_alerts_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/free_space_alerts.yaml"))
+        ).unwrap();
+        let mut free_space_alerts = HashMap::new();
+        free_space_alerts.insert(String::from("/"), 200);
+        free_space_alerts.insert(String::from("/var/Log.d"), 1_073_741_824);
+        assert_eq!(free_space_alerts, result.free_space_alerts);
+    }
+
+    #[test]
+    fn load_free_space_alerts_without_alert_topic() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/free_space_alerts_without_alert_topic.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Free space alerts require an alert topic", result.to_string());
+    }
+
+    #[test]
+    fn load_zero_free_space_alert() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/zero_free_space_alert.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Free space alert for mount '/data' must be greater than 0 bytes",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_read_only_alert_without_disk_read_only() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/read_only_alert_without_disk_read_only.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Read-only alerts require include_disk_read_only", result.to_string());
+    }
+
+    #[test]
+    fn load_out_of_range_threshold() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_threshold.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Threshold for section 'disks' must be between 0 and 100 percent", result.to_string());
+    }
+
+    #[test]
+    fn load_dual_publish_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/dual_publish.yaml"))
+        ).unwrap();
+        assert!(result.dual_publish);
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/state")), result.snapshot_topic);
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/events")), result.event_topic);
+    }
+
+    #[test]
+    fn load_dual_publish_without_event_topic() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/dual_publish_without_event_topic.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Dual publishing requires both a snapshot topic and an event topic", result.to_string());
+    }
+
+    #[test]
+    fn load_split_topics_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/split_topics.yaml"))
+        ).unwrap();
+        assert!(result.split_topics);
+    }
+
+    #[test]
+    fn load_disk_io_busy_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/disk_io_busy.yaml"))
+        ).unwrap();
+        assert!(result.include_disk_io_busy);
+    }
+
+    #[test]
+    fn load_read_only_alert_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/read_only_alert.yaml"))
+        ).unwrap();
+        assert!(result.include_disk_read_only);
+        assert!(result.read_only_alert);
+        assert_eq!(Some(String::from("devices/alerts")), result.alert_topic);
+    }
+
+    #[test]
+    fn load_file_descriptors_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/file_descriptors.yaml"))
+        ).unwrap();
+        assert!(result.include_file_descriptors);
+    }
+
+    #[test]
+    fn load_frequency_unit_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/frequency_unit.yaml"))
+        ).unwrap();
+        assert_eq!(FrequencyUnit::GHz, result.frequency_unit);
+    }
+
+    #[test]
+    fn load_unrecognized_frequency_unit() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_frequency_unit.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected frequency unit 'THz'", result.to_string());
+    }
+
+    #[test]
+    fn load_history_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/history.yaml"))
+        ).unwrap();
+        assert_eq!(Some(20), result.history_size);
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/commands")), result.command_topic);
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/history")), result.history_topic);
+    }
+
+    #[test]
+    fn load_history_without_topics() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/history_without_topics.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: A report history requires both a command topic and a history topic", result.to_string());
+    }
+
+    #[test]
+    fn load_command_topic_without_keep_connected() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/command_topic_without_keep_connected.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: A command topic requires keep_connected to be enabled", result.to_string());
+    }
+
+    #[test]
+    fn load_config_topic_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/config_topic.yaml"))
+        ).unwrap();
+        assert_eq!(Some(String::from("devices/Test%20Device%20Name/config")), result.config_topic);
+    }
+
+    #[test]
+    fn load_config_topic_without_keep_connected() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/config_topic_without_keep_connected.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: A config topic requires keep_connected to be enabled", result.to_string());
+    }
+
+    #[test]
+    fn parse_valid_config_update() {
+        let config_update = parse_config_update(
+            r#"{"check_interval": 10, "topic": "devices/{device_id}/stats", "thresholds": {"memory": 90.0}}"#,
+            "device 1",
+            None,
+            true
+        ).unwrap();
+        let mut thresholds = HashMap::new();
+        thresholds.insert(MEMORY_SECTION, 90.0);
+        assert_eq!(
+            ConfigUpdate {
+                check_interval: Some(10),
+                topic: Some(String::from("devices/device%201/stats")),
+                thresholds: Some(thresholds),
+            },
+            config_update
+        );
+        assert_eq!(ConfigUpdate::default(), parse_config_update("{}", "device 1", None, false).unwrap());
+    }
+
+    #[test]
+    fn parse_invalid_config_update() {
+        let cases = [
+            (r#"{"check_interval": 0}"#, "Check interval must be between 1 and 240"),
+            (r#"{"check_interval": 1}"#, "Heartbeat interval must be between 1 and 59 seconds"),
+            (r#"{"thresholds": {"memory": 90.0}}"#, "Thresholds require an alert topic"),
+            (r#"{"topic": "devices/\u0000"}"#, "Topic 'devices/\\u{0}' contains a null character"),
+        ];
+        for (payload, reason) in cases.iter() {
+            let error = parse_config_update(payload, "device-1", Some(90), false).err().unwrap();
+            assert_eq!(format!("An illegal argument was encountered. Reason: {}", reason), error.to_string());
+        }
+        let error = parse_config_update(r#"{"qos": 2}"#, "device-1", None, false).err().unwrap();
+        assert!(error.to_string().contains("Malformed configuration update: unknown field `qos`"), "{}", error);
+    }
+
+    #[test]
+    fn load_oversized_history() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/oversized_history.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: History size must be between 1 and 1000 reports", result.to_string());
+    }
+
+    #[test]
+    fn load_keep_connected_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/keep_connected.yaml"))
+        ).unwrap();
+        assert!(result.keep_connected);
+        assert_eq!(10, result.connection_check_secs);
+    }
+
+    #[test]
+    fn load_zero_connection_check_interval() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/zero_connection_check_interval.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Connection check interval must be between 1 and 3600 seconds", result.to_string());
+    }
+
+    #[test]
+    fn load_disconnect_timeout_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/disconnect_timeout.yaml"))
+        ).unwrap();
+        assert_eq!(Some(2500), result.disconnect_timeout_ms);
+    }
+
+    #[test]
+    fn load_too_high_disconnect_timeout() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_high_disconnect_timeout.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Disconnect timeout must be between 1 and 60000 milliseconds", result.to_string());
+    }
+
+    #[test]
+    fn load_shutdown_grace_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/shutdown_grace.yaml"))
+        ).unwrap();
+        assert_eq!(Some(15), result.shutdown_grace_secs);
+    }
+
+    #[test]
+    fn load_too_high_shutdown_grace() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_high_shutdown_grace.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Shutdown grace period must be between 1 and 300 seconds", result.to_string());
+    }
+
+    #[test]
+    fn load_publish_ack_timeout_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/publish_ack_timeout.yaml"))
+        ).unwrap();
+        assert_eq!(5, result.publish_ack_timeout_secs);
+    }
+
+    #[test]
+    fn load_check_deadline_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/check_deadline.yaml"))
+        ).unwrap();
+        assert_eq!(Some(120), result.check_deadline_secs);
+    }
+
+    #[test]
+    fn load_out_of_range_check_deadline() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_check_deadline.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Check deadline must be between 1 and 3600 seconds", result.to_string());
+    }
+
+    #[test]
+    fn load_out_of_range_publish_ack_timeout() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_publish_ack_timeout.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Publish acknowledgment timeout must be between 1 and 300 seconds", result.to_string());
+    }
+
+    #[test]
+    fn load_message_expiry_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/message_expiry.yaml"))
+        ).unwrap();
+        assert_eq!(Some(3600), result.message_expiry_secs);
+    }
+
+    #[test]
+    fn load_zero_message_expiry() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/zero_message_expiry.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Message expiry interval must be between 1 and 2147483647 seconds", result.to_string());
+    }
+
+    #[test]
+    fn load_topic_with_null_character() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/null_character_topic.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Topic 'devices/\\u{0}/status' contains a null character", result.to_string());
+    }
+
+    #[test]
+    fn load_key_case_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/key_case.yaml"))
+        ).unwrap();
+        assert_eq!(KeyCase::Snake, result.key_case);
+    }
+
+    #[test]
+    fn load_message_id_format_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/message_id_format.yaml"))
+        ).unwrap();
+        assert_eq!(MessageIdFormat::UuidV7, result.message_id_format);
+    }
+
+    #[test]
+    fn load_unknown_message_id_format() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unknown_message_id_format.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected message id format 'ulid'; supported formats are uuid_v4, uuid_v7 and sequence",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_influx_line_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/influx_line.yaml"))
+        ).unwrap();
+        assert_eq!(ReportFormat::InfluxLine, result.format);
+        assert_eq!(Some(&String::from("plant-7")), result.tags.get("site"));
+        assert_eq!(Some(&String::from("b2")), result.tags.get("rack"));
+    }
+
+    #[test]
+    fn load_location_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/location.yaml"))
+        ).unwrap();
+        assert_eq!(Some(Location { latitude: -33.8688, longitude: 151.2093 }), result.location);
+    }
+
+    #[test]
+    fn load_out_of_range_latitude() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_latitude.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Latitude must be between -90 and 90 degrees", result.to_string());
+    }
+
+    #[test]
+    fn load_out_of_range_longitude() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_longitude.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Longitude must be between -180 and 180 degrees", result.to_string());
+    }
+
+    #[test]
+    fn load_latitude_without_longitude() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/latitude_without_longitude.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Latitude and longitude must be configured together",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_unknown_format() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unknown_format.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected report format 'csv'; supported formats are json and influx_line",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_influx_line_with_split_topics() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/influx_line_split_topics.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: The influx_line format cannot be combined with split topics or report signing",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_reserved_tag() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/reserved_tag.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: The 'device_id' tag is reserved", result.to_string());
+    }
+
+    #[test]
+    fn load_flatten_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/flatten.yaml"))
+        ).unwrap();
+        assert!(result.flatten);
+    }
+
+    #[test]
+    fn load_unrecognized_key_case() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_key_case.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected key case 'kebab'", result.to_string());
+    }
+
+    #[test]
+    fn load_counter_reset_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/counter_reset.yaml"))
+        ).unwrap();
+        assert_eq!(CounterReset::Zero, result.counter_reset);
+    }
+
+    #[test]
+    fn load_unrecognized_counter_reset() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_counter_reset.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected counter reset handling 'wrap'; supported values are omit and zero",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_unrecognized_destination_type() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_destination_type.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected destination type 'carrier_pigeon'", result.to_string());
+    }
+
+    #[test]
+    fn load_unrecognized_temperature_unit() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unrecognized_temperature_unit.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected temperature unit 'k'", result.to_string());
+    }
+
+    #[test]
+    fn load_too_many_publish_retries() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_many_publish_retries.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Publish retries must be between 0 and 10", result.to_string());
+    }
+
+    #[test]
+    fn load_spool_max_age_without_spool_dir() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/spool_max_age_without_spool_dir.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: A spool maximum age requires a spool directory", result.to_string());
+    }
+
+    #[test]
+    fn load_out_of_range_spool_max_age() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/out_of_range_spool_max_age.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Spool maximum age must be between 1 and 2592000 seconds",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_unknown_persistence() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unknown_persistence.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected persistence 'memory'; supported values are none, file and default",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_persistence_dir_without_file_persistence() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/persistence_dir_without_file_persistence.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: A persistence directory requires file persistence",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_too_small_spool_max_bytes() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/too_small_spool_max_bytes.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Spool maximum size must be at least 1024 bytes",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_spool_full_strategy_without_spool_max_bytes() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/spool_full_strategy_without_spool_max_bytes.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: A spool full strategy requires a spool maximum size",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_unknown_spool_full_strategy() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/unknown_spool_full_strategy.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected spool full strategy 'drop_random'; supported values are drop_oldest, drop_newest and block",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn load_password_file_config() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/password_file.yaml"))
+        ).unwrap();
+        assert_eq!("FileSecretPassword", result.user_password);
+    }
+
+    #[test]
+    fn load_empty_config_file() {
+        for config_path in ["resources/test/good/empty.yaml", "resources/test/good/comments_only.yaml"].iter() {
+            let result = load_config(Some(&String::from(*config_path))).unwrap();
+            assert_ne!("", result.device_id);
+            assert_eq!(DEFAULT_SERVER_ADDRESS, result.server_address);
+            assert_eq!(DEFAULT_USER_NAME, result.user_name);
+            assert_eq!(DEFAULT_TOPIC, result.topic);
+            assert_eq!(RuntimeMode::Single, result.runtime_mode);
+            assert_eq!(DEFAULT_CHECK_INTERVAL, result.check_interval);
+            assert_eq!(Codec::Lz4, result.compression);
+            assert_eq!(vec![DestinationConfig::Mqtt { compress: true }], result.destinations);
+        }
+    }
+
+    #[test]
+    fn load_missing_config_file() {
+        let result = load_config(
+            Some(&String::from("resources/test/good/nonexistent.yaml"))
+        ).err().unwrap().downcast::<RuntimeError>().unwrap();
+        assert!(result.to_string().starts_with("An error was encountered during runtime. Reason: "));
+    }
+
+    #[test]
+    fn load_missing_password_file() {
+        let result = load_config(
+            Some(&String::from("resources/test/bad/missing_password_file.yaml"))
+        ).err().unwrap().downcast::<IllegalArgumentError>().unwrap();
+        assert!(result.to_string().starts_with("An illegal argument was encountered. Reason: Unable to read secret file 'resources/test/secrets/missing_password': "));
     }
 
     #[test]
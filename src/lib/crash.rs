@@ -0,0 +1,79 @@
+use std::panic::{self, PanicHookInfo};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use paho_mqtt::Message;
+
+use crate::lib::report::CrashMessage;
+use crate::lib::transport::Transport;
+
+/// Builds the crash message for a panic from its payload and source location.
+pub fn crash_message<'a>(device_id: &'a str, info: &PanicHookInfo, timestamp: u64) -> CrashMessage<'a> {
+    let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+        String::from(*message)
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Unknown panic")
+    };
+    let location = info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+    CrashMessage::new(device_id, timestamp, message, location)
+}
+
+/// Installs a panic hook that publishes a crash message before handing over to the previous hook.
+pub fn install_crash_hook(device_id: String, crash_topic: String, qos: i32, transport: Box<dyn Transport>) {
+    let transport = Mutex::new(transport);
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        publish_crash(device_id.as_str(), crash_topic.as_str(), qos, &transport, info);
+        previous_hook(info);
+    }));
+}
+
+/// Publishing is best-effort: the process is already failing, so any error is dropped.
+fn publish_crash(device_id: &str, crash_topic: &str, qos: i32, transport: &Mutex<Box<dyn Transport>>, info: &PanicHookInfo) {
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let crash_json = match serde_json::to_string(&crash_message(device_id, info, timestamp)) {
+        Ok(crash_json) => crash_json,
+        Err(_) => return
+    };
+    // A panic while the transport was in use must not stop the crash from being reported
+    let transport = match transport.lock() {
+        Ok(transport) => transport,
+        Err(poisoned) => poisoned.into_inner()
+    };
+    if transport.connect().is_ok() {
+        let _ = transport.publish(Message::new(crash_topic, crash_json, qos));
+        let _ = transport.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::thread;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use crate::lib::crash::install_crash_hook;
+    use crate::lib::transport::mock::MockTransport;
+
+    #[test]
+    fn publish_crash_on_panic() {
+        let transport = MockTransport::new();
+        install_crash_hook(String::from("Test Device Name"), String::from("Device_Crash"), 1, Box::new(transport.clone()));
+        let result = thread::spawn(|| panic!("Forced panic {}", 1)).join();
+        let _ = panic::take_hook();
+        assert!(result.is_err());
+        let crash_messages: Vec<Value> = transport.messages().iter()
+            .filter(|m| m.topic() == "Device_Crash")
+            .map(|m| serde_json::from_slice(m.payload()).unwrap())
+            .filter(|m: &Value| m["message"] == "Forced panic 1")
+            .collect();
+        assert_eq!(1, crash_messages.len());
+        assert_eq!("Test Device Name", crash_messages[0]["deviceId"]);
+        assert_eq!(env!("CARGO_PKG_VERSION"), crash_messages[0]["agentVersion"]);
+        assert!(crash_messages[0]["location"].as_str().unwrap().starts_with("src/lib/crash.rs:"));
+    }
+}
@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::lib::common::RuntimeError;
+
+/// Output beyond this is not a small set of custom metrics and is rejected rather than bloating every report.
+const MAX_OUTPUT_BYTES: u64 = 64 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Runs the command through the platform's shell and parses its standard output as a JSON object of custom metrics.
+/// The command's standard error is passed through to the reporter's own. A command still running once the timeout
+/// passes is killed.
+pub fn run_custom_command(command: &str, timeout: Duration) -> Result<Value, Box<dyn Error>> {
+    let mut child = match shell_command(command).stdin(Stdio::null()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(format!("Unable to run custom command: {}", e).as_str()));
+            return Err(error);
+        }
+    };
+    // Read on another thread so that a command writing more than the pipe holds cannot block before exiting
+    let stdout = child.stdout.take();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(stdout) = stdout {
+            stdout.take(MAX_OUTPUT_BYTES + 1).read_to_end(&mut output)?;
+        }
+        Ok::<Vec<u8>, std::io::Error>(output)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                let error = Box::new(RuntimeError::new(
+                    format!("Custom command did not finish within {} ms", timeout.as_millis()).as_str()
+                ));
+                return Err(error);
+            }
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(format!("Unable to run custom command: {}", e).as_str()));
+                return Err(error);
+            }
+        }
+    };
+    if !status.success() {
+        let error = Box::new(RuntimeError::new(format!("Custom command failed with {}", status).as_str()));
+        return Err(error);
+    }
+    let output = match reader.join() {
+        Ok(Ok(output)) => output,
+        _ => {
+            let error = Box::new(RuntimeError::new("Unable to read custom command output"));
+            return Err(error);
+        }
+    };
+    if output.len() as u64 > MAX_OUTPUT_BYTES {
+        let error = Box::new(RuntimeError::new(
+            format!("Custom command output is larger than {} bytes", MAX_OUTPUT_BYTES).as_str()
+        ));
+        return Err(error);
+    }
+    match serde_json::from_slice(&output) {
+        Ok(Value::Object(metrics)) => Ok(Value::Object(metrics)),
+        _ => {
+            let error = Box::new(RuntimeError::new("Custom command output is not a JSON object"));
+            Err(error)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut shell_command = Command::new("sh");
+    shell_command.arg("-c").arg(command);
+    shell_command
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell_command = Command::new("cmd");
+    shell_command.arg("/C").arg(command);
+    shell_command
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use crate::lib::custom::run_custom_command;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn parse_command_output() {
+        let metrics = run_custom_command(r#"echo '{"queueDepth": 7, "mode": "batch"}'"#, TIMEOUT).unwrap();
+        assert_eq!(json!({"queueDepth": 7, "mode": "batch"}), metrics);
+    }
+
+    #[test]
+    fn fail_on_exit_status() {
+        let error = run_custom_command("exit 3", TIMEOUT).err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Custom command failed with exit status: 3",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn fail_on_output_other_than_object() {
+        for command in ["echo not-json", "echo '[1, 2]'"].iter() {
+            let error = run_custom_command(command, TIMEOUT).err().unwrap();
+            assert_eq!(
+                "An error was encountered during runtime. Reason: Custom command output is not a JSON object",
+                error.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn kill_command_past_timeout() {
+        let error = run_custom_command("sleep 5", Duration::from_millis(100)).err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Custom command did not finish within 100 ms",
+            error.to_string()
+        );
+    }
+}
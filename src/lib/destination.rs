@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::lib::common::{IllegalArgumentError, RuntimeError};
+
+const HTTP_SCHEME: &str = "http://";
+const DEFAULT_HTTP_PORT: u16 = 80;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A sink other than the MQTT broker that report payloads are delivered to.
+pub trait Destination: Send {
+    /// Identifies the destination in delivery errors.
+    fn name(&self) -> String;
+    fn send(&self, payload: &[u8]) -> Result<(), Box<dyn Error>>;
+}
+
+/// POSTs each payload to an `http://` webhook URL with any configured headers, treating a non-2xx response as a
+/// failed delivery.
+pub struct HttpDestination {
+    url: String,
+    host: String,
+    port: u16,
+    path: String,
+    headers: BTreeMap<String, String>,
+}
+
+impl HttpDestination {
+    pub fn new(url: &str, headers: BTreeMap<String, String>) -> Result<HttpDestination, IllegalArgumentError> {
+        let unsupported_url = || IllegalArgumentError::new(
+            format!("Unsupported destination URL '{}'; only http:// URLs are supported", url).as_str()
+        );
+        let address = match url.strip_prefix(HTTP_SCHEME) {
+            Some(address) => address,
+            None => return Err(unsupported_url())
+        };
+        let (authority, path) = match address.find('/') {
+            Some(path_start) => (&address[..path_start], &address[path_start..]),
+            None => (address, "/")
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => return Err(unsupported_url())
+            },
+            None => (authority, DEFAULT_HTTP_PORT)
+        };
+        if host.is_empty() {
+            return Err(unsupported_url());
+        }
+        // A line break would let a header value inject further headers or end the request head early
+        for (name, value) in headers.iter() {
+            if name.is_empty() || name.contains(['\r', '\n', ':']) || value.contains(['\r', '\n']) {
+                return Err(IllegalArgumentError::new(
+                    format!("Webhook header '{}' is not a valid HTTP header", name.escape_default()).as_str()
+                ));
+            }
+        }
+        Ok(HttpDestination {
+            url: String::from(url),
+            host: String::from(host),
+            port,
+            path: String::from(path),
+            headers,
+        })
+    }
+
+    fn request_head(&self, content_length: usize) -> String {
+        let mut request_head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.host,
+            self.port,
+            content_length
+        );
+        for (name, value) in self.headers.iter() {
+            request_head.push_str(format!("{}: {}\r\n", name, value).as_str());
+        }
+        request_head.push_str("\r\n");
+        request_head
+    }
+}
+
+/// Reads the status code from the status line of an HTTP response.
+fn parse_status_code(response: &[u8]) -> Option<u16> {
+    let status_line_end = response.iter().position(|b| *b == b'\r').unwrap_or(response.len());
+    let status_line = std::str::from_utf8(&response[..status_line_end]).ok()?;
+    let mut status_parts = status_line.split(' ');
+    if !status_parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    status_parts.next()?.parse().ok()
+}
+
+impl Destination for HttpDestination {
+    fn name(&self) -> String {
+        format!("http ({})", self.url)
+    }
+
+    fn send(&self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        let result = TcpStream::connect((self.host.as_str(), self.port)).and_then(|mut stream| {
+            stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+            stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+            stream.write_all(self.request_head(payload.len()).as_bytes())?;
+            stream.write_all(payload)?;
+            stream.flush()?;
+            // The server closes the connection once it has responded
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response)?;
+            Ok(response)
+        });
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+        };
+        match parse_status_code(&response) {
+            Some(status_code) if (200..300).contains(&status_code) => Ok(()),
+            Some(status_code) => {
+                let error = Box::new(RuntimeError::new(format!("Webhook responded with status {}", status_code).as_str()));
+                Err(error)
+            }
+            None => {
+                let error = Box::new(RuntimeError::new("Webhook returned a malformed response"));
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::lib::common::RuntimeError;
+    use crate::lib::destination::Destination;
+
+    /// Records every payload delivered to it, or fails every delivery while `failing` is set.
+    #[derive(Clone,Default)]
+    pub struct RecordingDestination {
+        pub payloads: Arc<Mutex<Vec<Vec<u8>>>>,
+        pub failing: Arc<AtomicBool>,
+    }
+
+    impl RecordingDestination {
+        pub fn new() -> RecordingDestination {
+            RecordingDestination::default()
+        }
+
+        pub fn failing() -> RecordingDestination {
+            RecordingDestination {
+                failing: Arc::new(AtomicBool::new(true)),
+                ..RecordingDestination::default()
+            }
+        }
+
+        pub fn payloads(&self) -> Vec<Vec<u8>> {
+            self.payloads.lock().unwrap().clone()
+        }
+    }
+
+    impl Destination for RecordingDestination {
+        fn name(&self) -> String {
+            String::from("recording")
+        }
+
+        fn send(&self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(Box::new(RuntimeError::new("Destination is unavailable")));
+            }
+            self.payloads.lock().unwrap().push(payload.to_vec());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::thread::JoinHandle;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::destination::{Destination, HttpDestination, parse_status_code};
+
+    /// Accepts a single request on a local port, answers it with the given status line and returns the raw request.
+    fn mock_http_server(status_line: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let request_text = String::from_utf8_lossy(&request).to_string();
+                if let Some(head_end) = request_text.find("\r\n\r\n") {
+                    let content_length: usize = request_text.lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .map(|length| length.parse().unwrap())
+                        .unwrap_or(0);
+                    if read == 0 || request.len() >= head_end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+            stream.write_all(format!("{}\r\nContent-Length: 0\r\n\r\n", status_line).as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (port, server)
+    }
+
+    fn webhook_headers() -> BTreeMap<String, String> {
+        let mut headers = BTreeMap::new();
+        headers.insert(String::from("authorization"), String::from("Bearer token-1"));
+        headers.insert(String::from("x-fleet"), String::from("north"));
+        headers
+    }
+
+    #[test]
+    fn parse_http_url() {
+        let destination = HttpDestination::new("http://collector.local:8080/reports/ingest", BTreeMap::new()).unwrap();
+        assert_eq!("collector.local", destination.host);
+        assert_eq!(8080, destination.port);
+        assert_eq!("/reports/ingest", destination.path);
+    }
+
+    #[test]
+    fn parse_http_url_with_defaults() {
+        let destination = HttpDestination::new("http://collector.local", BTreeMap::new()).unwrap();
+        assert_eq!("collector.local", destination.host);
+        assert_eq!(80, destination.port);
+        assert_eq!("/", destination.path);
+    }
+
+    #[test]
+    fn reject_unsupported_url() {
+        let result = HttpDestination::new("https://collector.local/reports", BTreeMap::new()).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unsupported destination URL 'https://collector.local/reports'; only http:// URLs are supported", result.to_string());
+    }
+
+    #[test]
+    fn build_request_head() {
+        let destination = HttpDestination::new("http://collector.local:8080/reports", webhook_headers()).unwrap();
+        assert_eq!(
+            "POST /reports HTTP/1.1\r\nHost: collector.local:8080\r\nContent-Type: application/octet-stream\r\nContent-Length: 42\r\nConnection: close\r\nauthorization: Bearer token-1\r\nx-fleet: north\r\n\r\n",
+            destination.request_head(42)
+        );
+    }
+
+    #[test]
+    fn reject_header_with_line_break() {
+        let mut headers = BTreeMap::new();
+        headers.insert(String::from("x-fleet"), String::from("north\r\nx-injected: 1"));
+        let result = HttpDestination::new("http://collector.local/reports", headers).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Webhook header 'x-fleet' is not a valid HTTP header", result.to_string());
+    }
+
+    #[test]
+    fn parse_status_codes() {
+        assert_eq!(Some(204), parse_status_code(b"HTTP/1.1 204 No Content\r\n\r\n"));
+        assert_eq!(Some(503), parse_status_code(b"HTTP/1.0 503 Service Unavailable\r\n"));
+        assert_eq!(None, parse_status_code(b"SSH-2.0-OpenSSH\r\n"));
+        assert_eq!(None, parse_status_code(b""));
+    }
+
+    #[test]
+    fn post_payload_to_webhook() {
+        let (port, server) = mock_http_server("HTTP/1.1 204 No Content");
+        let destination = HttpDestination::new(format!("http://127.0.0.1:{}/hooks/stats", port).as_str(), webhook_headers()).unwrap();
+        destination.send(b"{\"deviceId\":\"device-1\"}").unwrap();
+        let request = server.join().unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let head_lines: Vec<&str> = head.lines().collect();
+        assert_eq!("POST /hooks/stats HTTP/1.1", head_lines[0]);
+        assert!(head_lines.contains(&"authorization: Bearer token-1"));
+        assert!(head_lines.contains(&"x-fleet: north"));
+        assert!(head_lines.contains(&"Content-Length: 23"));
+        assert_eq!("{\"deviceId\":\"device-1\"}", body);
+    }
+
+    #[test]
+    fn webhook_error_status_fails_delivery() {
+        let (port, server) = mock_http_server("HTTP/1.1 401 Unauthorized");
+        let destination = HttpDestination::new(format!("http://127.0.0.1:{}/hooks/stats", port).as_str(), BTreeMap::new()).unwrap();
+        let result = destination.send(b"payload").err().unwrap();
+        server.join().unwrap();
+        assert_eq!("An error was encountered during runtime. Reason: Webhook responded with status 401", result.to_string());
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::VecDeque;
+
+/// Retains the most recent serialized reports, dropping the oldest once full.
+pub struct ReportHistory {
+    capacity: usize,
+    reports: VecDeque<String>,
+}
+
+impl ReportHistory {
+    pub fn new(capacity: usize) -> ReportHistory {
+        ReportHistory {
+            capacity,
+            reports: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn record(&mut self, report_json: String) {
+        if self.reports.len() == self.capacity {
+            self.reports.pop_front();
+        }
+        self.reports.push_back(report_json);
+    }
+
+    /// The retained reports as a JSON array, oldest first.
+    pub fn to_json(&self) -> String {
+        format!("[{}]", self.reports.iter().map(|r| r.as_str()).collect::<Vec<&str>>().join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::history::ReportHistory;
+
+    #[test]
+    fn empty_history() {
+        assert_eq!("[]", ReportHistory::new(3).to_json());
+    }
+
+    #[test]
+    fn drop_oldest_reports_once_full() {
+        let mut history = ReportHistory::new(2);
+        history.record(String::from("{\"sequence\":1}"));
+        history.record(String::from("{\"sequence\":2}"));
+        assert_eq!("[{\"sequence\":1},{\"sequence\":2}]", history.to_json());
+        history.record(String::from("{\"sequence\":3}"));
+        assert_eq!("[{\"sequence\":2},{\"sequence\":3}]", history.to_json());
+    }
+}
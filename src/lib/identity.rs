@@ -0,0 +1,176 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use uuid::Uuid;
+
+use crate::lib::common::{IllegalArgumentError, RuntimeError};
+
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum DeviceIdSource {
+    /// The `device_id` configuration value.
+    Config,
+    /// The machine ID assigned by the OS, which persists across reboots.
+    MachineId,
+    Hostname,
+    /// A random ID generated on every start.
+    Uuid,
+}
+
+// Device ID source names
+pub const CONFIG_SOURCE: &str = "config";
+pub const MACHINE_ID_SOURCE: &str = "machine_id";
+pub const HOSTNAME_SOURCE: &str = "hostname";
+pub const UUID_SOURCE: &str = "uuid";
+
+const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+const HOSTNAME_PATHS: [&str; 2] = ["/proc/sys/kernel/hostname", "/etc/hostname"];
+const HOSTNAME_VARS: [&str; 2] = ["HOSTNAME", "COMPUTERNAME"];
+
+impl DeviceIdSource {
+    pub fn from_name(name: &str) -> Result<DeviceIdSource, IllegalArgumentError> {
+        match name {
+            CONFIG_SOURCE => Ok(DeviceIdSource::Config),
+            MACHINE_ID_SOURCE => Ok(DeviceIdSource::MachineId),
+            HOSTNAME_SOURCE => Ok(DeviceIdSource::Hostname),
+            UUID_SOURCE => Ok(DeviceIdSource::Uuid),
+            _ => Err(IllegalArgumentError::new(format!("Unexpected device ID source '{}'", name).as_str()))
+        }
+    }
+}
+
+/// Where the host-derived identities are read from.
+pub trait HostIdentity {
+    fn machine_id(&self) -> Result<String, Box<dyn Error>>;
+    fn hostname(&self) -> Result<String, Box<dyn Error>>;
+}
+
+pub struct SystemIdentity;
+
+impl HostIdentity for SystemIdentity {
+    fn machine_id(&self) -> Result<String, Box<dyn Error>> {
+        match read_first_line(&MACHINE_ID_PATHS) {
+            Some(machine_id) => Ok(machine_id),
+            None => Err(Box::new(RuntimeError::new("Unable to read the machine ID on this platform")))
+        }
+    }
+
+    fn hostname(&self) -> Result<String, Box<dyn Error>> {
+        if let Some(hostname) = read_first_line(&HOSTNAME_PATHS) {
+            return Ok(hostname);
+        }
+        match HOSTNAME_VARS.iter().filter_map(|var| env::var(var).ok()).find(|h| !h.is_empty()) {
+            Some(hostname) => Ok(hostname),
+            None => Err(Box::new(RuntimeError::new("Unable to determine the hostname")))
+        }
+    }
+}
+
+/// Returns the first line of the first readable, non-empty file.
+fn read_first_line(paths: &[&str]) -> Option<String> {
+    paths.iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|contents| contents.lines().next().map(|line| String::from(line.trim())))
+        .find(|line| !line.is_empty())
+}
+
+pub fn resolve_device_id(source: DeviceIdSource, configured_device_id: Option<String>, host: &dyn HostIdentity) -> Result<String, Box<dyn Error>> {
+    match source {
+        DeviceIdSource::Config => match configured_device_id {
+            Some(device_id) => Ok(device_id),
+            None => Err(Box::new(IllegalArgumentError::new("A device_id must be configured when the device ID source is 'config'")))
+        },
+        DeviceIdSource::MachineId => host.machine_id(),
+        DeviceIdSource::Hostname => host.hostname(),
+        DeviceIdSource::Uuid => Ok(Uuid::new_v4().to_string()),
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::error::Error;
+
+    use crate::lib::common::RuntimeError;
+    use crate::lib::identity::HostIdentity;
+
+    /// A host with fixed identities; a `None` identity fails to be read.
+    pub struct FakeIdentity {
+        pub machine_id: Option<&'static str>,
+        pub hostname: Option<&'static str>,
+    }
+
+    impl HostIdentity for FakeIdentity {
+        fn machine_id(&self) -> Result<String, Box<dyn Error>> {
+            match self.machine_id {
+                Some(machine_id) => Ok(String::from(machine_id)),
+                None => Err(Box::new(RuntimeError::new("Machine ID unavailable")))
+            }
+        }
+
+        fn hostname(&self) -> Result<String, Box<dyn Error>> {
+            match self.hostname {
+                Some(hostname) => Ok(String::from(hostname)),
+                None => Err(Box::new(RuntimeError::new("Hostname unavailable")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use pretty_assertions::assert_ne;
+
+    use crate::lib::identity::{DeviceIdSource, resolve_device_id};
+    use crate::lib::identity::fake::FakeIdentity;
+
+    const HOST: FakeIdentity = FakeIdentity {
+        machine_id: Some("4c4c4544004d3510804cb4c04f4d3532"),
+        hostname: Some("edge-gateway-01"),
+    };
+
+    #[test]
+    fn resolve_from_config() {
+        let result = resolve_device_id(DeviceIdSource::Config, Some(String::from("Test Device Name")), &HOST).unwrap();
+        assert_eq!("Test Device Name", result);
+    }
+
+    #[test]
+    fn resolve_from_missing_config() {
+        let result = resolve_device_id(DeviceIdSource::Config, None, &HOST).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: A device_id must be configured when the device ID source is 'config'", result.to_string());
+    }
+
+    #[test]
+    fn resolve_from_machine_id() {
+        let result = resolve_device_id(DeviceIdSource::MachineId, Some(String::from("Test Device Name")), &HOST).unwrap();
+        assert_eq!("4c4c4544004d3510804cb4c04f4d3532", result);
+    }
+
+    #[test]
+    fn resolve_from_unavailable_machine_id() {
+        let host = FakeIdentity { machine_id: None, hostname: None };
+        let result = resolve_device_id(DeviceIdSource::MachineId, None, &host).err().unwrap();
+        assert_eq!("An error was encountered during runtime. Reason: Machine ID unavailable", result.to_string());
+    }
+
+    #[test]
+    fn resolve_from_hostname() {
+        let result = resolve_device_id(DeviceIdSource::Hostname, None, &HOST).unwrap();
+        assert_eq!("edge-gateway-01", result);
+    }
+
+    #[test]
+    fn resolve_from_uuid() {
+        let first = resolve_device_id(DeviceIdSource::Uuid, None, &HOST).unwrap();
+        let second = resolve_device_id(DeviceIdSource::Uuid, None, &HOST).unwrap();
+        assert_eq!(36, first.len());
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn unrecognized_source() {
+        let result = DeviceIdSource::from_name("serial_number").err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Unexpected device ID source 'serial_number'", result.to_string());
+    }
+}
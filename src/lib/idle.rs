@@ -0,0 +1,96 @@
+use std::time::Instant;
+
+use crate::lib::collector::{counter_delta, CounterReset, CpuSample};
+
+/// Decides whether the device was idle over the last check, so that reporting can be skipped to save energy. The
+/// device is idle while both its average CPU usage and its network rate are below their thresholds.
+pub struct IdleDetector {
+    cpu_threshold: f64,
+    network_threshold_bps: u64,
+    counter_reset: CounterReset,
+    /// When the network byte counter was last read, and its value then.
+    previous_network: Option<(Instant, u64)>,
+}
+
+impl IdleDetector {
+    pub fn new(cpu_threshold: f64, network_threshold_bps: u64, counter_reset: CounterReset) -> IdleDetector {
+        IdleDetector {
+            cpu_threshold,
+            network_threshold_bps,
+            counter_reset,
+            previous_network: None,
+        }
+    }
+
+    /// Whether the device was idle since the previous check, given the CPU readings and the cumulative count of
+    /// network bytes received and transmitted. The first check has no network rate to go by and never counts as idle,
+    /// and nor does one after the counter has gone backwards unless resets are taken as no traffic.
+    pub fn is_idle(&mut self, sampled_at: Instant, cpus: &[CpuSample], network_bytes: u64) -> bool {
+        let counter_reset = self.counter_reset;
+        let network_rate = self.previous_network.and_then(|(previous_sampled_at, previous_network_bytes)| {
+            let elapsed_secs = sampled_at.duration_since(previous_sampled_at).as_secs_f64();
+            network_rate_bps(previous_network_bytes, network_bytes, elapsed_secs, counter_reset)
+        });
+        self.previous_network = Some((sampled_at, network_bytes));
+        let network_idle = match network_rate {
+            Some(network_rate) => network_rate < self.network_threshold_bps as f64,
+            None => return false
+        };
+        network_idle && average_usage(cpus) < self.cpu_threshold
+    }
+}
+
+fn network_rate_bps(previous_bytes: u64, current_bytes: u64, elapsed_secs: f64, counter_reset: CounterReset) -> Option<f64> {
+    if elapsed_secs <= 0.0 {
+        return None;
+    }
+    Some(counter_delta(previous_bytes, current_bytes, counter_reset)? as f64 / elapsed_secs)
+}
+
+fn average_usage(cpus: &[CpuSample]) -> f64 {
+    if cpus.is_empty() {
+        return 0.0;
+    }
+    cpus.iter().map(|c| c.usage as f64).sum::<f64>() / cpus.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::lib::collector::{CounterReset, CpuSample};
+    use crate::lib::idle::{IdleDetector, network_rate_bps};
+
+    fn cpus(usage: f32) -> Vec<CpuSample> {
+        vec![CpuSample {
+            name: String::from("cpu0"),
+            brand: String::from("Test Brand"),
+            vendor_id: String::from("Test Vendor"),
+            frequency: 2400,
+            usage,
+        }]
+    }
+
+    #[test]
+    fn detect_idle_and_active_checks() {
+        let mut idle_detector = IdleDetector::new(5.0, 1024, CounterReset::Omit);
+        let start = Instant::now();
+        // There is no network rate to go by on the first check
+        assert!(!idle_detector.is_idle(start, &cpus(1.0), 10_000));
+        assert!(idle_detector.is_idle(start + Duration::from_secs(10), &cpus(1.0), 15_000));
+        // Busy CPU
+        assert!(!idle_detector.is_idle(start + Duration::from_secs(20), &cpus(40.0), 20_000));
+        // Busy network, at 10 KB/s
+        assert!(!idle_detector.is_idle(start + Duration::from_secs(30), &cpus(1.0), 120_000));
+        assert!(idle_detector.is_idle(start + Duration::from_secs(40), &cpus(1.0), 120_000));
+    }
+
+    #[test]
+    fn network_rate_bounds() {
+        assert_eq!(Some(100.0), network_rate_bps(1000, 2000, 10.0, CounterReset::Omit));
+        assert_eq!(None, network_rate_bps(2000, 1000, 10.0, CounterReset::Omit));
+        // A reset reads as no traffic rather than a wrapped, huge rate
+        assert_eq!(Some(0.0), network_rate_bps(2000, 1000, 10.0, CounterReset::Zero));
+        assert_eq!(None, network_rate_bps(1000, 2000, 0.0, CounterReset::Omit));
+    }
+}
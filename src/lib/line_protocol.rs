@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use crate::lib::report::SystemReport;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Renders the report as InfluxDB line protocol, one line per disk, CPU, component and section, so that it can be
+/// ingested without a bridge. Every line is tagged with the device ID and the configured tags. Network addresses and
+/// the agent process have no numeric readings to report, so they are left out.
+pub fn render_report(report: &SystemReport, device_id: &str, tags: &BTreeMap<String, String>, timestamp: u64) -> String {
+    let mut common_tags = format!("device_id={}", escape_tag(device_id));
+    for (key, value) in tags.iter() {
+        common_tags.push_str(format!(",{}={}", escape_tag(key), escape_tag(value)).as_str());
+    }
+    let timestamp_ns = timestamp * NANOSECONDS_PER_SECOND;
+    let mut lines = Vec::new();
+    let mut push_line = |measurement: &str, tags: Option<(&str, &str)>, fields: Vec<(&str, Field)>| {
+        let mut line = format!("{},{}", measurement, common_tags);
+        if let Some((key, value)) = tags {
+            line.push_str(format!(",{}={}", key, escape_tag(value)).as_str());
+        }
+        let fields: Vec<String> = fields.into_iter().map(|(key, value)| format!("{}={}", key, value.render())).collect();
+        lines.push(format!("{} {} {}", line, fields.join(","), timestamp_ns));
+    };
+    for disk in report.disks.iter() {
+        let mut fields = vec![("used", Field::Integer(disk.disk_used)), ("capacity", Field::Integer(disk.disk_capacity))];
+        if let Some(io_busy_pct) = disk.io_busy_pct {
+            fields.push(("io_busy_pct", Field::Float(io_busy_pct as f64)));
+        }
+        if let Some(inodes_used) = disk.inodes_used {
+            fields.push(("inodes_used", Field::Integer(inodes_used)));
+        }
+        if let Some(inodes_total) = disk.inodes_total {
+            fields.push(("inodes_total", Field::Integer(inodes_total)));
+        }
+        if let Some(read_only) = disk.read_only {
+            fields.push(("read_only", Field::Boolean(read_only)));
+        }
+        if let Some(disk_temperature) = disk.disk_temperature {
+            fields.push(("temperature", Field::Float(disk_temperature as f64)));
+        }
+        if let Some(near_full) = disk.near_full {
+            fields.push(("near_full", Field::Boolean(near_full)));
+        }
+        push_line("disk", Some(("name", disk.name.as_str())), fields);
+    }
+    for cpu in report.cpus.iter() {
+        let mut fields = vec![("usage", Field::Float(cpu.usage as f64)), ("frequency", Field::Float(cpu.frequency))];
+        if let (Some(frequency_min), Some(frequency_max)) = (cpu.frequency_min, cpu.frequency_max) {
+            fields.push(("frequency_min", Field::Float(frequency_min)));
+            fields.push(("frequency_max", Field::Float(frequency_max)));
+        }
+        if let Some(temperature) = cpu.temperature {
+            fields.push(("temperature", Field::Float(temperature as f64)));
+        }
+        push_line("cpu", Some(("name", cpu.name.as_str())), fields);
+    }
+    if let Some(memory) = report.memory.as_ref() {
+        let mut fields = vec![("used", Field::Integer(memory.memory_used)), ("capacity", Field::Integer(memory.memory_capacity))];
+        if let Some(buffers) = memory.buffers {
+            fields.push(("buffers", Field::Integer(buffers)));
+        }
+        if let Some(cached) = memory.cached {
+            fields.push(("cached", Field::Integer(cached)));
+        }
+        if let Some(truly_used) = memory.truly_used {
+            fields.push(("truly_used", Field::Integer(truly_used)));
+        }
+        if let Some(swap_in_per_sec) = memory.swap_in_per_sec {
+            fields.push(("swap_in_per_sec", Field::Float(swap_in_per_sec)));
+        }
+        if let Some(swap_out_per_sec) = memory.swap_out_per_sec {
+            fields.push(("swap_out_per_sec", Field::Float(swap_out_per_sec)));
+        }
+        push_line("memory", None, fields);
+    }
+    if let Some(file_descriptors) = report.file_descriptors.as_ref() {
+        push_line("file_descriptors", None, vec![
+            ("open", Field::Integer(file_descriptors.open_file_descriptors)),
+            ("max", Field::Integer(file_descriptors.max_file_descriptors)),
+        ]);
+    }
+    for component in report.components.iter() {
+        push_line("component", Some(("label", component.label.as_str())), vec![
+            ("temperature", Field::Float(component.temperature as f64)),
+        ]);
+    }
+    if let Some(power) = report.power.as_ref() {
+        let mut fields = vec![("energy_joules", Field::Float(power.energy_joules))];
+        if let Some(watts) = power.watts {
+            fields.push(("watts", Field::Float(watts)));
+        }
+        push_line("power", None, fields);
+    }
+    if let Some(processes) = report.processes.as_ref() {
+        let mut fields = vec![("count", Field::Integer(processes.process_count))];
+        if let Some(thread_count) = processes.thread_count {
+            fields.push(("threads", Field::Integer(thread_count)));
+        }
+        push_line("processes", None, fields);
+    }
+    lines.join("\n")
+}
+
+enum Field {
+    Integer(u64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl Field {
+    fn render(&self) -> String {
+        match self {
+            // Integers are suffixed so that InfluxDB does not store them as floats
+            Field::Integer(value) => format!("{}i", value),
+            Field::Float(value) => value.to_string(),
+            Field::Boolean(value) => value.to_string(),
+        }
+    }
+}
+
+/// Escapes the characters that delimit tag keys and values.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == ',' || c == '=' || c == ' ' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::line_protocol::{escape_tag, render_report};
+    use crate::lib::report::{ComponentReport, CPUReport, DiskReport, FrequencyUnit, MemoryReport, ProcessCountReport, SystemReport, TemperatureUnit};
+
+    fn report() -> SystemReport {
+        SystemReport {
+            disks: Box::new([DiskReport {
+                name: String::from("sda1"),
+                disk_used: 600,
+                disk_capacity: 1000,
+                io_busy_pct: None,
+                inodes_used: None,
+                inodes_total: None,
+                read_only: None,
+                disk_temperature: None,
+                near_full: None,
+            }]),
+            cpus: Box::new([CPUReport {
+                name: String::from("cpu0"),
+                brand: String::from("Test Brand"),
+                vendor_id: String::from("Test Vendor"),
+                frequency: 2400.0,
+                frequency_unit: FrequencyUnit::MHz,
+                frequency_min: None,
+                frequency_max: None,
+                usage: 12.5,
+                temperature: Some(48.0),
+            }]),
+            memory: Some(MemoryReport {
+                memory_used: 1536,
+                memory_capacity: 2048,
+                buffers: None,
+                cached: None,
+                truly_used: None,
+                swap_in_per_sec: None,
+                swap_out_per_sec: None,
+            }),
+            file_descriptors: None,
+            components: Box::new([ComponentReport {
+                label: String::from("coretemp Package id 0"),
+                temperature: 45.0,
+                temperature_unit: TemperatureUnit::Celsius,
+            }]),
+            power: None,
+            networks: Box::new([]),
+            agent: None,
+            processes: Some(ProcessCountReport {
+                process_count: 120,
+                thread_count: Some(480),
+            }),
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        }
+    }
+
+    #[test]
+    fn render_sample_report() {
+        let mut tags = BTreeMap::new();
+        tags.insert(String::from("site"), String::from("plant 7"));
+        tags.insert(String::from("rack"), String::from("b2"));
+        assert_eq!(
+            "disk,device_id=device-1,rack=b2,site=plant\\ 7,name=sda1 used=600i,capacity=1000i 1623715200000000000\n\
+             cpu,device_id=device-1,rack=b2,site=plant\\ 7,name=cpu0 usage=12.5,frequency=2400,temperature=48 1623715200000000000\n\
+             memory,device_id=device-1,rack=b2,site=plant\\ 7 used=1536i,capacity=2048i 1623715200000000000\n\
+             component,device_id=device-1,rack=b2,site=plant\\ 7,label=coretemp\\ Package\\ id\\ 0 temperature=45 1623715200000000000\n\
+             processes,device_id=device-1,rack=b2,site=plant\\ 7 count=120i,threads=480i 1623715200000000000",
+            render_report(&report(), "device-1", &tags, 1_623_715_200)
+        );
+    }
+
+    #[test]
+    fn render_tags_in_sorted_order() {
+        let mut tags = BTreeMap::new();
+        for key in ["zone", "rack", "site", "building"].iter() {
+            tags.insert(key.to_string(), String::from("a"));
+        }
+        let report_lines = render_report(&report(), "device-1", &tags, 1_623_715_200);
+        assert!(report_lines.lines().all(|l| l.contains(",device_id=device-1,building=a,rack=a,site=a,zone=a")));
+    }
+
+    #[test]
+    fn escape_tag_delimiters() {
+        assert_eq!("a\\,b\\=c\\ d", escape_tag("a,b=c d"));
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+/// How the ID of each published report message is generated.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum MessageIdFormat {
+    /// A random UUID.
+    UuidV4,
+    /// A UUID led by the millisecond timestamp, so that IDs sort by creation time.
+    UuidV7,
+    /// A counter starting from 1 each run; the cheapest to generate, and unique when paired with the session ID.
+    Sequence,
+}
+
+pub struct MessageIdGenerator {
+    format: MessageIdFormat,
+    sequence: u64,
+}
+
+impl MessageIdGenerator {
+    pub fn new(format: MessageIdFormat) -> MessageIdGenerator {
+        MessageIdGenerator {
+            format,
+            sequence: 0,
+        }
+    }
+
+    pub fn next_id(&mut self) -> String {
+        match self.format {
+            MessageIdFormat::UuidV4 => Uuid::new_v4().to_string(),
+            MessageIdFormat::UuidV7 => {
+                // A clock before the epoch only costs the IDs their ordering
+                let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+                uuid_v7(timestamp_ms).to_string()
+            }
+            MessageIdFormat::Sequence => {
+                self.sequence += 1;
+                self.sequence.to_string()
+            }
+        }
+    }
+}
+
+/// Builds a version 7 UUID as laid out in RFC 9562: the 48-bit Unix millisecond timestamp followed by random bits.
+/// uuid 0.8 predates version 7, so the random bits and variant are taken from a version 4 UUID.
+fn uuid_v7(timestamp_ms: u64) -> Uuid {
+    let mut bytes = *Uuid::new_v4().as_bytes();
+    bytes[..6].copy_from_slice(&timestamp_ms.to_be_bytes()[2..]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x70;
+    Uuid::from_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    use crate::lib::message_id::{MessageIdFormat, MessageIdGenerator, uuid_v7};
+
+    #[test]
+    fn generate_uuid_v4_ids() {
+        let mut generator = MessageIdGenerator::new(MessageIdFormat::UuidV4);
+        let message_id = Uuid::parse_str(generator.next_id().as_str()).unwrap();
+        assert_eq!(4, message_id.get_version_num());
+        assert_ne!(generator.next_id(), generator.next_id());
+    }
+
+    #[test]
+    fn generate_uuid_v7_ids() {
+        let mut generator = MessageIdGenerator::new(MessageIdFormat::UuidV7);
+        let message_id = Uuid::parse_str(generator.next_id().as_str()).unwrap();
+        assert_eq!(7, message_id.get_version_num());
+        assert_eq!(uuid::Variant::RFC4122, message_id.get_variant().unwrap());
+    }
+
+    #[test]
+    fn uuid_v7_sorts_by_timestamp() {
+        let earlier = uuid_v7(0x0123_4567_89ab);
+        let later = uuid_v7(0x0123_4567_89ac);
+        assert!(earlier.to_string().starts_with("01234567-89ab-7"));
+        assert!(earlier.to_string() < later.to_string());
+    }
+
+    #[test]
+    fn generate_sequence_ids() {
+        let mut generator = MessageIdGenerator::new(MessageIdFormat::Sequence);
+        assert_eq!("1", generator.next_id());
+        assert_eq!("2", generator.next_id());
+    }
+}
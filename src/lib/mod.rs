@@ -1,4 +1,36 @@
 pub mod runner;
 pub mod report;
+pub mod collector;
+pub mod alert;
+pub mod budget;
+#[cfg(target_os = "linux")]
+pub mod cgroup;
+pub mod change;
+pub mod chunk;
 pub mod common;
-pub mod config;
\ No newline at end of file
+pub mod compression;
+pub mod config;
+pub mod crash;
+pub mod custom;
+pub mod destination;
+pub mod history;
+pub mod identity;
+pub mod idle;
+pub mod line_protocol;
+pub mod message_id;
+pub mod network_type;
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+pub mod notify;
+#[cfg(all(target_os = "linux", feature = "powercap"))]
+pub mod powercap;
+pub mod quiet_hours;
+pub mod schedule;
+pub mod service;
+pub mod signing;
+#[cfg(feature = "smart")]
+pub mod smart;
+pub mod spool;
+pub mod state;
+pub mod stats;
+pub mod topic;
+pub mod transport;
\ No newline at end of file
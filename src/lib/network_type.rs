@@ -0,0 +1,158 @@
+use crate::lib::collector::NetworkInterfaceSample;
+
+// Interface name prefixes by the kind of link they usually carry, following the kernel's and udev's naming
+const CELLULAR_PREFIXES: [&str; 6] = ["wwan", "wwp", "ppp", "rmnet", "ccmni", "qmimux"];
+const WIFI_PREFIXES: [&str; 2] = ["wl", "ath"];
+const ETHERNET_PREFIXES: [&str; 3] = ["eth", "en", "em"];
+
+/// The kind of link an interface provides, as guessed from its name.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ConnectionType {
+    Ethernet,
+    Wifi,
+    Cellular,
+    /// Loopback, bridges, tunnels and other virtual interfaces, which carry no traffic of their own off the device.
+    Other,
+}
+
+/// How much is reported over the connection in use.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ReportingProfile {
+    Full,
+    /// Reports are sent less often and without per-core and per-process detail.
+    Reduced,
+}
+
+/// Guesses the kind of link an interface provides from its name.
+pub fn classify_interface(name: &str) -> ConnectionType {
+    let has_prefix = |prefixes: &[&str]| prefixes.iter().any(|prefix| name.starts_with(prefix));
+    if has_prefix(&CELLULAR_PREFIXES) {
+        ConnectionType::Cellular
+    } else if has_prefix(&WIFI_PREFIXES) {
+        ConnectionType::Wifi
+    } else if has_prefix(&ETHERNET_PREFIXES) {
+        ConnectionType::Ethernet
+    } else {
+        ConnectionType::Other
+    }
+}
+
+/// Whether the interface is connected, i.e. has an address beyond an IPv6 link-local one.
+fn is_connected(interface: &NetworkInterfaceSample) -> bool {
+    interface.ip_addresses.iter().any(|ip_address| !ip_address.starts_with("fe80:"))
+}
+
+/// The profile to report under: reduced when the device is only connected over cellular, and full otherwise,
+/// including when no connection can be identified.
+pub fn reporting_profile(interfaces: &[NetworkInterfaceSample]) -> ReportingProfile {
+    let connection_types: Vec<ConnectionType> = interfaces.iter()
+        .filter(|interface| is_connected(interface))
+        .map(|interface| classify_interface(interface.name.as_str()))
+        .collect();
+    let cellular_only = connection_types.contains(&ConnectionType::Cellular)
+        && !connection_types.iter().any(|t| *t == ConnectionType::Ethernet || *t == ConnectionType::Wifi);
+    if cellular_only {
+        ReportingProfile::Reduced
+    } else {
+        ReportingProfile::Full
+    }
+}
+
+/// Spaces reports out while the device reports under the reduced profile, sending only every Nth one.
+pub struct CellularThrottle {
+    interval_multiplier: u64,
+    skipped_reports: u64,
+}
+
+impl CellularThrottle {
+    pub fn new(interval_multiplier: u64) -> CellularThrottle {
+        CellularThrottle {
+            interval_multiplier,
+            skipped_reports: 0,
+        }
+    }
+
+    /// Decides whether a report due under the profile should be sent. The first report after switching to the
+    /// reduced profile goes out, then only every Nth one.
+    pub fn should_send_report(&mut self, profile: ReportingProfile) -> bool {
+        match profile {
+            ReportingProfile::Full => {
+                self.skipped_reports = 0;
+                true
+            }
+            ReportingProfile::Reduced => {
+                let send = self.skipped_reports == 0;
+                self.skipped_reports = (self.skipped_reports + 1) % self.interval_multiplier;
+                send
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::collector::NetworkInterfaceSample;
+    use crate::lib::network_type::{CellularThrottle, classify_interface, ConnectionType, reporting_profile, ReportingProfile};
+
+    fn interface(name: &str, ip_addresses: &[&str]) -> NetworkInterfaceSample {
+        NetworkInterfaceSample {
+            name: String::from(name),
+            mac_address: None,
+            ip_addresses: ip_addresses.iter().map(|ip_address| String::from(*ip_address)).collect(),
+        }
+    }
+
+    #[test]
+    fn classify_interface_names() {
+        for name in ["wwan0", "wwp0s20f0u6", "ppp0", "rmnet_data0"].iter() {
+            assert_eq!(ConnectionType::Cellular, classify_interface(name), "{}", name);
+        }
+        for name in ["wlan0", "wlp3s0", "wlx00c0ca123456", "ath0"].iter() {
+            assert_eq!(ConnectionType::Wifi, classify_interface(name), "{}", name);
+        }
+        for name in ["eth0", "enp0s31f6", "eno1", "enx00e04c680001", "em1"].iter() {
+            assert_eq!(ConnectionType::Ethernet, classify_interface(name), "{}", name);
+        }
+        for name in ["docker0", "veth1a2b3c", "br-5f1e", "tun0"].iter() {
+            assert_eq!(ConnectionType::Other, classify_interface(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn reduce_profile_on_cellular_only() {
+        let interfaces = [interface("wwan0", &["10.64.12.7"]), interface("docker0", &["172.17.0.1"])];
+        assert_eq!(ReportingProfile::Reduced, reporting_profile(&interfaces));
+    }
+
+    #[test]
+    fn keep_full_profile_alongside_other_links() {
+        let interfaces = [interface("wwan0", &["10.64.12.7"]), interface("wlan0", &["192.168.1.20"])];
+        assert_eq!(ReportingProfile::Full, reporting_profile(&interfaces));
+        let interfaces = [interface("wwan0", &["10.64.12.7"]), interface("eth0", &["192.168.1.21"])];
+        assert_eq!(ReportingProfile::Full, reporting_profile(&interfaces));
+        // Without any identifiable connection, nothing is held back
+        assert_eq!(ReportingProfile::Full, reporting_profile(&[interface("tun0", &["10.8.0.2"])]));
+        assert_eq!(ReportingProfile::Full, reporting_profile(&[]));
+    }
+
+    #[test]
+    fn ignore_disconnected_interfaces() {
+        // The Ethernet port has no address beyond a link-local one, leaving the modem as the only connection
+        let interfaces = [interface("eth0", &["fe80::42:acff:fe11:2"]), interface("wlan0", &[]), interface("ppp0", &["100.72.3.9"])];
+        assert_eq!(ReportingProfile::Reduced, reporting_profile(&interfaces));
+    }
+
+    #[test]
+    fn throttle_reports_on_reduced_profile() {
+        let mut throttle = CellularThrottle::new(3);
+        let sent: Vec<bool> = (0..7).map(|_| throttle.should_send_report(ReportingProfile::Reduced)).collect();
+        assert_eq!(vec![true, false, false, true, false, false, true], sent);
+        // Returning to the full profile sends every report, and a later switch starts over
+        assert!(throttle.should_send_report(ReportingProfile::Full));
+        assert!(throttle.should_send_report(ReportingProfile::Full));
+        assert!(throttle.should_send_report(ReportingProfile::Reduced));
+        assert!(!throttle.should_send_report(ReportingProfile::Reduced));
+    }
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+use crate::lib::common::RuntimeError;
+
+const NOTIFY_SOCKET_VAR: &str = "NOTIFY_SOCKET";
+
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum NotifyState {
+    /// Startup has finished and the service is reporting.
+    Ready,
+    /// Keepalive for the systemd watchdog.
+    Watchdog,
+}
+
+impl NotifyState {
+    fn assignment(&self) -> &'static str {
+        match self {
+            NotifyState::Ready => "READY=1",
+            NotifyState::Watchdog => "WATCHDOG=1",
+        }
+    }
+}
+
+pub fn notify_message(states: &[NotifyState]) -> String {
+    states.iter()
+        .map(|state| format!("{}\n", state.assignment()))
+        .collect()
+}
+
+/// Sends the states to the socket systemd passed in `NOTIFY_SOCKET`; does nothing when not run under systemd.
+pub fn sd_notify(states: &[NotifyState]) -> Result<(), Box<dyn Error>> {
+    match env::var(NOTIFY_SOCKET_VAR) {
+        Ok(socket_path) => send_notify(socket_path.as_str(), states),
+        Err(_) => Ok(()),
+    }
+}
+
+pub fn send_notify(socket_path: &str, states: &[NotifyState]) -> Result<(), Box<dyn Error>> {
+    let addr = match socket_path.strip_prefix('@') {
+        Some(abstract_name) => SocketAddr::from_abstract_name(abstract_name.as_bytes()),
+        None => SocketAddr::from_pathname(socket_path),
+    };
+    let result = addr.and_then(|addr| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(notify_message(states).as_bytes(), &addr)
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(format!("Unable to notify systemd: {}", e).as_str()));
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixDatagram;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::notify::{NotifyState, notify_message, send_notify};
+
+    #[test]
+    fn build_messages() {
+        assert_eq!("READY=1\n", notify_message(&[NotifyState::Ready]));
+        assert_eq!("WATCHDOG=1\n", notify_message(&[NotifyState::Watchdog]));
+        assert_eq!("READY=1\nWATCHDOG=1\n", notify_message(&[NotifyState::Ready, NotifyState::Watchdog]));
+    }
+
+    #[test]
+    fn send_to_socket() {
+        let socket_path = std::env::temp_dir().join(format!("device_stats_reporter_notify_{}", std::process::id()));
+        let _ = std::fs::remove_file(&socket_path);
+        let receiver = UnixDatagram::bind(&socket_path).unwrap();
+        send_notify(socket_path.to_str().unwrap(), &[NotifyState::Ready]).unwrap();
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        let _ = std::fs::remove_file(&socket_path);
+        assert_eq!("READY=1\n", String::from_utf8_lossy(&buf[..len]));
+    }
+
+    #[test]
+    fn send_to_missing_socket() {
+        let result = send_notify("/nonexistent/notify.sock", &[NotifyState::Watchdog]).err().unwrap();
+        assert!(result.to_string().starts_with("An error was encountered during runtime. Reason: Unable to notify systemd: "));
+    }
+}
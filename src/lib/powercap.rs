@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::lib::common::RuntimeError;
+
+pub const POWERCAP_ROOT: &str = "/sys/class/powercap";
+// Top-level RAPL zones are named e.g. "intel-rapl:0"; their subzones ("intel-rapl:0:0") are already included in them
+const RAPL_ZONE_PREFIX: &str = "rapl:";
+
+/// Sums the cumulative energy counters, in microjoules, of the top-level RAPL zones beneath the powercap root, or
+/// returns `None` if there are no such zones.
+pub fn read_energy_uj(powercap_root: &Path) -> Result<Option<u64>, Box<dyn Error>> {
+    let entries = match fs::read_dir(powercap_root) {
+        Ok(entries) => entries,
+        // Kernels without powercap support do not create the directory
+        Err(_) => return Ok(None)
+    };
+    let mut total_energy_uj = None;
+    for entry in entries.flatten() {
+        let zone_name = entry.file_name().to_string_lossy().to_string();
+        if !is_top_level_rapl_zone(zone_name.as_str()) {
+            continue;
+        }
+        let energy_path = entry.path().join("energy_uj");
+        let energy_uj = match fs::read_to_string(&energy_path) {
+            Ok(energy_uj) => energy_uj,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(
+                    format!("Unable to read {}: {}", energy_path.display(), e).as_str()
+                ));
+                return Err(error);
+            }
+        };
+        match parse_energy_uj(energy_uj.as_str()) {
+            Some(energy_uj) => total_energy_uj = Some(total_energy_uj.unwrap_or(0) + energy_uj),
+            None => {
+                let error = Box::new(RuntimeError::new(
+                    format!("Unexpected contents of {}: '{}'", energy_path.display(), energy_uj.trim()).as_str()
+                ));
+                return Err(error);
+            }
+        }
+    }
+    Ok(total_energy_uj)
+}
+
+fn is_top_level_rapl_zone(zone_name: &str) -> bool {
+    match zone_name.find(RAPL_ZONE_PREFIX) {
+        Some(prefix_start) => {
+            let zone_index = &zone_name[prefix_start + RAPL_ZONE_PREFIX.len()..];
+            !zone_index.is_empty() && zone_index.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false
+    }
+}
+
+fn parse_energy_uj(energy_uj: &str) -> Option<u64> {
+    energy_uj.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    use crate::lib::powercap::{is_top_level_rapl_zone, parse_energy_uj, read_energy_uj};
+
+    /// Lays out a powercap tree of zones and their energy counters in a fresh temporary directory.
+    fn powercap_tree(zones: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("powercap-{}", Uuid::new_v4()));
+        for (zone_name, energy_uj) in zones {
+            let zone_path = root.join(zone_name);
+            fs::create_dir_all(&zone_path).unwrap();
+            fs::write(zone_path.join("energy_uj"), energy_uj).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn parse_energy_counter() {
+        assert_eq!(Some(123456789), parse_energy_uj("123456789\n"));
+        assert_eq!(None, parse_energy_uj("unavailable\n"));
+    }
+
+    #[test]
+    fn identify_top_level_zones() {
+        assert!(is_top_level_rapl_zone("intel-rapl:0"));
+        assert!(is_top_level_rapl_zone("intel-rapl:1"));
+        assert!(!is_top_level_rapl_zone("intel-rapl:0:1"));
+        assert!(!is_top_level_rapl_zone("intel-rapl"));
+        assert!(!is_top_level_rapl_zone("dtpm"));
+    }
+
+    #[test]
+    fn sum_top_level_zones() {
+        let root = powercap_tree(&[
+            ("intel-rapl:0", "1000000\n"),
+            ("intel-rapl:0:0", "600000\n"),
+            ("intel-rapl:1", "250000\n"),
+        ]);
+        assert_eq!(Some(1250000), read_energy_uj(&root).unwrap());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn read_without_zones() {
+        let root = powercap_tree(&[]);
+        assert_eq!(None, read_energy_uj(&root).unwrap());
+    }
+
+    #[test]
+    fn read_malformed_counter() {
+        let root = powercap_tree(&[("intel-rapl:0", "n/a\n")]);
+        let result = read_energy_uj(&root).err().unwrap();
+        assert!(result.to_string().contains("Unexpected contents of"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+const MINUTES_PER_DAY: i64 = 1440;
+const SECONDS_PER_MINUTE: i64 = 60;
+
+/// A daily window, in local time at a fixed offset from UTC, during which reports are held back. A window whose end
+/// comes before its start runs over midnight.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct QuietHours {
+    /// Minutes past local midnight.
+    start: u32,
+    end: u32,
+    utc_offset_minutes: i32,
+}
+
+impl QuietHours {
+    pub fn new(start: u32, end: u32, utc_offset_minutes: i32) -> QuietHours {
+        QuietHours {
+            start,
+            end,
+            utc_offset_minutes,
+        }
+    }
+
+    /// Whether the Unix timestamp falls inside the window. The start is inside the window and the end is not.
+    pub fn contains(&self, timestamp: u64) -> bool {
+        let local_minutes = (timestamp as i64 / SECONDS_PER_MINUTE + self.utc_offset_minutes as i64)
+            .rem_euclid(MINUTES_PER_DAY) as u32;
+        if self.start < self.end {
+            self.start <= local_minutes && local_minutes < self.end
+        } else {
+            self.start <= local_minutes || local_minutes < self.end
+        }
+    }
+}
+
+/// Parses a `HH:MM` time of day into minutes past midnight.
+pub fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = parse_hours_minutes(time)?;
+    if hours >= 24 {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+/// Parses a `+HH:MM` or `-HH:MM` offset from UTC into minutes.
+pub fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let (sign, hours_minutes) = match offset.strip_prefix('+') {
+        Some(hours_minutes) => (1, hours_minutes),
+        None => (-1, offset.strip_prefix('-')?)
+    };
+    let (hours, minutes) = parse_hours_minutes(hours_minutes)?;
+    if hours > 14 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes) as i32)
+}
+
+fn parse_hours_minutes(value: &str) -> Option<(u32, u32)> {
+    let (hours, minutes) = value.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 || !hours.bytes().chain(minutes.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let minutes = minutes.parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    Some((hours.parse().ok()?, minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::quiet_hours::{parse_time_of_day, parse_utc_offset, QuietHours};
+
+    // 2021-06-15T00:00:00Z
+    const MIDNIGHT_UTC: u64 = 1_623_715_200;
+
+    fn at(hours: u64, minutes: u64) -> u64 {
+        MIDNIGHT_UTC + hours * 3600 + minutes * 60
+    }
+
+    #[test]
+    fn contain_times_within_the_day() {
+        let quiet_hours = QuietHours::new(2 * 60, 4 * 60, 0);
+        assert!(!quiet_hours.contains(at(1, 59)));
+        assert!(quiet_hours.contains(at(2, 0)));
+        assert!(quiet_hours.contains(at(3, 59)));
+        assert!(!quiet_hours.contains(at(4, 0)));
+    }
+
+    #[test]
+    fn contain_times_over_midnight() {
+        let quiet_hours = QuietHours::new(22 * 60, 6 * 60, 0);
+        assert!(quiet_hours.contains(at(23, 0)));
+        assert!(quiet_hours.contains(at(0, 30)));
+        assert!(!quiet_hours.contains(at(6, 0)));
+        assert!(!quiet_hours.contains(at(12, 0)));
+    }
+
+    #[test]
+    fn contain_local_times() {
+        // 02:00-04:00 at UTC-05:00 is 07:00-09:00 UTC
+        let quiet_hours = QuietHours::new(2 * 60, 4 * 60, -5 * 60);
+        assert!(!quiet_hours.contains(at(3, 0)));
+        assert!(quiet_hours.contains(at(7, 30)));
+        // 02:00-04:00 at UTC+05:30 is 20:30-22:30 UTC the previous day
+        let quiet_hours = QuietHours::new(2 * 60, 4 * 60, 5 * 60 + 30);
+        assert!(quiet_hours.contains(at(21, 0)));
+    }
+
+    #[test]
+    fn parse_times_and_offsets() {
+        assert_eq!(Some(22 * 60 + 30), parse_time_of_day("22:30"));
+        assert_eq!(Some(0), parse_time_of_day("00:00"));
+        assert_eq!(None, parse_time_of_day("24:00"));
+        assert_eq!(None, parse_time_of_day("7:00"));
+        assert_eq!(None, parse_time_of_day("07:60"));
+        assert_eq!(Some(5 * 60 + 30), parse_utc_offset("+05:30"));
+        assert_eq!(Some(-8 * 60), parse_utc_offset("-08:00"));
+        assert_eq!(None, parse_utc_offset("08:00"));
+        assert_eq!(None, parse_utc_offset("+15:00"));
+    }
+}
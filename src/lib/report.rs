@@ -1,21 +1,76 @@
 use serde::Serialize as SerdeSerialize;
+use serde_json::{Map, Value};
+
+use crate::lib::collector::{CpuSample, DISKS_SECTION, DiskSample, MemorySample};
+
+/// The version of the reporter that produced a message.
+pub const AGENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[derive(Debug,SerdeSerialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ReportMessage<'a> {
     pub device_id: &'a str,
     pub message_id: &'a str,
+    /// Shared by every report from one process run, so that consumers can group them and detect restarts.
+    pub session_id: &'a str,
+    /// Identical across every delivery attempt of the same report, unlike the per-attempt message ID.
+    pub dedup_id: &'a str,
     pub timestamp: &'a u64,
-    pub report: &'a SystemReport
+    pub agent_version: &'static str,
+    pub report: &'a SystemReport,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub collection_errors: &'a [CollectionError],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression_ratio: Option<f64>,
+    /// Sections left out of the report because they have not changed enough since they were last sent.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub unchanged_sections: &'a [&'static str],
+    /// Identifies the key that the message is signed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// Where the device is, in decimal degrees.
+#[derive(Debug,PartialEq,Clone,Copy,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
 }
 
 impl<'a> ReportMessage<'a> {
-    pub fn new(device_id: &'a str, message_id: &'a str, timestamp: &'a u64, report: &'a SystemReport) -> ReportMessage<'a> {
-        return ReportMessage{
+    pub fn new(device_id: &'a str, message_id: &'a str, session_id: &'a str, dedup_id: &'a str, timestamp: &'a u64, report: &'a SystemReport, collection_errors: &'a [CollectionError]) -> ReportMessage<'a> {
+        ReportMessage{
             device_id,
             message_id,
+            session_id,
+            dedup_id,
             timestamp,
-            report
+            agent_version: AGENT_VERSION,
+            report,
+            collection_errors,
+            compression_ratio: None,
+            unchanged_sections: &[],
+            key_id: None,
+            location: None,
+        }
+    }
+}
+
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionError {
+    pub section: String,
+    pub message: String,
+}
+
+impl CollectionError {
+    pub fn new(section: &str, message: &str) -> CollectionError {
+        CollectionError {
+            section: String::from(section),
+            message: String::from(message),
         }
     }
 }
@@ -25,7 +80,29 @@ impl<'a> ReportMessage<'a> {
 pub struct SystemReport {
     pub disks: Box<[DiskReport]>,
     pub cpus: Box<[CPUReport]>,
-    pub memory: MemoryReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_descriptors: Option<FileDescriptorReport>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub components: Box<[ComponentReport]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerReport>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub networks: Box<[NetworkReport]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<AgentReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processes: Option<ProcessCountReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<RawReport>,
+    /// The JSON object printed by the configured custom command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom: Option<Value>,
+    /// Set when per-core and per-process detail was dropped because the device was under heavy load or only connected
+    /// over cellular.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reduced_detail: bool,
 }
 
 #[derive(Debug,SerdeSerialize)]
@@ -34,6 +111,22 @@ pub struct DiskReport {
     pub name: String,
     pub disk_used: u64,
     pub disk_capacity: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_busy_pct: Option<f32>,
+    /// Inode usage, on Unix filesystems that have a fixed inode table.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inodes_used: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inodes_total: Option<u64>,
+    /// Whether the disk is mounted read-only, on platforms that expose mount flags.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+    /// The drive's temperature in the configured temperature unit, where its SMART data reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_temperature: Option<f32>,
+    /// Whether the disk's usage has reached the configured near-full percentage of its capacity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub near_full: Option<bool>,
 }
 
 #[derive(Debug,SerdeSerialize)]
@@ -42,8 +135,27 @@ pub struct CPUReport {
     pub name: String,
     pub brand: String,
     pub vendor_id: String,
-    pub frequency: u64,
+    /// The core's frequency, or its average over the frequency sample window when one is configured.
+    pub frequency: f64,
+    pub frequency_unit: FrequencyUnit,
+    /// The lowest frequency seen over the frequency sample window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_min: Option<f64>,
+    /// The highest frequency seen over the frequency sample window.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_max: Option<f64>,
     pub usage: f32,
+    /// The core's temperature in the configured temperature unit, where a component sensor is labelled with the core.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug,PartialEq,Clone,Copy,SerdeSerialize)]
+pub enum FrequencyUnit {
+    #[serde(rename = "MHz")]
+    MHz,
+    #[serde(rename = "GHz")]
+    GHz,
 }
 
 #[derive(Debug,SerdeSerialize)]
@@ -51,4 +163,607 @@ pub struct CPUReport {
 pub struct MemoryReport {
     pub memory_used: u64,
     pub memory_capacity: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffers: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached: Option<u64>,
+    /// Memory in use once buffers and reclaimable cache are discounted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truly_used: Option<u64>,
+    /// Pages swapped in from swap space per second since the previous report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_in_per_sec: Option<f64>,
+    /// Pages swapped out to swap space per second since the previous report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_out_per_sec: Option<f64>,
+}
+
+/// System-wide file descriptor usage; only available on Linux.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDescriptorReport {
+    pub open_file_descriptors: u64,
+    pub max_file_descriptors: u64,
+}
+
+/// Energy drawn by the processor packages, as measured by RAPL; only available on Linux.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerReport {
+    /// Average power draw since the previous report; absent from the first report and after the counter wraps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watts: Option<f64>,
+    pub energy_joules: f64,
+}
+
+/// The reporter's own process, so that operators can detect restarts and correlate reports with system logs.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentReport {
+    pub pid: u32,
+    /// Seconds since the Unix epoch at which the process started.
+    pub start_time: u64,
+}
+
+/// How many processes, and threads across them, are running; a lightweight summary in place of a process list.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessCountReport {
+    pub process_count: u64,
+    /// Only counted on Linux.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_count: Option<u64>,
+}
+
+/// The readings the report was generated from, before any subtraction or unit conversion, for troubleshooting.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawReport {
+    pub disks: Vec<DiskSample>,
+    pub cpus: Vec<CpuSample>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemorySample>,
+}
+
+/// A network interface's addresses, for inventory and correlating a device with its traffic.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkReport {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ip_addresses: Vec<String>,
+}
+
+/// A hardware component's temperature sensor reading.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentReport {
+    pub label: String,
+    pub temperature: f32,
+    pub temperature_unit: TemperatureUnit,
+}
+
+#[derive(Debug,PartialEq,Clone,Copy,SerdeSerialize)]
+pub enum TemperatureUnit {
+    #[serde(rename = "C")]
+    Celsius,
+    #[serde(rename = "F")]
+    Fahrenheit,
+}
+
+/// The casing of keys in published JSON. Messages are declared in camel case and rewritten when another case is wanted.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum KeyCase {
+    Camel,
+    Snake,
+}
+
+/// How reports are rendered for publishing.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ReportFormat {
+    Json,
+    /// InfluxDB line protocol, for ingestion into InfluxDB without a bridge.
+    InfluxLine,
+}
+
+/// Rewrites every object key in the JSON value into the given case.
+pub fn apply_key_case(value: Value, key_case: KeyCase) -> Value {
+    if key_case == KeyCase::Camel {
+        return value;
+    }
+    match value {
+        Value::Object(object) => Value::Object(
+            object.into_iter()
+                .map(|(key, value)| (to_snake_case(key.as_str()), apply_key_case(value, key_case)))
+                .collect::<Map<String, Value>>()
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(|v| apply_key_case(v, key_case)).collect()),
+        _ => value
+    }
+}
+
+/// Flattens the JSON value into a single-level object whose keys are the dotted paths to each value, with array
+/// elements addressed by index. Empty objects and arrays hold no values and so are left out.
+pub fn flatten_value(value: Value) -> Value {
+    let mut flattened = Map::new();
+    flatten_into(&mut flattened, None, value);
+    Value::Object(flattened)
+}
+
+fn flatten_into(flattened: &mut Map<String, Value>, path: Option<String>, value: Value) {
+    let child_path = |key: String| match &path {
+        Some(path) => format!("{}.{}", path, key),
+        None => key,
+    };
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten_into(flattened, Some(child_path(key)), value);
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                flatten_into(flattened, Some(child_path(index.to_string())), value);
+            }
+        }
+        _ => {
+            flattened.insert(path.unwrap_or_default(), value);
+        }
+    }
+}
+
+fn to_snake_case(key: &str) -> String {
+    let mut snake_key = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_uppercase() {
+            snake_key.push('_');
+            snake_key.extend(c.to_lowercase());
+        } else {
+            snake_key.push(c);
+        }
+    }
+    snake_key
+}
+
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatMessage<'a> {
+    pub device_id: &'a str,
+    pub timestamp: &'a u64,
+    pub sequence: &'a u64,
+}
+
+impl<'a> HeartbeatMessage<'a> {
+    pub fn new(device_id: &'a str, timestamp: &'a u64, sequence: &'a u64) -> HeartbeatMessage<'a> {
+        HeartbeatMessage {
+            device_id,
+            timestamp,
+            sequence,
+        }
+    }
+}
+
+/// One piece of a report payload too large to publish whole. Consumers collect the chunks sharing a message ID and
+/// join their base64-decoded data in index order to recover the payload.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkMessage<'a> {
+    pub device_id: &'a str,
+    pub message_id: &'a str,
+    /// Counted from 0.
+    pub chunk_index: u32,
+    pub chunk_total: u32,
+    pub data: &'a str,
+}
+
+impl<'a> ChunkMessage<'a> {
+    pub fn new(device_id: &'a str, message_id: &'a str, chunk_index: u32, chunk_total: u32, data: &'a str) -> ChunkMessage<'a> {
+        ChunkMessage {
+            device_id,
+            message_id,
+            chunk_index,
+            chunk_total,
+            data,
+        }
+    }
+}
+
+/// Published out of band when a section's usage rises above its alert threshold.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertMessage<'a> {
+    pub device_id: &'a str,
+    pub timestamp: &'a u64,
+    pub section: &'a str,
+    /// The usage percentage that crossed the threshold.
+    pub value: f64,
+    pub threshold: f64,
+}
+
+impl<'a> AlertMessage<'a> {
+    pub fn new(device_id: &'a str, timestamp: &'a u64, section: &'a str, value: f64, threshold: f64) -> AlertMessage<'a> {
+        AlertMessage {
+            device_id,
+            timestamp,
+            section,
+            value,
+            threshold,
+        }
+    }
+}
+
+/// Published out of band when a mount's free space drops below its alert threshold.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeSpaceAlertMessage<'a> {
+    pub device_id: &'a str,
+    pub timestamp: &'a u64,
+    pub section: &'a str,
+    pub mount_point: &'a str,
+    pub available_bytes: u64,
+    pub threshold_bytes: u64,
+}
+
+impl<'a> FreeSpaceAlertMessage<'a> {
+    pub fn new(device_id: &'a str, timestamp: &'a u64, mount_point: &'a str, available_bytes: u64, threshold_bytes: u64) -> FreeSpaceAlertMessage<'a> {
+        FreeSpaceAlertMessage {
+            device_id,
+            timestamp,
+            section: DISKS_SECTION,
+            mount_point,
+            available_bytes,
+            threshold_bytes,
+        }
+    }
+}
+
+/// Published out of band when a disk that was mounted read-write is found mounted read-only, as happens when the
+/// kernel remounts a failing disk.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadOnlyAlertMessage<'a> {
+    pub device_id: &'a str,
+    pub timestamp: &'a u64,
+    pub section: &'a str,
+    pub disk: &'a str,
+    pub read_only: bool,
+}
+
+impl<'a> ReadOnlyAlertMessage<'a> {
+    pub fn new(device_id: &'a str, timestamp: &'a u64, disk: &'a str) -> ReadOnlyAlertMessage<'a> {
+        ReadOnlyAlertMessage {
+            device_id,
+            timestamp,
+            section: DISKS_SECTION,
+            disk,
+            read_only: true,
+        }
+    }
+}
+
+/// Published when a check fails or report sections fail to collect, so that device-side problems reach a central
+/// monitor rather than only stderr.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticMessage<'a> {
+    pub device_id: &'a str,
+    pub timestamp: &'a u64,
+    /// What went wrong: a failed `check`, a `collection` of sections that failed, or a rejected `config` update.
+    pub kind: &'a str,
+    pub message: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub collection_errors: &'a [CollectionError],
+    /// Diagnostics dropped by the rate limit since the previous one was published.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub suppressed: u64,
+}
+
+impl<'a> DiagnosticMessage<'a> {
+    pub fn new(device_id: &'a str, timestamp: &'a u64, kind: &'a str, message: &'a str) -> DiagnosticMessage<'a> {
+        DiagnosticMessage {
+            device_id,
+            timestamp,
+            kind,
+            message,
+            collection_errors: &[],
+            suppressed: 0,
+        }
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Published once on startup, ahead of the first report, so that consumers learn of the device and what it reports
+/// straight away.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupMessage<'a> {
+    pub device_id: &'a str,
+    pub session_id: &'a str,
+    pub timestamp: u64,
+    pub agent_version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_capacity: Option<u64>,
+    /// The sections that reports carry.
+    pub sections: Vec<&'static str>,
+}
+
+/// Published when the reporter panics, as a richer complement to a last-will message.
+#[derive(Debug,SerdeSerialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashMessage<'a> {
+    pub device_id: &'a str,
+    pub timestamp: u64,
+    pub agent_version: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+impl<'a> CrashMessage<'a> {
+    pub fn new(device_id: &'a str, timestamp: u64, message: String, location: Option<String>) -> CrashMessage<'a> {
+        CrashMessage {
+            device_id,
+            timestamp,
+            agent_version: AGENT_VERSION,
+            message,
+            location,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::Value;
+
+    use crate::lib::report::{apply_key_case, CollectionError, flatten_value, CPUReport, DiskReport, FrequencyUnit, KeyCase, MemoryReport, ReportMessage, SystemReport};
+
+    #[test]
+    fn serialize_collection_errors() {
+        let report = SystemReport {
+            disks: Box::new([]),
+            cpus: Box::new([]),
+            memory: None,
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        };
+        let collection_errors = vec![CollectionError::new("memory", "Memory unavailable")];
+        let report_message = ReportMessage::new("Test Device Name", "1", "session-1", "session-1-1", &1, &report, &collection_errors);
+        assert_eq!(
+            r#"{"deviceId":"Test Device Name","messageId":"1","sessionId":"session-1","dedupId":"session-1-1","timestamp":1,"agentVersion":"0.1.0","report":{"disks":[],"cpus":[]},"collectionErrors":[{"section":"memory","message":"Memory unavailable"}]}"#,
+            serde_json::to_string(&report_message).unwrap()
+        );
+    }
+
+    #[test]
+    fn serialize_without_collection_errors() {
+        let report = SystemReport {
+            disks: Box::new([]),
+            cpus: Box::new([]),
+            memory: None,
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        };
+        let report_message = ReportMessage::new("Test Device Name", "1", "session-1", "session-1-1", &1, &report, &[]);
+        assert_eq!(
+            r#"{"deviceId":"Test Device Name","messageId":"1","sessionId":"session-1","dedupId":"session-1-1","timestamp":1,"agentVersion":"0.1.0","report":{"disks":[],"cpus":[]}}"#,
+            serde_json::to_string(&report_message).unwrap()
+        );
+    }
+
+    #[test]
+    fn serialize_agent_version() {
+        let report = SystemReport {
+            disks: Box::new([]),
+            cpus: Box::new([]),
+            memory: None,
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        };
+        let report_message = ReportMessage::new("Test Device Name", "1", "session-1", "session-1-1", &1, &report, &[]);
+        let report_json: Value = serde_json::to_value(&report_message).unwrap();
+        assert_eq!(env!("CARGO_PKG_VERSION"), report_json["agentVersion"]);
+    }
+
+    /// Compares the message against a committed JSON fixture so that schema changes show up in review.
+    fn assert_golden(fixture: &str, report_message: &ReportMessage) {
+        let expected = fs::read_to_string(format!("resources/test/golden/{}", fixture)).unwrap();
+        assert_eq!(expected.trim_end(), serde_json::to_string_pretty(report_message).unwrap());
+    }
+
+    #[test]
+    fn golden_empty_report() {
+        let report = SystemReport {
+            disks: Box::new([]),
+            cpus: Box::new([]),
+            memory: None,
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        };
+        let mut report_message = ReportMessage::new("Test Device Name", "1", "session-1", "session-1-1", &1, &report, &[]);
+        // Pinned so that the fixture does not change with every release
+        report_message.agent_version = "0.0.0";
+        assert_golden("empty_report.json", &report_message);
+    }
+
+    #[test]
+    fn golden_full_report() {
+        let report = SystemReport {
+            disks: Box::new([DiskReport {
+                name: String::from("/dev/sda1"),
+                disk_used: 600,
+                disk_capacity: 1000,
+                io_busy_pct: Some(25.0),
+                inodes_used: None,
+                inodes_total: None,
+                read_only: None,
+                disk_temperature: None,
+                near_full: None,
+            }]),
+            cpus: Box::new([CPUReport {
+                name: String::from("cpu0"),
+                brand: String::from("Test Brand"),
+                vendor_id: String::from("Test Vendor"),
+                frequency: 2.4,
+                frequency_unit: FrequencyUnit::GHz,
+                frequency_min: None,
+                frequency_max: None,
+                usage: 12.5,
+                temperature: None,
+            }]),
+            memory: Some(MemoryReport {
+                memory_used: 1536,
+                memory_capacity: 2048,
+                buffers: None,
+                cached: None,
+                truly_used: None,
+                swap_in_per_sec: None,
+                swap_out_per_sec: None,
+            }),
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        };
+        let collection_errors = vec![CollectionError::new("diskIo", "Disk statistics unavailable")];
+        let mut report_message = ReportMessage::new("Test Device Name", "1", "session-1", "session-1-1", &1, &report, &collection_errors);
+        report_message.agent_version = "0.0.0";
+        report_message.compression_ratio = Some(0.5);
+        assert_golden("full_report.json", &report_message);
+    }
+
+    #[test]
+    fn flatten_report() {
+        let report_json = serde_json::json!({
+            "deviceId": "Test Device Name",
+            "report": {
+                "disks": [{"name": "sda1", "diskUsed": 600}, {"name": "sdb1", "diskUsed": 200}],
+                "cpus": [],
+                "memory": {"memoryUsed": 1536, "memoryCapacity": 2048}
+            }
+        });
+        assert_eq!(
+            serde_json::json!({
+                "deviceId": "Test Device Name",
+                "report.disks.0.name": "sda1",
+                "report.disks.0.diskUsed": 600,
+                "report.disks.1.name": "sdb1",
+                "report.disks.1.diskUsed": 200,
+                "report.memory.memoryUsed": 1536,
+                "report.memory.memoryCapacity": 2048
+            }),
+            flatten_value(report_json)
+        );
+    }
+
+    #[test]
+    fn apply_camel_key_case() {
+        let report_json = serde_json::json!({"deviceId": "Test Device Name", "report": {"disks": [{"diskUsed": 600}]}});
+        assert_eq!(report_json.clone(), apply_key_case(report_json, KeyCase::Camel));
+    }
+
+    #[test]
+    fn apply_snake_key_case() {
+        let report = SystemReport {
+            disks: Box::new([DiskReport {
+                name: String::from("/dev/sda1"),
+                disk_used: 600,
+                disk_capacity: 1000,
+                io_busy_pct: Some(25.0),
+                inodes_used: None,
+                inodes_total: None,
+                read_only: None,
+                disk_temperature: None,
+                near_full: None,
+            }]),
+            cpus: Box::new([]),
+            memory: Some(MemoryReport {
+                memory_used: 1536,
+                memory_capacity: 2048,
+                buffers: None,
+                cached: None,
+                truly_used: None,
+                swap_in_per_sec: None,
+                swap_out_per_sec: None,
+            }),
+            file_descriptors: None,
+            components: Box::new([]),
+            networks: Box::new([]),
+            power: None,
+            agent: None,
+            processes: None,
+            raw: None,
+            custom: None,
+            reduced_detail: false,
+        };
+        let mut report_message = ReportMessage::new("Test Device Name", "1", "session-1", "session-1-1", &1, &report, &[]);
+        report_message.agent_version = "0.0.0";
+        let report_json = serde_json::to_value(&report_message).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "device_id": "Test Device Name",
+                "message_id": "1",
+                "session_id": "session-1",
+                "dedup_id": "session-1-1",
+                "timestamp": 1,
+                "agent_version": "0.0.0",
+                "report": {
+                    "disks": [{"name": "/dev/sda1", "disk_used": 600, "disk_capacity": 1000, "io_busy_pct": 25.0}],
+                    "cpus": [],
+                    "memory": {"memory_used": 1536, "memory_capacity": 2048}
+                }
+            }),
+            apply_key_case(report_json, KeyCase::Snake)
+        );
+    }
 }
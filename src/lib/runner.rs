@@ -1,27 +1,166 @@
+use std::collections::BTreeMap;
 use std::env::args;
 use std::error::Error;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::fmt;
+use std::io::{stdout, Write};
+use std::sync::{Arc, mpsc};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
-use lz4_flex::compress_prepend_size;
-use paho_mqtt::{Client, ConnectOptions};
-use sysinfo::{DiskExt, ProcessorExt, System, SystemExt};
+use paho_mqtt::{Message, MessageBuilder, Properties, PropertyCode};
+use serde::Serialize;
+use serde_json::Value;
+use sysinfo::{System, SystemExt};
 
-use crate::lib::common::{MINUTES_MULTIPLIER, RuntimeError, RuntimeMode};
-use crate::lib::config::{load_config, RunnerConfig};
-use crate::lib::report::{CPUReport, DiskReport, MemoryReport, SystemReport, ReportMessage};
+use crate::lib::alert::{FreeSpaceMonitor, ReadOnlyMonitor, ThresholdMonitor};
+use crate::lib::budget::ByteBudget;
+use crate::lib::change::ChangeFilter;
+use crate::lib::chunk::{chunk_data_bytes, encode_base64};
+use crate::lib::common::{ErrorFormat, IllegalArgumentError, MINUTES_MULTIPLIER, RuntimeError, RuntimeMode};
+use crate::lib::compression::{Codec, CODECS, compress, compress_with_header, decode, pad_payload, REPORT_DICTIONARY};
+use crate::lib::config::{apply_overrides, ConfigOverrides, DestinationConfig, load_config, parse_config_update, RunnerConfig};
+use crate::lib::crash::install_crash_hook;
+use crate::lib::destination::{Destination, HttpDestination};
+use crate::lib::history::ReportHistory;
+use crate::lib::idle::IdleDetector;
+use crate::lib::message_id::MessageIdGenerator;
+use crate::lib::network_type::{CellularThrottle, reporting_profile, ReportingProfile};
+use crate::lib::collector::{AGENT_SECTION, COMPONENTS_SECTION, CPUS_SECTION, CUSTOM_SECTION, DISKS_SECTION, FILE_DESCRIPTORS_SECTION, MEMORY_SECTION, NETWORKS_SECTION, POWER_SECTION, PROCESSES_SECTION, ReportGenerator, SystemSource};
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+use crate::lib::notify::{NotifyState, sd_notify};
+use crate::lib::line_protocol::render_report;
+use crate::lib::report::{AGENT_VERSION, AlertMessage, apply_key_case, ChunkMessage, CollectionError, DiagnosticMessage, DiskReport, flatten_value, FreeSpaceAlertMessage, HeartbeatMessage, KeyCase, Location, ReadOnlyAlertMessage, ReportFormat, ReportMessage, StartupMessage, SystemReport};
+use crate::lib::quiet_hours::QuietHours;
+use crate::lib::schedule::{RateGuard, Schedule, ScheduledTask};
+use crate::lib::service::RunControl;
+use crate::lib::signing::SigningKey;
+use crate::lib::spool::Spool;
+use crate::lib::state::DeviceState;
+use crate::lib::stats::CompressionStats;
+use crate::lib::transport::{DisabledTransport, MqttTransport, Transport, uses_mqtt};
 use uuid::Uuid;
 
+// Command-line flags
+const COMPARE_COMPRESSION_FLAG: &str = "--compare-compression";
+const INTERVAL_FLAG: &str = "--interval";
+const TOPIC_FLAG: &str = "--topic";
+const STATS_FLAG: &str = "--stats";
+const ERROR_FORMAT_FLAG: &str = "--error-format";
+const SELF_TEST_FLAG: &str = "--self-test";
+const SERVICE_FLAG: &str = "--service";
+// Values of the error format flag
+const TEXT_ERROR_FORMAT: &str = "text";
+const JSON_ERROR_FORMAT: &str = "json";
+// How long a new connection must survive before the device ID is taken to be unique
+const DUPLICATE_ID_SETTLE_TIME: Duration = Duration::from_secs(3);
+// Commands received over the command topic
+const DUMP_HISTORY_COMMAND: &str = "dump_history";
+// Commands are subscribed to at least once so that a request is not lost
+const COMMAND_QOS: i32 = 1;
+// Kinds of diagnostic
+const CHECK_DIAGNOSTIC: &str = "check";
+const COLLECTION_DIAGNOSTIC: &str = "collection";
+const CONFIG_DIAGNOSTIC: &str = "config";
+// How long the self-test waits for its report to come back from the broker, and how often it looks
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SELF_TEST_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// The wait before the first retry of a failed delivery, doubled for every further retry up to the maximum
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAXIMUM_PUBLISH_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 pub fn run() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = args().collect();
-    let runner_config = load_config(args.get(1))?;
-    let runner = Arc::new(Runner::new(&runner_config)?);
+    let command_line = parse_command_line(&args[1..])?;
+    if command_line.service {
+        return start_service();
+    }
+    execute(command_line, None)
+}
+
+/// Runs the reporter under a service manager, which stops and pauses it through the given control rather than Ctrl-C.
+#[cfg(all(windows, feature = "windows-service"))]
+pub fn run_controlled(run_control: Arc<RunControl>) -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = args().collect();
+    let command_line = parse_command_line(&args[1..])?;
+    execute(command_line, Some(run_control))
+}
+
+#[cfg(all(windows, feature = "windows-service"))]
+fn start_service() -> Result<(), Box<dyn Error>> {
+    crate::lib::service::windows::start()
+}
+
+#[cfg(not(all(windows, feature = "windows-service")))]
+fn start_service() -> Result<(), Box<dyn Error>> {
+    let error = Box::new(IllegalArgumentError::new(
+        format!("The {} flag requires a Windows build with the windows-service feature", SERVICE_FLAG).as_str()
+    ));
+    Err(error)
+}
+
+/// Runs the reporter as the command line directs. Without a run control, the continuous loop is stopped by Ctrl-C.
+fn execute(command_line: CommandLine, run_control: Option<Arc<RunControl>>) -> Result<(), Box<dyn Error>> {
+    let mut runner_config = load_config(command_line.config_path.as_ref())?;
+    apply_overrides(&mut runner_config, &command_line.overrides)?;
+    if command_line.show_stats {
+        return match &runner_config.compression_stats_file {
+            Some(compression_stats_file) => {
+                println!("{}", CompressionStats::load(compression_stats_file.as_str()));
+                Ok(())
+            }
+            None => {
+                let error = Box::new(IllegalArgumentError::new("The --stats command requires a compression statistics file"));
+                Err(error)
+            }
+        };
+    }
+    if command_line.compare_compression {
+        let mut runner = Runner::new(&runner_config)?;
+        return runner.compare_compression(&mut System::new_all());
+    }
+    if command_line.self_test {
+        let transport = MqttTransport::with_consumer(&runner_config)?;
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport));
+        runner.self_test(&mut System::new_all(), SELF_TEST_TIMEOUT)?;
+        println!("Self-test passed: the report was received back from the broker unchanged");
+        return Ok(());
+    }
+    if let Some(warning) = default_check_interval_warning(&runner_config) {
+        eprintln!("{}", warning);
+    }
+    if runner_config.insecure_skip_verify {
+        eprintln!(
+            "WARNING: insecure_skip_verify is enabled, so the broker's TLS certificate is not verified. The connection \
+            can be intercepted, exposing the broker credentials and reports; never use this in production."
+        );
+    }
+    if let Some(crash_topic) = &runner_config.crash_topic {
+        let transport = MqttTransport::for_crash_hook(&runner_config)?;
+        install_crash_hook(runner_config.device_id.clone(), crash_topic.clone(), runner_config.qos, Box::new(transport));
+    }
+    let mut runner = Runner::new(&runner_config)?;
+    if runner_config.probe_on_start && uses_mqtt(&runner_config) {
+        runner.probe()?;
+    }
+    if runner_config.duplicate_id_check && !runner_config.clean_session && uses_mqtt(&runner_config)
+        && runner.detect_duplicate_device_id(DUPLICATE_ID_SETTLE_TIME)? {
+        eprintln!("{}", duplicate_device_id_warning(runner_config.device_id.as_str()));
+    }
+    #[cfg(all(target_os = "linux", feature = "systemd"))]
+    if let Err(e) = sd_notify(&[NotifyState::Ready]) {
+        eprintln!("{}", e);
+    }
     let mut sys = System::new_all();
+    if let Err(e) = runner.send_startup_report(&sys) {
+        eprintln!("An error occurred while sending the startup report: {}", e);
+    }
     match runner_config.runtime_mode {
         RuntimeMode::Single => {
-            match runner.execute_check(&mut sys) {
+            let result = match runner_config.check_deadline_secs {
+                Some(check_deadline_secs) => execute_check_with_deadline(runner, sys, Duration::from_secs(check_deadline_secs)),
+                None => runner.execute_check(&mut sys)
+            };
+            match result {
                 Ok(_) => {}
                 Err(e) => {
                     eprintln!("An error occurred during check: {}", e);
@@ -29,156 +168,3091 @@ pub fn run() -> Result<(), Box<dyn Error>> {
             };
         }
         RuntimeMode::Continuous => {
-            let running = Arc::new(AtomicBool::new(true));
-            let r = running.clone();
+            let handle_ctrl_c = run_control.is_none();
+            let run_control = run_control.unwrap_or_else(|| Arc::new(RunControl::new()));
+            let r = run_control.clone();
+            let mut schedule = Schedule::new(
+                Duration::from_secs(runner_config.check_interval * MINUTES_MULTIPLIER),
+                runner_config.heartbeat_interval_secs.map(Duration::from_secs),
+            );
+            let connection_check_interval = Duration::from_secs(runner_config.connection_check_secs);
+            let threshold_check_interval = Duration::from_secs(runner_config.threshold_check_secs);
             let run_thread = thread::spawn(move || {
-                while running.load(Ordering::SeqCst) {
-                    match runner.execute_check(&mut sys) {
-                        Ok(_) => {}
+                let start = Instant::now();
+                let mut next_threshold_check = threshold_check_interval;
+                while run_control.is_running() {
+                    let (due, task) = schedule.next_task();
+                    while run_control.is_running() && start.elapsed() < due {
+                        let mut wake = due;
+                        if runner.keep_connected {
+                            wake = wake.min(start.elapsed() + connection_check_interval);
+                        }
+                        if runner.monitors_thresholds() {
+                            wake = wake.min(next_threshold_check);
+                        }
+                        thread::park_timeout(wake.saturating_sub(start.elapsed()));
+                        if runner.keep_connected {
+                            if let Err(e) = runner.ensure_connected() {
+                                eprintln!("An error occurred while reconnecting to the broker: {}", e);
+                            }
+                            if let Err(e) = runner.handle_commands() {
+                                eprintln!("An error occurred while handling commands: {}", e);
+                            }
+                            if let Some(report_interval) = runner.report_interval_update.take() {
+                                schedule.set_report_interval(report_interval);
+                            }
+                        }
+                        // Thresholds are checked between scheduled checks, but not while paused
+                        if runner.monitors_thresholds() && start.elapsed() >= next_threshold_check {
+                            next_threshold_check = start.elapsed() + threshold_check_interval;
+                            if !run_control.is_paused() {
+                                if let Err(e) = runner.check_thresholds(&mut sys) {
+                                    eprintln!("An error occurred while checking thresholds: {}", e);
+                                }
+                            }
+                        }
+                    }
+                    if !run_control.is_running() {
+                        break;
+                    }
+                    // Tasks falling due while paused are dropped rather than replayed on continue
+                    if run_control.is_paused() {
+                        continue;
+                    }
+                    let result = match task {
+                        ScheduledTask::Report => runner.execute_check(&mut sys),
+                        ScheduledTask::Heartbeat => runner.send_heartbeat(),
+                    };
+                    match result {
+                        Ok(_) => {
+                            #[cfg(all(target_os = "linux", feature = "systemd"))]
+                            if let ScheduledTask::Report = task {
+                                if let Err(e) = sd_notify(&[NotifyState::Watchdog]) {
+                                    eprintln!("{}", e);
+                                }
+                            }
+                        }
                         Err(e) => {
                             eprintln!("An error occurred during check runtime loop: {}", e);
                         }
                     }
-                    thread::park_timeout(Duration::from_secs(runner_config.check_interval * MINUTES_MULTIPLIER));
                 }
-            });
-            let run_thread_shutdown = run_thread.thread().clone();
-            match ctrlc::set_handler(move || {
-                r.store(false, Ordering::SeqCst);
-                run_thread_shutdown.unpark();
-            }) {
-                Ok(()) => {}
-                Err(e) => {
-                    let error = Box::new(RuntimeError::new(e.to_string().as_str()));
-                    return Err(error);
+                if let Err(e) = runner.disconnect_on_shutdown() {
+                    eprintln!("An error occurred while disconnecting from the broker: {}", e);
                 }
-            };
+                let _ = writeln!(runner.output, "{}", runner.run_summary);
+            });
+            r.attach(run_thread.thread().clone());
+            if handle_ctrl_c {
+                install_stop_handler(ctrlc::set_handler, r);
+            }
             run_thread.join().unwrap();
         }
     }
     Ok(())
 }
 
+/// Runs the check on a worker thread, abandoning it with a timeout error if it has not finished by the deadline. An
+/// abandoned check keeps its thread until the process exits, so this is only meant for the single check of a run.
+fn execute_check_with_deadline<S>(mut runner: Runner, mut sys: S, deadline: Duration) -> Result<(), Box<dyn Error>>
+    where S: SystemSource + Send + 'static {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // Errors cannot cross threads, so the check's error is passed back as its message
+        let result = runner.execute_check(&mut sys).map_err(|e| e.to_string());
+        let _ = sender.send(result);
+    });
+    match receiver.recv_timeout(deadline) {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => {
+            let error = Box::new(RuntimeError::new(e.as_str()));
+            Err(error)
+        }
+        Err(_) => {
+            let error = Box::new(RuntimeError::new(
+                format!("Check did not finish within its deadline of {} seconds", deadline.as_secs_f64()).as_str()
+            ));
+            Err(error)
+        }
+    }
+}
+
+/// Registers the handler that stops the continuous loop on Ctrl-C, returning whether it was registered. The loop is
+/// already running by then, so a failed registration is only warned about: the reporter keeps working and is stopped
+/// by terminating the process instead.
+fn install_stop_handler<S, E>(set_handler: S, run_control: Arc<RunControl>) -> bool
+    where S: FnOnce(Box<dyn FnMut() + Send>) -> Result<(), E>, E: fmt::Display {
+    let result = set_handler(Box::new(move || run_control.stop()));
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("WARNING: Unable to register the Ctrl-C handler, so the reporter cannot shut down gracefully: {}", e);
+            false
+        }
+    }
+}
+
+/// The command-line arguments: an optional config file path, plus flags.
+#[derive(Debug,PartialEq)]
+struct CommandLine {
+    config_path: Option<String>,
+    compare_compression: bool,
+    show_stats: bool,
+    self_test: bool,
+    /// Runs as a Windows service, handing control to the service control manager.
+    service: bool,
+    error_format: ErrorFormat,
+    overrides: ConfigOverrides,
+}
+
+impl Default for CommandLine {
+    fn default() -> CommandLine {
+        CommandLine {
+            config_path: None,
+            compare_compression: false,
+            show_stats: false,
+            self_test: false,
+            service: false,
+            error_format: ErrorFormat::Text,
+            overrides: ConfigOverrides::default(),
+        }
+    }
+}
+
+fn parse_command_line(args: &[String]) -> Result<CommandLine, Box<dyn Error>> {
+    let mut command_line = CommandLine::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            COMPARE_COMPRESSION_FLAG => command_line.compare_compression = true,
+            STATS_FLAG => command_line.show_stats = true,
+            SELF_TEST_FLAG => command_line.self_test = true,
+            SERVICE_FLAG => command_line.service = true,
+            ERROR_FORMAT_FLAG => command_line.error_format = parse_error_format(flag_value(ERROR_FORMAT_FLAG, args.next())?.as_str())?,
+            INTERVAL_FLAG => command_line.overrides.check_interval = Some(flag_value(INTERVAL_FLAG, args.next())?),
+            TOPIC_FLAG => command_line.overrides.topic = Some(flag_value(TOPIC_FLAG, args.next())?),
+            _ if command_line.config_path.is_none() => command_line.config_path = Some(arg.clone()),
+            _ => {
+                let error = Box::new(IllegalArgumentError::new(format!("Unexpected argument '{}'", arg).as_str()));
+                return Err(error);
+            }
+        }
+    }
+    Ok(command_line)
+}
+
+/// The format the error ending a run is printed in. This is looked up on its own, rather than through the full
+/// command-line parse, so that errors in the rest of the command line are printed in the requested format too.
+pub fn error_format(args: &[String]) -> ErrorFormat {
+    args.windows(2)
+        .filter(|pair| pair[0] == ERROR_FORMAT_FLAG)
+        .find_map(|pair| parse_error_format(pair[1].as_str()).ok())
+        .unwrap_or(ErrorFormat::Text)
+}
+
+fn parse_error_format(error_format: &str) -> Result<ErrorFormat, Box<dyn Error>> {
+    match error_format {
+        TEXT_ERROR_FORMAT => Ok(ErrorFormat::Text),
+        JSON_ERROR_FORMAT => Ok(ErrorFormat::Json),
+        _ => {
+            let error = Box::new(IllegalArgumentError::new(
+                format!("Unexpected error format '{}'; supported formats are text and json", error_format).as_str()
+            ));
+            Err(error)
+        }
+    }
+}
+
+fn flag_value(flag: &str, value: Option<&String>) -> Result<String, Box<dyn Error>> {
+    match value {
+        Some(value) => Ok(value.clone()),
+        None => {
+            let error = Box::new(IllegalArgumentError::new(format!("Flag '{}' requires a value", flag).as_str()));
+            Err(error)
+        }
+    }
+}
+
+/// Counters describing a continuous run, printed when it shuts down.
+struct RunSummary {
+    started: Instant,
+    checks: u64,
+    successes: u64,
+    failures: u64,
+    bytes_transmitted: u64,
+}
+
+impl RunSummary {
+    fn new() -> RunSummary {
+        RunSummary {
+            started: Instant::now(),
+            checks: 0,
+            successes: 0,
+            failures: 0,
+            bytes_transmitted: 0,
+        }
+    }
+
+    fn record_check(&mut self, succeeded: bool) {
+        self.checks += 1;
+        if succeeded {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let uptime_secs = self.started.elapsed().as_secs();
+        write!(
+            f,
+            "Run summary: {} checks ({} succeeded, {} failed), {} bytes transmitted, uptime {}h {}m {}s",
+            self.checks,
+            self.successes,
+            self.failures,
+            self.bytes_transmitted,
+            uptime_secs / 3600,
+            uptime_secs % 3600 / 60,
+            uptime_secs % 60
+        )
+    }
+}
+
+/// A topic that a payload is published to, with its resolved QoS and whether the broker retains the payload for new
+/// subscribers.
+#[derive(Clone)]
+struct PublishTopic {
+    name: String,
+    qos: i32,
+    retained: bool,
+}
+
+/// Somewhere a report is delivered to: the MQTT broker, or one of the other destinations by its index.
+#[derive(Debug,PartialEq,Clone,Copy)]
+enum DeliveryTarget {
+    Broker,
+    Destination(usize),
+}
+
+// How the broker is written among the targets in a spooled report's label
+const SPOOL_LABEL_BROKER: &str = "mqtt";
+
+/// The label a report is spooled under: its message ID followed by the targets it is still to be delivered to, the
+/// broker by name and the other destinations by their index.
+fn spool_label(message_id: &str, targets: &[DeliveryTarget]) -> String {
+    let targets: Vec<String> = targets.iter().map(|target| match target {
+        DeliveryTarget::Broker => String::from(SPOOL_LABEL_BROKER),
+        DeliveryTarget::Destination(index) => index.to_string(),
+    }).collect();
+    format!("{} {}", message_id, targets.join(","))
+}
+
+/// Splits a spooled report's label into its message ID and the targets it is still to be delivered to, which are
+/// `None` for a report spooled before its targets were recorded.
+fn parse_spool_label(label: &str) -> (String, Option<Vec<DeliveryTarget>>) {
+    let (message_id, targets) = match label.split_once(' ') {
+        Some((message_id, targets)) => (message_id, targets),
+        None => return (String::from(label), None)
+    };
+    let targets = targets.split(',').filter_map(|target| match target {
+        SPOOL_LABEL_BROKER => Some(DeliveryTarget::Broker),
+        index => index.parse().ok().map(DeliveryTarget::Destination),
+    }).collect();
+    (String::from(message_id), Some(targets))
+}
+
 struct Runner {
     device_id: String,
-    topic_name: String,
-    mqtt_client: Client,
-    conn_opts: ConnectOptions,
+    session_id: String,
+    report_sequence: u64,
+    message_ids: MessageIdGenerator,
+    /// How many more times delivery of a report is attempted after it fails.
+    publish_retries: u32,
+    /// Reports that could not be delivered, flushed after the next successful delivery.
+    spool: Option<Spool>,
+    topics: Vec<PublishTopic>,
+    heartbeat_topics: Vec<PublishTopic>,
+    heartbeat_sequence: u64,
+    /// Where the startup message is published; none is sent unless a startup report is configured.
+    startup_topic: Option<PublishTopic>,
+    /// Recent reports, kept so that they can be requested over the command topic.
+    history: Option<ReportHistory>,
+    command_topic: Option<String>,
+    config_topic: Option<String>,
+    /// Kept to validate configuration updates against.
+    heartbeat_interval_secs: Option<u64>,
+    /// A report interval received in a configuration update, waiting to be applied to the schedule.
+    report_interval_update: Option<Duration>,
+    history_topics: Vec<PublishTopic>,
+    threshold_monitor: Option<ThresholdMonitor>,
+    free_space_monitor: Option<FreeSpaceMonitor>,
+    read_only_monitor: Option<ReadOnlyMonitor>,
+    alert_topics: Vec<PublishTopic>,
+    diagnostic_topics: Vec<PublishTopic>,
+    /// Spaces diagnostics out so that a persistent fault cannot flood the broker with them.
+    diagnostic_rate_guard: RateGuard,
+    /// Diagnostics dropped by the rate guard since the last one was published.
+    suppressed_diagnostics: u64,
+    compression: Codec,
+    compression_level: Option<u32>,
+    compression_min_bytes: Option<usize>,
+    compression_dictionary: bool,
+    compression_stats: Option<CompressionStats>,
+    pad_to_bytes: Option<usize>,
+    max_message_bytes: Option<usize>,
+    signing_key: Option<SigningKey>,
+    message_expiry_secs: Option<u32>,
+    split_topics: bool,
+    byte_budget: Option<ByteBudget>,
+    cellular_throttle: Option<CellularThrottle>,
+    idle_detector: Option<IdleDetector>,
+    rate_guard: Option<RateGuard>,
+    quiet_hours: Option<QuietHours>,
+    /// Reads the current Unix timestamp for reports; replaced in tests to run checks at a chosen time.
+    clock: fn() -> Result<u64, Box<dyn Error>>,
+    /// Waits out the delay between delivery retries; replaced in tests to retry without waiting.
+    sleep: Box<dyn FnMut(Duration) + Send>,
+    change_filter: ChangeFilter,
+    key_case: KeyCase,
+    flatten: bool,
+    format: ReportFormat,
+    tags: BTreeMap<String, String>,
+    location: Option<Location>,
+    transport: Box<dyn Transport>,
+    keep_connected: bool,
+    shutdown_grace: Option<Duration>,
+    publish_ack_timeout: Duration,
+    /// Whether reports are published to the MQTT broker as well as to the other destinations.
+    publish_reports: bool,
+    /// Whether the reports published to the MQTT broker are compressed.
+    compress_published_reports: bool,
+    /// The other destinations, each with whether the reports sent to it are compressed.
+    destinations: Vec<(Box<dyn Destination>, bool)>,
+    verbose: bool,
+    output: Box<dyn Write + Send>,
+    report_generator: ReportGenerator,
+    run_summary: RunSummary,
+    device_state: Option<DeviceState>,
 }
 
 impl Runner {
+    /// Creates a runner publishing over MQTT, unless nothing is configured to travel over it, in which case no MQTT
+    /// client is created and the broker settings go unused.
     fn new(runner_config: &RunnerConfig) -> Result<Runner, Box<dyn Error>> {
-        let mqtt_opts = paho_mqtt::CreateOptionsBuilder::new()
-            .server_uri(runner_config.server_address.as_str())
-            .client_id(runner_config.device_id.as_str())
-            .finalize();
-        let mqtt_client = match paho_mqtt::Client::new(mqtt_opts) {
-            Ok(mqtt_client) => mqtt_client,
-            Err(e) => {
-                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
-                return Err(error);
+        let transport: Box<dyn Transport> = if uses_mqtt(runner_config) {
+            Box::new(MqttTransport::new(runner_config)?)
+        } else {
+            Box::new(DisabledTransport)
+        };
+        let mut runner = Runner::with_transport(runner_config, transport);
+        // There is no connection to keep open
+        runner.keep_connected &= uses_mqtt(runner_config);
+        for destination in runner_config.destinations.iter() {
+            if let DestinationConfig::Http { url, headers, compress } = destination {
+                runner.destinations.push((Box::new(HttpDestination::new(url.as_str(), headers.clone())?), *compress));
             }
+        }
+        Ok(runner)
+    }
+
+    fn with_transport(runner_config: &RunnerConfig, transport: Box<dyn Transport>) -> Runner {
+        // In dual publish mode the report is retained as a snapshot for new subscribers and streamed as an event
+        let topics = if runner_config.dual_publish {
+            vec![
+                PublishTopic { name: runner_config.snapshot_topic.clone().unwrap_or_default(), qos: runner_config.qos, retained: true },
+                PublishTopic { name: runner_config.event_topic.clone().unwrap_or_default(), qos: runner_config.qos, retained: false },
+            ]
+        } else if runner_config.topics.is_empty() {
+            vec![PublishTopic { name: runner_config.topic.clone(), qos: runner_config.qos, retained: false }]
+        } else {
+            runner_config.topics.iter().map(|t| {
+                PublishTopic { name: t.name.clone(), qos: t.qos.unwrap_or(runner_config.qos), retained: false }
+            }).collect()
         };
-        let conn_opts = paho_mqtt::ConnectOptionsBuilder::new()
-            .user_name(runner_config.user_name.as_str())
-            .password(runner_config.user_password.as_str())
-            .keep_alive_interval(Duration::from_secs(20))
-            .clean_session(true)
-            .finalize();
-        return Ok(Runner {
+        let device_state = runner_config.state_file.as_ref().map(|state_file| DeviceState::load(state_file.as_str()));
+        Runner {
             device_id: runner_config.device_id.clone(),
-            topic_name: runner_config.topic.clone(),
-            mqtt_client,
-            conn_opts,
-        });
+            session_id: Uuid::new_v4().to_string(),
+            report_sequence: device_state.as_ref().map_or(0, |device_state| device_state.report_sequence),
+            message_ids: MessageIdGenerator::new(runner_config.message_id_format),
+            publish_retries: runner_config.publish_retries,
+            spool: runner_config.spool_dir.as_ref().map(|spool_dir| {
+                Spool::new(
+                    spool_dir.as_str(),
+                    runner_config.spool_max_age_secs,
+                    runner_config.spool_max_bytes,
+                    runner_config.spool_full_strategy
+                )
+            }),
+            topics,
+            heartbeat_topics: vec![PublishTopic { name: runner_config.heartbeat_topic.clone(), qos: runner_config.qos, retained: false }],
+            heartbeat_sequence: device_state.as_ref().map_or(0, |device_state| device_state.heartbeat_sequence),
+            startup_topic: runner_config.startup_topic.clone()
+                .filter(|_| runner_config.send_startup_report)
+                .map(|startup_topic| PublishTopic { name: startup_topic, qos: runner_config.qos, retained: false }),
+            history: runner_config.history_size.map(ReportHistory::new),
+            command_topic: runner_config.command_topic.clone(),
+            config_topic: runner_config.config_topic.clone(),
+            heartbeat_interval_secs: runner_config.heartbeat_interval_secs,
+            report_interval_update: None,
+            history_topics: runner_config.history_topic.iter().map(|history_topic| {
+                PublishTopic { name: history_topic.clone(), qos: runner_config.qos, retained: false }
+            }).collect(),
+            threshold_monitor: if runner_config.thresholds.is_empty() {
+                None
+            } else {
+                Some(ThresholdMonitor::new(runner_config.thresholds.clone()))
+            },
+            free_space_monitor: if runner_config.free_space_alerts.is_empty() {
+                None
+            } else {
+                Some(FreeSpaceMonitor::new(runner_config.free_space_alerts.clone()))
+            },
+            read_only_monitor: if runner_config.read_only_alert { Some(ReadOnlyMonitor::new()) } else { None },
+            diagnostic_topics: runner_config.diagnostic_topic.iter().map(|diagnostic_topic| {
+                PublishTopic { name: diagnostic_topic.clone(), qos: runner_config.qos, retained: false }
+            }).collect(),
+            diagnostic_rate_guard: RateGuard::new(Duration::from_secs(runner_config.diagnostic_min_interval_secs)),
+            suppressed_diagnostics: 0,
+            alert_topics: runner_config.alert_topic.iter().map(|alert_topic| {
+                PublishTopic { name: alert_topic.clone(), qos: runner_config.qos, retained: false }
+            }).collect(),
+            compression: runner_config.compression,
+            compression_level: runner_config.compression_level,
+            compression_min_bytes: runner_config.compression_min_bytes,
+            compression_dictionary: runner_config.compression_dictionary,
+            compression_stats: runner_config.compression_stats_file.as_ref().map(|compression_stats_file| {
+                CompressionStats::load(compression_stats_file.as_str())
+            }),
+            pad_to_bytes: runner_config.pad_to_bytes,
+            max_message_bytes: runner_config.max_message_bytes,
+            signing_key: runner_config.hmac_secret.as_ref().map(|hmac_secret| {
+                SigningKey::new(hmac_secret.as_str(), runner_config.hmac_key_id.clone())
+            }),
+            message_expiry_secs: runner_config.message_expiry_secs,
+            split_topics: runner_config.split_topics,
+            idle_detector: if runner_config.idle_skip {
+                Some(IdleDetector::new(
+                    runner_config.idle_cpu_threshold,
+                    runner_config.idle_network_threshold_bps,
+                    runner_config.counter_reset
+                ))
+            } else {
+                None
+            },
+            byte_budget: runner_config.monthly_byte_budget.map(|monthly_byte_budget| {
+                ByteBudget::new(monthly_byte_budget, runner_config.budget_state_file.clone())
+            }),
+            cellular_throttle: if runner_config.cellular_throttle {
+                Some(CellularThrottle::new(runner_config.cellular_interval_multiplier))
+            } else {
+                None
+            },
+            rate_guard: runner_config.min_publish_interval_ms.map(|min_publish_interval_ms| {
+                RateGuard::new(Duration::from_millis(min_publish_interval_ms))
+            }),
+            quiet_hours: runner_config.quiet_hours,
+            clock: current_timestamp,
+            sleep: Box::new(thread::sleep),
+            change_filter: ChangeFilter::new(runner_config.change_thresholds.clone()),
+            key_case: runner_config.key_case,
+            flatten: runner_config.flatten,
+            format: runner_config.format,
+            tags: runner_config.tags.clone(),
+            location: runner_config.location,
+            transport,
+            keep_connected: runner_config.keep_connected,
+            shutdown_grace: runner_config.shutdown_grace_secs.map(Duration::from_secs),
+            publish_ack_timeout: Duration::from_secs(runner_config.publish_ack_timeout_secs),
+            publish_reports: runner_config.destinations.iter().any(DestinationConfig::is_mqtt),
+            compress_published_reports: !runner_config.destinations.contains(&DestinationConfig::Mqtt { compress: false }),
+            destinations: Vec::new(),
+            verbose: runner_config.verbose,
+            output: Box::new(stdout()),
+            report_generator: ReportGenerator::new(runner_config),
+            run_summary: RunSummary::new(),
+            device_state,
+        }
     }
 
-    fn execute_check(&self, sys: &mut System) -> Result<(), Box<dyn Error>> {
-        let message_id = Uuid::new_v4().to_string();
-        let timestamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(n) => n.as_secs(),
-            Err(e) => {
-                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+    /// Connects to and disconnects from the broker once so that an unreachable broker fails the run immediately.
+    fn probe(&self) -> Result<(), Box<dyn Error>> {
+        if let Err(e) = self.transport.connect().and_then(|_| self.transport.disconnect()) {
+            let error = Box::new(RuntimeError::new(format!("Startup connectivity probe failed: {}", e).as_str()));
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Connects and waits for the connection to settle. A broker hands a client ID to its newest connection, so a
+    /// connection dropped straight away suggests another device with the same ID reconnected and took it over.
+    fn detect_duplicate_device_id(&self, settle_time: Duration) -> Result<bool, Box<dyn Error>> {
+        self.transport.connect()?;
+        thread::sleep(settle_time);
+        if !self.transport.is_connected() {
+            return Ok(true);
+        }
+        self.transport.disconnect()?;
+        Ok(false)
+    }
+
+    fn execute_check(&mut self, sys: &mut dyn SystemSource) -> Result<(), Box<dyn Error>> {
+        let result = self.generate_and_transmit(sys);
+        self.run_summary.record_check(result.is_ok());
+        if let Some(device_state) = self.device_state.as_mut() {
+            device_state.record_check(result.is_ok());
+        }
+        if let Err(e) = &result {
+            self.publish_diagnostic(CHECK_DIAGNOSTIC, e.to_string().as_str(), &[]);
+        }
+        if let Err(e) = self.save_state() {
+            eprintln!("An error occurred while saving the device state: {}", e);
+        }
+        result
+    }
+
+    /// Persists the sequence numbers and lifetime counters, if a state file is configured.
+    fn save_state(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.device_state.as_mut() {
+            Some(device_state) => {
+                device_state.report_sequence = self.report_sequence;
+                device_state.heartbeat_sequence = self.heartbeat_sequence;
+                device_state.save()
+            }
+            None => Ok(())
+        }
+    }
+
+    fn generate_and_transmit(&mut self, sys: &mut dyn SystemSource) -> Result<(), Box<dyn Error>> {
+        if !self.acquire_publish_slot("report") {
+            return Ok(());
+        }
+        let timestamp = (self.clock)()?;
+        if let Some(byte_budget) = self.byte_budget.as_mut() {
+            if !byte_budget.should_send_report(timestamp) {
+                return Ok(());
+            }
+        }
+        if let Some(cellular_throttle) = self.cellular_throttle.as_mut() {
+            // Interfaces that cannot be listed leave the connection unidentified, which is not throttled
+            let profile = reporting_profile(&sys.network_interfaces().unwrap_or_default());
+            if !cellular_throttle.should_send_report(profile) {
+                if self.verbose {
+                    let _ = writeln!(self.output, "Skipping report while connected over cellular");
+                }
+                return Ok(());
+            }
+            self.report_generator.set_metered_link(profile == ReportingProfile::Reduced);
+        }
+        if self.spool.as_ref().is_some_and(|spool| spool.is_full()) {
+            // Collection resumes once the spooled reports can be delivered and have made room
+            self.flush_spool(timestamp)?;
+            if self.verbose {
+                let _ = writeln!(self.output, "Resuming reports now that the spool has been flushed");
+            }
+        }
+        let (mut report, collection_errors) = self.report_generator.generate_report(sys);
+        if !collection_errors.is_empty() {
+            let message = format!("{} report sections failed to collect", collection_errors.len());
+            self.publish_diagnostic(COLLECTION_DIAGNOSTIC, message.as_str(), &collection_errors);
+        }
+        if let Err(e) = self.alert_read_only_disks(&report.disks, timestamp) {
+            eprintln!("An error occurred while publishing read-only alerts: {}", e);
+        }
+        // Alerts still go out during quiet hours; only the report is held back
+        if self.quiet_hours.is_some_and(|quiet_hours| quiet_hours.contains(timestamp)) {
+            if self.verbose {
+                let _ = writeln!(self.output, "Skipping report during quiet hours");
+            }
+            return Ok(());
+        }
+        if self.idle(sys) {
+            if self.verbose {
+                let _ = writeln!(self.output, "Skipping report while the device is idle");
+            }
+            return Ok(());
+        }
+        let unchanged_sections = self.change_filter.apply(&mut report, &collection_errors);
+        if self.split_topics {
+            let sent_bytes = self.transmit_sections(&report, &unchanged_sections)?;
+            return self.record_sent_bytes(sent_bytes, timestamp);
+        }
+        // Every attempt at delivering this report carries the same dedup id, letting consumers discard retries
+        self.report_sequence += 1;
+        let dedup_id = format!("{}-{}", self.session_id, self.report_sequence);
+        let device_id = self.device_id.clone();
+        let session_id = self.session_id.clone();
+        let key_id = self.signing_key.as_ref().and_then(|signing_key| signing_key.key_id.clone());
+        let mut targets = self.delivery_targets();
+        let mut attempt = 0;
+        loop {
+            let message_id = self.message_ids.next_id();
+            let mut report_message = ReportMessage::new(
+                device_id.as_str(),
+                message_id.as_str(),
+                session_id.as_str(),
+                dedup_id.as_str(),
+                &timestamp,
+                &report,
+                &collection_errors
+            );
+            report_message.unchanged_sections = &unchanged_sections;
+            report_message.key_id = key_id.as_deref();
+            report_message.location = self.location;
+            let (report_json, compressed_report) = match self.format {
+                ReportFormat::Json => self.encode_report(&mut report_message)?,
+                ReportFormat::InfluxLine => {
+                    let report_lines = render_report(&report, device_id.as_str(), &self.tags, timestamp);
+                    let compressed_report = self.compress_report(report_lines.as_bytes())?;
+                    (report_lines, compressed_report)
+                }
+            };
+            if attempt == 0 {
+                if let Some(compression_stats) = self.compression_stats.as_mut() {
+                    compression_stats.record(report_json.len(), compressed_report.len())?;
+                }
+                if let Some(history) = self.history.as_mut() {
+                    history.record(report_json.clone());
+                }
+            }
+            let (sent_bytes, failures) = self.deliver_report_to(
+                &targets, message_id.as_str(), report_json.as_bytes(), &compressed_report
+            );
+            self.record_sent_bytes(sent_bytes, timestamp)?;
+            if failures.is_empty() {
+                return self.flush_spool(timestamp);
+            }
+            if attempt == self.publish_retries {
+                if let Some(spool) = self.spool.as_mut() {
+                    // The report is only flushed to the targets that failed, so that the others do not receive it twice
+                    let failed_targets: Vec<DeliveryTarget> = failures.iter().map(|(target, _)| *target).collect();
+                    spool.store(spool_label(message_id.as_str(), &failed_targets).as_str(), &compressed_report, timestamp)?;
+                }
+                let failures: Vec<String> = failures.into_iter().map(|(_, failure)| failure).collect();
+                let error = Box::new(RuntimeError::new(
+                    format!("Unable to deliver report to {}", failures.join("; ")).as_str()
+                ));
                 return Err(error);
             }
+            // Only the destinations that failed are retried, so that the others do not receive the report twice
+            targets = failures.into_iter().map(|(target, _)| target).collect();
+            (self.sleep)(publish_retry_delay(attempt));
+            attempt += 1;
+        }
+    }
+
+    /// Whether reporting is to be skipped because the device was idle over the last check. Readings that cannot be
+    /// taken never count as idle, so that reporting carries on.
+    fn idle(&mut self, sys: &dyn SystemSource) -> bool {
+        let idle_detector = match self.idle_detector.as_mut() {
+            Some(idle_detector) => idle_detector,
+            None => return false
+        };
+        match (sys.cpus(), sys.network_bytes()) {
+            (Ok(cpus), Ok(Some(network_bytes))) => idle_detector.is_idle(Instant::now(), &cpus, network_bytes),
+            _ => false
+        }
+    }
+
+    /// Serializes, signs and compresses the report message, filling in its compression ratio. Both the serialized
+    /// and the compressed report are returned.
+    fn encode_report(&mut self, report_message: &mut ReportMessage) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+        // The ratio is measured on the message without the ratio field, then the message is re-encoded with it
+        let report_json = self.serialize_report(report_message)?;
+        let compressed_report = self.compress_report(report_json.as_bytes())?;
+        report_message.compression_ratio = Some(compression_ratio(compressed_report.len(), report_json.len()));
+        let report_json = self.serialize_report(report_message)?;
+        let compressed_report = self.compress_report(report_json.as_bytes())?;
+        if self.verbose {
+            // Diagnostic output is best-effort and must not fail the check
+            let _ = writeln!(self.output, "System Report: {:?}", report_json);
+            let _ = writeln!(self.output, "Compressed Report: {:?}", compressed_report);
+            let _ = writeln!(self.output, "Compression: {}/{}", compressed_report.len(), report_json.len());
+        }
+        Ok((report_json, compressed_report))
+    }
+
+    fn serialize_report(&self, report_message: &ReportMessage) -> Result<String, Box<dyn Error>> {
+        let mut report_json = serialize_message(report_message, self.key_case)?;
+        if self.flatten {
+            report_json = flatten_json(report_json.as_str())?;
+        }
+        match &self.signing_key {
+            Some(signing_key) => Ok(signing_key.sign_message(report_json.as_str())),
+            None => Ok(report_json)
+        }
+    }
+
+    /// The broker, when reports are published to it, followed by every other destination.
+    fn delivery_targets(&self) -> Vec<DeliveryTarget> {
+        let broker = if self.publish_reports { Some(DeliveryTarget::Broker) } else { None };
+        broker.into_iter().chain((0..self.destinations.len()).map(DeliveryTarget::Destination)).collect()
+    }
+
+    /// Sends the report to each of the targets, compressed or as-is according to each destination's setting,
+    /// returning the bytes sent and the targets that failed along with a description of each failure. A failing
+    /// destination does not prevent delivery to the rest. A report too large for a single MQTT message is published
+    /// in chunks carrying its message ID.
+    fn deliver_report_to(&self, targets: &[DeliveryTarget], message_id: &str, report: &[u8], compressed_report: &[u8]) -> (usize, Vec<(DeliveryTarget, String)>) {
+        let mut sent_bytes = 0;
+        let mut failures = Vec::new();
+        for target in targets.iter() {
+            match target {
+                DeliveryTarget::Broker => {
+                    let payload = if self.compress_published_reports { compressed_report } else { report };
+                    match self.transmit_report_in_chunks(&self.topics, message_id, payload) {
+                        Ok(mqtt_bytes) => sent_bytes += mqtt_bytes,
+                        Err(e) => failures.push((*target, format!("mqtt: {}", e))),
+                    }
+                }
+                DeliveryTarget::Destination(index) => {
+                    let (destination, compress) = &self.destinations[*index];
+                    let payload = if *compress { compressed_report } else { report };
+                    match destination.send(payload) {
+                        Ok(_) => sent_bytes += payload.len(),
+                        Err(e) => failures.push((*target, format!("{}: {}", destination.name(), e))),
+                    }
+                }
+            }
+        }
+        (sent_bytes, failures)
+    }
+
+    /// Delivers the spooled reports to the targets that failed to take them now that delivery has succeeded again,
+    /// dropping those too old to be useful.
+    fn flush_spool(&mut self, timestamp: u64) -> Result<(), Box<dyn Error>> {
+        let mut spool = match self.spool.take() {
+            Some(spool) => spool,
+            None => return Ok(())
         };
-        let report = generate_report(sys)?;
+        let mut sent_bytes = 0;
+        let result = spool.flush(timestamp, |label, payload| {
+            let (message_id, spooled_targets) = parse_spool_label(label);
+            // Targets that are no longer configured are dropped
+            let targets: Vec<DeliveryTarget> = match spooled_targets {
+                Some(spooled_targets) => {
+                    let configured_targets = self.delivery_targets();
+                    spooled_targets.into_iter().filter(|target| configured_targets.contains(target)).collect()
+                }
+                None => self.delivery_targets()
+            };
+            // Reports are spooled compressed; destinations taking them as-is get them decoded
+            let report = if self.sends_uncompressed_reports() { self.decode_report(payload)? } else { payload.to_vec() };
+            let (payload_bytes, failures) = self.deliver_report_to(&targets, message_id.as_str(), &report, payload);
+            sent_bytes += payload_bytes;
+            if failures.is_empty() {
+                Ok(())
+            } else {
+                // The targets that took the report are not sent it again at the next flush
+                let failed_targets: Vec<DeliveryTarget> = failures.iter().map(|(target, _)| *target).collect();
+                *label = spool_label(message_id.as_str(), &failed_targets);
+                let failures: Vec<String> = failures.into_iter().map(|(_, failure)| failure).collect();
+                let error = Box::new(RuntimeError::new(
+                    format!("Unable to flush spooled report to {}", failures.join("; ")).as_str()
+                ));
+                Err(error)
+            }
+        });
+        self.spool = Some(spool);
+        self.record_sent_bytes(sent_bytes, timestamp)?;
+        result.map(|_| ())
+    }
+
+    /// Generates one report and prints its size under every codec without transmitting it, to help pick a codec.
+    fn compare_compression(&mut self, sys: &mut dyn SystemSource) -> Result<(), Box<dyn Error>> {
+        let message_id = self.message_ids.next_id();
+        let timestamp = current_timestamp()?;
+        let (report, collection_errors) = self.report_generator.generate_report(sys);
         let report_message = ReportMessage::new(
             self.device_id.as_str(),
             message_id.as_str(),
+            self.session_id.as_str(),
+            message_id.as_str(),
             &timestamp,
-            &report
+            &report,
+            &collection_errors
         );
+        let report_json = serialize_message(&report_message, self.key_case)?;
+        for codec in CODECS.iter() {
+            let compressed_report = compress(*codec, None, None, report_json.as_bytes())?;
+            let _ = writeln!(
+                self.output,
+                "{}: {} bytes (ratio {:.3})",
+                codec.name(),
+                compressed_report.len(),
+                compression_ratio(compressed_report.len(), report_json.len())
+            );
+        }
+        Ok(())
+    }
 
-        let report_json = match serde_json::to_string(&report_message) {
-            Ok(report_json) => report_json,
-            Err(e) => {
-                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+    /// Publishes one report to the first report topic while subscribed to it, and waits for the report to come back
+    /// from the broker, checking that it decodes to exactly what was published. This exercises the whole
+    /// encode-publish-subscribe-decode path; messages from other devices or earlier runs sharing the topic are skipped.
+    fn self_test(&mut self, sys: &mut dyn SystemSource, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        if self.format != ReportFormat::Json {
+            let error = Box::new(IllegalArgumentError::new("The self-test requires the json format"));
+            return Err(error);
+        }
+        let topic = match self.topics.first() {
+            Some(topic) => topic.clone(),
+            None => {
+                let error = Box::new(IllegalArgumentError::new("The self-test requires a report topic"));
                 return Err(error);
             }
         };
-        let compressed_report = compress_prepend_size(report_json.as_bytes());
-        println!("System Report: {:?}", report_json);
-        println!("Compressed Report: {:?}", compressed_report);
-        println!("Compression: {}/{}", compressed_report.len(), report_json.len());
-        self.transmit_report(&compressed_report)
+        let device_id = self.device_id.clone();
+        let session_id = self.session_id.clone();
+        let message_id = self.message_ids.next_id();
+        let timestamp = current_timestamp()?;
+        let (report, collection_errors) = self.report_generator.generate_report(sys);
+        let mut report_message = ReportMessage::new(
+            device_id.as_str(),
+            message_id.as_str(),
+            session_id.as_str(),
+            message_id.as_str(),
+            &timestamp,
+            &report,
+            &collection_errors
+        );
+        let (report_json, compressed_report) = self.encode_report(&mut report_message)?;
+        self.transport.connect()?;
+        let result = self.transport.subscribe(topic.name.as_str(), topic.qos)
+            .and_then(|_| self.transport.publish(self.build_message(&topic, &compressed_report)?))
+            .and_then(|_| self.await_echo(&topic.name, report_json.as_str(), message_id.as_str(), timeout));
+        self.transport.disconnect()?;
+        result
     }
 
-    fn transmit_report(&self, payload: &[u8]) -> Result<(), Box<dyn Error>> {
-        if let Err(e) = self.mqtt_client.connect(self.conn_opts.clone()) {
-            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
-            return Err(error);
+    fn await_echo(&self, topic: &str, report_json: &str, message_id: &str, timeout: Duration) -> Result<(), Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        let mut skipped = 0;
+        loop {
+            while let Some(msg) = self.transport.try_receive() {
+                if msg.topic() != topic {
+                    continue;
+                }
+                match self.compare_echo(msg.payload(), report_json, message_id) {
+                    Echo::Matched => return Ok(()),
+                    Echo::Mismatched(reason) => {
+                        let error = Box::new(RuntimeError::new(format!("Self-test failed: {}", reason).as_str()));
+                        return Err(error);
+                    }
+                    Echo::Unrelated => skipped += 1,
+                }
+            }
+            if Instant::now() >= deadline {
+                let error = Box::new(RuntimeError::new(format!(
+                    "Self-test failed: the report was not received back on {} within {} seconds ({} other messages were skipped)",
+                    topic,
+                    timeout.as_secs_f64(),
+                    skipped
+                ).as_str()));
+                return Err(error);
+            }
+            thread::sleep(SELF_TEST_POLL_INTERVAL);
         }
-        let msg = paho_mqtt::Message::new(self.topic_name.clone(), payload, 0);
-        if let Err(e) = self.mqtt_client.publish(msg) {
-            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
-            return Err(error);
+    }
+
+    /// Decodes a payload received during the self-test and compares it with the published report. A payload that
+    /// does not decode, or carries another message ID, is taken to belong to another publisher.
+    fn compare_echo(&self, payload: &[u8], report_json: &str, message_id: &str) -> Echo {
+        let decoded = match self.decode_report(payload) {
+            Ok(decoded) => decoded,
+            Err(_) => return Echo::Unrelated
+        };
+        let decoded_message: Value = match serde_json::from_slice(&decoded) {
+            Ok(decoded_message) => decoded_message,
+            Err(_) => return Echo::Unrelated
+        };
+        let message_id_key = match self.key_case {
+            KeyCase::Camel => "messageId",
+            KeyCase::Snake => "message_id",
+        };
+        if decoded_message.get(message_id_key).and_then(Value::as_str) != Some(message_id) {
+            return Echo::Unrelated;
         }
-        match self.mqtt_client.disconnect(None) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
-                Err(error)
+        if decoded != report_json.as_bytes() {
+            return Echo::Mismatched(String::from("the report received back differs from the one published"));
+        }
+        Echo::Matched
+    }
+
+    /// Reverses `compress_report`.
+    fn decode_report(&self, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let dictionary = if self.compression_dictionary { Some(REPORT_DICTIONARY) } else { None };
+        let with_header = self.pad_to_bytes.is_some() || self.compression_min_bytes.is_some();
+        decode(self.compression, dictionary, with_header, payload)
+    }
+
+    /// Whether any destination takes reports without compression.
+    fn sends_uncompressed_reports(&self) -> bool {
+        (self.publish_reports && !self.compress_published_reports) || self.destinations.iter().any(|(_, compress)| !compress)
+    }
+
+    fn compress_report(&self, report_json: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let dictionary = if self.compression_dictionary { Some(REPORT_DICTIONARY) } else { None };
+        // Padding is marked in the format header, so padded payloads always carry one
+        if let Some(pad_to_bytes) = self.pad_to_bytes {
+            let min_bytes = self.compression_min_bytes.unwrap_or(0);
+            let encoded = compress_with_header(self.compression, self.compression_level, dictionary, min_bytes, report_json)?;
+            return pad_payload(&encoded, pad_to_bytes);
+        }
+        match self.compression_min_bytes {
+            Some(min_bytes) => compress_with_header(self.compression, self.compression_level, dictionary, min_bytes, report_json),
+            None => compress(self.compression, self.compression_level, dictionary, report_json)
+        }
+    }
+
+    /// Publishes a minimal, uncompressed liveness message between full reports.
+    fn send_heartbeat(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.acquire_publish_slot("heartbeat") {
+            return Ok(());
+        }
+        self.heartbeat_sequence += 1;
+        let timestamp = current_timestamp()?;
+        let heartbeat_message = HeartbeatMessage::new(
+            self.device_id.as_str(),
+            &timestamp,
+            &self.heartbeat_sequence
+        );
+        let heartbeat_json = serialize_message(&heartbeat_message, self.key_case)?;
+        let sent_bytes = self.transmit_report(&self.heartbeat_topics, heartbeat_json.as_bytes())?;
+        let result = self.record_sent_bytes(sent_bytes, timestamp);
+        if let Err(e) = self.save_state() {
+            eprintln!("An error occurred while saving the device state: {}", e);
+        }
+        result
+    }
+
+    /// Publishes the uncompressed startup message describing the device, when a startup report is configured.
+    fn send_startup_report(&mut self, sys: &dyn SystemSource) -> Result<(), Box<dyn Error>> {
+        let startup_topic = match &self.startup_topic {
+            Some(startup_topic) => startup_topic.clone(),
+            None => return Ok(())
+        };
+        let timestamp = current_timestamp()?;
+        let host = sys.host();
+        let startup_message = StartupMessage {
+            device_id: self.device_id.as_str(),
+            session_id: self.session_id.as_str(),
+            timestamp,
+            agent_version: AGENT_VERSION,
+            host_name: host.host_name,
+            os_name: host.os_name,
+            os_version: host.os_version,
+            kernel_version: host.kernel_version,
+            cpu_count: sys.cpus().ok().map(|cpus| cpus.len()),
+            memory_capacity: sys.memory().ok().map(|memory| memory.total_memory),
+            sections: self.report_generator.enabled_sections(),
+        };
+        let startup_json = serialize_message(&startup_message, self.key_case)?;
+        let sent_bytes = self.transmit_report(&[startup_topic], startup_json.as_bytes())?;
+        self.record_sent_bytes(sent_bytes, timestamp)
+    }
+
+    /// Publishes an uncompressed alert for every section whose usage has newly crossed its threshold.
+    /// Also publishes an alert for every mount whose free space has newly dropped below its threshold.
+    fn check_thresholds(&mut self, sys: &mut dyn SystemSource) -> Result<(), Box<dyn Error>> {
+        let crossings = match self.threshold_monitor.as_mut() {
+            Some(threshold_monitor) => threshold_monitor.evaluate(sys)?,
+            None => {
+                sys.refresh();
+                Vec::new()
+            }
+        };
+        let shortfalls = match self.free_space_monitor.as_mut() {
+            Some(free_space_monitor) => free_space_monitor.evaluate(&sys.disks()?),
+            None => Vec::new()
+        };
+        for crossing in crossings {
+            if !self.acquire_publish_slot("alert") {
+                continue;
+            }
+            let timestamp = current_timestamp()?;
+            let alert_message = AlertMessage::new(
+                self.device_id.as_str(),
+                &timestamp,
+                crossing.section,
+                crossing.value,
+                crossing.threshold
+            );
+            let alert_json = serialize_message(&alert_message, self.key_case)?;
+            let sent_bytes = self.transmit_report(&self.alert_topics, alert_json.as_bytes())?;
+            self.record_sent_bytes(sent_bytes, timestamp)?;
+        }
+        for shortfall in shortfalls {
+            if !self.acquire_publish_slot("alert") {
+                continue;
             }
+            let timestamp = current_timestamp()?;
+            let alert_message = FreeSpaceAlertMessage::new(
+                self.device_id.as_str(),
+                &timestamp,
+                shortfall.mount_point.as_str(),
+                shortfall.available_bytes,
+                shortfall.threshold_bytes
+            );
+            let alert_json = serialize_message(&alert_message, self.key_case)?;
+            let sent_bytes = self.transmit_report(&self.alert_topics, alert_json.as_bytes())?;
+            self.record_sent_bytes(sent_bytes, timestamp)?;
         }
+        Ok(())
     }
-}
 
-fn generate_report(sys: &mut System) -> Result<SystemReport, Box<dyn Error>> {
-    sys.refresh_all();
-    // Collect disk data
-    let disk_reports: Vec<DiskReport> = sys.get_disks().iter().filter_map(|d| {
-        let disk_name = match d.get_name().to_str() {
-            Some(name) => name.trim(),
-            None => return None
+    /// Whether usage thresholds or free space alerts are checked between scheduled checks.
+    fn monitors_thresholds(&self) -> bool {
+        self.threshold_monitor.is_some() || self.free_space_monitor.is_some()
+    }
+
+    /// Publishes an uncompressed alert for every disk that has gone from read-write to read-only. The alerts go out
+    /// with the report whose check found the remount, so they do not wait on the publish slot the report has taken.
+    fn alert_read_only_disks(&mut self, disks: &[DiskReport], timestamp: u64) -> Result<(), Box<dyn Error>> {
+        let remounted = match self.read_only_monitor.as_mut() {
+            Some(read_only_monitor) => read_only_monitor.evaluate(disks),
+            None => return Ok(())
         };
-        let disk_capacity = d.get_total_space();
-        Some(DiskReport {
-            name: String::from(disk_name),
-            disk_used: disk_capacity - d.get_available_space(),
-            disk_capacity,
-        })
-    }).collect();
-    // Collect memory data
-    let memory_capacity = sys.get_total_memory();
-    let memory_report = MemoryReport {
-        memory_used: memory_capacity - sys.get_available_memory(),
-        memory_capacity,
-    };
-    // Collect CPU data
-    let cpu_reports: Vec<CPUReport> = sys.get_processors().iter().map(|x| {
-        CPUReport {
-            name: String::from(x.get_name().trim()),
-            brand: String::from(x.get_brand().trim()),
-            vendor_id: String::from(x.get_vendor_id().trim()),
-            frequency: x.get_frequency(),
-            usage: x.get_cpu_usage(),
+        for disk in remounted {
+            let alert_message = ReadOnlyAlertMessage::new(self.device_id.as_str(), &timestamp, disk.as_str());
+            let alert_json = serialize_message(&alert_message, self.key_case)?;
+            let sent_bytes = self.transmit_report(&self.alert_topics, alert_json.as_bytes())?;
+            self.record_sent_bytes(sent_bytes, timestamp)?;
         }
-    }).collect();
-    // Create report
-    Ok(SystemReport {
-        disks: disk_reports.into_boxed_slice(),
-        cpus: cpu_reports.into_boxed_slice(),
-        memory: memory_report,
-    })
+        Ok(())
+    }
+
+    /// Publishes a diagnostic to the diagnostic topic, if one is configured and the rate guard allows it. Diagnostics
+    /// are best-effort: the failure they describe has already been reported on stderr, and when the broker is the
+    /// problem they cannot be delivered anyway, so a failed publish is not reported in turn.
+    fn publish_diagnostic(&mut self, kind: &str, message: &str, collection_errors: &[CollectionError]) {
+        if self.diagnostic_topics.is_empty() {
+            return;
+        }
+        if !self.diagnostic_rate_guard.try_acquire(Instant::now()) {
+            self.suppressed_diagnostics += 1;
+            return;
+        }
+        let timestamp = match current_timestamp() {
+            Ok(timestamp) => timestamp,
+            Err(_) => return
+        };
+        let mut diagnostic_message = DiagnosticMessage::new(self.device_id.as_str(), &timestamp, kind, message);
+        diagnostic_message.collection_errors = collection_errors;
+        diagnostic_message.suppressed = self.suppressed_diagnostics;
+        let sent_bytes = serialize_message(&diagnostic_message, self.key_case)
+            .and_then(|diagnostic_json| self.transmit_report(&self.diagnostic_topics, diagnostic_json.as_bytes()));
+        if let Ok(sent_bytes) = sent_bytes {
+            self.suppressed_diagnostics = 0;
+            let _ = self.record_sent_bytes(sent_bytes, timestamp);
+        }
+    }
+
+    /// Acts on every command and configuration update received since the last call. Commands other than a request
+    /// for the report history are ignored.
+    fn handle_commands(&mut self) -> Result<(), Box<dyn Error>> {
+        while let Some(msg) = self.transport.try_receive() {
+            if self.config_topic.as_deref() == Some(msg.topic()) {
+                self.apply_config_update(msg.payload_str().as_ref());
+                continue;
+            }
+            match msg.payload_str().trim() {
+                DUMP_HISTORY_COMMAND => self.publish_history()?,
+                command => eprintln!("Ignoring unknown command '{}'", command),
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies a configuration update received on the config topic. An invalid update changes nothing and is reported
+    /// as a diagnostic. A new report interval applies once the report currently due has been sent.
+    fn apply_config_update(&mut self, payload: &str) {
+        let result = parse_config_update(
+            payload,
+            self.device_id.as_str(),
+            self.heartbeat_interval_secs,
+            !self.alert_topics.is_empty()
+        ).and_then(|config_update| {
+            // Dual publishing and topic lists have no single topic to replace
+            if config_update.topic.is_some() && self.topics.len() != 1 {
+                let error = Box::new(IllegalArgumentError::new(
+                    "The report topic can only be updated while reports are published to a single topic"
+                ));
+                return Err(error as Box<dyn Error>);
+            }
+            Ok(config_update)
+        });
+        let config_update = match result {
+            Ok(config_update) => config_update,
+            Err(e) => {
+                eprintln!("Rejecting configuration update: {}", e);
+                self.publish_diagnostic(CONFIG_DIAGNOSTIC, e.to_string().as_str(), &[]);
+                return;
+            }
+        };
+        if let Some(check_interval) = config_update.check_interval {
+            self.report_interval_update = Some(Duration::from_secs(check_interval * MINUTES_MULTIPLIER));
+        }
+        if let Some(topic) = config_update.topic {
+            self.topics[0].name = topic;
+        }
+        if let Some(thresholds) = config_update.thresholds {
+            self.threshold_monitor = if thresholds.is_empty() { None } else { Some(ThresholdMonitor::new(thresholds)) };
+        }
+    }
+
+    /// Publishes every retained report to the history topic as a single JSON array, oldest first.
+    fn publish_history(&mut self) -> Result<(), Box<dyn Error>> {
+        let history_json = match &self.history {
+            Some(history) => history.to_json(),
+            None => return Ok(())
+        };
+        if !self.acquire_publish_slot("history") {
+            return Ok(());
+        }
+        let timestamp = current_timestamp()?;
+        let compressed_history = self.compress_report(history_json.as_bytes())?;
+        let sent_bytes = self.transmit_report(&self.history_topics, &compressed_history)?;
+        self.record_sent_bytes(sent_bytes, timestamp)
+    }
+
+    /// Checks the minimum publish interval, warning that the message is dropped if it comes too soon after the last.
+    fn acquire_publish_slot(&mut self, message_kind: &str) -> bool {
+        let rate_guard = match self.rate_guard.as_mut() {
+            Some(rate_guard) => rate_guard,
+            None => return true
+        };
+        if rate_guard.try_acquire(Instant::now()) {
+            return true;
+        }
+        eprintln!("Dropping {} as it follows the previous publish too closely; check the configured intervals", message_kind);
+        false
+    }
+
+    /// Counts published payload bytes against the monthly budget, if one is configured.
+    fn record_sent_bytes(&mut self, sent_bytes: usize, timestamp: u64) -> Result<(), Box<dyn Error>> {
+        self.run_summary.bytes_transmitted += sent_bytes as u64;
+        if let Some(device_state) = self.device_state.as_mut() {
+            device_state.bytes_transmitted += sent_bytes as u64;
+        }
+        match self.byte_budget.as_mut() {
+            Some(byte_budget) => byte_budget.record(sent_bytes as u64, timestamp),
+            None => Ok(())
+        }
+    }
+
+    /// Publishes each report section as its own payload to a subtopic named after the section.
+    fn transmit_sections(&self, report: &SystemReport, unchanged_sections: &[&str]) -> Result<usize, Box<dyn Error>> {
+        let mut msgs = Vec::new();
+        for (section, section_json) in split_report(report, self.key_case)? {
+            if unchanged_sections.contains(&section) {
+                continue;
+            }
+            let compressed_section = self.compress_report(section_json.as_bytes())?;
+            for topic in self.topics.iter() {
+                let section_topic = PublishTopic { name: format!("{}/{}", topic.name, section), qos: topic.qos, retained: topic.retained };
+                msgs.push(self.build_message(&section_topic, &compressed_section)?);
+            }
+        }
+        self.transmit_messages(msgs)
+    }
+
+    fn transmit_report(&self, topics: &[PublishTopic], payload: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let mut msgs = Vec::with_capacity(topics.len());
+        for topic in topics {
+            msgs.push(self.build_message(topic, payload)?);
+        }
+        self.transmit_messages(msgs)
+    }
+
+    /// Publishes the payload whole when it fits in the maximum message size, and otherwise as numbered chunks carrying
+    /// the report's message ID, each chunk carrying a base64-encoded slice of the payload.
+    fn transmit_report_in_chunks(&self, topics: &[PublishTopic], message_id: &str, payload: &[u8]) -> Result<usize, Box<dyn Error>> {
+        let max_message_bytes = match self.max_message_bytes {
+            Some(max_message_bytes) if payload.len() > max_message_bytes => max_message_bytes,
+            _ => return self.transmit_report(topics, payload)
+        };
+        // The envelope is measured with the widest chunk numbers so that no chunk can outgrow the maximum
+        let envelope = ChunkMessage::new(self.device_id.as_str(), message_id, u32::MAX, u32::MAX, "");
+        let envelope_bytes = serialize_message(&envelope, self.key_case)?.len();
+        let data_bytes = match chunk_data_bytes(max_message_bytes, envelope_bytes) {
+            Some(data_bytes) => data_bytes,
+            None => {
+                let error = Box::new(RuntimeError::new(
+                    format!("Maximum message size of {} bytes leaves no room for chunk data", max_message_bytes).as_str()
+                ));
+                return Err(error);
+            }
+        };
+        let chunk_total = payload.len().div_ceil(data_bytes) as u32;
+        let mut msgs = Vec::with_capacity(topics.len() * chunk_total as usize);
+        for (chunk_index, chunk) in payload.chunks(data_bytes).enumerate() {
+            let data = encode_base64(chunk);
+            let chunk_message = ChunkMessage::new(
+                self.device_id.as_str(),
+                message_id,
+                chunk_index as u32,
+                chunk_total,
+                data.as_str()
+            );
+            let chunk_json = serialize_message(&chunk_message, self.key_case)?;
+            for topic in topics {
+                msgs.push(self.build_message(topic, chunk_json.as_bytes())?);
+            }
+        }
+        self.transmit_messages(msgs)
+    }
+
+    fn build_message(&self, topic: &PublishTopic, payload: &[u8]) -> Result<Message, Box<dyn Error>> {
+        let mut msg_builder = MessageBuilder::new()
+            .topic(topic.name.as_str())
+            .payload(payload)
+            .qos(topic.qos)
+            .retained(topic.retained);
+        if let Some(message_expiry_secs) = self.message_expiry_secs {
+            let mut properties = Properties::new();
+            if let Err(e) = properties.push_int(PropertyCode::MessageExpiryInterval, message_expiry_secs as i32) {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+            msg_builder = msg_builder.properties(properties);
+        }
+        Ok(msg_builder.finalize())
+    }
+
+    /// Publishes the messages over a single connection, returning the number of payload bytes sent. The connection
+    /// is opened and closed around the messages unless it is kept open between reports.
+    fn transmit_messages(&self, msgs: Vec<Message>) -> Result<usize, Box<dyn Error>> {
+        if self.keep_connected {
+            self.ensure_connected()?;
+        } else {
+            self.transport.connect()?;
+        }
+        let mut sent_bytes = 0;
+        let mut awaiting_ack = false;
+        for msg in msgs {
+            sent_bytes += msg.payload().len();
+            awaiting_ack |= msg.qos() > 0;
+            self.transport.publish(msg)?;
+        }
+        if !self.keep_connected {
+            // Disconnecting straight away could race the broker's acknowledgment and lose the report
+            let delivery = if awaiting_ack {
+                self.transport.wait_for_delivery(self.publish_ack_timeout)
+            } else {
+                Ok(())
+            };
+            self.transport.disconnect()?;
+            delivery?;
+        }
+        Ok(sent_bytes)
+    }
+
+    /// Closes a kept-open connection at the end of a continuous run, giving in-flight publishes the shutdown grace
+    /// period to be acknowledged first. Over the MQTT transport every publish has already been acknowledged by then,
+    /// so the grace period only changes anything with a transport that leaves publishes in flight.
+    fn disconnect_on_shutdown(&self) -> Result<(), Box<dyn Error>> {
+        if !self.keep_connected || !self.transport.is_connected() {
+            return Ok(());
+        }
+        match self.shutdown_grace {
+            Some(shutdown_grace) => self.transport.disconnect_after(shutdown_grace),
+            None => self.transport.disconnect()
+        }
+    }
+
+    /// Reconnects if the broker has dropped the connection, e.g. after a missed keep-alive, renewing the command
+    /// subscription.
+    fn ensure_connected(&self) -> Result<(), Box<dyn Error>> {
+        if self.transport.is_connected() {
+            return Ok(());
+        }
+        self.transport.connect()?;
+        for topic in self.command_topic.iter().chain(self.config_topic.iter()) {
+            self.transport.subscribe(topic.as_str(), COMMAND_QOS)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a message received during the self-test compares with the published report.
+#[derive(Debug,PartialEq)]
+enum Echo {
+    Matched,
+    Mismatched(String),
+    Unrelated,
+}
+
+/// The warning given when continuous mode runs on the default check interval because none was configured.
+fn default_check_interval_warning(runner_config: &RunnerConfig) -> Option<String> {
+    if !runner_config.check_interval_defaulted {
+        return None;
+    }
+    Some(format!(
+        "WARNING: check_interval is not set, so continuous mode falls back to checking every {} minute(s). Set \
+        check_interval to choose the interval, or require_explicit_interval to reject configurations without one.",
+        runner_config.check_interval
+    ))
+}
+
+fn duplicate_device_id_warning(device_id: &str) -> String {
+    format!(
+        "WARNING: the broker dropped the connection for device ID '{}' as soon as it was made. Another device is \
+        likely using the same ID, and the two will keep disconnecting each other; give each device a unique \
+        device_id or set device_id_source.",
+        device_id
+    )
+}
+
+/// Serializes each section of the report separately, omitting optional sections that were not collected.
+fn split_report(report: &SystemReport, key_case: KeyCase) -> Result<Vec<(&'static str, String)>, Box<dyn Error>> {
+    let mut sections = vec![
+        (DISKS_SECTION, serialize_message(&report.disks, key_case)?),
+        (CPUS_SECTION, serialize_message(&report.cpus, key_case)?),
+    ];
+    if let Some(memory) = &report.memory {
+        sections.push((MEMORY_SECTION, serialize_message(memory, key_case)?));
+    }
+    if let Some(file_descriptors) = &report.file_descriptors {
+        sections.push((FILE_DESCRIPTORS_SECTION, serialize_message(file_descriptors, key_case)?));
+    }
+    if let Some(power) = &report.power {
+        sections.push((POWER_SECTION, serialize_message(power, key_case)?));
+    }
+    if !report.components.is_empty() {
+        sections.push((COMPONENTS_SECTION, serialize_message(&report.components, key_case)?));
+    }
+    if !report.networks.is_empty() {
+        sections.push((NETWORKS_SECTION, serialize_message(&report.networks, key_case)?));
+    }
+    if let Some(agent) = &report.agent {
+        sections.push((AGENT_SECTION, serialize_message(agent, key_case)?));
+    }
+    if let Some(processes) = &report.processes {
+        sections.push((PROCESSES_SECTION, serialize_message(processes, key_case)?));
+    }
+    if let Some(custom) = &report.custom {
+        sections.push((CUSTOM_SECTION, serialize_message(custom, key_case)?));
+    }
+    Ok(sections)
+}
+
+fn serialize_message<T: Serialize>(message: &T, key_case: KeyCase) -> Result<String, Box<dyn Error>> {
+    let message_json = match serde_json::to_string(message) {
+        Ok(message_json) => message_json,
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            return Err(error);
+        }
+    };
+    if key_case == KeyCase::Camel {
+        return Ok(message_json);
+    }
+    // Re-parsed from text rather than converted directly so that f32 values keep their shortest representation
+    match serde_json::from_str(message_json.as_str()).and_then(|v| serde_json::to_string(&apply_key_case(v, key_case))) {
+        Ok(message_json) => Ok(message_json),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            Err(error)
+        }
+    }
+}
+
+fn flatten_json(message_json: &str) -> Result<String, Box<dyn Error>> {
+    match serde_json::from_str(message_json).and_then(|v| serde_json::to_string(&flatten_value(v))) {
+        Ok(message_json) => Ok(message_json),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            Err(error)
+        }
+    }
+}
+
+/// How long to wait before the retry following the given attempt, counting the first attempt as 0.
+fn publish_retry_delay(attempt: u32) -> Duration {
+    PUBLISH_RETRY_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(MAXIMUM_PUBLISH_RETRY_DELAY)
+}
+
+fn compression_ratio(compressed_len: usize, original_len: usize) -> f64 {
+    if original_len == 0 {
+        return 1.0;
+    }
+    compressed_len as f64 / original_len as f64
+}
+
+fn current_timestamp() -> Result<u64, Box<dyn Error>> {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => Ok(n.as_secs()),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use paho_mqtt::{Message, PropertyCode};
+    use pretty_assertions::{assert_eq, assert_ne};
+    use serde_json::Value;
+
+    use crate::lib::collector::{CPUS_SECTION, CpuSample, DISKS_SECTION, DiskSample, MemorySample, ReportGenerator, SystemSource};
+    use crate::lib::chunk::decode_base64;
+    use crate::lib::collector::fake::FakeSource;
+    use crate::lib::compression::{Codec, decompress, decompress_with_dictionary, decompress_with_header, REPORT_DICTIONARY};
+    use crate::lib::config::{apply_overrides, ConfigOverrides, DestinationConfig, load_config, RunnerConfig, TopicConfig};
+    use crate::lib::destination::mock::RecordingDestination;
+    use crate::lib::history::ReportHistory;
+    use crate::lib::message_id::MessageIdFormat;
+    use crate::lib::quiet_hours::QuietHours;
+    use crate::lib::report::{AGENT_VERSION, KeyCase, ReportFormat, ReportMessage};
+    use crate::lib::common::ErrorFormat;
+    use crate::lib::runner::{CommandLine, compression_ratio, current_timestamp, default_check_interval_warning, DeliveryTarget, duplicate_device_id_warning, Echo, error_format, execute_check_with_deadline, install_stop_handler, parse_command_line, parse_spool_label, publish_retry_delay, Runner, spool_label, start_service};
+    use crate::lib::service::RunControl;
+    use crate::lib::signing::SigningKey;
+    use crate::lib::spool::{Spool, SpoolFullStrategy};
+    use crate::lib::transport::mock::{MockTransport, TransportEvent};
+
+    #[derive(Clone,Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| String::from(*a)).collect()
+    }
+
+    #[test]
+    fn parse_command_line_flags() {
+        let command_line = parse_command_line(&args(&["config.yaml", "--interval", "10", "--topic", "Override"])).unwrap();
+        assert_eq!(
+            CommandLine {
+                config_path: Some(String::from("config.yaml")),
+                compare_compression: false,
+                show_stats: false,
+                self_test: false,
+                service: false,
+                error_format: ErrorFormat::Text,
+                overrides: ConfigOverrides {
+                    check_interval: Some(String::from("10")),
+                    topic: Some(String::from("Override")),
+                },
+            },
+            command_line
+        );
+        let command_line = parse_command_line(&args(&["--compare-compression", "config.yaml"])).unwrap();
+        assert!(command_line.compare_compression);
+        assert_eq!(Some(String::from("config.yaml")), command_line.config_path);
+        let command_line = parse_command_line(&args(&["config.yaml", "--stats"])).unwrap();
+        assert!(command_line.show_stats);
+        let command_line = parse_command_line(&args(&["config.yaml", "--self-test"])).unwrap();
+        assert!(command_line.self_test);
+        let command_line = parse_command_line(&args(&["--service", "config.yaml"])).unwrap();
+        assert!(command_line.service);
+        let command_line = parse_command_line(&args(&["--error-format", "json", "config.yaml"])).unwrap();
+        assert_eq!(ErrorFormat::Json, command_line.error_format);
+        assert_eq!(Some(String::from("config.yaml")), command_line.config_path);
+    }
+
+    #[test]
+    fn find_error_format_despite_invalid_arguments() {
+        assert_eq!(ErrorFormat::Json, error_format(&args(&["a.yaml", "b.yaml", "--error-format", "json"])));
+        assert_eq!(ErrorFormat::Text, error_format(&args(&["a.yaml", "--error-format", "xml"])));
+        assert_eq!(ErrorFormat::Text, error_format(&args(&["a.yaml"])));
+        let error = parse_command_line(&args(&["--error-format", "xml"])).err().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Unexpected error format 'xml'; supported formats are text and json",
+            error.to_string()
+        );
+    }
+
+    #[cfg(not(all(windows, feature = "windows-service")))]
+    #[test]
+    fn reject_service_flag_without_service_support() {
+        let error = start_service().err().unwrap();
+        assert_eq!(
+            "An illegal argument was encountered. Reason: The --service flag requires a Windows build with the windows-service feature",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_command_line_without_flag_value() {
+        let error = parse_command_line(&args(&["config.yaml", "--topic"])).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Flag '--topic' requires a value", error.to_string());
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let command_line = parse_command_line(
+            &args(&["resources/test/good/full_continuous.yaml", "--interval", "10", "--topic", "Override Topic"])
+        ).unwrap();
+        let mut runner_config = load_config(command_line.config_path.as_ref()).unwrap();
+        assert_eq!(5, runner_config.check_interval);
+        apply_overrides(&mut runner_config, &command_line.overrides).unwrap();
+        assert_eq!(10, runner_config.check_interval);
+        assert_eq!("Override Topic", runner_config.topic);
+    }
+
+    #[test]
+    fn keep_running_when_stop_handler_registration_fails() {
+        let run_control = Arc::new(RunControl::new());
+        let loop_control = run_control.clone();
+        let iterations = Arc::new(AtomicU64::new(0));
+        let loop_iterations = iterations.clone();
+        let run_thread = thread::spawn(move || {
+            while loop_control.is_running() {
+                loop_iterations.fetch_add(1, Ordering::SeqCst);
+                thread::park_timeout(Duration::from_millis(1));
+            }
+        });
+        run_control.attach(run_thread.thread().clone());
+        let installed = install_stop_handler(|_| Err("handler already registered"), run_control.clone());
+        assert!(!installed);
+        let iterations_after_failure = iterations.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(20));
+        assert!(run_control.is_running());
+        assert!(iterations.load(Ordering::SeqCst) > iterations_after_failure);
+        run_control.stop();
+        run_thread.join().unwrap();
+    }
+
+    #[test]
+    fn stop_loop_through_registered_handler() {
+        let run_control = Arc::new(RunControl::new());
+        let loop_control = run_control.clone();
+        let run_thread = thread::spawn(move || {
+            while loop_control.is_running() {
+                thread::park();
+            }
+        });
+        run_control.attach(run_thread.thread().clone());
+        let mut registered_handler = None;
+        let installed = install_stop_handler(|handler| {
+            registered_handler = Some(handler);
+            Ok::<(), String>(())
+        }, run_control.clone());
+        assert!(installed);
+        (registered_handler.unwrap())();
+        run_thread.join().unwrap();
+        assert!(!run_control.is_running());
+    }
+
+    #[test]
+    fn compression_ratio_bounds() {
+        assert_eq!(0.5, compression_ratio(50, 100));
+        assert_eq!(1.0, compression_ratio(0, 0));
+    }
+
+    #[test]
+    fn check_reports_compression_ratio() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let payload = decompress(Codec::Lz4, transport.messages()[0].payload()).unwrap();
+        let report_message: Value = serde_json::from_slice(&payload).unwrap();
+        let ratio = report_message["compressionRatio"].as_f64().unwrap();
+        assert!(ratio > 0.0 && ratio < 1.5, "Unexpected compression ratio {}", ratio);
+    }
+
+    #[test]
+    fn reports_share_session_id_within_run() {
+        let runner_config = load_config(None).unwrap();
+        let session_ids = |transport: &MockTransport| -> Vec<String> {
+            transport.messages().iter().map(|m| {
+                let report_message: Value = serde_json::from_slice(&decompress(Codec::Lz4, m.payload()).unwrap()).unwrap();
+                String::from(report_message["sessionId"].as_str().unwrap())
+            }).collect()
+        };
+        let first_transport = MockTransport::new();
+        let mut first_runner = Runner::with_transport(&runner_config, Box::new(first_transport.clone()));
+        first_runner.execute_check(&mut FakeSource::new()).unwrap();
+        first_runner.execute_check(&mut FakeSource::new()).unwrap();
+        let first_session_ids = session_ids(&first_transport);
+        assert_eq!(vec![first_runner.session_id.clone(); 2], first_session_ids);
+        // A restart starts a new session
+        let second_transport = MockTransport::new();
+        let mut second_runner = Runner::with_transport(&runner_config, Box::new(second_transport.clone()));
+        second_runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert_ne!(first_session_ids[0], session_ids(&second_transport)[0]);
+    }
+
+    #[test]
+    fn retried_report_keeps_dedup_id() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_retries = 1;
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.sleep = Box::new(|_| {});
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let report_messages: Vec<Value> = transport.messages().iter().map(|m| {
+            serde_json::from_slice(&decompress(Codec::Lz4, m.payload()).unwrap()).unwrap()
+        }).collect();
+        assert_eq!(2, report_messages.len());
+        assert_eq!(report_messages[0]["dedupId"], report_messages[1]["dedupId"]);
+        assert_ne!(report_messages[0]["messageId"], report_messages[1]["messageId"]);
+        // The next report is a new logical report
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let next_message: Value = serde_json::from_slice(&decompress(Codec::Lz4, transport.messages()[2].payload()).unwrap()).unwrap();
+        assert_ne!(report_messages[0]["dedupId"], next_message["dedupId"]);
+    }
+
+    #[test]
+    fn retried_report_takes_next_sequence_message_id() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_retries = 1;
+        runner_config.message_id_format = MessageIdFormat::Sequence;
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.sleep = Box::new(|_| {});
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let message_ids: Vec<Value> = transport.messages().iter().map(|m| {
+            let report_message: Value = serde_json::from_slice(&decompress(Codec::Lz4, m.payload()).unwrap()).unwrap();
+            report_message["messageId"].clone()
+        }).collect();
+        assert_eq!(vec![Value::from("1"), Value::from("2")], message_ids);
+    }
+
+    #[test]
+    fn report_fails_once_retries_are_exhausted() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_retries = 1;
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(2, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.sleep = Box::new(|_| {});
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        assert_eq!(2, transport.messages().len());
+    }
+
+    #[test]
+    fn back_off_between_retries() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_retries = 3;
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(3, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let sleeps = Arc::new(Mutex::new(Vec::new()));
+        let recorded_sleeps = sleeps.clone();
+        runner.sleep = Box::new(move |duration| recorded_sleeps.lock().unwrap().push(duration));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert_eq!(4, transport.messages().len());
+        assert_eq!(vec![Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(4)], *sleeps.lock().unwrap());
+        assert_eq!(Duration::from_secs(30), publish_retry_delay(10));
+    }
+
+    #[test]
+    fn retry_only_failed_destinations() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_retries = 1;
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let destination = RecordingDestination::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.sleep = Box::new(|_| {});
+        runner.destinations.push((Box::new(destination.clone()), true));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        // The broker is retried, but the destination that took the report the first time is not sent it again
+        assert_eq!(2, transport.messages().len());
+        assert_eq!(1, destination.payloads().len());
+    }
+
+    #[test]
+    fn spool_undelivered_report_until_next_delivery() {
+        let spool_dir = std::env::temp_dir().join(format!("device_stats_reporter_runner_spool_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.spool_dir = Some(String::from(spool_dir.to_str().unwrap()));
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let report_messages: Vec<Value> = transport.messages().iter().map(|m| {
+            serde_json::from_slice(&decompress(Codec::Lz4, m.payload()).unwrap()).unwrap()
+        }).collect();
+        // The failed report is flushed again after the report that succeeded
+        assert_eq!(3, report_messages.len());
+        assert_eq!(report_messages[0]["dedupId"], report_messages[2]["dedupId"]);
+        assert_ne!(report_messages[1]["dedupId"], report_messages[2]["dedupId"]);
+    }
+
+    #[test]
+    fn skip_collection_while_blocking_spool_is_full() {
+        let spool_dir = std::env::temp_dir().join(format!("device_stats_reporter_runner_full_spool_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.spool_dir = Some(String::from(spool_dir.to_str().unwrap()));
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(3, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        // Leave room for the spooled report but not for another
+        let spooled_bytes = std::fs::read_dir(&spool_dir).unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum::<u64>();
+        runner.spool = Some(Spool::new(
+            spool_dir.to_str().unwrap(),
+            None,
+            Some(spooled_bytes * 3 / 2),
+            SpoolFullStrategy::Block
+        ));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        // The spool is full, so the check only attempts to flush it
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        assert_eq!(3, transport.messages().len());
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let report_messages: Vec<Value> = transport.messages().iter().map(|m| {
+            serde_json::from_slice(&decompress(Codec::Lz4, m.payload()).unwrap()).unwrap()
+        }).collect();
+        // Only the spooled report is flushed, ahead of a newly collected one
+        assert_eq!(5, report_messages.len());
+        assert_eq!(report_messages[0]["dedupId"], report_messages[2]["dedupId"]);
+        assert_eq!(report_messages[0]["dedupId"], report_messages[3]["dedupId"]);
+        assert_ne!(report_messages[1]["dedupId"], report_messages[4]["dedupId"]);
+        assert_ne!(report_messages[0]["dedupId"], report_messages[4]["dedupId"]);
+    }
+
+    #[test]
+    fn record_compression_stats_once_per_report() {
+        let state_path = std::env::temp_dir().join(format!("device_stats_reporter_runner_stats_{}", std::process::id()));
+        let state_path = String::from(state_path.to_str().unwrap());
+        let _ = std::fs::remove_file(&state_path);
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_retries = 1;
+        runner_config.compression_stats_file = Some(state_path.clone());
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.sleep = Box::new(|_| {});
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let persisted = std::fs::read_to_string(&state_path).unwrap();
+        let _ = std::fs::remove_file(&state_path);
+        // The retry of the same report is not counted again
+        assert!(persisted.starts_with("1 "));
+    }
+
+    #[test]
+    fn continue_sequences_across_runs() {
+        let state_path = std::env::temp_dir().join(format!("device_stats_reporter_runner_state_{}", std::process::id()));
+        let state_path = String::from(state_path.to_str().unwrap());
+        let _ = std::fs::remove_file(&state_path);
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.state_file = Some(state_path.clone());
+        let mut first_run = Runner::with_transport(&runner_config, Box::new(MockTransport::new()));
+        first_run.execute_check(&mut FakeSource::new()).unwrap();
+        first_run.execute_check(&mut FakeSource::new()).unwrap();
+        first_run.send_heartbeat().unwrap();
+        let transport = MockTransport::new();
+        let mut second_run = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        second_run.execute_check(&mut FakeSource::new()).unwrap();
+        second_run.send_heartbeat().unwrap();
+        let persisted = std::fs::read_to_string(&state_path).unwrap();
+        let _ = std::fs::remove_file(&state_path);
+        let messages: Vec<Value> = transport.messages().iter().map(|m| serde_json::from_slice(m.payload()).unwrap()).collect();
+        assert_eq!(format!("{}-3", second_run.session_id), messages[0]["dedupId"]);
+        assert_eq!(2, messages[1]["sequence"]);
+        assert!(persisted.starts_with("3 2 3 0 "), "Unexpected device state {}", persisted);
+    }
+
+    #[test]
+    fn check_below_compression_threshold_is_sent_raw() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression_min_bytes = Some(1024 * 1024);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let payload = transport.messages()[0].payload().to_vec();
+        assert_eq!(Codec::None.id(), payload[0]);
+        let report_message: Value = serde_json::from_slice(&decompress_with_header(&payload).unwrap()).unwrap();
+        // Only the format header is added to a raw payload
+        let ratio = report_message["compressionRatio"].as_f64().unwrap();
+        assert!(ratio > 1.0 && ratio < 1.01, "Unexpected compression ratio {}", ratio);
+    }
+
+    #[test]
+    fn send_startup_report_once() {
+        let mut runner_config = load_config(
+            Some(&String::from("resources/test/good/startup_report.yaml"))
+        ).unwrap();
+        runner_config.keep_connected = true;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        runner.send_startup_report(&source).unwrap();
+        runner.execute_check(&mut source).unwrap();
+        runner.execute_check(&mut source).unwrap();
+        let startup_messages: Vec<Value> = transport.messages().iter()
+            .filter(|m| m.topic() == "devices/Test%20Device%20Name/startup")
+            .map(|m| serde_json::from_slice(m.payload()).unwrap())
+            .collect();
+        assert_eq!(1, startup_messages.len());
+        let startup_message = &startup_messages[0];
+        assert_eq!("Test Device Name", startup_message["deviceId"]);
+        assert_eq!(runner.session_id.as_str(), startup_message["sessionId"]);
+        assert_eq!(AGENT_VERSION, startup_message["agentVersion"]);
+        assert_eq!("edge-gateway-01", startup_message["hostName"]);
+        assert_eq!("Debian GNU/Linux", startup_message["osName"]);
+        assert_eq!("5.10.0-21-amd64", startup_message["kernelVersion"]);
+        assert_eq!(1, startup_message["cpuCount"]);
+        assert_eq!(2048, startup_message["memoryCapacity"]);
+        assert_eq!(serde_json::json!(["disks", "cpus", "memory"]), startup_message["sections"]);
+        // The startup message comes ahead of the first report
+        assert_eq!("devices/Test%20Device%20Name/startup", transport.messages()[0].topic());
+    }
+
+    #[test]
+    fn skip_startup_report_unless_configured() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.send_startup_report(&FakeSource::new()).unwrap();
+        assert!(transport.messages().is_empty());
+    }
+
+    #[test]
+    fn check_delivers_to_every_destination() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.destinations = vec![DestinationConfig::Mqtt { compress: true }];
+        let transport = MockTransport::new();
+        let destination = RecordingDestination::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.destinations.push((Box::new(destination.clone()), true));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let messages = transport.messages();
+        assert_eq!(1, messages.len());
+        assert_eq!(vec![messages[0].payload().to_vec()], destination.payloads());
+    }
+
+    #[test]
+    fn check_delivers_uncompressed_to_opted_out_destination() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.destinations = vec![DestinationConfig::Mqtt { compress: true }];
+        let transport = MockTransport::new();
+        let destination = RecordingDestination::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.destinations.push((Box::new(destination.clone()), false));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let raw_report = destination.payloads()[0].clone();
+        let compressed_report = transport.messages()[0].payload().to_vec();
+        assert_ne!(raw_report, compressed_report);
+        assert_eq!(raw_report, decompress(Codec::Lz4, &compressed_report).unwrap());
+        let report_message: Value = serde_json::from_slice(&raw_report).unwrap();
+        assert_eq!(runner_config.device_id, report_message["deviceId"]);
+    }
+
+    #[test]
+    fn flush_spooled_report_uncompressed_to_opted_out_destination() {
+        let spool_dir = std::env::temp_dir().join(format!("device_stats_reporter_runner_raw_spool_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.spool_dir = Some(String::from(spool_dir.to_str().unwrap()));
+        let transport = MockTransport::new();
+        let destination = RecordingDestination::failing();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.destinations.push((Box::new(destination.clone()), false));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        destination.failing.store(false, Ordering::SeqCst);
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        // The spooled report reaches the destination as-is, like the reports delivered straight away
+        let payloads = destination.payloads();
+        assert_eq!(2, payloads.len());
+        let report_messages: Vec<Value> = payloads.iter().map(|p| serde_json::from_slice(p).unwrap()).collect();
+        assert_eq!(runner_config.device_id, report_messages[1]["deviceId"]);
+        assert_ne!(report_messages[0]["dedupId"], report_messages[1]["dedupId"]);
+        // The broker took the report the first time, so it is not published again when the spool is flushed
+        assert_eq!(2, transport.messages().len());
+    }
+
+    #[test]
+    fn flush_spooled_report_only_to_failed_destination() {
+        let spool_dir = std::env::temp_dir().join(format!("device_stats_reporter_runner_target_spool_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.spool_dir = Some(String::from(spool_dir.to_str().unwrap()));
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let destination = RecordingDestination::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.destinations.push((Box::new(destination.clone()), true));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        // The broker fails again while the spool is flushed, so the report stays spooled for it alone
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        assert!(runner.flush_spool(current_timestamp().unwrap()).is_err());
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        let published: Vec<Value> = transport.messages().iter().map(|m| {
+            serde_json::from_slice(&decompress(Codec::Lz4, m.payload()).unwrap()).unwrap()
+        }).collect();
+        let delivered: Vec<Value> = destination.payloads().iter().map(|p| {
+            serde_json::from_slice(&decompress(Codec::Lz4, p).unwrap()).unwrap()
+        }).collect();
+        // The spooled report reaches the broker at last, and the destination that took it the first time only once
+        assert_eq!(4, published.len());
+        assert_eq!(published[0]["messageId"], published[3]["messageId"]);
+        assert_eq!(2, delivered.len());
+        assert_eq!(published[0]["messageId"], delivered[0]["messageId"]);
+        assert_ne!(published[0]["messageId"], delivered[1]["messageId"]);
+    }
+
+    #[test]
+    fn parse_spool_labels() {
+        let label = spool_label("message-1", &[DeliveryTarget::Broker, DeliveryTarget::Destination(2)]);
+        assert_eq!("message-1 mqtt,2", label);
+        assert_eq!(
+            (String::from("message-1"), Some(vec![DeliveryTarget::Broker, DeliveryTarget::Destination(2)])),
+            parse_spool_label(label.as_str())
+        );
+        // A report spooled before its targets were recorded goes to every target
+        assert_eq!((String::from("message-1"), None), parse_spool_label("message-1"));
+    }
+
+    #[test]
+    fn check_without_mqtt_destination() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.destinations = Vec::new();
+        let transport = MockTransport::new();
+        let destination = RecordingDestination::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.destinations.push((Box::new(destination.clone()), true));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert!(transport.events().is_empty());
+        assert_eq!(1, destination.payloads().len());
+    }
+
+    #[test]
+    fn check_reports_failed_destination() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let destination = RecordingDestination::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.destinations.push((Box::new(RecordingDestination::failing()), true));
+        runner.destinations.push((Box::new(destination.clone()), true));
+        let result = runner.execute_check(&mut FakeSource::new()).err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Unable to deliver report to recording: An error was encountered during runtime. Reason: Destination is unavailable",
+            result.to_string()
+        );
+        // The failure does not stop delivery to the broker or the later destination
+        assert_eq!(1, transport.messages().len());
+        assert_eq!(1, destination.payloads().len());
+    }
+
+    #[test]
+    fn check_with_compression_dictionary() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression_dictionary = true;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let payload = decompress_with_dictionary(transport.messages()[0].payload(), REPORT_DICTIONARY).unwrap();
+        let report_message: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(runner_config.device_id.as_str(), report_message["deviceId"]);
+    }
+
+    #[test]
+    fn check_with_padding() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.pad_to_bytes = Some(1024);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let payload = transport.messages()[0].payload().to_vec();
+        assert_eq!(0, payload.len() % 1024);
+        let report_message: Value = serde_json::from_slice(&decompress_with_header(&payload).unwrap()).unwrap();
+        assert_eq!(runner_config.device_id.as_str(), report_message["deviceId"]);
+    }
+
+    #[test]
+    fn quiet_check_prints_nothing() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.output = Box::new(output.clone());
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert_eq!("", output.contents());
+        assert_eq!(1, transport.messages().len());
+    }
+
+    #[test]
+    fn verbose_check_prints_report() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.verbose = true;
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(MockTransport::new()));
+        runner.output = Box::new(output.clone());
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let contents = output.contents();
+        assert!(contents.contains("System Report: "));
+        assert!(contents.contains("Compressed Report: "));
+        assert!(contents.contains("Compression: "));
+    }
+
+    #[test]
+    fn self_test_round_trip() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::echoing();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.self_test(&mut FakeSource::new(), Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            vec![
+                TransportEvent::Connect,
+                TransportEvent::Subscribe(String::from("Device_Status"), 0),
+                TransportEvent::Publish(String::from("Device_Status"), 0),
+                TransportEvent::Disconnect,
+            ],
+            transport.events()
+        );
+    }
+
+    #[test]
+    fn self_test_round_trip_with_padded_dictionary_payload() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::Lz4;
+        runner_config.compression_dictionary = true;
+        runner_config.pad_to_bytes = Some(256);
+        runner_config.key_case = KeyCase::Snake;
+        let mut runner = Runner::with_transport(&runner_config, Box::new(MockTransport::echoing()));
+        runner.self_test(&mut FakeSource::new(), Duration::from_secs(1)).unwrap();
+    }
+
+    #[test]
+    fn self_test_times_out_without_echo() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        transport.deliver(Message::new("Device_Status", "{\"messageId\":\"earlier\"}", 0));
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let error = runner.self_test(&mut FakeSource::new(), Duration::from_millis(0)).err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Self-test failed: the report was not received back on Device_Status within 0 seconds (1 other messages were skipped)",
+            error.to_string()
+        );
+        assert_eq!(Some(&TransportEvent::Disconnect), transport.events().last());
+    }
+
+    #[test]
+    fn compare_echoed_reports() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        let runner = Runner::with_transport(&runner_config, Box::new(MockTransport::new()));
+        let report_json = r#"{"deviceId":"device-1","messageId":"message-1","report":{"disks":[]}}"#;
+        assert_eq!(Echo::Matched, runner.compare_echo(report_json.as_bytes(), report_json, "message-1"));
+        let altered_json = r#"{"deviceId":"device-1","messageId":"message-1","report":{"disks":[1]}}"#;
+        assert_eq!(
+            Echo::Mismatched(String::from("the report received back differs from the one published")),
+            runner.compare_echo(altered_json.as_bytes(), report_json, "message-1")
+        );
+        // Other publishers' messages on the same topic are skipped
+        assert_eq!(Echo::Unrelated, runner.compare_echo(report_json.as_bytes(), report_json, "message-2"));
+        assert_eq!(Echo::Unrelated, runner.compare_echo(b"not json", report_json, "message-1"));
+    }
+
+    #[test]
+    fn publish_oversized_report_in_chunks() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.max_message_bytes = Some(1024);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut sys = FakeSource::new();
+        sys.disks = (0..50).map(|i| DiskSample {
+            name: format!("sda{}", i),
+            mount_point: format!("/mnt/{}", i),
+            total_space: 1000,
+            available_space: 400,
+        }).collect();
+        runner.execute_check(&mut sys).unwrap();
+        let messages = transport.messages();
+        assert!(messages.len() > 1);
+        let chunks: Vec<Value> = messages.iter().map(|m| {
+            assert!(m.payload().len() <= 1024);
+            serde_json::from_slice(m.payload()).unwrap()
+        }).collect();
+        let message_id = chunks[0]["messageId"].clone();
+        let mut payload = Vec::new();
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(message_id, chunk["messageId"]);
+            assert_eq!(Value::from(chunk_index), chunk["chunkIndex"]);
+            assert_eq!(Value::from(chunks.len()), chunk["chunkTotal"]);
+            payload.extend(decode_base64(chunk["data"].as_str().unwrap()));
+        }
+        let report_message: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(50, report_message["report"]["disks"].as_array().unwrap().len());
+        // The chunks link back to the report they carry
+        assert_eq!(report_message["messageId"], message_id);
+    }
+
+    #[test]
+    fn chunk_with_configured_message_id_format() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.max_message_bytes = Some(1024);
+        runner_config.message_id_format = MessageIdFormat::Sequence;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut sys = FakeSource::new();
+        sys.disks = (0..50).map(|i| DiskSample {
+            name: format!("sda{}", i),
+            mount_point: format!("/mnt/{}", i),
+            total_space: 1000,
+            available_space: 400,
+        }).collect();
+        runner.execute_check(&mut sys).unwrap();
+        let messages = transport.messages();
+        assert!(messages.len() > 1);
+        for message in messages.iter() {
+            let chunk: Value = serde_json::from_slice(message.payload()).unwrap();
+            assert_eq!(Value::from("1"), chunk["messageId"]);
+        }
+    }
+
+    #[test]
+    fn publish_small_report_whole() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.max_message_bytes = Some(65_536);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let messages = transport.messages();
+        assert_eq!(1, messages.len());
+        let report_message: Value = serde_json::from_slice(messages[0].payload()).unwrap();
+        assert!(report_message.get("chunkIndex").is_none());
+        assert!(report_message.get("report").is_some());
+    }
+
+    #[test]
+    fn self_test_requires_json_format() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.format = ReportFormat::InfluxLine;
+        let transport = MockTransport::echoing();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let error = runner.self_test(&mut FakeSource::new(), Duration::from_secs(1)).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: The self-test requires the json format", error.to_string());
+        assert!(transport.events().is_empty());
+    }
+
+    #[test]
+    fn probe_reachable_broker() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.probe().unwrap();
+        assert_eq!(vec![TransportEvent::Connect, TransportEvent::Disconnect], transport.events());
+    }
+
+    #[test]
+    fn create_no_mqtt_client_without_mqtt_destination() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.server_address = String::from("unsupported://broker");
+        runner_config.destinations = vec![DestinationConfig::Http {
+            url: String::from("http://localhost:8080/reports"),
+            headers: Default::default(),
+            compress: true,
+        }];
+        let runner = Runner::new(&runner_config).unwrap();
+        assert_eq!(1, runner.destinations.len());
+        let error = runner.transport.connect().err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: MQTT is not in use; no broker connection was configured",
+            error.to_string()
+        );
+        // The same settings cannot create an MQTT client
+        runner_config.destinations.push(DestinationConfig::Mqtt { compress: true });
+        assert!(Runner::new(&runner_config).is_err());
+    }
+
+    #[test]
+    fn probe_unreachable_broker() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::unreachable();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let result = runner.probe().err().unwrap();
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Startup connectivity probe failed: An error was encountered during runtime. Reason: Broker is unreachable",
+            result.to_string()
+        );
+        assert!(transport.messages().is_empty());
+    }
+
+    #[test]
+    fn detect_duplicate_device_id() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::colliding();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(runner.detect_duplicate_device_id(Duration::from_millis(1)).unwrap());
+        assert_eq!(vec![TransportEvent::Connect], transport.events());
+        assert!(duplicate_device_id_warning("device-1").contains("device ID 'device-1'"));
+    }
+
+    #[test]
+    fn warn_of_default_check_interval_in_continuous_mode() {
+        let runner_config = load_config(Some(&String::from("resources/test/good/bare_continuous.yaml"))).unwrap();
+        let warning = default_check_interval_warning(&runner_config).unwrap();
+        assert!(warning.starts_with("WARNING: check_interval is not set"), "{}", warning);
+        assert!(warning.contains("every 1 minute(s)"), "{}", warning);
+        // Nothing to note where the interval is configured, or not used at all
+        let runner_config = load_config(Some(&String::from("resources/test/good/full_continuous.yaml"))).unwrap();
+        assert_eq!(None, default_check_interval_warning(&runner_config));
+        let runner_config = load_config(Some(&String::from("resources/test/good/bare_single.yaml"))).unwrap();
+        assert_eq!(None, default_check_interval_warning(&runner_config));
+    }
+
+    #[test]
+    fn detect_unique_device_id() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(!runner.detect_duplicate_device_id(Duration::from_millis(1)).unwrap());
+        assert_eq!(vec![TransportEvent::Connect, TransportEvent::Disconnect], transport.events());
+    }
+
+    #[test]
+    fn transmit_to_single_topic() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        assert_eq!(
+            vec![
+                TransportEvent::Connect,
+                TransportEvent::Publish(String::from("Device_Status"), 0),
+                TransportEvent::Disconnect,
+            ],
+            transport.events()
+        );
+    }
+
+    #[test]
+    fn check_with_dual_publish() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/dual_publish.yaml"))
+        ).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let messages = transport.messages();
+        assert_eq!(2, messages.len());
+        assert_eq!("devices/Test%20Device%20Name/state", messages[0].topic());
+        assert!(messages[0].retained());
+        assert_eq!("devices/Test%20Device%20Name/events", messages[1].topic());
+        assert!(!messages[1].retained());
+        assert_eq!(messages[0].payload(), messages[1].payload());
+    }
+
+    #[test]
+    fn transmit_without_retain() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        assert!(!transport.messages()[0].retained());
+    }
+
+    #[test]
+    fn transmit_with_per_topic_qos() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.qos = 2;
+        runner_config.topics = vec![
+            TopicConfig { name: String::from("aggregate/status"), qos: Some(0) },
+            TopicConfig { name: String::from("devices/1/status"), qos: Some(1) },
+            TopicConfig { name: String::from("archive/status"), qos: None },
+        ];
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        assert_eq!(
+            vec![
+                TransportEvent::Connect,
+                TransportEvent::Publish(String::from("aggregate/status"), 0),
+                TransportEvent::Publish(String::from("devices/1/status"), 1),
+                TransportEvent::Publish(String::from("archive/status"), 2),
+                TransportEvent::Disconnect,
+            ],
+            transport.events()
+        );
+        assert!(transport.messages().iter().all(|m| m.payload() == b"payload"));
+    }
+
+    #[test]
+    fn transmit_with_message_expiry() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.message_expiry_secs = Some(3600);
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        let messages = transport.messages();
+        assert_eq!(Some(3600), messages[0].properties().get_int(PropertyCode::MessageExpiryInterval));
+    }
+
+    #[test]
+    fn transmit_without_message_expiry() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        let messages = transport.messages();
+        assert_eq!(None, messages[0].properties().get_int(PropertyCode::MessageExpiryInterval));
+    }
+
+    #[test]
+    fn check_with_split_topics() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.split_topics = true;
+        runner_config.compression = Codec::None;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert_eq!(
+            vec![
+                TransportEvent::Connect,
+                TransportEvent::Publish(String::from("Device_Status/disks"), 0),
+                TransportEvent::Publish(String::from("Device_Status/cpus"), 0),
+                TransportEvent::Publish(String::from("Device_Status/memory"), 0),
+                TransportEvent::Disconnect,
+            ],
+            transport.events()
+        );
+        let payloads: Vec<String> = transport.messages().iter()
+            .map(|m| String::from_utf8(m.payload().to_vec()).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                String::from(r#"[{"name":"sda1","diskUsed":600,"diskCapacity":1000}]"#),
+                String::from(r#"[{"name":"cpu0","brand":"Test Brand","vendorId":"Test Vendor","frequency":2400.0,"frequencyUnit":"MHz","usage":12.5}]"#),
+                String::from(r#"{"memoryUsed":1536,"memoryCapacity":2048}"#),
+            ],
+            payloads
+        );
+    }
+
+    /// A source whose refresh stalls, as if a disk or network filesystem had stopped responding.
+    struct StalledSource {
+        source: FakeSource,
+        stall: Duration,
+    }
+
+    impl SystemSource for StalledSource {
+        fn refresh(&mut self) {
+            thread::sleep(self.stall);
+        }
+
+        fn disks(&self) -> Result<Vec<DiskSample>, Box<dyn Error>> {
+            self.source.disks()
+        }
+
+        fn cpus(&self) -> Result<Vec<CpuSample>, Box<dyn Error>> {
+            self.source.cpus()
+        }
+
+        fn memory(&self) -> Result<MemorySample, Box<dyn Error>> {
+            self.source.memory()
+        }
+    }
+
+    #[test]
+    fn abandon_check_past_deadline() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let source = StalledSource { source: FakeSource::new(), stall: Duration::from_secs(30) };
+        let started = Instant::now();
+        let result = execute_check_with_deadline(runner, source, Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Check did not finish within its deadline of 0.1 seconds",
+            result.err().unwrap().to_string()
+        );
+        assert!(transport.messages().is_empty());
+    }
+
+    #[test]
+    fn finish_check_within_deadline() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let source = StalledSource { source: FakeSource::new(), stall: Duration::from_millis(10) };
+        execute_check_with_deadline(runner, source, Duration::from_secs(30)).unwrap();
+        assert_eq!(1, transport.messages().len());
+        // The check's own errors are passed back from the worker thread
+        let runner = Runner::with_transport(&runner_config, Box::new(MockTransport::unreachable()));
+        let source = StalledSource { source: FakeSource::new(), stall: Duration::from_millis(10) };
+        assert!(execute_check_with_deadline(runner, source, Duration::from_secs(30)).is_err());
+    }
+
+    fn diagnostic_messages(transport: &MockTransport) -> Vec<Value> {
+        transport.messages().iter()
+            .filter(|m| m.topic() == "diagnostics")
+            .map(|m| serde_json::from_slice(m.payload()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn publish_collection_error_diagnostic() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.diagnostic_topic = Some(String::from("diagnostics"));
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![DISKS_SECTION];
+        runner.execute_check(&mut source).unwrap();
+        let diagnostic_messages = diagnostic_messages(&transport);
+        assert_eq!(1, diagnostic_messages.len());
+        assert_eq!("collection", diagnostic_messages[0]["kind"]);
+        assert_eq!("1 report sections failed to collect", diagnostic_messages[0]["message"]);
+        assert_eq!(DISKS_SECTION, diagnostic_messages[0]["collectionErrors"][0]["section"]);
+        assert_eq!(
+            "An error was encountered during runtime. Reason: Collection of disks failed",
+            diagnostic_messages[0]["collectionErrors"][0]["message"]
+        );
+        // The report still goes out alongside the diagnostic
+        assert_eq!(2, transport.messages().len());
+    }
+
+    #[test]
+    fn publish_check_failure_diagnostic() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.diagnostic_topic = Some(String::from("diagnostics"));
+        let transport = MockTransport::new();
+        transport.unacknowledged_publishes.store(1, Ordering::SeqCst);
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        let diagnostic_messages = diagnostic_messages(&transport);
+        assert_eq!(1, diagnostic_messages.len());
+        assert_eq!("check", diagnostic_messages[0]["kind"]);
+        assert!(diagnostic_messages[0]["message"].as_str().unwrap().contains("Publish was not acknowledged"));
+    }
+
+    #[test]
+    fn rate_limit_diagnostics() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.diagnostic_topic = Some(String::from("diagnostics"));
+        runner_config.diagnostic_min_interval_secs = 1;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        source.failing_sections = vec![DISKS_SECTION];
+        for _ in 0..3 {
+            runner.execute_check(&mut source).unwrap();
+        }
+        assert_eq!(1, diagnostic_messages(&transport).len());
+        thread::sleep(Duration::from_secs(1));
+        runner.execute_check(&mut source).unwrap();
+        let diagnostic_messages = diagnostic_messages(&transport);
+        assert_eq!(2, diagnostic_messages.len());
+        assert_eq!(2, diagnostic_messages[1]["suppressed"]);
+    }
+
+    #[test]
+    fn skip_reports_while_idle() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.idle_skip = true;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        source.cpus[0].usage = 1.0;
+        let mut check = |source: &mut FakeSource, network_bytes: u64| {
+            source.network_bytes = Some(network_bytes);
+            // Leaves time for a network rate to be measured between checks
+            thread::sleep(Duration::from_millis(10));
+            runner.execute_check(source).unwrap();
+            transport.messages().len()
+        };
+        // The first check has no network rate to go by
+        assert_eq!(1, check(&mut source, 1000));
+        assert_eq!(1, check(&mut source, 1000));
+        // Network traffic
+        assert_eq!(2, check(&mut source, 1_000_000));
+        assert_eq!(2, check(&mut source, 1_000_000));
+        // CPU usage
+        source.cpus[0].usage = 50.0;
+        assert_eq!(3, check(&mut source, 1_000_000));
+    }
+
+    #[test]
+    fn report_while_idle_without_idle_skip() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        source.cpus[0].usage = 1.0;
+        runner.execute_check(&mut source).unwrap();
+        runner.execute_check(&mut source).unwrap();
+        assert_eq!(2, transport.messages().len());
+    }
+
+    #[test]
+    fn hold_back_reports_during_quiet_hours() {
+        let mut runner_config = load_config(None).unwrap();
+        // 02:00 to 04:00 UTC
+        runner_config.quiet_hours = Some(QuietHours::new(2 * 60, 4 * 60, 0));
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        // 2021-06-15T03:00:00Z
+        runner.clock = || Ok(1_623_726_000);
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert!(transport.messages().is_empty());
+        // 2021-06-15T04:00:00Z
+        runner.clock = || Ok(1_623_729_600);
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert_eq!(1, transport.messages().len());
+    }
+
+    #[test]
+    fn throttle_checks_past_byte_budget() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.monthly_byte_budget = Some(1);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        for _ in 0..3 {
+            runner.execute_check(&mut FakeSource::new()).unwrap();
+        }
+        // The first report exhausts the budget, so the following ones are skipped
+        assert_eq!(1, transport.messages().len());
+        let sent_bytes = transport.messages()[0].payload().len() as u64;
+        assert_eq!(sent_bytes, runner.byte_budget.as_ref().unwrap().used_bytes());
+    }
+
+    #[test]
+    fn throttle_checks_over_cellular() {
+        let mut runner_config = load_config(Some(&String::from("resources/test/good/cellular_throttle.yaml"))).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.cellular_interval_multiplier = 2;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        source.network_interfaces[0].name = String::from("wwan0");
+        for _ in 0..3 {
+            runner.execute_check(&mut source).unwrap();
+        }
+        // Only every other report goes out over cellular, and without per-core detail
+        assert_eq!(2, transport.messages().len());
+        let report_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert_eq!(true, report_message["report"]["reducedDetail"]);
+        // Back on Ethernet every report is sent in full
+        source.network_interfaces[0].name = String::from("eth0");
+        runner.execute_check(&mut source).unwrap();
+        assert_eq!(3, transport.messages().len());
+        let report_message: Value = serde_json::from_slice(transport.messages()[2].payload()).unwrap();
+        assert!(report_message["report"].get("reducedDetail").is_none());
+    }
+
+    #[test]
+    fn summarize_checks() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        for _ in 0..2 {
+            runner.execute_check(&mut FakeSource::new()).unwrap();
+        }
+        runner.transport = Box::new(MockTransport::unreachable());
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        let sent_bytes: u64 = transport.messages().iter().map(|m| m.payload().len() as u64).sum();
+        assert_eq!(3, runner.run_summary.checks);
+        assert_eq!(2, runner.run_summary.successes);
+        assert_eq!(1, runner.run_summary.failures);
+        assert_eq!(sent_bytes, runner.run_summary.bytes_transmitted);
+        assert_eq!(
+            format!("Run summary: 3 checks (2 succeeded, 1 failed), {} bytes transmitted, uptime 0h 0m 0s", sent_bytes),
+            runner.run_summary.to_string()
+        );
+    }
+
+    #[test]
+    fn drop_rapid_publishes() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.min_publish_interval_ms = Some(60_000);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        runner.send_heartbeat().unwrap();
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        // Only the first publish falls outside the minimum interval
+        assert_eq!(1, transport.messages().len());
+        assert_eq!(0, runner.heartbeat_sequence);
+    }
+
+    #[test]
+    fn check_omits_unchanged_sections() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.change_thresholds.insert(CPUS_SECTION, 5.0);
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        runner.execute_check(&mut source).unwrap();
+        source.cpus[0].usage = 15.0;
+        runner.execute_check(&mut source).unwrap();
+        source.cpus[0].usage = 20.0;
+        runner.execute_check(&mut source).unwrap();
+        let report_messages: Vec<Value> = transport.messages().iter()
+            .map(|m| serde_json::from_slice(m.payload()).unwrap())
+            .collect();
+        assert_eq!(1, report_messages[0]["report"]["cpus"].as_array().unwrap().len());
+        assert!(report_messages[0].get("unchangedSections").is_none());
+        assert!(report_messages[1]["report"]["cpus"].as_array().unwrap().is_empty());
+        assert_eq!(serde_json::json!(["cpus"]), report_messages[1]["unchangedSections"]);
+        assert_eq!(20.0, report_messages[2]["report"]["cpus"][0]["usage"]);
+        // Sections without a threshold are always sent
+        assert!(report_messages.iter().all(|m| m["report"]["memory"].is_object()));
+    }
+
+    #[test]
+    fn check_with_influx_line_report() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.format = ReportFormat::InfluxLine;
+        runner_config.tags.insert(String::from("site"), String::from("plant-7"));
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let payload = String::from_utf8(transport.messages()[0].payload().to_vec()).unwrap();
+        let memory_line = payload.lines().find(|l| l.starts_with("memory,")).unwrap();
+        let prefix = format!("memory,device_id={},site=plant-7 used=1536i,capacity=2048i ", runner_config.device_id);
+        assert!(memory_line.starts_with(prefix.as_str()), "Unexpected line {}", memory_line);
+        assert!(payload.lines().any(|l| l.starts_with("cpu,") && l.contains(",name=cpu0 usage=12.5,frequency=2400 ")));
+    }
+
+    #[test]
+    fn check_with_signed_report() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.hmac_secret = Some(String::from("TestSigningSecret"));
+        runner_config.hmac_key_id = Some(String::from("2024-06"));
+        let signed_report = |runner_config: &RunnerConfig| -> (Value, String) {
+            let transport = MockTransport::new();
+            let mut runner = Runner::with_transport(runner_config, Box::new(transport.clone()));
+            runner.execute_check(&mut FakeSource::new()).unwrap();
+            let payload = String::from_utf8(transport.messages()[0].payload().to_vec()).unwrap();
+            (serde_json::from_str(payload.as_str()).unwrap(), payload)
+        };
+        let (report_message, payload) = signed_report(&runner_config);
+        assert_eq!("2024-06", report_message["keyId"]);
+        let signature = report_message["signature"].as_str().unwrap();
+        assert_eq!(64, signature.len());
+        // The signature covers the message as it was before the signature was appended, key ID included
+        let unsigned_payload = format!("{}}}", payload.strip_suffix(format!(",\"signature\":\"{}\"}}", signature).as_str()).unwrap());
+        let signing_key = SigningKey::new("TestSigningSecret", None);
+        assert_eq!(payload, signing_key.sign_message(unsigned_payload.as_str()));
+        // A rotated key is identified in the message
+        runner_config.hmac_key_id = Some(String::from("2024-07"));
+        let (rotated_report_message, _) = signed_report(&runner_config);
+        assert_eq!("2024-07", rotated_report_message["keyId"]);
+        assert!(rotated_report_message["signature"].is_string());
+    }
+
+    #[test]
+    fn check_with_located_report() {
+        let mut runner_config = load_config(Some(&String::from("resources/test/good/location.yaml"))).unwrap();
+        runner_config.compression = Codec::None;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let report_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert_eq!(serde_json::json!({"latitude": -33.8688, "longitude": 151.2093}), report_message["location"]);
+        // Left out unless configured
+        runner_config.location = None;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let report_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert!(report_message.get("location").is_none());
+    }
+
+    #[test]
+    fn serialize_identical_reports_identically() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.key_case = KeyCase::Snake;
+        runner_config.flatten = true;
+        runner_config.hmac_secret = Some(String::from("TestSigningSecret"));
+        runner_config.include_process_counts = true;
+        // Each run has its own runner and report, as separate processes would
+        let serialized_report = || -> String {
+            let runner = Runner::with_transport(&runner_config, Box::new(MockTransport::new()));
+            let (report, collection_errors) = ReportGenerator::new(&runner_config).generate_report(&mut FakeSource::new());
+            let report_message = ReportMessage::new(
+                "device-1",
+                "message-1",
+                "session-1",
+                "session-1-1",
+                &1_623_715_200,
+                &report,
+                &collection_errors
+            );
+            runner.serialize_report(&report_message).unwrap()
+        };
+        let first_report = serialized_report();
+        for _ in 0..10 {
+            assert_eq!(first_report, serialized_report());
+        }
+    }
+
+    #[test]
+    fn check_without_signature() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let report_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert!(report_message.get("signature").is_none());
+        assert!(report_message.get("keyId").is_none());
+    }
+
+    #[test]
+    fn dump_history_on_command() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/history.yaml"))
+        ).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.history = Some(ReportHistory::new(2));
+        for _ in 0..3 {
+            runner.execute_check(&mut FakeSource::new()).unwrap();
+        }
+        assert!(transport.events().contains(&TransportEvent::Subscribe(String::from("devices/Test%20Device%20Name/commands"), 1)));
+        transport.deliver(Message::new("devices/Test%20Device%20Name/commands", "dump_history", 1));
+        runner.handle_commands().unwrap();
+        let messages = transport.messages();
+        assert_eq!(4, messages.len());
+        assert_eq!("devices/Test%20Device%20Name/history", messages[3].topic());
+        let history: Value = serde_json::from_slice(&decompress(Codec::Lz4, messages[3].payload()).unwrap()).unwrap();
+        let dedup_ids: Vec<&str> = history.as_array().unwrap().iter().map(|r| r["dedupId"].as_str().unwrap()).collect();
+        // Only the two most recent reports are retained
+        assert_eq!(vec![format!("{}-2", runner.session_id), format!("{}-3", runner.session_id)], dedup_ids);
+    }
+
+    #[test]
+    fn apply_config_update_from_config_topic() {
+        let mut runner_config = load_config(
+            Some(&String::from("resources/test/good/config_topic.yaml"))
+        ).unwrap();
+        runner_config.diagnostic_topic = Some(String::from("devices/diagnostics"));
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.ensure_connected().unwrap();
+        assert!(transport.events().contains(&TransportEvent::Subscribe(String::from("devices/Test%20Device%20Name/config"), 1)));
+        transport.deliver(Message::new(
+            "devices/Test%20Device%20Name/config",
+            r#"{"check_interval": 10, "topic": "devices/{device_id}/stats", "thresholds": {"memory": 90.0}}"#,
+            1
+        ));
+        runner.handle_commands().unwrap();
+        assert_eq!(Some(Duration::from_secs(600)), runner.report_interval_update.take());
+        assert_eq!("devices/Test%20Device%20Name/stats", runner.topics[0].name);
+        assert!(runner.threshold_monitor.is_some());
+        assert!(transport.messages().is_empty());
+        // An invalid update changes nothing
+        transport.deliver(Message::new("devices/Test%20Device%20Name/config", r#"{"check_interval": 0, "topic": "other"}"#, 1));
+        runner.handle_commands().unwrap();
+        assert_eq!(None, runner.report_interval_update);
+        assert_eq!("devices/Test%20Device%20Name/stats", runner.topics[0].name);
+        let messages = transport.messages();
+        assert_eq!(1, messages.len());
+        assert_eq!("devices/diagnostics", messages[0].topic());
+        let diagnostic_message: Value = serde_json::from_slice(messages[0].payload()).unwrap();
+        assert_eq!("config", diagnostic_message["kind"]);
+        assert_eq!(
+            "An illegal argument was encountered. Reason: Check interval must be between 1 and 240",
+            diagnostic_message["message"]
+        );
+    }
+
+    #[test]
+    fn ignore_unknown_command() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/history.yaml"))
+        ).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        transport.deliver(Message::new("devices/Test%20Device%20Name/commands", "reboot", 1));
+        runner.handle_commands().unwrap();
+        assert!(transport.messages().is_empty());
+    }
+
+    #[test]
+    fn alert_once_per_threshold_crossing() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/thresholds.yaml"))
+        ).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        source.memory.available_memory = 64;
+        runner.check_thresholds(&mut source).unwrap();
+        runner.check_thresholds(&mut source).unwrap();
+        assert_eq!(1, transport.messages().len());
+        let alert_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert_eq!("devices/Test%20Device%20Name/alerts", transport.messages()[0].topic());
+        assert_eq!("memory", alert_message["section"]);
+        assert_eq!(96.875, alert_message["value"]);
+        assert_eq!(95.0, alert_message["threshold"]);
+        // Falling back below the threshold re-arms the alert for the next crossing
+        source.memory.available_memory = 512;
+        runner.check_thresholds(&mut source).unwrap();
+        source.memory.available_memory = 64;
+        runner.check_thresholds(&mut source).unwrap();
+        assert_eq!(2, transport.messages().len());
+    }
+
+    #[test]
+    fn alert_once_when_free_space_runs_low() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/free_space_alerts.yaml"))
+        ).unwrap();
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(runner.monitors_thresholds());
+        let mut source = FakeSource::new();
+        runner.check_thresholds(&mut source).unwrap();
+        assert!(transport.messages().is_empty());
+        // The disk fills between scheduled checks
+        source.disks[0].available_space = 150;
+        runner.check_thresholds(&mut source).unwrap();
+        source.disks[0].available_space = 120;
+        runner.check_thresholds(&mut source).unwrap();
+        let messages = transport.messages();
+        assert_eq!(1, messages.len());
+        assert_eq!("devices/Test%20Device%20Name/alerts", messages[0].topic());
+        let alert_message: Value = serde_json::from_slice(messages[0].payload()).unwrap();
+        assert_eq!("disks", alert_message["section"]);
+        assert_eq!("/", alert_message["mountPoint"]);
+        assert_eq!(150, alert_message["availableBytes"]);
+        assert_eq!(200, alert_message["thresholdBytes"]);
+    }
+
+    #[test]
+    fn alert_when_disk_remounts_read_only() {
+        let mut runner_config = load_config(
+            Some(&String::from("resources/test/good/read_only_alert.yaml"))
+        ).unwrap();
+        runner_config.compression = Codec::None;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let mut source = FakeSource::new();
+        runner.execute_check(&mut source).unwrap();
+        source.read_only_mounts.insert(String::from("/"));
+        runner.execute_check(&mut source).unwrap();
+        runner.execute_check(&mut source).unwrap();
+        let messages = transport.messages();
+        let report_message: Value = serde_json::from_slice(messages.last().unwrap().payload()).unwrap();
+        assert_eq!(true, report_message["report"]["disks"][0]["readOnly"]);
+        let alerts: Vec<&Message> = messages.iter().filter(|m| m.topic() == "devices/alerts").collect();
+        assert_eq!(1, alerts.len());
+        let alert_message: Value = serde_json::from_slice(alerts[0].payload()).unwrap();
+        assert_eq!("disks", alert_message["section"]);
+        assert_eq!("sda1", alert_message["disk"]);
+        assert_eq!(true, alert_message["readOnly"]);
+    }
+
+    #[test]
+    fn check_with_flattened_report() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.flatten = true;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let report_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert!(report_message["deviceId"].is_string());
+        assert_eq!(600, report_message["report.disks.0.diskUsed"]);
+        assert_eq!(1536, report_message["report.memory.memoryUsed"]);
+        assert_eq!("Test Vendor", report_message["report.cpus.0.vendorId"]);
+        assert!(report_message.as_object().unwrap().values().all(|v| !v.is_object() && !v.is_array()));
+    }
+
+    #[test]
+    fn check_with_snake_key_case() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.compression = Codec::None;
+        runner_config.key_case = KeyCase::Snake;
+        let transport = MockTransport::new();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        let report_message: Value = serde_json::from_slice(transport.messages()[0].payload()).unwrap();
+        assert!(report_message["device_id"].is_string());
+        assert!(report_message["compression_ratio"].is_number());
+        assert_eq!(600, report_message["report"]["disks"][0]["disk_used"]);
+        assert_eq!("Test Vendor", report_message["report"]["cpus"][0]["vendor_id"]);
+        assert!(report_message.get("deviceId").is_none());
+    }
+
+    #[test]
+    fn compare_compression_lists_each_codec() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::new();
+        let output = SharedBuffer::default();
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.output = Box::new(output.clone());
+        runner.compare_compression(&mut FakeSource::new()).unwrap();
+        let contents = output.contents();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(4, lines.len());
+        assert!(lines[0].starts_with("none: "));
+        assert!(lines[0].ends_with(" bytes (ratio 1.000)"));
+        assert!(lines[1].starts_with("lz4: "));
+        assert!(lines[2].starts_with("lz4_frame: "));
+        assert!(lines[3].starts_with("gzip: "));
+        assert!(transport.events().is_empty());
+    }
+
+    fn slow_acking_runner(ack_delay: Duration, shutdown_grace_secs: Option<u64>) -> (Runner, MockTransport) {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.keep_connected = true;
+        runner_config.qos = 1;
+        runner_config.shutdown_grace_secs = shutdown_grace_secs;
+        let transport = MockTransport::slow_acking(ack_delay);
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        (runner, transport)
+    }
+
+    #[test]
+    fn wait_for_delivery_before_disconnect() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.qos = 1;
+        let transport = MockTransport::slow_acking(Duration::from_millis(50));
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        // The publish was acknowledged before the disconnect, which would otherwise have lost it
+        assert_eq!(1, transport.acknowledged_publishes.load(Ordering::SeqCst));
+        assert_eq!(0, transport.lost_publishes.load(Ordering::SeqCst));
+        assert_eq!(Some(&TransportEvent::Disconnect), transport.events().last());
+    }
+
+    #[test]
+    fn fail_report_when_delivery_times_out() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.qos = 1;
+        runner_config.publish_ack_timeout_secs = 1;
+        let transport = MockTransport::slow_acking(Duration::from_secs(30));
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        assert!(runner.execute_check(&mut FakeSource::new()).is_err());
+        assert_eq!(1, transport.lost_publishes.load(Ordering::SeqCst));
+        // The connection is closed even though delivery failed
+        assert_eq!(Some(&TransportEvent::Disconnect), transport.events().last());
+    }
+
+    #[test]
+    fn skip_delivery_wait_at_qos_0() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MockTransport::slow_acking(Duration::from_secs(30));
+        let mut runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        let started = Instant::now();
+        runner.execute_check(&mut FakeSource::new()).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(0, transport.lost_publishes.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn wait_for_in_flight_publishes_on_shutdown() {
+        let (runner, transport) = slow_acking_runner(Duration::from_millis(50), Some(1));
+        let started = Instant::now();
+        runner.disconnect_on_shutdown().unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(50) && elapsed < Duration::from_secs(1), "Waited {:?}", elapsed);
+        assert_eq!(1, transport.acknowledged_publishes.load(Ordering::SeqCst));
+        assert_eq!(0, transport.lost_publishes.load(Ordering::SeqCst));
+        assert_eq!(Some(&TransportEvent::DisconnectAfter(Duration::from_secs(1))), transport.events().last());
+    }
+
+    #[test]
+    fn bound_shutdown_by_grace_period() {
+        let (runner, transport) = slow_acking_runner(Duration::from_secs(30), Some(1));
+        let started = Instant::now();
+        runner.disconnect_on_shutdown().unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(5), "Waited {:?}", elapsed);
+        assert_eq!(1, transport.lost_publishes.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn lose_in_flight_publishes_without_grace_period() {
+        let (runner, transport) = slow_acking_runner(Duration::from_millis(50), None);
+        runner.disconnect_on_shutdown().unwrap();
+        assert_eq!(1, transport.lost_publishes.load(Ordering::SeqCst));
+        assert_eq!(Some(&TransportEvent::Disconnect), transport.events().last());
+    }
+
+    #[test]
+    fn keep_connection_between_reports() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.keep_connected = true;
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        assert_eq!(
+            vec![
+                TransportEvent::Connect,
+                TransportEvent::Publish(String::from("Device_Status"), 0),
+                TransportEvent::Publish(String::from("Device_Status"), 0),
+            ],
+            transport.events()
+        );
+    }
+
+    #[test]
+    fn reconnect_dropped_connection_before_publish() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.keep_connected = true;
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        transport.drop_connection();
+        runner.transmit_report(&runner.topics, b"payload").unwrap();
+        assert_eq!(
+            vec![
+                TransportEvent::Connect,
+                TransportEvent::Publish(String::from("Device_Status"), 0),
+                TransportEvent::Connect,
+                TransportEvent::Publish(String::from("Device_Status"), 0),
+            ],
+            transport.events()
+        );
+    }
+
+    #[test]
+    fn reconnect_dropped_connection_between_reports() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.keep_connected = true;
+        let transport = MockTransport::new();
+        let runner = Runner::with_transport(&runner_config, Box::new(transport.clone()));
+        runner.ensure_connected().unwrap();
+        runner.ensure_connected().unwrap();
+        assert_eq!(vec![TransportEvent::Connect], transport.events());
+        transport.drop_connection();
+        runner.ensure_connected().unwrap();
+        assert_eq!(vec![TransportEvent::Connect, TransportEvent::Connect], transport.events());
+    }
 }
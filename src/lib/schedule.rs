@@ -0,0 +1,160 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug,PartialEq)]
+pub enum ScheduledTask {
+    Report,
+    Heartbeat,
+}
+
+/// Tracks when the next full report and heartbeat are due, as offsets from the start of the run.
+/// A full report also proves liveness, so a heartbeat falling due at the same time as a report is skipped.
+pub struct Schedule {
+    report_interval: Duration,
+    heartbeat_interval: Option<Duration>,
+    next_report: Duration,
+    next_heartbeat: Duration,
+}
+
+impl Schedule {
+    pub fn new(report_interval: Duration, heartbeat_interval: Option<Duration>) -> Schedule {
+        Schedule {
+            report_interval,
+            heartbeat_interval,
+            next_report: Duration::from_secs(0),
+            next_heartbeat: heartbeat_interval.unwrap_or(report_interval),
+        }
+    }
+
+    /// Changes the report interval, rescheduling the next report not yet returned by `next_task` to follow the last one
+    /// returned by the new interval.
+    pub fn set_report_interval(&mut self, report_interval: Duration) {
+        self.next_report = self.next_report.saturating_sub(self.report_interval) + report_interval;
+        self.report_interval = report_interval;
+    }
+
+    pub fn next_task(&mut self) -> (Duration, ScheduledTask) {
+        if let Some(heartbeat_interval) = self.heartbeat_interval {
+            while self.next_heartbeat <= self.next_report {
+                if self.next_heartbeat < self.next_report {
+                    let due = self.next_heartbeat;
+                    self.next_heartbeat += heartbeat_interval;
+                    return (due, ScheduledTask::Heartbeat);
+                }
+                self.next_heartbeat += heartbeat_interval;
+            }
+        }
+        let due = self.next_report;
+        self.next_report += self.report_interval;
+        (due, ScheduledTask::Report)
+    }
+}
+
+/// Enforces a minimum time between publishes, whatever triggered them, so that a misconfiguration cannot flood the
+/// broker.
+pub struct RateGuard {
+    min_interval: Duration,
+    last_publish: Option<Instant>,
+}
+
+impl RateGuard {
+    pub fn new(min_interval: Duration) -> RateGuard {
+        RateGuard {
+            min_interval,
+            last_publish: None,
+        }
+    }
+
+    /// Returns whether a publish may go ahead at `now`, counting it towards the spacing if so.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        if let Some(last_publish) = self.last_publish {
+            if now.saturating_duration_since(last_publish) < self.min_interval {
+                return false;
+            }
+        }
+        self.last_publish = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::schedule::{RateGuard, Schedule, ScheduledTask};
+
+    #[test]
+    fn rate_guard_enforces_spacing() {
+        let mut rate_guard = RateGuard::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let allowed: Vec<bool> = [0, 1, 5, 10, 12, 19, 20].iter()
+            .map(|offset| rate_guard.try_acquire(start + Duration::from_secs(*offset)))
+            .collect();
+        assert_eq!(vec![true, false, false, true, false, false, true], allowed);
+    }
+
+    #[test]
+    fn schedule_reports_only() {
+        let mut schedule = Schedule::new(Duration::from_secs(60), None);
+        let tasks: Vec<(Duration, ScheduledTask)> = (0..3).map(|_| schedule.next_task()).collect();
+        assert_eq!(
+            vec![
+                (Duration::from_secs(0), ScheduledTask::Report),
+                (Duration::from_secs(60), ScheduledTask::Report),
+                (Duration::from_secs(120), ScheduledTask::Report),
+            ],
+            tasks
+        );
+    }
+
+    #[test]
+    fn schedule_reports_at_changed_interval() {
+        let mut schedule = Schedule::new(Duration::from_secs(60), None);
+        schedule.next_task();
+        schedule.next_task();
+        schedule.set_report_interval(Duration::from_secs(120));
+        let tasks: Vec<(Duration, ScheduledTask)> = (0..2).map(|_| schedule.next_task()).collect();
+        assert_eq!(
+            vec![
+                (Duration::from_secs(180), ScheduledTask::Report),
+                (Duration::from_secs(300), ScheduledTask::Report),
+            ],
+            tasks
+        );
+    }
+
+    #[test]
+    fn schedule_interleaves_heartbeats() {
+        let mut schedule = Schedule::new(Duration::from_secs(60), Some(Duration::from_secs(20)));
+        let tasks: Vec<(Duration, ScheduledTask)> = (0..7).map(|_| schedule.next_task()).collect();
+        assert_eq!(
+            vec![
+                (Duration::from_secs(0), ScheduledTask::Report),
+                (Duration::from_secs(20), ScheduledTask::Heartbeat),
+                (Duration::from_secs(40), ScheduledTask::Heartbeat),
+                (Duration::from_secs(60), ScheduledTask::Report),
+                (Duration::from_secs(80), ScheduledTask::Heartbeat),
+                (Duration::from_secs(100), ScheduledTask::Heartbeat),
+                (Duration::from_secs(120), ScheduledTask::Report),
+            ],
+            tasks
+        );
+    }
+
+    #[test]
+    fn schedule_interleaves_unaligned_heartbeats() {
+        let mut schedule = Schedule::new(Duration::from_secs(60), Some(Duration::from_secs(45)));
+        let tasks: Vec<(Duration, ScheduledTask)> = (0..5).map(|_| schedule.next_task()).collect();
+        assert_eq!(
+            vec![
+                (Duration::from_secs(0), ScheduledTask::Report),
+                (Duration::from_secs(45), ScheduledTask::Heartbeat),
+                (Duration::from_secs(60), ScheduledTask::Report),
+                (Duration::from_secs(90), ScheduledTask::Heartbeat),
+                (Duration::from_secs(120), ScheduledTask::Report),
+            ],
+            tasks
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::Thread;
+
+/// Control events that a service manager can send to the continuous loop.
+#[cfg(any(test, all(windows, feature = "windows-service")))]
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum ServiceControl {
+    Stop,
+    Shutdown,
+    Pause,
+    Continue,
+}
+
+/// Shared state through which Ctrl-C and service control events steer the continuous loop.
+pub struct RunControl {
+    running: AtomicBool,
+    paused: AtomicBool,
+    /// The thread running the loop, woken when a change must be observed before its next scheduled task.
+    run_thread: Mutex<Option<Thread>>,
+}
+
+impl RunControl {
+    pub fn new() -> RunControl {
+        RunControl {
+            running: AtomicBool::new(true),
+            paused: AtomicBool::new(false),
+            run_thread: Mutex::new(None),
+        }
+    }
+
+    /// Registers the thread running the loop, so that stopping and continuing wake it.
+    pub fn attach(&self, run_thread: Thread) {
+        *self.run_thread.lock().unwrap() = Some(run_thread);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.wake();
+    }
+
+    /// Applies a control event. Pausing takes effect at the loop's next scheduled task, so only stopping and
+    /// continuing wake it.
+    #[cfg(any(test, all(windows, feature = "windows-service")))]
+    pub fn apply(&self, control: ServiceControl) {
+        match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => self.stop(),
+            ServiceControl::Pause => self.paused.store(true, Ordering::SeqCst),
+            ServiceControl::Continue => {
+                self.paused.store(false, Ordering::SeqCst);
+                self.wake();
+            }
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(run_thread) = self.run_thread.lock().unwrap().as_ref() {
+            run_thread.unpark();
+        }
+    }
+}
+
+/// The Windows service entry point, which runs the continuous loop under the service control manager.
+#[cfg(all(windows, feature = "windows-service"))]
+pub mod windows {
+    use std::error::Error;
+    use std::ffi::OsString;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use windows_service::{define_windows_service, service_control_handler, service_dispatcher};
+    use windows_service::service::{ServiceControl as WindowsControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType};
+    use windows_service::service_control_handler::{ServiceControlHandlerResult, ServiceStatusHandle};
+
+    use crate::lib::common::RuntimeError;
+    use crate::lib::runner::run_controlled;
+    use crate::lib::service::{RunControl, ServiceControl};
+
+    /// The name the service is installed under.
+    const SERVICE_NAME: &str = "DeviceStatsReporter";
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Hands the process over to the service control manager, returning once the service has stopped.
+    pub fn start() -> Result<(), Box<dyn Error>> {
+        match service_dispatcher::start(SERVICE_NAME, ffi_service_main) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    /// Maps a service control event onto the event the loop handles, if it is one the reporter acts on.
+    fn from_windows_control(control: WindowsControl) -> Option<ServiceControl> {
+        match control {
+            WindowsControl::Stop => Some(ServiceControl::Stop),
+            WindowsControl::Shutdown => Some(ServiceControl::Shutdown),
+            WindowsControl::Pause => Some(ServiceControl::Pause),
+            WindowsControl::Continue => Some(ServiceControl::Continue),
+            _ => None,
+        }
+    }
+
+    /// The state reported to the service control manager once the event has been applied.
+    fn reported_state(control: ServiceControl) -> ServiceState {
+        match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => ServiceState::StopPending,
+            ServiceControl::Pause => ServiceState::Paused,
+            ServiceControl::Continue => ServiceState::Running,
+        }
+    }
+
+    fn service_status(current_state: ServiceState, exit_code: u32) -> ServiceStatus {
+        let controls_accepted = match current_state {
+            ServiceState::Running | ServiceState::Paused => {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN | ServiceControlAccept::PAUSE_CONTINUE
+            }
+            _ => ServiceControlAccept::empty(),
+        };
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        }
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        // A service has no console, so an error can only be reported through the exit code
+        let _ = run_service();
+    }
+
+    fn run_service() -> Result<(), windows_service::Error> {
+        let run_control = Arc::new(RunControl::new());
+        let handler_control = run_control.clone();
+        // The handler is registered before the status handle exists, so it is shared once registration returns
+        let status_handle: Arc<Mutex<Option<ServiceStatusHandle>>> = Arc::new(Mutex::new(None));
+        let handler_status_handle = status_handle.clone();
+        let registered_handle = service_control_handler::register(SERVICE_NAME, move |control| {
+            if control == WindowsControl::Interrogate {
+                return ServiceControlHandlerResult::NoError;
+            }
+            match from_windows_control(control) {
+                Some(control) => {
+                    handler_control.apply(control);
+                    if let Some(status_handle) = handler_status_handle.lock().unwrap().as_ref() {
+                        let _ = status_handle.set_service_status(service_status(reported_state(control), 0));
+                    }
+                    ServiceControlHandlerResult::NoError
+                }
+                None => ServiceControlHandlerResult::NotImplemented,
+            }
+        })?;
+        *status_handle.lock().unwrap() = Some(registered_handle);
+        registered_handle.set_service_status(service_status(ServiceState::Running, 0))?;
+        let exit_code = match run_controlled(run_control) {
+            Ok(()) => 0,
+            Err(_) => 1,
+        };
+        registered_handle.set_service_status(service_status(ServiceState::Stopped, exit_code))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use pretty_assertions::assert_eq;
+        use windows_service::service::{ServiceControl as WindowsControl, ServiceState};
+
+        use crate::lib::service::ServiceControl;
+        use crate::lib::service::windows::{from_windows_control, reported_state};
+
+        #[test]
+        fn map_windows_controls() {
+            assert_eq!(Some(ServiceControl::Stop), from_windows_control(WindowsControl::Stop));
+            assert_eq!(Some(ServiceControl::Shutdown), from_windows_control(WindowsControl::Shutdown));
+            assert_eq!(Some(ServiceControl::Pause), from_windows_control(WindowsControl::Pause));
+            assert_eq!(Some(ServiceControl::Continue), from_windows_control(WindowsControl::Continue));
+            assert_eq!(None, from_windows_control(WindowsControl::ParamChange));
+        }
+
+        #[test]
+        fn report_state_after_control() {
+            assert_eq!(ServiceState::StopPending, reported_state(ServiceControl::Stop));
+            assert_eq!(ServiceState::Paused, reported_state(ServiceControl::Pause));
+            assert_eq!(ServiceState::Running, reported_state(ServiceControl::Continue));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::lib::service::{RunControl, ServiceControl};
+
+    #[test]
+    fn stop_ends_run() {
+        let run_control = RunControl::new();
+        assert!(run_control.is_running());
+        run_control.stop();
+        assert!(!run_control.is_running());
+    }
+
+    #[test]
+    fn stop_and_shutdown_controls_end_run() {
+        for control in [ServiceControl::Stop, ServiceControl::Shutdown].iter() {
+            let run_control = RunControl::new();
+            run_control.apply(*control);
+            assert!(!run_control.is_running());
+        }
+    }
+
+    #[test]
+    fn pause_and_continue() {
+        let run_control = RunControl::new();
+        run_control.apply(ServiceControl::Pause);
+        assert!(run_control.is_running());
+        assert!(run_control.is_paused());
+        run_control.apply(ServiceControl::Continue);
+        assert!(run_control.is_running());
+        assert!(!run_control.is_paused());
+    }
+
+    #[test]
+    fn stop_wakes_attached_thread() {
+        let run_control = Arc::new(RunControl::new());
+        let loop_control = run_control.clone();
+        let run_thread = thread::spawn(move || {
+            while loop_control.is_running() {
+                thread::park();
+            }
+        });
+        run_control.attach(run_thread.thread().clone());
+        run_control.apply(ServiceControl::Stop);
+        run_thread.join().unwrap();
+    }
+}
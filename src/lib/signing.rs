@@ -0,0 +1,146 @@
+// SHA-256 round constants: the first 32 bits of the fractional parts of the cube roots of the first 64 primes
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+// SHA-256 initial hash values: the first 32 bits of the fractional parts of the square roots of the first 8 primes
+const INITIAL_HASH: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+const BLOCK_BYTES: usize = 64;
+const INNER_PAD: u8 = 0x36;
+const OUTER_PAD: u8 = 0x5c;
+
+/// The secret that reports are signed with, along with the ID consumers use to pick the key to verify them with.
+pub struct SigningKey {
+    pub key_id: Option<String>,
+    secret: Vec<u8>,
+}
+
+impl SigningKey {
+    pub fn new(secret: &str, key_id: Option<String>) -> SigningKey {
+        SigningKey {
+            key_id,
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    /// Appends a `signature` field holding the hex-encoded HMAC-SHA256 of the message JSON as it was before the
+    /// field was appended. Consumers verify a message by removing the trailing field and hashing the remaining text.
+    pub fn sign_message(&self, message_json: &str) -> String {
+        let signature = to_hex(&hmac_sha256(&self.secret, message_json.as_bytes()));
+        let body = message_json.strip_suffix('}').unwrap_or(message_json);
+        format!("{},\"signature\":\"{}\"}}", body, signature)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Computes the HMAC of the message using SHA-256, as defined by RFC 2104.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    // Keys longer than a block are hashed down first; shorter keys are zero-padded
+    let mut block_key = [0u8; BLOCK_BYTES];
+    if key.len() > BLOCK_BYTES {
+        block_key[..32].copy_from_slice(&sha256(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+    let mut inner_input: Vec<u8> = block_key.iter().map(|b| b ^ INNER_PAD).collect();
+    inner_input.extend_from_slice(message);
+    let mut outer_input: Vec<u8> = block_key.iter().map(|b| b ^ OUTER_PAD).collect();
+    outer_input.extend_from_slice(&sha256(&inner_input));
+    sha256(&outer_input)
+}
+
+/// Computes the SHA-256 digest of the data, as defined by FIPS 180-4.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % BLOCK_BYTES != BLOCK_BYTES - 8 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    let mut hash = INITIAL_HASH;
+    for block in padded.chunks(BLOCK_BYTES) {
+        let mut schedule = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            schedule[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = schedule[i - 15].rotate_right(7) ^ schedule[i - 15].rotate_right(18) ^ (schedule[i - 15] >> 3);
+            let s1 = schedule[i - 2].rotate_right(17) ^ schedule[i - 2].rotate_right(19) ^ (schedule[i - 2] >> 10);
+            schedule[i] = schedule[i - 16].wrapping_add(s0).wrapping_add(schedule[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = hash;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(ROUND_CONSTANTS[i]).wrapping_add(schedule[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (value, added) in hash.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *value = value.wrapping_add(*added);
+        }
+    }
+    let mut digest = [0u8; 32];
+    for (i, value) in hash.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::signing::{hmac_sha256, sha256, SigningKey, to_hex};
+
+    #[test]
+    fn sha256_test_vectors() {
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", to_hex(&sha256(b"")));
+        assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", to_hex(&sha256(b"abc")));
+        assert_eq!(
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            to_hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"))
+        );
+    }
+
+    #[test]
+    fn hmac_sha256_test_vectors() {
+        // RFC 4231 test cases 2 and 6
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?"))
+        );
+        assert_eq!(
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            to_hex(&hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First"))
+        );
+    }
+
+    #[test]
+    fn sign_message() {
+        let signing_key = SigningKey::new("Jefe", Some(String::from("key-1")));
+        let signed_message = signing_key.sign_message("{\"deviceId\":\"device-1\"}");
+        let signature = to_hex(&hmac_sha256(b"Jefe", b"{\"deviceId\":\"device-1\"}"));
+        assert_eq!(format!("{{\"deviceId\":\"device-1\",\"signature\":\"{}\"}}", signature), signed_message);
+    }
+}
@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::io::ErrorKind;
+use std::process::{Command, Stdio};
+
+use crate::lib::common::RuntimeError;
+
+const SMARTCTL_COMMAND: &str = "smartctl";
+// ATA attributes reporting the drive temperature; the first is preferred where a drive reports both
+const TEMPERATURE_ATTRIBUTES: [&str; 2] = ["Temperature_Celsius", "Airflow_Temperature_Cel"];
+// The raw value is the last column of the ATA attribute table and may be followed by e.g. "(Min/Max 20/45)"
+const RAW_VALUE_COLUMN: usize = 9;
+const NVME_TEMPERATURE_PREFIX: &str = "Temperature:";
+const SCSI_TEMPERATURE_PREFIX: &str = "Current Drive Temperature:";
+
+/// Reads the drive temperature, in Celsius, from the device's SMART attributes through `smartctl`. Returns `None`
+/// where `smartctl` is not installed, the device cannot be opened (typically for lack of permission), the drive is
+/// in standby or it does not report a temperature. Drives in standby are not spun up.
+pub fn read_disk_temperature(device: &str) -> Result<Option<f32>, Box<dyn Error>> {
+    let output = Command::new(SMARTCTL_COMMAND)
+        .args(["-A", "-n", "standby", device])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(output) => Ok(parse_smartctl_temperature(String::from_utf8_lossy(&output.stdout).as_ref())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            let error = Box::new(RuntimeError::new(
+                format!("Unable to run {} for {}: {}", SMARTCTL_COMMAND, device, e).as_str()
+            ));
+            Err(error)
+        }
+    }
+}
+
+/// Extracts the drive temperature, in Celsius, from the output of `smartctl -A`, which lists it as an ATA attribute,
+/// an NVMe health log entry or a SCSI log entry depending on the drive.
+fn parse_smartctl_temperature(output: &str) -> Option<f32> {
+    for attribute in TEMPERATURE_ATTRIBUTES.iter() {
+        let raw_value = output.lines()
+            .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+            .find(|columns| columns.len() > RAW_VALUE_COLUMN && columns[1] == *attribute)
+            .and_then(|columns| columns[RAW_VALUE_COLUMN].parse().ok());
+        if raw_value.is_some() {
+            return raw_value;
+        }
+    }
+    output.lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            line.strip_prefix(NVME_TEMPERATURE_PREFIX).or_else(|| line.strip_prefix(SCSI_TEMPERATURE_PREFIX))
+        })
+        .find_map(|value| value.split_whitespace().next()?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::smart::parse_smartctl_temperature;
+
+    #[test]
+    fn parse_ata_temperature() {
+        let output = "\
+smartctl 7.2 2020-12-30 r5155 [x86_64-linux-5.10.0] (local build)
+Copyright (C) 2002-20, Bruce Allen, Christian Franke, www.smartmontools.org
+
+=== START OF READ SMART DATA SECTION ===
+SMART Attributes Data Structure revision number: 16
+Vendor Specific SMART Attributes with Thresholds:
+ID# ATTRIBUTE_NAME          FLAG     VALUE WORST THRESH TYPE      UPDATED  WHEN_FAILED RAW_VALUE
+  5 Reallocated_Sector_Ct   0x0033   100   100   010    Pre-fail  Always       -       0
+  9 Power_On_Hours          0x0032   095   095   000    Old_age   Always       -       21893
+190 Airflow_Temperature_Cel 0x0032   064   052   000    Old_age   Always       -       36
+194 Temperature_Celsius     0x0022   064   052   000    Old_age   Always       -       35 (Min/Max 20/48)
+199 UDMA_CRC_Error_Count    0x003e   200   200   000    Old_age   Always       -       0
+";
+        assert_eq!(Some(35.0), parse_smartctl_temperature(output));
+    }
+
+    #[test]
+    fn parse_airflow_temperature() {
+        let output = "\
+ID# ATTRIBUTE_NAME          FLAG     VALUE WORST THRESH TYPE      UPDATED  WHEN_FAILED RAW_VALUE
+190 Airflow_Temperature_Cel 0x0032   067   045   045    Old_age   Always   In_the_past 33 (0 183 33 21 0)
+";
+        assert_eq!(Some(33.0), parse_smartctl_temperature(output));
+    }
+
+    #[test]
+    fn parse_nvme_temperature() {
+        let output = "\
+=== START OF SMART DATA SECTION ===
+SMART/Health Information (NVMe Log 0x02)
+Critical Warning:                   0x00
+Temperature:                        41 Celsius
+Available Spare:                    100%
+Warning  Comp. Temperature Time:    0
+Temperature Sensor 1:               41 Celsius
+";
+        assert_eq!(Some(41.0), parse_smartctl_temperature(output));
+    }
+
+    #[test]
+    fn parse_scsi_temperature() {
+        let output = "\
+=== START OF READ SMART DATA SECTION ===
+Current Drive Temperature:     29 C
+Drive Trip Temperature:        65 C
+";
+        assert_eq!(Some(29.0), parse_smartctl_temperature(output));
+    }
+
+    #[test]
+    fn parse_output_without_temperature() {
+        let output = "\
+smartctl 7.2 2020-12-30 r5155 [x86_64-linux-5.10.0] (local build)
+Smartctl open device: /dev/sda failed: Permission denied
+";
+        assert_eq!(None, parse_smartctl_temperature(output));
+        assert_eq!(None, parse_smartctl_temperature("Device is in STANDBY mode, exit(2)\n"));
+    }
+}
@@ -0,0 +1,332 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lib::common::RuntimeError;
+
+const SPOOL_FILE_EXTENSION: &str = "spool";
+
+/// What happens to a payload that would take the spool past its maximum size.
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum SpoolFullStrategy {
+    /// The oldest payloads are deleted to make room for it.
+    DropOldest,
+    /// It is discarded, keeping the payloads already spooled.
+    DropNewest,
+    /// It is discarded and the spool reports itself full, so that no further reports are collected until spooled
+    /// payloads are flushed.
+    Block,
+}
+
+/// Holds payloads that could not be delivered in a directory, one file per payload, so that they survive a restart
+/// and can be flushed once delivery succeeds again. Each file holds a single-line label ahead of its payload, carrying
+/// what is needed to send the payload again, such as the ID of the report it holds.
+pub struct Spool {
+    dir: PathBuf,
+    /// Payloads older than this are discarded rather than flushed.
+    max_age_secs: Option<u64>,
+    /// The most the spooled payloads may take up on disk.
+    max_bytes: Option<u64>,
+    full_strategy: SpoolFullStrategy,
+    /// Whether a payload has been turned away since the spool was last flushed; only tracked when blocking.
+    full: bool,
+    sequence: u64,
+}
+
+impl Spool {
+    pub fn new(dir: &str, max_age_secs: Option<u64>, max_bytes: Option<u64>, full_strategy: SpoolFullStrategy) -> Spool {
+        Spool {
+            dir: PathBuf::from(dir),
+            max_age_secs,
+            max_bytes,
+            full_strategy,
+            full: false,
+            sequence: 0,
+        }
+    }
+
+    /// Whether collection is to be held back until spooled payloads are flushed, which only happens when blocking.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Writes the payload of a report generated at the timestamp to the spool along with its label, which must not
+    /// contain a line break. Where the entry would take the spool past its maximum size, room is made for it or it is
+    /// discarded according to the spool's full strategy.
+    pub fn store(&mut self, label: &str, payload: &[u8], timestamp: u64) -> Result<(), Box<dyn Error>> {
+        if let Some(max_bytes) = self.max_bytes {
+            if !self.make_room((label.len() + 1 + payload.len()) as u64, max_bytes)? {
+                if self.full_strategy == SpoolFullStrategy::Block {
+                    self.full = true;
+                }
+                return Ok(());
+            }
+        }
+        self.sequence += 1;
+        // Zero-padding keeps the file names in the order the payloads were spooled
+        let path = self.dir.join(format!("{:020}-{:010}.{}", timestamp, self.sequence, SPOOL_FILE_EXTENSION));
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            let error = Box::new(RuntimeError::new(format!("Unable to spool payload to '{}': {}", path.display(), e).as_str()));
+            return Err(error);
+        }
+        write_entry(&path, label, payload)
+    }
+
+    /// Whether a payload of the given size fits within the maximum size, deleting the oldest payloads to make room
+    /// for it when dropping the oldest. A payload larger than the maximum size never fits.
+    fn make_room(&self, payload_bytes: u64, max_bytes: u64) -> Result<bool, Box<dyn Error>> {
+        if payload_bytes > max_bytes {
+            return Ok(false);
+        }
+        let mut files = Vec::new();
+        let mut spooled_bytes = 0;
+        for (_, path) in self.spooled_files()? {
+            let file_bytes = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+            spooled_bytes += file_bytes;
+            files.push((path, file_bytes));
+        }
+        if self.full_strategy != SpoolFullStrategy::DropOldest {
+            return Ok(spooled_bytes + payload_bytes <= max_bytes);
+        }
+        for (path, file_bytes) in files.iter() {
+            if spooled_bytes + payload_bytes <= max_bytes {
+                break;
+            }
+            remove_spooled_file(path)?;
+            spooled_bytes -= file_bytes;
+        }
+        Ok(true)
+    }
+
+    /// Sends the spooled payloads oldest first along with their labels, deleting each once sent. Payloads older than
+    /// the maximum age at the timestamp are deleted without being sent. Flushing stops at the first payload that fails
+    /// to send, leaving it and the rest spooled; the sender may change its label to record what is left to be done,
+    /// such as the destinations it was not delivered to. Returns the number of payloads sent.
+    pub fn flush<F>(&mut self, timestamp: u64, mut send: F) -> Result<usize, Box<dyn Error>>
+        where F: FnMut(&mut String, &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut sent = 0;
+        for (spooled_at, path) in self.spooled_files()? {
+            if let Some(max_age_secs) = self.max_age_secs {
+                if timestamp.saturating_sub(spooled_at) > max_age_secs {
+                    remove_spooled_file(&path)?;
+                    continue;
+                }
+            }
+            let entry = match fs::read(&path) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let error = Box::new(RuntimeError::new(format!("Unable to read spooled payload '{}': {}", path.display(), e).as_str()));
+                    return Err(error);
+                }
+            };
+            // A file without a label line was spooled before labels were stored, and holds only the payload
+            let (label, payload) = match entry.iter().position(|b| *b == b'\n') {
+                Some(label_end) => (String::from_utf8_lossy(&entry[..label_end]).into_owned(), &entry[label_end + 1..]),
+                None => (String::new(), &entry[..]),
+            };
+            let mut sent_label = label.clone();
+            if let Err(e) = send(&mut sent_label, payload) {
+                if sent_label != label {
+                    write_entry(&path, sent_label.as_str(), payload)?;
+                }
+                return Err(e);
+            }
+            remove_spooled_file(&path)?;
+            sent += 1;
+        }
+        self.full = false;
+        Ok(sent)
+    }
+
+    /// The spooled files with the timestamps they were spooled at, oldest first.
+    fn spooled_files(&self) -> Result<Vec<(u64, PathBuf)>, Box<dyn Error>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            // Nothing has been spooled yet
+            Err(_) => return Ok(Vec::new())
+        };
+        let mut files: Vec<(u64, PathBuf)> = entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == SPOOL_FILE_EXTENSION))
+            .filter_map(|path| {
+                let spooled_at = path.file_stem()?.to_str()?.split('-').next()?.parse().ok()?;
+                Some((spooled_at, path))
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Writes the label line followed by the payload to the spooled file, replacing any entry already there.
+fn write_entry(path: &Path, label: &str, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut entry = Vec::with_capacity(label.len() + 1 + payload.len());
+    entry.extend_from_slice(label.as_bytes());
+    entry.push(b'\n');
+    entry.extend_from_slice(payload);
+    if let Err(e) = fs::write(path, &entry) {
+        let error = Box::new(RuntimeError::new(format!("Unable to spool payload to '{}': {}", path.display(), e).as_str()));
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn remove_spooled_file(path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Err(e) = fs::remove_file(path) {
+        let error = Box::new(RuntimeError::new(format!("Unable to remove spooled payload '{}': {}", path.display(), e).as_str()));
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::spool::{Spool, SpoolFullStrategy};
+
+    const NOW: u64 = 1_623_715_200;
+
+    fn spool_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("device_stats_reporter_spool_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        String::from(dir.to_str().unwrap())
+    }
+
+    #[test]
+    fn flush_oldest_first() {
+        let dir = spool_dir("order");
+        let mut spool = Spool::new(dir.as_str(), None, None, SpoolFullStrategy::DropOldest);
+        spool.store("label", b"second", NOW - 10).unwrap();
+        spool.store("label", b"first", NOW - 20).unwrap();
+        let mut flushed = Vec::new();
+        assert_eq!(2, spool.flush(NOW, |_, p| { flushed.push(p.to_vec()); Ok(()) }).unwrap());
+        assert_eq!(vec![b"first".to_vec(), b"second".to_vec()], flushed);
+        assert_eq!(0, spool.flush(NOW, |_, _| Ok(())).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn discard_expired_payloads() {
+        let dir = spool_dir("expiry");
+        let mut spool = Spool::new(dir.as_str(), Some(300), None, SpoolFullStrategy::DropOldest);
+        spool.store("label", b"old", NOW - 3600).unwrap();
+        spool.store("label", b"fresh", NOW - 60).unwrap();
+        let mut flushed = Vec::new();
+        assert_eq!(1, spool.flush(NOW, |_, p| { flushed.push(p.to_vec()); Ok(()) }).unwrap());
+        assert_eq!(vec![b"fresh".to_vec()], flushed);
+        // The expired payload was deleted rather than left behind
+        assert_eq!(0, fs::read_dir(&dir).unwrap().count());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn flush_payloads_with_their_labels() {
+        let dir = spool_dir("labels");
+        let mut spool = Spool::new(dir.as_str(), None, None, SpoolFullStrategy::DropOldest);
+        spool.store("report-1", b"first\nline", NOW).unwrap();
+        // A payload spooled before labels were stored is flushed whole, with an empty label
+        fs::write(std::path::Path::new(&dir).join(format!("{:020}-{:010}.spool", NOW + 1, 0)), b"second").unwrap();
+        let mut flushed = Vec::new();
+        spool.flush(NOW + 10, |l, p| { flushed.push((l.clone(), p.to_vec())); Ok(()) }).unwrap();
+        assert_eq!(
+            vec![(String::from("report-1"), b"first\nline".to_vec()), (String::new(), b"second".to_vec())],
+            flushed
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keep_label_left_by_failed_send() {
+        let dir = spool_dir("relabel");
+        let mut spool = Spool::new(dir.as_str(), None, None, SpoolFullStrategy::DropOldest);
+        spool.store("a,b", b"payload", NOW).unwrap();
+        let result = spool.flush(NOW, |l, _| {
+            *l = String::from("b");
+            Err("unreachable".into())
+        });
+        assert!(result.is_err());
+        let mut flushed = Vec::new();
+        spool.flush(NOW, |l, p| { flushed.push((l.clone(), p.to_vec())); Ok(()) }).unwrap();
+        assert_eq!(vec![(String::from("b"), b"payload".to_vec())], flushed);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keep_payloads_that_fail_to_send() {
+        let dir = spool_dir("failure");
+        let mut spool = Spool::new(dir.as_str(), None, None, SpoolFullStrategy::DropOldest);
+        spool.store("label", b"payload", NOW).unwrap();
+        assert!(spool.flush(NOW, |_, _| Err("unreachable".into())).is_err());
+        let mut flushed = Vec::new();
+        assert_eq!(1, spool.flush(NOW, |_, p| { flushed.push(p.to_vec()); Ok(()) }).unwrap());
+        assert_eq!(vec![b"payload".to_vec()], flushed);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Stores five eight-byte payloads without labels in a spool with room for three, returning the payloads flushed afterwards.
+    fn fill_spool(spool: &mut Spool) -> Vec<Vec<u8>> {
+        for (index, payload) in [b"report-1", b"report-2", b"report-3", b"report-4", b"report-5"].iter().enumerate() {
+            spool.store("", *payload, NOW + index as u64).unwrap();
+        }
+        let mut flushed = Vec::new();
+        spool.flush(NOW + 10, |_, p| { flushed.push(p.to_vec()); Ok(()) }).unwrap();
+        flushed
+    }
+
+    #[test]
+    fn drop_oldest_payloads_when_full() {
+        let dir = spool_dir("drop_oldest");
+        let mut spool = Spool::new(dir.as_str(), None, Some(27), SpoolFullStrategy::DropOldest);
+        assert_eq!(
+            vec![b"report-3".to_vec(), b"report-4".to_vec(), b"report-5".to_vec()],
+            fill_spool(&mut spool)
+        );
+        assert!(!spool.is_full());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn drop_newest_payloads_when_full() {
+        let dir = spool_dir("drop_newest");
+        let mut spool = Spool::new(dir.as_str(), None, Some(27), SpoolFullStrategy::DropNewest);
+        assert_eq!(
+            vec![b"report-1".to_vec(), b"report-2".to_vec(), b"report-3".to_vec()],
+            fill_spool(&mut spool)
+        );
+        assert!(!spool.is_full());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn block_when_full_until_flushed() {
+        let dir = spool_dir("block");
+        let mut spool = Spool::new(dir.as_str(), None, Some(27), SpoolFullStrategy::Block);
+        for (index, payload) in [b"report-1", b"report-2", b"report-3"].iter().enumerate() {
+            spool.store("", *payload, NOW + index as u64).unwrap();
+            assert!(!spool.is_full());
+        }
+        spool.store("", b"report-4", NOW + 3).unwrap();
+        assert!(spool.is_full());
+        let mut flushed = Vec::new();
+        assert_eq!(3, spool.flush(NOW + 10, |_, p| { flushed.push(p.to_vec()); Ok(()) }).unwrap());
+        assert_eq!(vec![b"report-1".to_vec(), b"report-2".to_vec(), b"report-3".to_vec()], flushed);
+        assert!(!spool.is_full());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn discard_payload_larger_than_spool() {
+        let dir = spool_dir("oversized");
+        let mut spool = Spool::new(dir.as_str(), None, Some(27), SpoolFullStrategy::DropOldest);
+        spool.store("", b"report-1", NOW).unwrap();
+        spool.store("", &[0; 32], NOW + 1).unwrap();
+        let mut flushed = Vec::new();
+        spool.flush(NOW + 10, |_, p| { flushed.push(p.to_vec()); Ok(()) }).unwrap();
+        // The spooled payload is not evicted for one that could never fit
+        assert_eq!(vec![b"report-1".to_vec()], flushed);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
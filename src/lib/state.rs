@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::fs;
+
+use crate::lib::common::RuntimeError;
+
+/// The sequence numbers and lifetime counters persisted across runs, so that consumers can detect gaps in the
+/// sequence even when the reporter restarts.
+#[derive(Debug,Default,PartialEq)]
+pub struct DeviceState {
+    state_path: String,
+    pub report_sequence: u64,
+    pub heartbeat_sequence: u64,
+    pub checks: u64,
+    pub failures: u64,
+    pub bytes_transmitted: u64,
+}
+
+impl DeviceState {
+    /// Loads the state from the state file. A missing file starts everything from zero, as does a corrupt one, with
+    /// a warning, so that a damaged file cannot stop the reporter.
+    pub fn load(state_path: &str) -> DeviceState {
+        let mut device_state = DeviceState {
+            state_path: String::from(state_path),
+            ..DeviceState::default()
+        };
+        if let Ok(state) = fs::read_to_string(state_path) {
+            match parse_state(state.as_str()) {
+                Some([report_sequence, heartbeat_sequence, checks, failures, bytes_transmitted]) => {
+                    device_state.report_sequence = report_sequence;
+                    device_state.heartbeat_sequence = heartbeat_sequence;
+                    device_state.checks = checks;
+                    device_state.failures = failures;
+                    device_state.bytes_transmitted = bytes_transmitted;
+                }
+                None => eprintln!("WARNING: Device state file '{}' is corrupt; reinitializing the state", state_path)
+            }
+        }
+        device_state
+    }
+
+    pub fn record_check(&mut self, succeeded: bool) {
+        self.checks += 1;
+        if !succeeded {
+            self.failures += 1;
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let state = format!(
+            "{} {} {} {} {}\n",
+            self.report_sequence,
+            self.heartbeat_sequence,
+            self.checks,
+            self.failures,
+            self.bytes_transmitted
+        );
+        if let Err(e) = fs::write(&self.state_path, state) {
+            let error = Box::new(RuntimeError::new(
+                format!("Unable to write device state file '{}': {}", self.state_path, e).as_str()
+            ));
+            return Err(error);
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `<report sequence> <heartbeat sequence> <checks> <failures> <bytes transmitted>` state file contents.
+fn parse_state(state: &str) -> Option<[u64; 5]> {
+    let mut fields = state.split_whitespace();
+    let mut values = [0; 5];
+    for value in values.iter_mut() {
+        *value = fields.next()?.parse().ok()?;
+    }
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::state::DeviceState;
+
+    fn state_path(name: &str) -> String {
+        let state_path = std::env::temp_dir().join(format!("device_stats_reporter_state_{}_{}", name, std::process::id()));
+        let state_path = String::from(state_path.to_str().unwrap());
+        let _ = fs::remove_file(&state_path);
+        state_path
+    }
+
+    #[test]
+    fn resume_across_runs() {
+        let state_path = state_path("runs");
+        let mut first_run = DeviceState::load(state_path.as_str());
+        first_run.report_sequence = 2;
+        first_run.record_check(true);
+        first_run.record_check(false);
+        first_run.bytes_transmitted = 900;
+        first_run.save().unwrap();
+        let second_run = DeviceState::load(state_path.as_str());
+        let _ = fs::remove_file(&state_path);
+        assert_eq!(first_run, second_run);
+        assert_eq!(2, second_run.checks);
+        assert_eq!(1, second_run.failures);
+    }
+
+    #[test]
+    fn reinitialize_corrupt_state() {
+        let state_path = state_path("corrupt");
+        fs::write(&state_path, "12 3 not-a-number 0 900\n").unwrap();
+        let device_state = DeviceState::load(state_path.as_str());
+        let _ = fs::remove_file(&state_path);
+        assert_eq!(0, device_state.report_sequence);
+        assert_eq!(0, device_state.checks);
+    }
+}
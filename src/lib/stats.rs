@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use crate::lib::common::RuntimeError;
+
+/// Cumulative compression statistics, persisted across runs to help evaluate a codec over weeks of reports.
+#[derive(Debug,Default,PartialEq)]
+pub struct CompressionStats {
+    state_path: String,
+    reports: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl CompressionStats {
+    /// Loads the statistics from the state file. A missing file starts the totals from zero, as does a corrupt one,
+    /// with a warning, so that a damaged file cannot stop the reporter.
+    pub fn load(state_path: &str) -> CompressionStats {
+        let mut stats = CompressionStats {
+            state_path: String::from(state_path),
+            ..CompressionStats::default()
+        };
+        if let Ok(state) = fs::read_to_string(state_path) {
+            match parse_state(state.as_str()) {
+                Some((reports, bytes_in, bytes_out)) => {
+                    stats.reports = reports;
+                    stats.bytes_in = bytes_in;
+                    stats.bytes_out = bytes_out;
+                }
+                None => eprintln!(
+                    "WARNING: Compression statistics file '{}' is corrupt; resetting the statistics",
+                    state_path
+                )
+            }
+        }
+        stats
+    }
+
+    /// Adds a report's uncompressed and compressed sizes to the totals and persists them.
+    pub fn record(&mut self, bytes_in: usize, bytes_out: usize) -> Result<(), Box<dyn Error>> {
+        self.reports += 1;
+        self.bytes_in += bytes_in as u64;
+        self.bytes_out += bytes_out as u64;
+        let state = format!("{} {} {}\n", self.reports, self.bytes_in, self.bytes_out);
+        if let Err(e) = fs::write(&self.state_path, state) {
+            let error = Box::new(RuntimeError::new(
+                format!("Unable to write compression statistics file '{}': {}", self.state_path, e).as_str()
+            ));
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// The compressed size as a fraction of the uncompressed size over every recorded report.
+    pub fn average_ratio(&self) -> f64 {
+        if self.bytes_in == 0 {
+            return 1.0;
+        }
+        self.bytes_out as f64 / self.bytes_in as f64
+    }
+}
+
+impl fmt::Display for CompressionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Compression statistics: {} reports, {} bytes in, {} bytes out, average ratio {:.3}",
+            self.reports,
+            self.bytes_in,
+            self.bytes_out,
+            self.average_ratio()
+        )
+    }
+}
+
+/// Parses the `<reports> <bytes in> <bytes out>` state file contents.
+fn parse_state(state: &str) -> Option<(u64, u64, u64)> {
+    let mut fields = state.split_whitespace();
+    let reports = fields.next()?.parse().ok()?;
+    let bytes_in = fields.next()?.parse().ok()?;
+    let bytes_out = fields.next()?.parse().ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((reports, bytes_in, bytes_out))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::stats::CompressionStats;
+
+    fn state_path(name: &str) -> String {
+        let state_path = std::env::temp_dir().join(format!("device_stats_reporter_stats_{}_{}", name, std::process::id()));
+        let state_path = String::from(state_path.to_str().unwrap());
+        let _ = fs::remove_file(&state_path);
+        state_path
+    }
+
+    #[test]
+    fn accumulate_across_runs() {
+        let state_path = state_path("runs");
+        let mut first_run = CompressionStats::load(state_path.as_str());
+        first_run.record(1000, 400).unwrap();
+        first_run.record(1000, 500).unwrap();
+        let mut second_run = CompressionStats::load(state_path.as_str());
+        second_run.record(2000, 600).unwrap();
+        let persisted = fs::read_to_string(&state_path).unwrap();
+        let _ = fs::remove_file(&state_path);
+        assert_eq!("3 4000 1500\n", persisted);
+        assert_eq!(0.375, second_run.average_ratio());
+        assert_eq!(
+            "Compression statistics: 3 reports, 4000 bytes in, 1500 bytes out, average ratio 0.375",
+            second_run.to_string()
+        );
+    }
+
+    #[test]
+    fn reset_corrupt_state() {
+        let state_path = state_path("corrupt");
+        fs::write(&state_path, "3 not-a-number 1500\n").unwrap();
+        let mut stats = CompressionStats::load(state_path.as_str());
+        assert_eq!(1.0, stats.average_ratio());
+        stats.record(1000, 250).unwrap();
+        let persisted = fs::read_to_string(&state_path).unwrap();
+        let _ = fs::remove_file(&state_path);
+        assert_eq!("1 1000 250\n", persisted);
+    }
+}
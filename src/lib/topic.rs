@@ -0,0 +1,135 @@
+use std::error::Error;
+
+use crate::lib::common::IllegalArgumentError;
+
+// Placeholder values
+pub const DEVICE_ID_PLACEHOLDER: &str = "{device_id}";
+// MQTT topic names are length-prefixed with an unsigned 16-bit integer
+const MAXIMUM_TOPIC_LENGTH: usize = 65535;
+
+pub fn render_topic(topic_template: &str, device_id: &str) -> Result<String, Box<dyn Error>> {
+    if !topic_template.contains(DEVICE_ID_PLACEHOLDER) {
+        return Ok(String::from(topic_template));
+    }
+    let sanitized_device_id = sanitize_device_id(device_id)?;
+    Ok(topic_template.replace(DEVICE_ID_PLACEHOLDER, sanitized_device_id.as_str()))
+}
+
+/// Checks that a topic can be published to; Rust strings are always valid UTF-8, leaving the length and null-byte rules.
+/// Blank topics are rejected too: MQTT forbids empty topic names, and one of only whitespace is taken to be a mistake.
+pub fn validate_topic(topic: &str) -> Result<(), IllegalArgumentError> {
+    if topic.trim().is_empty() {
+        return Err(IllegalArgumentError::new("Topic must not be empty"));
+    }
+    if topic.len() > MAXIMUM_TOPIC_LENGTH {
+        return Err(IllegalArgumentError::new(
+            format!("Topic is {} bytes long, exceeding the limit of {} bytes", topic.len(), MAXIMUM_TOPIC_LENGTH).as_str()
+        ));
+    }
+    if topic.contains('\0') {
+        return Err(IllegalArgumentError::new(
+            format!("Topic '{}' contains a null character", topic.escape_default()).as_str()
+        ));
+    }
+    Ok(())
+}
+
+pub fn sanitize_device_id(device_id: &str) -> Result<String, IllegalArgumentError> {
+    if device_id.is_empty() {
+        return Err(IllegalArgumentError::new("Device ID cannot be substituted into a topic as it is empty"));
+    }
+    let mut sanitized = String::with_capacity(device_id.len());
+    for c in device_id.chars() {
+        match c {
+            // Characters that would alter the topic structure or act as wildcards are escaped
+            '%' | '+' | '#' | '/' | ' ' => sanitized.push_str(format!("%{:02X}", c as u32).as_str()),
+            _ if c.is_control() => {
+                return Err(IllegalArgumentError::new(
+                    format!("Device ID '{}' contains a control character and cannot be substituted into a topic", device_id.escape_default()).as_str()
+                ));
+            }
+            _ => sanitized.push(c),
+        }
+    }
+    Ok(sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::topic::{render_topic, sanitize_device_id, validate_topic};
+
+    #[test]
+    fn render_topic_without_placeholder() {
+        let result = render_topic("Device_Status", "Device #1").unwrap();
+        assert_eq!("Device_Status", result);
+    }
+
+    #[test]
+    fn render_topic_with_plain_device_id() {
+        let result = render_topic("devices/{device_id}/status", "device-1").unwrap();
+        assert_eq!("devices/device-1/status", result);
+    }
+
+    #[test]
+    fn sanitize_multi_level_wildcard() {
+        let result = sanitize_device_id("device#1").unwrap();
+        assert_eq!("device%231", result);
+    }
+
+    #[test]
+    fn sanitize_single_level_wildcard() {
+        let result = sanitize_device_id("device+1").unwrap();
+        assert_eq!("device%2B1", result);
+    }
+
+    #[test]
+    fn sanitize_spaces() {
+        let result = render_topic("devices/{device_id}/status", "Test Device Name").unwrap();
+        assert_eq!("devices/Test%20Device%20Name/status", result);
+    }
+
+    #[test]
+    fn sanitize_separator_and_escape_character() {
+        let result = sanitize_device_id("rack/1 100%").unwrap();
+        assert_eq!("rack%2F1%20100%25", result);
+    }
+
+    #[test]
+    fn sanitize_empty_device_id() {
+        let result = render_topic("devices/{device_id}/status", "").err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Device ID cannot be substituted into a topic as it is empty", result.to_string());
+    }
+
+    #[test]
+    fn sanitize_control_character() {
+        let result = sanitize_device_id("device\u{0}1").err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Device ID 'device\\u{0}1' contains a control character and cannot be substituted into a topic", result.to_string());
+    }
+
+    #[test]
+    fn validate_topic_at_length_limit() {
+        assert!(validate_topic("a".repeat(65535).as_str()).is_ok());
+    }
+
+    #[test]
+    fn validate_over_long_topic() {
+        let result = validate_topic("a".repeat(65536).as_str()).err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Topic is 65536 bytes long, exceeding the limit of 65535 bytes", result.to_string());
+    }
+
+    #[test]
+    fn validate_blank_topic() {
+        for topic in ["", " \t"] {
+            let result = validate_topic(topic).err().unwrap();
+            assert_eq!("An illegal argument was encountered. Reason: Topic must not be empty", result.to_string());
+        }
+    }
+
+    #[test]
+    fn validate_topic_with_null_character() {
+        let result = validate_topic("devices/\u{0}/status").err().unwrap();
+        assert_eq!("An illegal argument was encountered. Reason: Topic 'devices/\\u{0}/status' contains a null character", result.to_string());
+    }
+}
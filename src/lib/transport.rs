@@ -0,0 +1,573 @@
+use std::error::Error;
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use paho_mqtt::{Client, ConnectOptions, DisconnectOptions, DisconnectOptionsBuilder, Message, PersistenceType, SslOptions, SslOptionsBuilder};
+
+use crate::lib::common::RuntimeError;
+use crate::lib::config::{DestinationConfig, MqttPersistence, RunnerConfig};
+
+// Server address schemes over which the client connects with TLS
+const TLS_SCHEMES: [&str; 3] = ["ssl://", "mqtts://", "wss://"];
+// Appended to the device ID to give the crash hook's client an ID of its own
+const CRASH_CLIENT_ID_SUFFIX: &str = "-crash";
+
+/// The connection that reports are published over.
+pub trait Transport: Send {
+    fn connect(&self) -> Result<(), Box<dyn Error>>;
+    fn publish(&self, msg: Message) -> Result<(), Box<dyn Error>>;
+    /// Blocks until every QoS 1 and 2 publish has been acknowledged by the broker, failing once the timeout passes.
+    fn wait_for_delivery(&self, timeout: Duration) -> Result<(), Box<dyn Error>>;
+    fn disconnect(&self) -> Result<(), Box<dyn Error>>;
+    /// Disconnects once in-flight publishes have been acknowledged, waiting no longer than the grace period.
+    fn disconnect_after(&self, grace: Duration) -> Result<(), Box<dyn Error>>;
+    fn is_connected(&self) -> bool;
+    fn subscribe(&self, topic: &str, qos: i32) -> Result<(), Box<dyn Error>>;
+    /// Returns the next message received on a subscribed topic, if one is waiting.
+    fn try_receive(&self) -> Option<Message>;
+}
+
+pub struct MqttTransport {
+    mqtt_client: Client,
+    conn_opts: ConnectOptions,
+    disconnect_timeout: Option<Duration>,
+    /// Incoming messages, only consumed when a command or config topic is configured or the transport is made for a self-test.
+    receiver: Option<Receiver<Option<Message>>>,
+}
+
+impl MqttTransport {
+    pub fn new(runner_config: &RunnerConfig) -> Result<MqttTransport, Box<dyn Error>> {
+        let consume = runner_config.command_topic.is_some() || runner_config.config_topic.is_some();
+        MqttTransport::create(runner_config, runner_config.device_id.as_str(), consume)
+    }
+
+    /// Creates a transport that consumes incoming messages whether or not a command topic is configured.
+    pub fn with_consumer(runner_config: &RunnerConfig) -> Result<MqttTransport, Box<dyn Error>> {
+        MqttTransport::create(runner_config, runner_config.device_id.as_str(), true)
+    }
+
+    /// Creates the transport the crash hook publishes over. It connects alongside the runner's transport, so it takes
+    /// a client ID of its own; sharing the device ID would make the broker drop the runner's session.
+    pub fn for_crash_hook(runner_config: &RunnerConfig) -> Result<MqttTransport, Box<dyn Error>> {
+        MqttTransport::create(runner_config, crash_client_id(runner_config).as_str(), false)
+    }
+
+    fn create(runner_config: &RunnerConfig, client_id: &str, consume: bool) -> Result<MqttTransport, Box<dyn Error>> {
+        // Message properties such as the expiry interval are only transmitted over MQTT 5
+        let mqtt_version = if runner_config.message_expiry_secs.is_some() {
+            paho_mqtt::MQTT_VERSION_5
+        } else {
+            runner_config.mqtt_version.unwrap_or(paho_mqtt::MQTT_VERSION_DEFAULT)
+        };
+        let mut mqtt_opts_builder = paho_mqtt::CreateOptionsBuilder::new()
+            .server_uri(runner_config.server_address.as_str())
+            .client_id(client_id)
+            .mqtt_version(mqtt_version);
+        if let Some(persistence) = persistence_type(runner_config) {
+            mqtt_opts_builder = mqtt_opts_builder.persistence(persistence);
+        }
+        let mqtt_opts = mqtt_opts_builder.finalize();
+        let mut mqtt_client = match paho_mqtt::Client::new(mqtt_opts) {
+            Ok(mqtt_client) => mqtt_client,
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                return Err(error);
+            }
+        };
+        // Every call on the synchronous client blocks on its token for up to this long, so a QoS 1 or 2 publish that
+        // the broker has not acknowledged in time fails rather than waiting on the client's five minute default
+        mqtt_client.set_timeout(Duration::from_secs(runner_config.publish_ack_timeout_secs));
+        let mut conn_opts_builder = paho_mqtt::ConnectOptionsBuilder::new();
+        conn_opts_builder
+            .user_name(runner_config.user_name.as_str())
+            .password(runner_config.user_password.as_str())
+            .keep_alive_interval(Duration::from_secs(20))
+            .mqtt_version(mqtt_version);
+        if mqtt_version == paho_mqtt::MQTT_VERSION_5 {
+            conn_opts_builder.clean_start(runner_config.clean_session);
+        } else {
+            conn_opts_builder.clean_session(runner_config.clean_session);
+        }
+        if TLS_SCHEMES.iter().any(|scheme| runner_config.server_address.starts_with(scheme)) {
+            conn_opts_builder.ssl_options(ssl_options(runner_config)?);
+        }
+        let conn_opts = conn_opts_builder.finalize();
+        // Consumption starts ahead of connecting so that no message arriving straight after a subscription is missed
+        let receiver = if consume { Some(mqtt_client.start_consuming()) } else { None };
+        Ok(MqttTransport {
+            mqtt_client,
+            conn_opts,
+            disconnect_timeout: runner_config.disconnect_timeout_ms.map(Duration::from_millis),
+            receiver,
+        })
+    }
+
+    /// The client only honours whole seconds; the timeout is truncated, with a minimum of one second.
+    fn disconnect_options(&self) -> Option<DisconnectOptions> {
+        self.disconnect_timeout.map(|timeout| DisconnectOptionsBuilder::new().timeout(timeout).finalize())
+    }
+}
+
+fn crash_client_id(runner_config: &RunnerConfig) -> String {
+    format!("{}{}", runner_config.device_id, CRASH_CLIENT_ID_SUFFIX)
+}
+
+/// The client's store for unacknowledged messages under the configured persistence, or `None` to keep the client
+/// library's default.
+fn persistence_type(runner_config: &RunnerConfig) -> Option<PersistenceType> {
+    match runner_config.persistence {
+        MqttPersistence::None => Some(PersistenceType::None),
+        MqttPersistence::File => Some(match &runner_config.persistence_dir {
+            Some(persistence_dir) => PersistenceType::from(persistence_dir.as_str()),
+            None => PersistenceType::File,
+        }),
+        MqttPersistence::Default => None,
+    }
+}
+
+/// Stands in for the broker connection when nothing is published over MQTT, so that no client is created and no broker
+/// settings are needed. Connecting and publishing fail, as they are never expected to be attempted.
+pub struct DisabledTransport;
+
+impl DisabledTransport {
+    fn disabled_error() -> Box<dyn Error> {
+        Box::new(RuntimeError::new("MQTT is not in use; no broker connection was configured"))
+    }
+}
+
+impl Transport for DisabledTransport {
+    fn connect(&self) -> Result<(), Box<dyn Error>> {
+        Err(DisabledTransport::disabled_error())
+    }
+
+    fn publish(&self, _msg: Message) -> Result<(), Box<dyn Error>> {
+        Err(DisabledTransport::disabled_error())
+    }
+
+    fn wait_for_delivery(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn disconnect_after(&self, _grace: Duration) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        false
+    }
+
+    fn subscribe(&self, _topic: &str, _qos: i32) -> Result<(), Box<dyn Error>> {
+        Err(DisabledTransport::disabled_error())
+    }
+
+    fn try_receive(&self) -> Option<Message> {
+        None
+    }
+}
+
+/// Whether anything is published over MQTT: reports sent to the broker destination, or any of the messages that only
+/// travel over MQTT. Heartbeats are only sent when an interval is configured.
+pub fn uses_mqtt(runner_config: &RunnerConfig) -> bool {
+    runner_config.destinations.iter().any(DestinationConfig::is_mqtt)
+        || runner_config.heartbeat_interval_secs.is_some()
+        || runner_config.alert_topic.is_some()
+        || runner_config.diagnostic_topic.is_some()
+        || runner_config.history_topic.is_some()
+        || runner_config.command_topic.is_some()
+        || runner_config.config_topic.is_some()
+        || runner_config.crash_topic.is_some()
+        || runner_config.send_startup_report
+}
+
+/// Builds the TLS options, verifying the broker's certificate and host name unless `insecure_skip_verify` is set.
+fn ssl_options(runner_config: &RunnerConfig) -> Result<SslOptions, Box<dyn Error>> {
+    let verify_server = !runner_config.insecure_skip_verify;
+    let mut ssl_opts_builder = SslOptionsBuilder::new();
+    ssl_opts_builder
+        .enable_server_cert_auth(verify_server)
+        .verify(verify_server);
+    if let Some(min_tls_version) = runner_config.min_tls_version {
+        ssl_opts_builder.ssl_version(min_tls_version);
+    }
+    if let Some(ca_file) = &runner_config.ca_file {
+        ssl_opts_builder.trust_store(ca_file)?;
+    }
+    if let Some(client_cert_file) = &runner_config.client_cert_file {
+        ssl_opts_builder.key_store(client_cert_file)?;
+    }
+    if let Some(client_key_file) = &runner_config.client_key_file {
+        ssl_opts_builder.private_key(client_key_file)?;
+    }
+    if !runner_config.alpn_protocols.is_empty() {
+        let alpn_protocols: Vec<&str> = runner_config.alpn_protocols.iter().map(String::as_str).collect();
+        ssl_opts_builder.alpn_protos(&alpn_protocols);
+    }
+    Ok(ssl_opts_builder.finalize())
+}
+
+impl Transport for MqttTransport {
+    fn connect(&self) -> Result<(), Box<dyn Error>> {
+        match self.mqtt_client.connect(self.conn_opts.clone()) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    fn publish(&self, msg: Message) -> Result<(), Box<dyn Error>> {
+        match self.mqtt_client.publish(msg) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    fn wait_for_delivery(&self, _timeout: Duration) -> Result<(), Box<dyn Error>> {
+        // The synchronous client's publish already waits on each message's delivery token, bounded by the publish
+        // acknowledgment timeout set on the client, so nothing is left in flight once it returns
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+        match self.mqtt_client.disconnect(self.disconnect_options()) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    fn disconnect_after(&self, grace: Duration) -> Result<(), Box<dyn Error>> {
+        // As with waiting for delivery, nothing is in flight once the synchronous client's publish returns, so the
+        // client disconnects without using the grace period
+        match self.mqtt_client.disconnect_after(grace) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        self.mqtt_client.is_connected()
+    }
+
+    fn subscribe(&self, topic: &str, qos: i32) -> Result<(), Box<dyn Error>> {
+        match self.mqtt_client.subscribe(topic, qos) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let error = Box::new(RuntimeError::new(e.to_string().as_str()));
+                Err(error)
+            }
+        }
+    }
+
+    fn try_receive(&self) -> Option<Message> {
+        // A lost connection is signalled with an empty entry, which is skipped as reconnecting is handled elsewhere
+        self.receiver.as_ref()?.try_iter().flatten().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::lib::config::{DestinationConfig, load_config, MqttPersistence};
+    use crate::lib::transport::{crash_client_id, MqttTransport, persistence_type, ssl_options, uses_mqtt};
+
+    #[test]
+    fn disconnect_with_configured_timeout() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.disconnect_timeout_ms = Some(2500);
+        let transport = MqttTransport::new(&runner_config).unwrap();
+        assert_eq!(Some(Duration::from_millis(2500)), transport.disconnect_timeout);
+        assert!(transport.disconnect_options().is_some());
+    }
+
+    #[test]
+    fn create_client_with_configured_persistence() {
+        let runner_config = load_config(None).unwrap();
+        assert!(persistence_type(&runner_config).is_none());
+        let runner_config = load_config(Some(&String::from("resources/test/good/no_persistence.yaml"))).unwrap();
+        assert_eq!("Some(None)", format!("{:?}", persistence_type(&runner_config)));
+        let mut runner_config = load_config(Some(&String::from("resources/test/good/file_persistence.yaml"))).unwrap();
+        assert_eq!("Some(File with Path)", format!("{:?}", persistence_type(&runner_config)));
+        runner_config.persistence_dir = None;
+        assert_eq!("Some(File)", format!("{:?}", persistence_type(&runner_config)));
+        runner_config.persistence = MqttPersistence::None;
+        assert_eq!("Some(None)", format!("{:?}", persistence_type(&runner_config)));
+    }
+
+    #[test]
+    fn create_client_persisting_to_directory() {
+        let persistence_dir = std::env::temp_dir().join(format!("device_stats_reporter_persistence_{}", std::process::id()));
+        let mut runner_config = load_config(Some(&String::from("resources/test/good/file_persistence.yaml"))).unwrap();
+        runner_config.persistence_dir = Some(String::from(persistence_dir.to_str().unwrap()));
+        let result = MqttTransport::new(&runner_config);
+        let _ = std::fs::remove_dir_all(&persistence_dir);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn verify_server_certificate_by_default() {
+        let runner_config = load_config(None).unwrap();
+        assert!(ssl_options(&runner_config).unwrap().enable_server_cert_auth());
+    }
+
+    #[test]
+    fn skip_server_certificate_verification() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/insecure_skip_verify.yaml"))
+        ).unwrap();
+        assert!(!ssl_options(&runner_config).unwrap().enable_server_cert_auth());
+    }
+
+    #[test]
+    fn offer_alpn_protocols_for_aws_iot() {
+        let runner_config = load_config(
+            Some(&String::from("resources/test/good/aws_iot_https_port.yaml"))
+        ).unwrap();
+        assert!(ssl_options(&runner_config).is_ok());
+        assert!(MqttTransport::new(&runner_config).is_ok());
+    }
+
+    #[test]
+    fn use_mqtt_only_when_publishing_over_it() {
+        let mut runner_config = load_config(None).unwrap();
+        assert!(uses_mqtt(&runner_config));
+        runner_config.destinations = vec![DestinationConfig::Http {
+            url: String::from("http://localhost:8080/reports"),
+            headers: Default::default(),
+            compress: true,
+        }];
+        assert!(!uses_mqtt(&runner_config));
+        runner_config.alert_topic = Some(String::from("devices/alerts"));
+        assert!(uses_mqtt(&runner_config));
+    }
+
+    #[test]
+    fn bound_client_calls_by_publish_ack_timeout() {
+        let mut runner_config = load_config(None).unwrap();
+        runner_config.publish_ack_timeout_secs = 5;
+        let transport = MqttTransport::new(&runner_config).unwrap();
+        assert_eq!(Duration::from_secs(5), transport.mqtt_client.timeout());
+    }
+
+    #[test]
+    fn give_crash_hook_its_own_client_id() {
+        let runner_config = load_config(None).unwrap();
+        assert_eq!(format!("{}-crash", runner_config.device_id), crash_client_id(&runner_config));
+        assert!(MqttTransport::for_crash_hook(&runner_config).is_ok());
+    }
+
+    #[test]
+    fn disconnect_without_timeout() {
+        let runner_config = load_config(None).unwrap();
+        let transport = MqttTransport::new(&runner_config).unwrap();
+        assert_eq!(None, transport.disconnect_timeout);
+        assert!(transport.disconnect_options().is_none());
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::collections::VecDeque;
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use paho_mqtt::Message;
+
+    use crate::lib::common::RuntimeError;
+    use crate::lib::transport::Transport;
+
+    #[derive(Debug,PartialEq,Clone)]
+    pub enum TransportEvent {
+        Connect,
+        Publish(String, i32),
+        Disconnect,
+        DisconnectAfter(Duration),
+        Subscribe(String, i32),
+    }
+
+    /// Records every call made to it so tests can assert on the traffic a runner produces.
+    #[derive(Clone,Default)]
+    pub struct MockTransport {
+        pub events: Arc<Mutex<Vec<TransportEvent>>>,
+        pub messages: Arc<Mutex<Vec<Message>>>,
+        pub unreachable: bool,
+        pub colliding: bool,
+        pub connected: Arc<AtomicBool>,
+        /// The number of upcoming publishes that reach the broker but fail as if their acknowledgement was lost.
+        pub unacknowledged_publishes: Arc<AtomicUsize>,
+        /// Messages waiting to be received, as if the broker had delivered them on a subscribed topic.
+        pub incoming: Arc<Mutex<VecDeque<Message>>>,
+        /// When set, QoS 1 and 2 publishes stay in flight for this long before the broker acknowledges them.
+        pub ack_delay: Option<Duration>,
+        /// When each in-flight publish is due to be acknowledged.
+        pub in_flight: Arc<Mutex<Vec<Instant>>>,
+        pub acknowledged_publishes: Arc<AtomicUsize>,
+        /// Publishes still in flight when the connection was closed.
+        pub lost_publishes: Arc<AtomicUsize>,
+        pub subscriptions: Arc<Mutex<Vec<String>>>,
+        /// When set, publishes to a subscribed topic are delivered back, as a broker does.
+        pub echoing: bool,
+    }
+
+    impl MockTransport {
+        pub fn new() -> MockTransport {
+            MockTransport::default()
+        }
+
+        pub fn unreachable() -> MockTransport {
+            MockTransport {
+                unreachable: true,
+                ..MockTransport::default()
+            }
+        }
+
+        /// Simulates another client with the same ID taking the connection over as soon as it is made.
+        pub fn colliding() -> MockTransport {
+            MockTransport {
+                colliding: true,
+                ..MockTransport::default()
+            }
+        }
+
+        pub fn events(&self) -> Vec<TransportEvent> {
+            self.events.lock().unwrap().clone()
+        }
+
+        pub fn messages(&self) -> Vec<Message> {
+            self.messages.lock().unwrap().clone()
+        }
+
+        /// Simulates the broker delivering a message on a subscribed topic.
+        pub fn deliver(&self, msg: Message) {
+            self.incoming.lock().unwrap().push_back(msg);
+        }
+
+        /// Simulates the broker silently dropping the connection.
+        pub fn drop_connection(&self) {
+            self.connected.store(false, Ordering::SeqCst);
+        }
+
+        /// Simulates a broker delivering publishes back to the subscribers of their topic.
+        pub fn echoing() -> MockTransport {
+            MockTransport {
+                echoing: true,
+                ..MockTransport::default()
+            }
+        }
+
+        /// Simulates a broker that is slow to acknowledge publishes.
+        pub fn slow_acking(ack_delay: Duration) -> MockTransport {
+            MockTransport {
+                ack_delay: Some(ack_delay),
+                ..MockTransport::default()
+            }
+        }
+
+        /// Acknowledges the in-flight publishes due by the deadline and loses the rest.
+        fn close_in_flight(&self, deadline: Instant) {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            let acknowledged = in_flight.iter().filter(|due| **due <= deadline).count();
+            self.acknowledged_publishes.fetch_add(acknowledged, Ordering::SeqCst);
+            self.lost_publishes.fetch_add(in_flight.len() - acknowledged, Ordering::SeqCst);
+            in_flight.clear();
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn connect(&self) -> Result<(), Box<dyn Error>> {
+            if self.unreachable {
+                return Err(Box::new(RuntimeError::new("Broker is unreachable")));
+            }
+            self.events.lock().unwrap().push(TransportEvent::Connect);
+            self.connected.store(!self.colliding, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn publish(&self, msg: Message) -> Result<(), Box<dyn Error>> {
+            self.events.lock().unwrap().push(TransportEvent::Publish(String::from(msg.topic()), msg.qos()));
+            if let Some(ack_delay) = self.ack_delay {
+                if msg.qos() > 0 {
+                    self.in_flight.lock().unwrap().push(Instant::now() + ack_delay);
+                }
+            }
+            if self.echoing && self.subscriptions.lock().unwrap().iter().any(|topic| topic == msg.topic()) {
+                self.deliver(msg.clone());
+            }
+            self.messages.lock().unwrap().push(msg);
+            let unacknowledged = self.unacknowledged_publishes.load(Ordering::SeqCst);
+            if unacknowledged > 0 {
+                self.unacknowledged_publishes.store(unacknowledged - 1, Ordering::SeqCst);
+                return Err(Box::new(RuntimeError::new("Publish was not acknowledged")));
+            }
+            Ok(())
+        }
+
+        fn wait_for_delivery(&self, timeout: Duration) -> Result<(), Box<dyn Error>> {
+            let deadline = Instant::now() + timeout;
+            let last_due = self.in_flight.lock().unwrap().iter().max().cloned();
+            if let Some(last_due) = last_due {
+                thread::sleep(last_due.min(deadline).saturating_duration_since(Instant::now()));
+            }
+            let mut in_flight = self.in_flight.lock().unwrap();
+            let in_flight_count = in_flight.len();
+            in_flight.retain(|due| *due > deadline);
+            self.acknowledged_publishes.fetch_add(in_flight_count - in_flight.len(), Ordering::SeqCst);
+            if !in_flight.is_empty() {
+                return Err(Box::new(RuntimeError::new("Timed out waiting for publishes to be acknowledged")));
+            }
+            Ok(())
+        }
+
+        fn disconnect(&self) -> Result<(), Box<dyn Error>> {
+            self.events.lock().unwrap().push(TransportEvent::Disconnect);
+            self.close_in_flight(Instant::now());
+            self.connected.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn disconnect_after(&self, grace: Duration) -> Result<(), Box<dyn Error>> {
+            self.events.lock().unwrap().push(TransportEvent::DisconnectAfter(grace));
+            let deadline = Instant::now() + grace;
+            let last_due = self.in_flight.lock().unwrap().iter().max().cloned();
+            if let Some(last_due) = last_due {
+                thread::sleep(last_due.min(deadline).saturating_duration_since(Instant::now()));
+            }
+            self.close_in_flight(deadline);
+            self.connected.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn is_connected(&self) -> bool {
+            self.connected.load(Ordering::SeqCst)
+        }
+
+        fn subscribe(&self, topic: &str, qos: i32) -> Result<(), Box<dyn Error>> {
+            self.events.lock().unwrap().push(TransportEvent::Subscribe(String::from(topic), qos));
+            self.subscriptions.lock().unwrap().push(String::from(topic));
+            Ok(())
+        }
+
+        fn try_receive(&self) -> Option<Message> {
+            self.incoming.lock().unwrap().pop_front()
+        }
+    }
+}
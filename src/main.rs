@@ -1,13 +1,22 @@
+#![allow(special_module_name)]
+
+use std::env;
+use std::process;
+
+use crate::lib::common::format_error;
 use crate::lib::runner;
 
 mod lib;
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let error_format = runner::error_format(&args[1..]);
     println!("Running Device Stats Reporter");
     match runner::run() {
         Ok(_) => { println!("Run complete") }
         Err(e) => {
-            eprintln!("Error running Device Stats Reporter: {:?}", e);
+            eprintln!("{}", format_error(e.as_ref(), error_format));
+            process::exit(1);
         }
     };
-}
\ No newline at end of file
+}